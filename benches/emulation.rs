@@ -0,0 +1,69 @@
+//! Measures emulation speed on a ROM that loops over a mix of loads, arithmetic, memory access
+//! and branches, with the LCD on. Run with `cargo bench`.
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rgb_emu::gameboy::{GameBoy, Options};
+
+/// Instructions run per iteration of the instruction benchmark
+const INSTRUCTIONS: u64 = 10_000;
+
+fn looping_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // NOP; JP 0x0150
+    rom[0x0150..0x0167].copy_from_slice(&[
+        0x21, 0x00, 0xC0, // LD HL, 0xC000
+        0x01, 0x00, 0x01, // LD BC, 0x0100
+        0x7E, // .loop: LD A, [HL]
+        0x81, // ADD A, C
+        0x22, // LD [HL+], A
+        0xA8, // XOR B
+        0xFE, 0x42, // CP 0x42
+        0x20, 0x01, // JR NZ, .skip
+        0x00, // NOP
+        0x0B, // .skip: DEC BC
+        0x78, // LD A, B
+        0xB1, // OR C
+        0x20, 0xF2, // JR NZ, .loop
+        0xC3, 0x50, 0x01, // JP 0x0150
+    ]);
+    rom
+}
+
+fn instructions(c: &mut Criterion) {
+    let mut gameboy = GameBoy::new(looping_rom(), Options::default()).unwrap();
+    let mut group = c.benchmark_group("instructions");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    group.bench_function("step", |b| {
+        b.iter(|| {
+            for _ in 0..INSTRUCTIONS {
+                gameboy.step();
+            }
+        });
+    });
+    group.finish();
+}
+
+fn frames(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frames");
+    group.throughput(Throughput::Elements(1));
+    let mut gameboy = GameBoy::new(looping_rom(), Options::default()).unwrap();
+    group.bench_function("run_frame", |b| b.iter(|| gameboy.run_frame()));
+    let options = Options {
+        block_cache: true,
+        ..Options::default()
+    };
+    let mut gameboy = GameBoy::new(looping_rom(), options).unwrap();
+    group.bench_function("run_frame/block_cache", |b| b.iter(|| gameboy.run_frame()));
+    #[cfg(feature = "jit")]
+    {
+        let options = Options {
+            jit: true,
+            ..Options::default()
+        };
+        let mut gameboy = GameBoy::new(looping_rom(), options).unwrap();
+        group.bench_function("run_frame/jit", |b| b.iter(|| gameboy.run_frame()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, instructions, frames);
+criterion_main!(benches);
@@ -0,0 +1,72 @@
+/* C API for the rgb Game Boy emulator. See src/ffi.rs for the implementation. */
+
+#ifndef RGB_H
+#define RGB_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RGB_SCREEN_WIDTH 160
+#define RGB_SCREEN_HEIGHT 144
+
+/* Bits for rgb_set_buttons */
+#define RGB_BUTTON_RIGHT (1 << 0)
+#define RGB_BUTTON_LEFT (1 << 1)
+#define RGB_BUTTON_UP (1 << 2)
+#define RGB_BUTTON_DOWN (1 << 3)
+#define RGB_BUTTON_A (1 << 4)
+#define RGB_BUTTON_B (1 << 5)
+#define RGB_BUTTON_SELECT (1 << 6)
+#define RGB_BUTTON_START (1 << 7)
+
+/* An opaque handle to a Game Boy, which may not have a ROM loaded yet */
+typedef struct RgbGameBoy RgbGameBoy;
+
+/* Creates a Game Boy with no ROM loaded. It must be freed with rgb_destroy. */
+RgbGameBoy *rgb_create(void);
+
+/* Frees a Game Boy. Does nothing if handle is NULL. */
+void rgb_destroy(RgbGameBoy *handle);
+
+/* Loads a ROM and powers on, replacing any ROM that was loaded. The model is picked from the
+ * cartridge header. Returns false if the ROM can't be loaded. */
+bool rgb_load_rom(RgbGameBoy *handle, const uint8_t *rom, size_t length);
+
+/* Runs until the next frame is finished. Does nothing if no ROM is loaded. */
+void rgb_run_frame(RgbGameBoy *handle);
+
+/* Switches the power off and on, keeping battery-backed cartridge RAM. Does nothing if no ROM
+ * is loaded. */
+void rgb_reset(RgbGameBoy *handle);
+
+/* Sets the buttons that are held, as RGB_BUTTON_* bits */
+void rgb_set_buttons(RgbGameBoy *handle, uint8_t pressed);
+
+/* Returns the last completed frame as 160x144 shades (0-3), one byte per pixel, or NULL if no
+ * ROM is loaded. The pointer is valid until the next call that runs or changes the Game Boy. */
+const uint8_t *rgb_framebuffer(const RgbGameBoy *handle);
+
+/* Returns the last completed frame as 160x144 RGB555 colors, or NULL if the frame has no
+ * colors (on DMG) or no ROM is loaded. The pointer is valid until the next call that runs or
+ * changes the Game Boy. */
+const uint16_t *rgb_color_framebuffer(const RgbGameBoy *handle);
+
+/* Saves the state into buffer if it's at least capacity bytes, and returns the size of the
+ * state either way, so it can be called with a NULL buffer first to get the size. Returns 0 if
+ * no ROM is loaded. */
+size_t rgb_save_state(const RgbGameBoy *handle, uint8_t *buffer, size_t capacity);
+
+/* Loads a state saved by rgb_save_state for the same ROM. Returns false if no ROM is loaded or
+ * the state is corrupt. */
+bool rgb_load_state(RgbGameBoy *handle, const uint8_t *data, size_t length);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RGB_H */
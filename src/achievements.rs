@@ -0,0 +1,121 @@
+//! Hooks for achievement systems like RetroAchievements.
+//!
+//! An achievement runtime identifies the game by `rom_hash`, and inspects memory through
+//! `Memory` once per emulated frame from the callbacks registered with `Achievements`.
+
+use crate::bus::Bus;
+use crate::cpu::Cpu;
+
+/// Side-effect free access to the Game Boy address space, as seen by RetroAchievements.
+///
+/// Addresses are the CPU's 16-bit addresses, so they're stable across emulators.
+pub struct Memory<'a> {
+    peek: &'a dyn Fn(u16) -> u8,
+}
+
+impl Memory<'_> {
+    /// Size of the address space exposed to achievements
+    pub const SIZE: u32 = 0x1_0000;
+
+    /// Returns `None` for addresses outside the address space
+    #[must_use]
+    pub fn read(&self, address: u32) -> Option<u8> {
+        let address = u16::try_from(address).ok()?;
+        Some((self.peek)(address))
+    }
+}
+
+type FrameCallback = Box<dyn FnMut(u64, &Memory)>;
+
+/// Calls the registered callbacks once per emulated frame
+#[derive(Default)]
+pub struct Achievements {
+    callbacks: Vec<FrameCallback>,
+    frames: u64,
+}
+
+impl Achievements {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a callback that's called with the frame number and memory after every frame
+    pub fn on_frame(&mut self, callback: impl FnMut(u64, &Memory) + 'static) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    /// Should be called by the frontend after each emulated frame. Frames aren't counted while
+    /// emulation is paused, so achievement timers only run while the game does.
+    pub fn frame<B: Bus + ?Sized>(&mut self, cpu: &Cpu<B>) {
+        self.frames += 1;
+        let peek = |address| cpu.bus.peek_byte(address);
+        let memory = Memory { peek: &peek };
+        for callback in &mut self.callbacks {
+            callback(self.frames, &memory);
+        }
+    }
+
+    /// Number of emulated frames since the hooks were created
+    #[must_use]
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
+
+/// Identifies a ROM the way RetroAchievements does for Game Boy games, as the lowercase
+/// hexadecimal MD5 hash of the whole ROM
+#[must_use]
+pub fn rom_hash(rom: &[u8]) -> String {
+    md5(rom).iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5,
+        9, 14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10,
+        15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    let constants: Vec<u32> = (0..64)
+        .map(|i| ((f64::from(i) + 1.0).sin().abs() * 4_294_967_296.0) as u32)
+        .collect();
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476];
+    for chunk in message.chunks_exact(64) {
+        let words: Vec<u32> = chunk
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(constants[i])
+                .wrapping_add(words[g])
+                .rotate_left(SHIFTS[i]);
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0; 16];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_le_bytes());
+    }
+    digest
+}
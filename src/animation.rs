@@ -0,0 +1,340 @@
+//! Recording gameplay as an animated GIF or APNG.
+//!
+//! Frames are timed by the Game Boy's frame rate of about 59.7 fps rather than the wall clock,
+//! so recordings play back at the right speed even when the emulator was fast-forwarded. Each
+//! frame is held until the next one arrives, since its duration isn't known before then.
+
+use crate::palette::Palette;
+use crate::png;
+use crate::ppu::{FRAME_CYCLES, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::video::{Frame, VideoSink};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// T-cycles per second
+const CLOCK_RATE: u64 = 4_194_304;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gif,
+    Apng,
+}
+
+impl Format {
+    /// Picks the format from a file extension: .gif for GIF, and .png or .apng for APNG
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Some(Format::Gif),
+            "png" | "apng" => Some(Format::Apng),
+            _ => None,
+        }
+    }
+
+    /// Units per second of frame delays
+    fn time_base(self) -> u64 {
+        match self {
+            // GIF delays are in hundredths of a second
+            Format::Gif => 100,
+            Format::Apng => 1000,
+        }
+    }
+
+    /// Shortest delay that viewers play correctly. Most GIF viewers slow down delays below 2,
+    /// so frames are dropped to keep the delays at least that long.
+    fn min_delay(self) -> u64 {
+        match self {
+            Format::Gif => 2,
+            Format::Apng => 1,
+        }
+    }
+}
+
+pub struct AnimationWriter<W: Write + Seek> {
+    /// Colors of the DMG shades
+    pub palette: Palette,
+    writer: W,
+    format: Format,
+    /// Whether identical consecutive frames are merged into one longer frame
+    deduplicate: bool,
+    /// The frame waiting to be written as RGB24, and the number of the frame it started at
+    pending: Option<(Vec<u8>, u64)>,
+    /// Number of the last frame added
+    last_frame: u64,
+    frames_written: u32,
+    /// APNG chunk sequence number
+    sequence: u32,
+    finished: bool,
+}
+
+impl AnimationWriter<BufWriter<File>> {
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be created or the header can't be written
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        format: Format,
+        deduplicate: bool,
+    ) -> std::io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), format, deduplicate)
+    }
+}
+
+impl<W: Write + Seek> AnimationWriter<W> {
+    /// # Errors
+    ///
+    /// Will return `Err` if the header can't be written
+    pub fn new(writer: W, format: Format, deduplicate: bool) -> std::io::Result<Self> {
+        let mut animation = Self {
+            palette: Palette::default(),
+            writer,
+            format,
+            deduplicate,
+            pending: None,
+            last_frame: 0,
+            frames_written: 0,
+            sequence: 0,
+            finished: false,
+        };
+        animation.write_header()?;
+        Ok(animation)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        match self.format {
+            Format::Gif => {
+                self.writer.write_all(b"GIF89a")?;
+                self.writer
+                    .write_all(&(SCREEN_WIDTH as u16).to_le_bytes())?;
+                self.writer
+                    .write_all(&(SCREEN_HEIGHT as u16).to_le_bytes())?;
+                // No global color table, since each frame has its own
+                self.writer.write_all(&[0, 0, 0])?;
+                // Loop forever
+                self.writer
+                    .write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+            }
+            Format::Apng => {
+                self.writer.write_all(png::SIGNATURE)?;
+                let header = png::header(SCREEN_WIDTH, SCREEN_HEIGHT);
+                self.write_chunk(b"IHDR", &header)?;
+                self.write_animation_control()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the APNG frame count and loop forever. It's rewritten when the recording is
+    /// finished, so it always comes right after the IHDR chunk.
+    fn write_animation_control(&mut self) -> std::io::Result<()> {
+        let mut control = self.frames_written.max(1).to_be_bytes().to_vec();
+        control.extend_from_slice(&0_u32.to_be_bytes());
+        self.write_chunk(b"acTL", &control)
+    }
+
+    fn write_chunk(&mut self, kind: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+        let mut chunk = Vec::with_capacity(data.len() + 12);
+        png::write_chunk(&mut chunk, kind, data);
+        self.writer.write_all(&chunk)
+    }
+
+    /// Time from power on to the start of a frame, in the format's delay units
+    fn timestamp(&self, frame: u64) -> u64 {
+        (frame * u64::from(FRAME_CYCLES) * self.format.time_base() + CLOCK_RATE / 2) / CLOCK_RATE
+    }
+
+    /// Adds a frame that's shown from the frame number `frame`
+    fn add_frame(&mut self, pixels: Vec<u8>, frame: u64) -> std::io::Result<()> {
+        self.last_frame = frame;
+        if let Some((pending, start)) = self.pending.take() {
+            if self.deduplicate && pending == pixels {
+                self.pending = Some((pending, start));
+                return Ok(());
+            }
+            let delay = self.timestamp(frame) - self.timestamp(start);
+            if delay < self.format.min_delay() {
+                // Drop the pending frame, and show this one in its place
+                self.pending = Some((pixels, start));
+                return Ok(());
+            }
+            self.write_frame(&pending, delay)?;
+        }
+        self.pending = Some((pixels, frame));
+        Ok(())
+    }
+
+    fn write_frame(&mut self, pixels: &[u8], delay: u64) -> std::io::Result<()> {
+        // Longer delays than fit are split into several copies of the frame
+        let mut remaining = delay;
+        while remaining > 0 {
+            let delay = remaining.min(u64::from(u16::MAX)) as u16;
+            remaining -= u64::from(delay);
+            match self.format {
+                Format::Gif => self.write_gif_frame(pixels, delay)?,
+                Format::Apng => self.write_apng_frame(pixels, delay)?,
+            }
+            self.frames_written += 1;
+        }
+        Ok(())
+    }
+
+    fn write_gif_frame(&mut self, pixels: &[u8], delay: u16) -> std::io::Result<()> {
+        let (palette, indices) = gif_palette(pixels);
+        // Graphic control extension with the delay, leaving the frame in place for the next one
+        self.writer.write_all(&[0x21, 0xF9, 0x04, 0x04])?;
+        self.writer.write_all(&delay.to_le_bytes())?;
+        self.writer.write_all(&[0x00, 0x00])?;
+        // Image descriptor, with a local color table of 256 colors
+        self.writer.write_all(&[0x2C, 0, 0, 0, 0])?;
+        self.writer
+            .write_all(&(SCREEN_WIDTH as u16).to_le_bytes())?;
+        self.writer
+            .write_all(&(SCREEN_HEIGHT as u16).to_le_bytes())?;
+        self.writer.write_all(&[0x87])?;
+        self.writer.write_all(&palette)?;
+        self.writer.write_all(&[8])?;
+        for block in lzw_uncompressed(&indices).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0])
+    }
+
+    fn write_apng_frame(&mut self, pixels: &[u8], delay: u16) -> std::io::Result<()> {
+        let mut control = self.sequence.to_be_bytes().to_vec();
+        self.sequence += 1;
+        control.extend_from_slice(&(SCREEN_WIDTH as u32).to_be_bytes());
+        control.extend_from_slice(&(SCREEN_HEIGHT as u32).to_be_bytes());
+        control.extend_from_slice(&[0; 8]); // Offset
+        control.extend_from_slice(&delay.to_be_bytes());
+        control.extend_from_slice(&(self.format.time_base() as u16).to_be_bytes());
+        control.extend_from_slice(&[0, 0]); // No disposal or blending
+        self.write_chunk(b"fcTL", &control)?;
+
+        let data = png::image_data(SCREEN_WIDTH, SCREEN_HEIGHT, pixels);
+        if self.frames_written == 0 {
+            self.write_chunk(b"IDAT", &data)
+        } else {
+            let mut frame_data = self.sequence.to_be_bytes().to_vec();
+            self.sequence += 1;
+            frame_data.extend_from_slice(&data);
+            self.write_chunk(b"fdAT", &frame_data)
+        }
+    }
+
+    /// Writes the last frame and the end of the file, and flushes the underlying writer. Nothing
+    /// more can be recorded afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be written
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        if let Some((pixels, start)) = self.pending.take() {
+            // The last frame lasts until the end of the last frame added
+            let delay = (self.timestamp(self.last_frame + 1) - self.timestamp(start))
+                .max(self.format.min_delay());
+            self.write_frame(&pixels, delay)?;
+        }
+        match self.format {
+            Format::Gif => self.writer.write_all(&[0x3B])?,
+            Format::Apng => {
+                self.write_chunk(b"IEND", &[])?;
+                self.writer.seek(SeekFrom::Start(33))?;
+                self.write_animation_control()?;
+                self.writer.seek(SeekFrom::End(0))?;
+            }
+        }
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Seek> VideoSink for AnimationWriter<W> {
+    fn push_frame(&mut self, frame: &Frame) {
+        if self.finished {
+            return;
+        }
+        let _ = self.add_frame(frame.to_rgb24(&self.palette), frame.number);
+    }
+}
+
+impl<W: Write + Seek> Drop for AnimationWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Builds a 256-color palette for an RGB24 image, along with each pixel's index into it. If the
+/// image has more colors than that, like some CGB games, the colors are reduced to 3-3-2 bits.
+fn gif_palette(pixels: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut colors = HashMap::new();
+    let mut palette = Vec::with_capacity(256 * 3);
+    let mut indices = Vec::with_capacity(pixels.len() / 3);
+    for pixel in pixels.chunks(3) {
+        let next = colors.len();
+        if next == 256 && !colors.contains_key(pixel) {
+            return reduced_palette(pixels);
+        }
+        let index = *colors.entry(pixel).or_insert_with(|| {
+            palette.extend_from_slice(pixel);
+            next as u8
+        });
+        indices.push(index);
+    }
+    palette.resize(256 * 3, 0);
+    (palette, indices)
+}
+
+fn reduced_palette(pixels: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let palette = (0..=255_u8)
+        .flat_map(|index| {
+            let scale = |value: u8, max: u8| (u16::from(value) * 255 / u16::from(max)) as u8;
+            [
+                scale(index >> 5, 7),
+                scale(index >> 2 & 7, 7),
+                scale(index & 3, 3),
+            ]
+        })
+        .collect();
+    let indices = pixels
+        .chunks(3)
+        .map(|pixel| pixel[0] & 0xE0 | (pixel[1] >> 3) & 0x1C | pixel[2] >> 6)
+        .collect();
+    (palette, indices)
+}
+
+/// Encodes 8-bit indices as GIF LZW data without compressing them: each index is sent as its
+/// own 9-bit code, with a clear code often enough that the code size never grows
+fn lzw_uncompressed(indices: &[u8]) -> Vec<u8> {
+    const CLEAR: u16 = 256;
+    const END: u16 = 257;
+    let mut data = Vec::with_capacity(indices.len() * 9 / 8 + 8);
+    let mut bits = 0_u32;
+    let mut bit_count = 0;
+    let mut emit = |code: u16| {
+        bits |= u32::from(code) << bit_count;
+        bit_count += 9;
+        while bit_count >= 8 {
+            data.push(bits as u8);
+            bits >>= 8;
+            bit_count -= 8;
+        }
+    };
+    for chunk in indices.chunks(254) {
+        emit(CLEAR);
+        for index in chunk {
+            emit(u16::from(*index));
+        }
+    }
+    emit(END);
+    if bit_count > 0 {
+        data.push(bits as u8);
+    }
+    data
+}
@@ -0,0 +1,559 @@
+use crate::audio::{AudioSink, SAMPLE_RATE};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// T-cycles per second
+const CLOCK_RATE: u32 = 4_194_304;
+
+/// Number of recent samples kept for visualization, about one frame's worth
+pub const HISTORY_LENGTH: usize = (SAMPLE_RATE / 60) as usize;
+
+/// Bits that always read back as 1 for each register in 0xFF10..=0xFF2F
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+/// Waveforms of the four square wave duty cycles, one bit per step
+const DUTY_CYCLES: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+/// Base periods of the noise channel in T-cycles, selected by NR43 bits 0-2
+const NOISE_DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// How much of the high-pass filter's charge remains after each sample. It loses 0.000042 per
+/// T-cycle on a DMG.
+const HIGH_PASS_CHARGE: f32 = 0.996_336;
+
+/// The four sound channels, in the order of their NR51 bits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Square1,
+    Square2,
+    Wave,
+    Noise,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 4] = [
+        Channel::Square1,
+        Channel::Square2,
+        Channel::Wave,
+        Channel::Noise,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Offset of the channel's first register (NRx0) from NR10
+    fn registers(self) -> usize {
+        self.index() * 5
+    }
+
+    fn max_length(self) -> u16 {
+        if self == Channel::Wave {
+            256
+        } else {
+            64
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Channel::Square1 => "Square 1",
+            Channel::Square2 => "Square 2",
+            Channel::Wave => "Wave",
+            Channel::Noise => "Noise",
+        })
+    }
+}
+
+/// The state of a channel that isn't in its registers
+#[derive(Clone, Copy, Default)]
+pub(crate) struct ChannelState {
+    pub(crate) enabled: bool,
+    /// Length clocks left before the channel turns itself off, if length is enabled
+    pub(crate) length: u16,
+    /// T-cycles until the waveform advances
+    pub(crate) timer: u32,
+    /// Step in the duty cycle, or sample in wave RAM
+    pub(crate) position: u8,
+    /// Current envelope volume (0-15)
+    pub(crate) volume: u8,
+    pub(crate) envelope_timer: u8,
+}
+
+#[derive(Default)]
+pub struct Apu {
+    pub(crate) registers: [u8; 0x20],
+    pub wave_ram: [u8; 0x10],
+    pub enabled: bool,
+    pub(crate) sample_clock: u32,
+    /// The most recently mixed samples, oldest first
+    pub history: VecDeque<(i16, i16)>,
+    /// The most recent output of each channel before mixing, oldest first. This includes muted
+    /// channels.
+    pub channel_history: [VecDeque<i16>; 4],
+    /// Channels left out of the mix. This is a frontend setting, so it's not saved in states.
+    pub(crate) muted: [bool; 4],
+    pub(crate) sink: Option<Box<dyn AudioSink>>,
+    /// Step (0-7) of the frame sequencer, which clocks length counters, sweep and envelopes
+    pub frame_sequencer_step: u8,
+    /// The timer's system clock as of the last tick
+    pub(crate) div: u16,
+    pub(crate) channels: [ChannelState; 4],
+    pub(crate) sweep_enabled: bool,
+    pub(crate) sweep_timer: u8,
+    /// Square 1's frequency as the sweep unit last calculated it
+    pub(crate) shadow_frequency: u16,
+    /// Whether the sweep has subtracted since the last trigger. Clearing NR10's negate bit after
+    /// that turns square 1 off.
+    pub(crate) sweep_negated: bool,
+    /// The noise channel's linear feedback shift register
+    pub(crate) lfsr: u16,
+    /// The wave RAM sample the wave channel is playing
+    pub(crate) wave_sample: u8,
+    /// Charge of the left and right high-pass filter capacitors. This only smooths the output,
+    /// so it's not saved in states.
+    capacitors: [f32; 2],
+}
+
+/// Which units are clocked by a frame sequencer step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameSequencerEvents {
+    pub length: bool,
+    pub sweep: bool,
+    pub envelope: bool,
+}
+
+impl FrameSequencerEvents {
+    #[must_use]
+    pub fn for_step(step: u8) -> Self {
+        Self {
+            length: step.is_multiple_of(2),
+            sweep: step == 2 || step == 6,
+            envelope: step == 7,
+        }
+    }
+}
+
+impl Apu {
+    /// Tick one M-cycle (4 T-cycles). `div` is the timer's system clock, whose bit 12 clocks
+    /// the frame sequencer (DIV-APU) when it falls.
+    pub(crate) fn tick(&mut self, div: u16) {
+        if self.div & 0x1000 != 0 && div & 0x1000 == 0 && self.enabled {
+            self.frame_sequencer_step = (self.frame_sequencer_step + 1) % 8;
+            self.clock_frame_sequencer();
+        }
+        self.div = div;
+        for channel in Channel::ALL {
+            self.step_channel(channel, 4);
+        }
+
+        self.sample_clock += 4 * SAMPLE_RATE;
+        if self.sample_clock >= CLOCK_RATE {
+            self.sample_clock -= CLOCK_RATE;
+            let outputs = Channel::ALL.map(|channel| self.output(channel));
+            for (history, output) in self.channel_history.iter_mut().zip(outputs) {
+                if history.len() == HISTORY_LENGTH {
+                    history.pop_front();
+                }
+                history.push_back(output);
+            }
+            let (left, right) = self.mix(outputs);
+            if self.history.len() == HISTORY_LENGTH {
+                self.history.pop_front();
+            }
+            self.history.push_back((left, right));
+            if let Some(sink) = &mut self.sink {
+                sink.push_sample(left, right);
+            }
+        }
+    }
+
+    /// T-cycles until the frame sequencer is next clocked, unless DIV is reset before then
+    #[must_use]
+    pub fn cycles_until_frame_sequencer_step(&self) -> u16 {
+        0x2000 - (self.div & 0x1FFF)
+    }
+
+    /// The units that will be clocked at the next frame sequencer step
+    #[must_use]
+    pub fn next_frame_sequencer_events(&self) -> FrameSequencerEvents {
+        FrameSequencerEvents::for_step((self.frame_sequencer_step + 1) % 8)
+    }
+
+    /// Leaves a channel out of the mix, or puts it back in
+    pub fn set_muted(&mut self, channel: Channel, muted: bool) {
+        self.muted[channel.index()] = muted;
+    }
+
+    #[must_use]
+    pub fn is_muted(&self, channel: Channel) -> bool {
+        self.muted[channel.index()]
+    }
+
+    /// Mutes every channel but one, or unmutes them all if that channel is already the only one
+    /// playing
+    pub fn solo(&mut self, channel: Channel) {
+        let solo = Channel::ALL.map(|other| other != channel);
+        self.muted = if self.muted == solo { [false; 4] } else { solo };
+    }
+
+    /// The peak amplitude of a channel over its recent history, for level meters
+    #[must_use]
+    pub fn level(&self, channel: Channel) -> u16 {
+        let history = &self.channel_history[channel.index()];
+        let (Some(min), Some(max)) = (history.iter().min(), history.iter().max()) else {
+            return 0;
+        };
+        ((i32::from(*max) - i32::from(*min)) / 2) as u16
+    }
+
+    /// Whether a channel is playing, as shown in NR52
+    #[must_use]
+    pub fn is_playing(&self, channel: Channel) -> bool {
+        self.channels[channel.index()].enabled
+    }
+
+    fn register(&self, channel: Channel, offset: usize) -> u8 {
+        self.registers[channel.registers() + offset]
+    }
+
+    fn frequency(&self, channel: Channel) -> u16 {
+        u16::from(self.register(channel, 4) & 0x07) << 8 | u16::from(self.register(channel, 3))
+    }
+
+    fn dac_enabled(&self, channel: Channel) -> bool {
+        match channel {
+            Channel::Wave => self.register(channel, 0) & 0x80 != 0,
+            _ => self.register(channel, 2) & 0xF8 != 0,
+        }
+    }
+
+    /// T-cycles between steps of a channel's waveform
+    fn period(&self, channel: Channel) -> u32 {
+        match channel {
+            Channel::Square1 | Channel::Square2 => (2048 - u32::from(self.frequency(channel))) * 4,
+            Channel::Wave => (2048 - u32::from(self.frequency(channel))) * 2,
+            Channel::Noise => {
+                let nr43 = self.register(channel, 3);
+                NOISE_DIVISORS[usize::from(nr43 & 0x07)] << (nr43 >> 4)
+            }
+        }
+    }
+
+    /// Runs a channel's waveform for a number of T-cycles
+    fn step_channel(&mut self, channel: Channel, mut cycles: u32) {
+        let index = channel.index();
+        if !self.channels[index].enabled {
+            return;
+        }
+        while cycles >= self.channels[index].timer {
+            cycles -= self.channels[index].timer;
+            self.channels[index].timer = self.period(channel);
+            self.advance(channel);
+        }
+        self.channels[index].timer -= cycles;
+    }
+
+    fn advance(&mut self, channel: Channel) {
+        let state = &mut self.channels[channel.index()];
+        match channel {
+            Channel::Square1 | Channel::Square2 => state.position = (state.position + 1) % 8,
+            Channel::Wave => {
+                state.position = (state.position + 1) % 32;
+                let byte = self.wave_ram[usize::from(state.position / 2)];
+                self.wave_sample = if state.position.is_multiple_of(2) {
+                    byte >> 4
+                } else {
+                    byte & 0x0F
+                };
+            }
+            Channel::Noise => {
+                let nr43 = self.register(channel, 3);
+                // With shifts of 14 and 15, the LFSR isn't clocked at all
+                if nr43 >> 4 >= 14 {
+                    return;
+                }
+                let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+                self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+                if nr43 & 0x08 != 0 {
+                    self.lfsr = (self.lfsr & !0x40) | (feedback << 6);
+                }
+            }
+        }
+    }
+
+    /// Clocks the units for the frame sequencer's current step
+    fn clock_frame_sequencer(&mut self) {
+        let events = FrameSequencerEvents::for_step(self.frame_sequencer_step);
+        if events.length {
+            for channel in Channel::ALL {
+                self.clock_length(channel);
+            }
+        }
+        if events.sweep {
+            self.clock_sweep();
+        }
+        if events.envelope {
+            for channel in [Channel::Square1, Channel::Square2, Channel::Noise] {
+                let nr_x2 = self.register(channel, 2);
+                let period = nr_x2 & 0x07;
+                let state = &mut self.channels[channel.index()];
+                if period == 0 {
+                    continue;
+                }
+                state.envelope_timer = state.envelope_timer.saturating_sub(1);
+                if state.envelope_timer == 0 {
+                    state.envelope_timer = period;
+                    if nr_x2 & 0x08 != 0 && state.volume < 15 {
+                        state.volume += 1;
+                    } else if nr_x2 & 0x08 == 0 && state.volume > 0 {
+                        state.volume -= 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn clock_length(&mut self, channel: Channel) {
+        let length_enabled = self.register(channel, 4) & 0x40 != 0;
+        let state = &mut self.channels[channel.index()];
+        if length_enabled && state.length > 0 {
+            state.length -= 1;
+            if state.length == 0 {
+                state.enabled = false;
+            }
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        self.sweep_timer = self.sweep_timer.saturating_sub(1);
+        if self.sweep_timer > 0 {
+            return;
+        }
+        let nr10 = self.register(Channel::Square1, 0);
+        let period = (nr10 >> 4) & 0x07;
+        self.sweep_timer = if period == 0 { 8 } else { period };
+        if self.sweep_enabled && period != 0 {
+            let frequency = self.sweep_frequency();
+            if frequency <= 2047 && nr10 & 0x07 != 0 {
+                self.shadow_frequency = frequency;
+                self.registers[0x03] = frequency as u8;
+                self.registers[0x04] = (self.registers[0x04] & !0x07) | (frequency >> 8) as u8;
+                self.sweep_frequency();
+            }
+        }
+    }
+
+    /// Calculates square 1's next frequency, and turns the channel off if it overflows
+    fn sweep_frequency(&mut self) -> u16 {
+        let nr10 = self.register(Channel::Square1, 0);
+        let delta = self.shadow_frequency >> (nr10 & 0x07);
+        let frequency = if nr10 & 0x08 != 0 {
+            self.sweep_negated = true;
+            self.shadow_frequency - delta
+        } else {
+            self.shadow_frequency + delta
+        };
+        if frequency > 2047 {
+            self.channels[Channel::Square1.index()].enabled = false;
+        }
+        frequency
+    }
+
+    fn trigger(&mut self, channel: Channel) {
+        let period = self.period(channel);
+        let nr_x2 = self.register(channel, 2);
+        let enabled = self.dac_enabled(channel);
+        // Reloading an expired length counter doesn't count the length clock that's next if
+        // that clock is going to be skipped
+        let skips_length = !self.next_frame_sequencer_events().length;
+        let length_enabled = self.register(channel, 4) & 0x40 != 0;
+        let state = &mut self.channels[channel.index()];
+        state.enabled = enabled;
+        if state.length == 0 {
+            state.length = channel.max_length();
+            if length_enabled && skips_length {
+                state.length -= 1;
+            }
+        }
+        state.timer = period;
+        state.volume = nr_x2 >> 4;
+        state.envelope_timer = nr_x2 & 0x07;
+        match channel {
+            Channel::Square1 => {
+                let nr10 = self.register(channel, 0);
+                self.shadow_frequency = self.frequency(channel);
+                self.sweep_negated = false;
+                let sweep_period = (nr10 >> 4) & 0x07;
+                self.sweep_timer = if sweep_period == 0 { 8 } else { sweep_period };
+                self.sweep_enabled = sweep_period != 0 || nr10 & 0x07 != 0;
+                if nr10 & 0x07 != 0 {
+                    self.sweep_frequency();
+                }
+            }
+            Channel::Square2 => (),
+            Channel::Wave => state.position = 0,
+            Channel::Noise => self.lfsr = 0x7FFF,
+        }
+    }
+
+    /// Handles a write to NRx4. Enabling length while the next frame sequencer step won't clock
+    /// it clocks it once right away.
+    fn write_control(&mut self, channel: Channel, value: u8) {
+        let was_enabled = self.register(channel, 4) & 0x40 != 0;
+        self.registers[channel.registers() + 4] = value;
+        if !was_enabled && value & 0x40 != 0 && !self.next_frame_sequencer_events().length {
+            self.clock_length(channel);
+        }
+        if value & 0x80 != 0 {
+            self.trigger(channel);
+        }
+    }
+
+    /// The channel's current DAC input (0-15)
+    fn digital_output(&self, channel: Channel) -> u8 {
+        let state = &self.channels[channel.index()];
+        if !state.enabled {
+            return 0;
+        }
+        match channel {
+            Channel::Square1 | Channel::Square2 => {
+                let duty = DUTY_CYCLES[usize::from(self.register(channel, 1) >> 6)];
+                if duty >> (7 - state.position) & 1 != 0 {
+                    state.volume
+                } else {
+                    0
+                }
+            }
+            Channel::Wave => match (self.register(channel, 2) >> 5) & 0x03 {
+                0 => 0,
+                shift => self.wave_sample >> (shift - 1),
+            },
+            Channel::Noise => {
+                if self.lfsr & 1 == 0 {
+                    state.volume
+                } else {
+                    0
+                }
+            }
+        }
+    }
+
+    /// The current output of a channel's DAC, between -0x2000 and 0x2000 so that all four can
+    /// be summed. A DAC that's turned off outputs 0.
+    fn output(&self, channel: Channel) -> i16 {
+        if !self.dac_enabled(channel) {
+            return 0;
+        }
+        let digital = i32::from(self.digital_output(channel));
+        ((digital * 2 - 15) * 0x2000 / 15) as i16
+    }
+
+    /// Mixes the unmuted channels into a stereo sample according to NR50 and NR51, and filters
+    /// out the DC offset of the DACs like the output capacitors do
+    fn mix(&mut self, outputs: [i16; 4]) -> (i16, i16) {
+        let nr50 = self.registers[0x14];
+        let nr51 = self.registers[0x15];
+        let (mut left, mut right) = (0i32, 0i32);
+        for (index, output) in outputs.iter().enumerate() {
+            if self.muted[index] {
+                continue;
+            }
+            if nr51 & (0x10 << index) != 0 {
+                left += i32::from(*output);
+            }
+            if nr51 & (1 << index) != 0 {
+                right += i32::from(*output);
+            }
+        }
+        let mut output = |side: usize, sum: i32, volume: u8| {
+            let sample = (sum * (i32::from(volume & 7) + 1) / 8) as f32;
+            let filtered = sample - self.capacitors[side];
+            self.capacitors[side] = sample - filtered * HIGH_PASS_CHARGE;
+            filtered.clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16
+        };
+        (output(0, left, nr50 >> 4), output(1, right, nr50))
+    }
+
+    #[must_use]
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0xFF26 => {
+                let playing = Channel::ALL
+                    .iter()
+                    .filter(|&&channel| self.is_playing(channel))
+                    .fold(0, |bits, &channel| bits | 1 << channel.index());
+                READ_MASKS[0x16] | (u8::from(self.enabled) << 7) | playing
+            }
+            0xFF10..=0xFF2F => {
+                self.registers[(address - 0xFF10) as usize]
+                    | READ_MASKS[(address - 0xFF10) as usize]
+            }
+            0xFF30..=0xFF3F => self.wave_ram[(address - 0xFF30) as usize],
+            // PCM12 and PCM34 on CGB, with the digital outputs of channels 1 and 2, or 3 and 4,
+            // in the low and high nibbles
+            0xFF76 => {
+                self.digital_output(Channel::Square2) << 4 | self.digital_output(Channel::Square1)
+            }
+            0xFF77 => self.digital_output(Channel::Noise) << 4 | self.digital_output(Channel::Wave),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF26 => {
+                let enabled = value & 0x80 != 0;
+                if enabled && !self.enabled {
+                    // The next step after powering on is step 0
+                    self.frame_sequencer_step = 7;
+                }
+                self.enabled = enabled;
+                if !self.enabled {
+                    self.registers = [0; 0x20];
+                    self.channels = [ChannelState::default(); 4];
+                }
+            }
+            0xFF10..=0xFF2F if self.enabled => {
+                let register = usize::from(address - 0xFF10);
+                let channel = Channel::ALL.get(register / 5).copied();
+                match (channel, register % 5) {
+                    (Some(channel), 4) => self.write_control(channel, value),
+                    (Some(channel), 1) => {
+                        self.registers[register] = value;
+                        let mask = if channel == Channel::Wave { 0xFF } else { 0x3F };
+                        self.channels[channel.index()].length =
+                            channel.max_length() - u16::from(value & mask);
+                    }
+                    (Some(Channel::Square1), 0)
+                        if self.sweep_negated
+                            && value & 0x08 == 0
+                            && self.registers[0] & 0x08 != 0 =>
+                    {
+                        self.registers[register] = value;
+                        self.channels[0].enabled = false;
+                    }
+                    _ => self.registers[register] = value,
+                }
+                // Turning a DAC off turns its channel off
+                if let Some(channel) = channel {
+                    if !self.dac_enabled(channel) {
+                        self.channels[channel.index()].enabled = false;
+                    }
+                }
+            }
+            0xFF10..=0xFF2F => (),
+            0xFF30..=0xFF3F => self.wave_ram[(address - 0xFF30) as usize] = value,
+            _ => unreachable!(),
+        }
+    }
+}
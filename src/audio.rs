@@ -0,0 +1,93 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Output sample rate of the APU mixer, in Hz
+pub const SAMPLE_RATE: u32 = 48000;
+
+/// Receives the APU's mixed stereo output, one frame of samples at a time.
+pub trait AudioSink {
+    fn push_sample(&mut self, left: i16, right: i16);
+}
+
+/// Writes 16-bit stereo PCM samples to a WAV file.
+///
+/// The RIFF header is patched with the correct sizes once per second of audio, and again when
+/// the writer is finished or dropped, so the file stays playable even if the emulator is killed.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    samples_written: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be created or the header can't be written
+    pub fn create<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// # Errors
+    ///
+    /// Will return `Err` if the header can't be written
+    pub fn new(writer: W) -> std::io::Result<Self> {
+        let mut wav = Self {
+            writer,
+            samples_written: 0,
+        };
+        wav.write_header()?;
+        Ok(wav)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let data_size = self.samples_written * 4;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(b"RIFF")?;
+        self.writer.write_all(&(36 + data_size).to_le_bytes())?;
+        self.writer.write_all(b"WAVE")?;
+        self.writer.write_all(b"fmt ")?;
+        self.writer.write_all(&16_u32.to_le_bytes())?; // fmt chunk size
+        self.writer.write_all(&1_u16.to_le_bytes())?; // PCM
+        self.writer.write_all(&2_u16.to_le_bytes())?; // channels
+        self.writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        self.writer.write_all(&(SAMPLE_RATE * 4).to_le_bytes())?; // byte rate
+        self.writer.write_all(&4_u16.to_le_bytes())?; // block align
+        self.writer.write_all(&16_u16.to_le_bytes())?; // bits per sample
+        self.writer.write_all(b"data")?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    /// Patches the header with the final sizes and flushes the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the header can't be written
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        self.write_header()?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavWriter<W> {
+    fn push_sample(&mut self, left: i16, right: i16) {
+        if self.writer.write_all(&left.to_le_bytes()).is_err()
+            || self.writer.write_all(&right.to_le_bytes()).is_err()
+        {
+            return;
+        }
+        self.samples_written += 1;
+        if self.samples_written.is_multiple_of(SAMPLE_RATE) {
+            let _ = self.write_header();
+        }
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
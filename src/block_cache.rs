@@ -0,0 +1,127 @@
+//! Caches straight-line runs of decoded instructions in cartridge ROM, so `Cpu::run_block` can
+//! run them without fetching and decoding each instruction again.
+//!
+//! Only ROM is cached. Its contents only change when another bank is mapped, so blocks are
+//! looked up by bank as well as address, and code in RAM, which can be written to, is always
+//! decoded as it runs.
+
+use crate::bus::Bus;
+use crate::cpu::{decode, instruction_length, Instruction};
+#[cfg(feature = "jit")]
+use crate::jit::Jit;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Blocks end after this many instructions, even without a jump
+const MAX_INSTRUCTIONS: usize = 64;
+
+#[derive(Clone, Copy)]
+pub struct CachedInstruction {
+    pub instruction: Instruction,
+    /// Number of bytes, which is also the number of M-cycles it takes to fetch
+    pub length: u8,
+}
+
+#[derive(Default)]
+pub struct BlockCache {
+    blocks: HashMap<(usize, u16), Rc<[CachedInstruction]>>,
+    /// Compiles the blocks that run the most to native code
+    #[cfg(feature = "jit")]
+    pub jit: Option<Jit>,
+}
+
+impl BlockCache {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A block cache that also compiles hot blocks with `jit`
+    #[cfg(feature = "jit")]
+    #[must_use]
+    pub fn with_jit(jit: Jit) -> Self {
+        Self {
+            jit: Some(jit),
+            ..Self::default()
+        }
+    }
+
+    /// The block starting at `address` in ROM, which is decoded and cached the first time it's
+    /// needed. It's empty if the instruction at `address` doesn't fit in the same bank.
+    pub fn get<B: Bus + ?Sized>(
+        &mut self,
+        bus: &B,
+        bank: usize,
+        address: u16,
+    ) -> Rc<[CachedInstruction]> {
+        Rc::clone(
+            self.blocks
+                .entry((bank, address))
+                .or_insert_with(|| decode_block(bus, address)),
+        )
+    }
+
+    /// Forgets all blocks, which is needed when another cartridge is inserted
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        #[cfg(feature = "jit")]
+        if let Some(jit) = &mut self.jit {
+            jit.clear();
+        }
+    }
+
+    /// Number of cached blocks
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+/// Decodes instructions until one that jumps, or one that doesn't fit in the bank at `address`
+fn decode_block<B: Bus + ?Sized>(bus: &B, address: u16) -> Rc<[CachedInstruction]> {
+    let bank_end = if address < 0x4000 { 0x4000 } else { 0x8000 };
+    let mut instructions = Vec::new();
+    let mut pc = address;
+    while instructions.len() < MAX_INSTRUCTIONS {
+        let opcode = bus.peek_byte(pc);
+        let length = instruction_length(opcode);
+        if u32::from(pc) + u32::from(length) > bank_end {
+            break;
+        }
+        let mut next = pc;
+        let instruction = decode(opcode, &mut || {
+            next += 1;
+            bus.peek_byte(next)
+        });
+        instructions.push(CachedInstruction {
+            instruction,
+            length: length as u8,
+        });
+        pc += length;
+        if ends_block(&instruction) {
+            break;
+        }
+    }
+    instructions.into()
+}
+
+/// Whether execution can continue somewhere other than the next instruction
+fn ends_block(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Jp(..)
+            | Instruction::Jr(..)
+            | Instruction::Call(..)
+            | Instruction::Ret(_)
+            | Instruction::Reti
+            | Instruction::Rst(_)
+            | Instruction::Halt
+            | Instruction::Stop
+            | Instruction::Illegal(_)
+    )
+}
@@ -0,0 +1,128 @@
+//! A free DMG boot ROM written for this emulator, so the boot sequence can run without
+//! Nintendo's copyrighted boot ROM. Like the original, it scrolls the logo from the cartridge
+//! header into place, plays the chime and leaves the registers the same way, but it doesn't lock
+//! up if the logo or header checksum is wrong.
+
+/// The boot ROM for the DMG, which also works on the DMG0 and MGB apart from the registers it
+/// leaves behind
+#[rustfmt::skip]
+pub const DMG: [u8; 256] = [
+    // Set up the stack and clear VRAM
+    0x31, 0xFE, 0xFF, // LD SP, $FFFE
+    0xAF, // XOR A
+    0x21, 0xFF, 0x9F, // LD HL, $9FFF
+    0x32, // .clear: LD [HL-], A
+    0xCB, 0x7C, // BIT 7, H
+    0x20, 0xFB, // JR NZ, .clear
+    // Turn on the sound, and set up channel 1 for the chime
+    0x21, 0x26, 0xFF, // LD HL, $FF26
+    0x3E, 0x80, // LD A, $80
+    0x32, // LD [HL-], A ; NR52
+    0x3E, 0xF3, // LD A, $F3
+    0x32, // LD [HL-], A ; NR51
+    0x3E, 0x77, // LD A, $77
+    0x77, // LD [HL], A ; NR50
+    0x3E, 0x80, // LD A, $80
+    0xE0, 0x11, // LDH [$11], A ; NR11
+    0x3E, 0xF3, // LD A, $F3
+    0xE0, 0x12, // LDH [$12], A ; NR12
+    0x3E, 0xFC, // LD A, $FC
+    0xE0, 0x47, // LDH [$47], A ; BGP
+    // Expand each nibble of the logo in the header into 2 rows of tiles 1-24
+    0x11, 0x04, 0x01, // LD DE, $0104
+    0x21, 0x10, 0x80, // LD HL, $8010
+    0x1A, // .logo: LD A, [DE]
+    0x4F, // LD C, A
+    0xCD, 0x9B, 0x00, // CALL Double
+    0xCD, 0x9B, 0x00, // CALL Double
+    0x13, // INC DE
+    0x7B, // LD A, E
+    0xFE, 0x34, // CP $34
+    0x20, 0xF2, // JR NZ, .logo
+    // Copy the (R) tile after it
+    0x11, 0xBA, 0x00, // LD DE, Registered
+    0x06, 0x08, // LD B, 8
+    0x1A, // .registered: LD A, [DE]
+    0x22, // LD [HL+], A
+    0x23, // INC HL
+    0x13, // INC DE
+    0x05, // DEC B
+    0x20, 0xF9, // JR NZ, .registered
+    // Tile map: (R) at $9910, then tiles 24-13 from $992F and 12-1 from $990F backwards
+    0x3E, 0x19, // LD A, $19
+    0xEA, 0x10, 0x99, // LD [$9910], A
+    0x21, 0x2F, 0x99, // LD HL, $992F
+    0x0E, 0x0C, // .row: LD C, 12
+    0x3D, // .tile: DEC A
+    0x28, 0x08, // JR Z, .scroll
+    0x32, // LD [HL-], A
+    0x0D, // DEC C
+    0x20, 0xF9, // JR NZ, .tile
+    0x2E, 0x0F, // LD L, $0F
+    0x18, 0xF3, // JR .row
+    // Turn on the screen and scroll the logo down into place, a line per frame
+    0x3E, 0x64, // .scroll: LD A, $64
+    0x57, // LD D, A
+    0xE0, 0x42, // LDH [$42], A ; SCY
+    0x3E, 0x91, // LD A, $91
+    0xE0, 0x40, // LDH [$40], A ; LCDC
+    0xCD, 0xAD, 0x00, // .frame: CALL VBlank
+    0x15, // DEC D
+    0x7A, // LD A, D
+    0xE0, 0x42, // LDH [$42], A ; SCY
+    0x20, 0xF7, // JR NZ, .frame
+    // Play the two notes of the chime, and hold the logo for a second
+    0x3E, 0x83, // LD A, $83
+    0xE0, 0x13, // LDH [$13], A ; NR13
+    0x3E, 0x87, // LD A, $87
+    0xE0, 0x14, // LDH [$14], A ; NR14
+    0x16, 0x08, // LD D, 8
+    0xCD, 0xAD, 0x00, // .note: CALL VBlank
+    0x15, // DEC D
+    0x20, 0xFA, // JR NZ, .note
+    0x3E, 0xC1, // LD A, $C1
+    0xE0, 0x13, // LDH [$13], A ; NR13
+    0x3E, 0x87, // LD A, $87
+    0xE0, 0x14, // LDH [$14], A ; NR14
+    0x16, 0x3C, // LD D, 60
+    0xCD, 0xAD, 0x00, // .hold: CALL VBlank
+    0x15, // DEC D
+    0x20, 0xFA, // JR NZ, .hold
+    // Leave the registers like the DMG boot ROM, and unmap the boot ROM at the end
+    0x21, 0xB0, 0x01, // LD HL, $01B0
+    0xE5, // PUSH HL
+    0xF1, // POP AF
+    0x01, 0x13, 0x00, // LD BC, $0013
+    0x11, 0xD8, 0x00, // LD DE, $00D8
+    0x21, 0x4D, 0x01, // LD HL, $014D
+    0x18, 0x63, // JR Unmap
+    // Shifts the top 4 bits of C out into A, doubling each bit, and writes A to 2 rows of a tile
+    0x06, 0x04, // Double: LD B, 4
+    0x87, // .bit: ADD A, A
+    0x87, // ADD A, A
+    0xCB, 0x21, // SLA C
+    0x30, 0x02, // JR NC, .skip
+    0xF6, 0x03, // OR %11
+    0x05, // .skip: DEC B
+    0x20, 0xF5, // JR NZ, .bit
+    0x22, // LD [HL+], A
+    0x23, // INC HL
+    0x22, // LD [HL+], A
+    0x23, // INC HL
+    0xC9, // RET
+    // Waits for the start of the next VBlank, and then for the first line of it to end
+    0xF0, 0x44, // VBlank: LDH A, [$44] ; LY
+    0xFE, 0x90, // CP $90
+    0x20, 0xFA, // JR NZ, VBlank
+    0xF0, 0x44, // .wait: LDH A, [$44] ; LY
+    0xFE, 0x90, // CP $90
+    0x28, 0xFA, // JR Z, .wait
+    0xC9, // RET
+    0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C, // Registered: the (R) tile
+    // Unused
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xE0, 0x50, // Unmap: LDH [$50], A ; execution continues at $0100
+];
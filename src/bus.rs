@@ -1,86 +1,399 @@
+use crate::apu::Apu;
+use crate::audio::AudioSink;
 use crate::cartridge::Cartridge;
-use crate::interrupts::Interrupt;
-use crate::ppu::Ppu;
+use crate::compat_palettes;
+use crate::dma::OamDma;
+use crate::hdma::Hdma;
+use crate::joypad::Joypad;
+use crate::metadata::Metadata;
+use crate::model::Model;
+use crate::peripheral::PeripheralEvent;
+use crate::ppu::{Mode, Ppu};
+use crate::scheduler::{Clock, Clocked, Component, Scheduler};
+use crate::serial::{LinkDevice, Serial};
+use crate::sgb::Sgb;
+use crate::state::State;
 use crate::timer::Timer;
+use crate::video::VideoSink;
+use crate::watchpoints::Watchpoints;
 
-pub trait Bus {
+pub trait Bus: State {
     fn tick(&mut self);
+    /// Number of M-cycles the CPU has spent since power on
+    fn cycles(&self) -> u64;
     fn read_byte(&mut self, address: u16) -> u8;
     fn peek_byte(&self, address: u16) -> u8;
-    fn read_word(&mut self, address: u16) -> u16;
+    /// Reads `length` bytes from `start` without side effects, like `peek_byte`, wrapping around
+    /// from 0xFFFF to 0x0000
+    fn peek_range(&self, start: u16, length: usize) -> Vec<u8> {
+        (0..length)
+            .map(|offset| self.peek_byte(start.wrapping_add(offset as u16)))
+            .collect()
+    }
+    /// Reads a little endian word. The high byte of a word at 0xFFFF is read from 0x0000.
+    fn read_word(&mut self, address: u16) -> u16 {
+        let low_byte = u16::from(self.read_byte(address));
+        u16::from(self.read_byte(address.wrapping_add(1))) << 8 | low_byte
+    }
     fn write_byte(&mut self, address: u16, value: u8);
-    fn write_word(&mut self, address: u16, value: u16);
+    /// Writes a little endian word. The high byte of a word at 0xFFFF is written to 0x0000.
+    fn write_word(&mut self, address: u16, value: u16) {
+        self.write_byte(address, (value & 0xFF) as u8);
+        self.write_byte(address.wrapping_add(1), (value >> 8) as u8);
+    }
     fn set_post_boot_state(&mut self);
+    /// Resets the hardware to its power-on state, mapping the boot ROM again if there is one.
+    /// The cartridge is left as it is, and so is everything set up by the frontend, like the
+    /// model, sinks, link device and settings.
+    fn reset(&mut self);
+    /// Called when the CPU executes STOP, which resets DIV. Returns `true` if this switched the
+    /// CGB's speed instead of entering STOP mode.
+    fn stop(&mut self) -> bool;
     fn get_interrupt_enable(&self) -> u8;
     fn set_interrupt_enable(&mut self, value: u8);
     fn get_interrupt_flags(&self) -> u8;
     fn set_interrupt_flags(&mut self, flags: u8);
+    /// Inserts a cartridge, replacing any that's inserted. This can be done while running,
+    /// though games don't expect it.
     fn insert_cartridge(&mut self, cartridge: Box<dyn Cartridge>);
+    /// Removes the cartridge. Without one, reads from the cartridge ROM and RAM areas return
+    /// 0xFF like an open bus, and writes there are ignored.
     fn remove_cartridge(&mut self);
+    fn get_cartridge(&self) -> Option<&dyn Cartridge>;
+    /// Sends an event from the frontend to the peripheral that handles it. Returns whether any
+    /// peripheral handled it.
+    fn send_peripheral_event(&mut self, event: PeripheralEvent) -> bool;
+    /// Sets the buttons that are held, as a mask of `joypad::Button::mask` bits
+    fn set_buttons(&mut self, pressed: u8);
+    /// Sets the boot ROM: 256 bytes for DMG, or 2304 bytes for CGB, which maps 0x200-0x8FF too
     fn set_boot_rom(&mut self, bootrom: Vec<u8>);
+    /// Whether the boot ROM is still mapped over the start of the cartridge ROM
+    fn boot_rom_mapped(&self) -> bool;
+    /// Whether an OAM DMA transfer is running or about to start, which can keep the CPU from
+    /// fetching instructions
+    fn oam_dma_pending(&self) -> bool;
+    /// Plugs a device into the serial port, like a link cable to another instance
+    fn set_link_device(&mut self, device: Box<dyn LinkDevice>);
+    fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>);
+    fn add_video_sink(&mut self, sink: Box<dyn VideoSink>);
+    /// Runs the CPU `factor` times faster than the rest of the system, to reduce slowdown in
+    /// games that lag on real hardware. This is not accurate, and 1 turns it off.
+    fn set_cpu_overclock(&mut self, factor: u8);
+    /// Called when the CPU increments or decrements a 16-bit register holding `address`, which
+    /// can corrupt OAM on DMG
+    fn oam_bug(&mut self, address: u16);
+    /// Turns emulation of the DMG OAM corruption bug on or off. It's off by default, since few
+    /// games trigger it on purpose.
+    fn set_oam_bug(&mut self, enabled: bool);
+    /// Keeps the CPU off VRAM and CGB palette RAM while the PPU is drawing, and off OAM while
+    /// it's scanning OAM or drawing, so reads return 0xFF and writes are dropped like on
+    /// hardware. It's off by
+    /// default, to be forgiving to games that get their timing slightly wrong.
+    fn set_ppu_access_locks(&mut self, enabled: bool);
+    /// Makes LY always read as the given value, as logging tools like Gameboy Doctor expect.
+    /// `None` turns this off.
+    fn stub_ly(&mut self, value: Option<u8>);
+    /// The model being emulated. This should be set before the boot ROM runs or the post-boot
+    /// state is set up.
+    fn model(&self) -> Model;
+    fn set_model(&mut self, model: Model);
+    /// The model, boot ROM and accuracy settings, which savestates and movies record so they
+    /// can be checked on load
+    fn metadata(&self) -> Metadata;
+    fn get_ppu(&self) -> Option<&Ppu>;
+    fn get_apu(&self) -> Option<&Apu>;
+    fn get_apu_mut(&mut self) -> Option<&mut Apu>;
+    /// Watchpoints checked on each read and write, or `None` if the bus doesn't support them
+    fn watchpoints(&self) -> Option<&Watchpoints>;
+    fn watchpoints_mut(&mut self) -> Option<&mut Watchpoints>;
 }
 
 pub struct DmgBus {
-    pub bootrom: [u8; 256],
+    pub model: Model,
+    pub bootrom: Vec<u8>,
     pub ppu: Ppu,
-    pub wram: [u8; 0x2000], // TODO banks
+    pub apu: Apu,
+    /// 8 banks of 4 KiB. Bank 0 is always at 0xC000, and 0xD000 has the bank selected by SVBK,
+    /// which is always bank 1 on DMG.
+    pub wram: [u8; 0x8000],
+    /// SVBK, where bank 0 selects bank 1
+    pub wram_bank: u8,
     pub hram: [u8; 127],
     pub bootrom_enabled: bool,
     pub interrupt_enable: u8,
     pub interrupt_flags: u8,
-    pub serial: u8,
-    pub serial_control: u8,
+    pub serial: Serial,
     pub(crate) timer: Timer,
+    pub joypad: Joypad,
+    pub hdma: Hdma,
+    pub dma: OamDma,
+    /// Whether the CGB is in double-speed mode, where the CPU and timer run twice as fast
+    pub double_speed: bool,
+    /// Whether a speed switch is prepared (KEY1 bit 0), to happen at the next STOP
+    pub speed_switch_armed: bool,
+    /// In double-speed mode, whether the current M-cycle is the second half of a normal-speed one
+    pub(crate) half_cycle: bool,
     pub cartridge: Option<Box<dyn Cartridge>>,
+    overclock: u8,
+    overclock_cycle: u8,
+    oam_bug: bool,
+    ppu_access_locks: bool,
+    pub(crate) cycles: u64,
+    pub(crate) scheduler: Scheduler,
+    ly_stub: Option<u8>,
+    watchpoints: Watchpoints,
 }
 
 impl Default for DmgBus {
     fn default() -> Self {
         Self {
-            bootrom: [0; 256],
-            wram: [0; 0x2000],
+            model: Model::Dmg,
+            bootrom: Vec::new(),
+            wram: [0; 0x8000],
+            wram_bank: 0,
             hram: [0; 127],
             ppu: Ppu::default(),
+            apu: Apu::default(),
             interrupt_enable: 0,
             interrupt_flags: 0,
-            serial: 0,
-            serial_control: 0,
+            serial: Serial::default(),
             timer: Timer::default(),
+            joypad: Joypad::default(),
+            hdma: Hdma::default(),
+            dma: OamDma::default(),
+            double_speed: false,
+            speed_switch_armed: false,
+            half_cycle: false,
             cartridge: None,
             bootrom_enabled: false,
+            overclock: 1,
+            overclock_cycle: 0,
+            oam_bug: false,
+            ppu_access_locks: false,
+            cycles: 0,
+            scheduler: Scheduler::default(),
+            ly_stub: None,
+            watchpoints: Watchpoints::default(),
         }
     }
 }
 
+/// The ® symbol drawn after the logo by the boot ROM
+const REGISTERED_TILE: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+
 impl DmgBus {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// The timer as it is now, including the M-cycles it hasn't been run for yet
+    fn current_timer(&self) -> Timer {
+        let mut timer = self.timer;
+        timer.skip(self.scheduler.owed(Component::Timer));
+        timer
+    }
+
+    /// Whether the PPU keeps the CPU off an address, if that's emulated. The PPU's mode only
+    /// changes on its events, so it's up to date without syncing.
+    fn ppu_blocks(&self, address: u16) -> bool {
+        self.ppu_access_locks
+            && match address {
+                0x8000..=0x9FFF => !self.ppu.vram_accessible(),
+                0xFE00..=0xFEFF => !self.ppu.oam_accessible(),
+                0xFF69 | 0xFF6B => self.model == Model::Cgb && !self.ppu.palettes_accessible(),
+                _ => false,
+            }
+    }
+
+    /// The component, as something the scheduler can run
+    fn component(&mut self, component: Component) -> &mut dyn Clocked {
+        match component {
+            Component::Timer => &mut self.timer,
+            Component::Serial => &mut self.serial,
+            Component::Ppu => &mut self.ppu,
+        }
+    }
+
+    /// Runs a component up to the current time, before accessing state that depends on it
+    fn sync(&mut self, component: Component) {
+        let cycles = self.scheduler.owed(component);
+        self.component(component).skip(cycles);
+        self.scheduler.sync(component);
+    }
+
+    /// Schedules a component's next event, after its state has changed
+    pub(crate) fn reschedule(&mut self, component: Component) {
+        let cycles = self.component(component).cycles_until_event();
+        self.scheduler.schedule(component, cycles);
+    }
+
+    /// Runs the components on a clock whose next event has come
+    fn run_events(&mut self, clock: Clock) {
+        for component in Component::ALL {
+            if component.clock() != clock || !self.scheduler.due(component) {
+                continue;
+            }
+            let cycles = self.scheduler.owed(component);
+            let mode = self.ppu.mode;
+            let clocked = self.component(component);
+            clocked.skip(cycles - 1);
+            let interrupt = clocked.tick();
+            self.scheduler.sync(component);
+            self.reschedule(component);
+            if let Some(irq) = interrupt {
+                self.interrupt_flags |= irq.mask();
+            }
+            if component == Component::Ppu
+                && self.hdma.hblank_active
+                && mode != Mode::HBlank
+                && self.ppu.mode == Mode::HBlank
+            {
+                self.hdma_block();
+            }
+        }
+    }
+
+    /// Returns the index into `wram` of an address in WRAM or its echo at 0xE000-0xFDFF
+    fn wram_index(&self, address: u16) -> usize {
+        let offset = usize::from(address & 0x1FFF);
+        if offset < 0x1000 {
+            offset
+        } else {
+            usize::from(self.wram_bank.max(1)) * 0x1000 + offset - 0x1000
+        }
+    }
+
+    /// Copies one 16-byte block of VRAM DMA, while the CPU is stalled
+    fn hdma_block(&mut self) {
+        for offset in 0..16 {
+            let byte = self.peek_byte(self.hdma.source.wrapping_add(offset));
+            let index = self.ppu.vram_index(0x8000 + self.hdma.destination + offset);
+            self.ppu.vram[index] = byte;
+        }
+        self.hdma.finish_block();
+        // The copy takes 8 M-cycles at normal speed, which is twice as many CPU cycles in
+        // double-speed mode
+        let cycles = if self.double_speed { 16 } else { 8 };
+        for _ in 0..cycles {
+            self.tick();
+        }
+    }
+
+    /// Whether the boot ROM is mapped at this address. CGB boot ROMs leave 0x100-0x1FF
+    /// unmapped for the cartridge header.
+    fn boot_rom_covers(&self, address: u16) -> bool {
+        self.bootrom_enabled
+            && usize::from(address) < self.bootrom.len()
+            && !(0x100..0x200).contains(&address)
+    }
+
+    /// Sets up VRAM with the logo from the cartridge header like the boot ROM does, without
+    /// scrolling it. Each nibble of the 48 logo bytes is a row of 4 pixels, scaled up 2×.
+    fn load_logo(&mut self) {
+        let Some(cartridge) = &self.cartridge else {
+            return;
+        };
+        let logo: Vec<u8> = (0x0104..0x0134)
+            .map(|address| cartridge.read_byte(address))
+            .collect();
+
+        let double = |nibble: u8| {
+            (0..4).fold(0_u8, |byte, bit| {
+                byte | (((nibble >> bit) & 1) * 0b11) << (bit * 2)
+            })
+        };
+        let rows = logo
+            .iter()
+            .flat_map(|byte| {
+                [
+                    double(byte >> 4),
+                    double(byte >> 4),
+                    double(byte & 0x0F),
+                    double(byte & 0x0F),
+                ]
+            })
+            .chain(REGISTERED_TILE);
+        // Tile 1 onwards, only the low bit plane
+        for (row, value) in rows.enumerate() {
+            self.ppu.vram[0x0010 + row * 2] = value;
+        }
+
+        for tile in 1..=12 {
+            self.ppu.vram[0x1903 + tile] = tile as u8;
+            self.ppu.vram[0x1923 + tile] = tile as u8 + 12;
+        }
+        self.ppu.vram[0x1910] = 0x19;
+    }
 }
 
 impl Bus for DmgBus {
     /// Tick one M-cycle (4 T-cycles)
     fn tick(&mut self) {
-        if let Some(irq) = self.ppu.tick() {
-            match irq {
-                Interrupt::VBlank => self.interrupt_flags |= 1,
-                Interrupt::Stat => self.interrupt_flags |= 2,
-                _ => unreachable!(),
+        self.cycles += 1;
+
+        // When overclocked, only every Nth CPU cycle advances the rest of the system
+        if self.overclock > 1 {
+            self.overclock_cycle = (self.overclock_cycle + 1) % self.overclock;
+            if self.overclock_cycle != 0 {
+                return;
+            }
+        }
+
+        if self.scheduler.advance(Clock::Cpu) {
+            self.run_events(Clock::Cpu);
+        }
+        if let Some((source, offset)) = self.dma.tick() {
+            self.ppu.oam[usize::from(offset)] = self.peek_byte(source);
+        }
+
+        // In double-speed mode, the PPU, APU and cartridge keep their normal rate
+        if self.double_speed {
+            self.half_cycle = !self.half_cycle;
+            if self.half_cycle {
+                return;
             }
         }
-        if let Some(Interrupt::Timer) = self.timer.tick() {
-            self.interrupt_flags |= 4;
+        if self.scheduler.advance(Clock::System) {
+            self.run_events(Clock::System);
+        }
+        // DIV-APU is clocked by bit 13 of the system clock instead of bit 12 in double speed,
+        // which keeps its rate the same
+        let timer_cycles = self.scheduler.owed(Component::Timer) as u16;
+        let sysclock = self
+            .timer
+            .sysclock
+            .wrapping_add(timer_cycles.wrapping_mul(4));
+        let div = if self.double_speed {
+            sysclock >> 1
+        } else {
+            sysclock
+        };
+        self.apu.tick(div);
+        if let Some(cartridge) = &mut self.cartridge {
+            cartridge.tick();
         }
     }
 
     fn set_boot_rom(&mut self, bootrom: Vec<u8>) {
-        self.bootrom[0..=0xFF].clone_from_slice(&bootrom[..]);
+        self.bootrom = bootrom;
         self.bootrom_enabled = true;
     }
 
+    fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    fn boot_rom_mapped(&self) -> bool {
+        self.bootrom_enabled
+    }
+
+    fn oam_dma_pending(&self) -> bool {
+        self.dma.pending()
+    }
+
     fn peek_byte(&self, address: u16) -> u8 {
         #[allow(clippy::match_overlapping_arm)]
-        if self.bootrom_enabled && (0x000..0x100).contains(&address) {
+        if self.boot_rom_covers(address) {
             self.bootrom[address as usize]
         } else {
             match address {
@@ -91,17 +404,30 @@ impl Bus for DmgBus {
                         0xFF
                     }
                 }
-                0x8000..=0x9FFF => self.ppu.vram[(address - 0x8000) as usize],
-                0xC000..=0xDFFF => self.wram[(address - 0xC000) as usize],
-                0xE000..=0xFDFF => self.wram[(address - 0xE000) as usize],
+                0x8000..=0x9FFF => self.ppu.vram[self.ppu.vram_index(address)],
+                0xC000..=0xFDFF => self.wram[self.wram_index(address)],
                 0xFE00..=0xFE9F => self.ppu.oam[(address - 0xFE00) as usize],
                 0xFEA0..=0xFEFF => 0x00,
-                0xFF01 => self.serial,
-                0xFF02 => self.serial_control,
-                0xFF04..=0xFF07 => self.timer.read_byte(address),
-                0xFF42 => self.ppu.scy,
-                0xFF44 => 0x90, // TODO hardcoded LY
+                0xFF01 | 0xFF02 => self.serial.read_byte(address),
+                0xFF04..=0xFF07 => self.current_timer().read_byte(address),
+                0xFF10..=0xFF3F => self.apu.read_byte(address),
+                0xFF76 | 0xFF77 if self.model == Model::Cgb => self.apu.read_byte(address),
+                0xFF44 => self.ly_stub.unwrap_or_else(|| self.ppu.read_byte(address)),
+                0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.ppu.read_byte(address),
+                0xFF4F | 0xFF68..=0xFF6C if self.model == Model::Cgb => self.ppu.read_byte(address),
+                0xFF00 => match self.ppu.sgb.as_deref().and_then(Sgb::joypad_id) {
+                    Some(id) if self.joypad.select == 0x30 => 0xF0 | id,
+                    // Only the first SGB controller has buttons pressed
+                    Some(id) if id != 0x0F => 0xCF | self.joypad.select,
+                    _ => self.joypad.read_byte(),
+                },
                 0xFF0F => self.interrupt_flags,
+                0xFF46 => self.dma.register,
+                0xFF70 if self.model == Model::Cgb => 0xF8 | self.wram_bank,
+                0xFF51..=0xFF55 if self.model == Model::Cgb => self.hdma.read_byte(address),
+                0xFF4D if self.model == Model::Cgb => {
+                    0x7E | u8::from(self.double_speed) << 7 | u8::from(self.speed_switch_armed)
+                }
                 0xFF00..=0xFF7F => 0x00,
                 0xFF80..=0xFFFE => self.hram[(address - 0xFF80) as usize],
                 0xFFFF => self.interrupt_enable,
@@ -109,20 +435,30 @@ impl Bus for DmgBus {
         }
     }
 
-    #[must_use]
     fn read_byte(&mut self, address: u16) -> u8 {
-        let byte = self.peek_byte(address);
+        let byte = if self.dma.blocks(address) || self.ppu_blocks(address) {
+            0xFF
+        } else {
+            self.peek_byte(address)
+        };
+        if !self.watchpoints.is_empty() {
+            self.watchpoints.check(address, byte, false);
+        }
         self.tick();
         byte
     }
 
-    #[must_use]
-    fn read_word(&mut self, address: u16) -> u16 {
-        let low_byte = u16::from(self.read_byte(address));
-        u16::from(self.read_byte(address + 1)) << 8 | low_byte
-    }
-
     fn write_byte(&mut self, address: u16, value: u8) {
+        if !self.watchpoints.is_empty() {
+            self.watchpoints.check(address, value, true);
+        }
+        if self.dma.blocks(address) || self.ppu_blocks(address) {
+            if matches!(address, 0xFF69 | 0xFF6B) {
+                self.ppu.advance_palette_index(address);
+            }
+            self.tick();
+            return;
+        }
         match address {
             0x0000..=0x7FFF | 0xA000..=0xBFFF => {
                 // TODO What happens when writing here while the boot ROM is mapped?
@@ -130,20 +466,58 @@ impl Bus for DmgBus {
                     cartridge.write_byte(address, value);
                 }
             }
-            0x8000..=0x9FFF => self.ppu.vram[(address - 0x8000) as usize] = value,
-            0xC000..=0xDFFF => self.wram[(address - 0xC000) as usize] = value,
-            0xE000..=0xFDFF => self.wram[(address - 0xE000) as usize] = value,
+            0x8000..=0x9FFF => {
+                let index = self.ppu.vram_index(address);
+                self.ppu.vram[index] = value;
+            }
+            0xC000..=0xFDFF => {
+                let index = self.wram_index(address);
+                self.wram[index] = value;
+            }
             0xFE00..=0xFE9F => self.ppu.oam[(address - 0xFE00) as usize] = value,
-            0xFF01 => self.serial = value,
-            0xFF02 => self.serial_control = value,
-            0xFF04..=0xFF07 => self.timer.write_byte(address, value),
+            0xFF00 => {
+                if let Some(irq) = self.joypad.write_byte(value) {
+                    self.interrupt_flags |= irq.mask();
+                }
+                if let Some(sgb) = &mut self.ppu.sgb {
+                    sgb.write_joypad(value);
+                }
+            }
+            0xFF01 | 0xFF02 => {
+                self.sync(Component::Serial);
+                self.serial.write_byte(address, value);
+                self.reschedule(Component::Serial);
+            }
+            0xFF04..=0xFF07 => {
+                self.sync(Component::Timer);
+                self.timer.write_byte(address, value);
+                self.reschedule(Component::Timer);
+            }
+            0xFF10..=0xFF3F => self.apu.write_byte(address, value),
             0xFF0F => self.interrupt_flags = 0xE0 | value,
-            0xFF42 => self.ppu.scy = value,
-            0xFF50 => {
-                if value > 0 {
-                    self.bootrom_enabled = false;
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => {
+                self.sync(Component::Ppu);
+                self.ppu.write_byte(address, value);
+                self.reschedule(Component::Ppu);
+            }
+            0xFF4F | 0xFF68..=0xFF6C if self.model == Model::Cgb => {
+                self.ppu.write_byte(address, value);
+            }
+            // KEY0: the CGB boot ROM switches to DMG compatibility mode for DMG cartridges
+            0xFF4C if self.model == Model::Cgb && self.bootrom_enabled => {
+                self.ppu.cgb = value & 0x04 == 0;
+                self.ppu.dmg_compat = !self.ppu.cgb;
+            }
+            0xFF4D if self.model == Model::Cgb => self.speed_switch_armed = value & 0x01 != 0,
+            0xFF51..=0xFF55 if self.model == Model::Cgb => {
+                // General-purpose DMA copies every block at once
+                for _ in 0..self.hdma.write_byte(address, value) {
+                    self.hdma_block();
                 }
             }
+            0xFF70 if self.model == Model::Cgb => self.wram_bank = value & 0x07,
+            0xFF46 => self.dma.write_byte(value),
+            0xFF50 if value > 0 => self.bootrom_enabled = false,
             0xFF80..=0xFFFE => self.hram[(address - 0xFF80) as usize] = value,
             0xFFFF => self.interrupt_enable = 0xE0 | value,
             _ => (),
@@ -152,13 +526,63 @@ impl Bus for DmgBus {
         self.tick();
     }
 
-    fn write_word(&mut self, address: u16, value: u16) {
-        self.write_byte(address, (value & 0xFF) as u8);
-        self.write_byte(address.wrapping_add(1), (value >> 8) as u8);
+    fn stop(&mut self) -> bool {
+        self.sync(Component::Timer);
+        self.timer.reset_clock();
+        self.reschedule(Component::Timer);
+        if self.speed_switch_armed {
+            self.speed_switch_armed = false;
+            self.double_speed = !self.double_speed;
+            self.half_cycle = false;
+            return true;
+        }
+        false
     }
 
+    /// Emulates the effects of the boot ROM: I/O registers, and the logo in VRAM
     fn set_post_boot_state(&mut self) {
-        self.timer.sysclock = 0xAB;
+        // DIV reads 0x18 after the DMG0 boot ROM and 0xAB after the DMG and MGB ones. The others
+        // take a varying time depending on the cartridge, so they get the DMG's value.
+        self.sync(Component::Timer);
+        self.timer.sysclock = match self.model {
+            Model::Dmg0 => 0x1800,
+            _ => 0xABCC,
+        };
+        self.reschedule(Component::Timer);
+        self.interrupt_flags = 0xE1;
+        for (address, value) in [
+            (0xFF26, 0x80),
+            (0xFF11, 0x80),
+            (0xFF12, 0xF3),
+            (0xFF25, 0xF3),
+            (0xFF24, 0x77),
+        ] {
+            self.apu.write_byte(address, value);
+        }
+        self.sync(Component::Ppu);
+        // The boot ROM turned the LCD on long ago, so none of the quirks of doing so apply
+        self.ppu.lcdc = 0x91;
+        self.ppu.mode = Mode::OamScan;
+        self.ppu.write_byte(0xFF47, 0xFC);
+        self.reschedule(Component::Ppu);
+        if self.model == Model::Cgb {
+            // CGB cartridges have bit 7 of the CGB flag in the header set; the rest run in DMG
+            // compatibility mode
+            self.ppu.cgb = self
+                .cartridge
+                .as_ref()
+                .is_some_and(|cartridge| cartridge.read_byte(0x143) & 0x80 != 0);
+            self.ppu.dmg_compat = !self.ppu.cgb;
+            self.ppu.bg_palettes = [0xFF; 64];
+            self.ppu.obj_palettes = [0xFF; 64];
+            if self.ppu.dmg_compat {
+                let palettes = compat_palettes::palettes(|address| self.peek_byte(address));
+                self.ppu.load_compat_palettes(palettes);
+            }
+        }
+        if !self.ppu.cgb {
+            self.load_logo();
+        }
     }
 
     fn get_interrupt_enable(&self) -> u8 {
@@ -184,4 +608,121 @@ impl Bus for DmgBus {
     fn remove_cartridge(&mut self) {
         self.cartridge = None;
     }
+
+    fn reset(&mut self) {
+        let mut bus = DmgBus {
+            bootrom_enabled: !self.bootrom.is_empty(),
+            bootrom: std::mem::take(&mut self.bootrom),
+            cartridge: self.cartridge.take(),
+            overclock: self.overclock,
+            oam_bug: self.oam_bug,
+            ppu_access_locks: self.ppu_access_locks,
+            ly_stub: self.ly_stub,
+            watchpoints: std::mem::take(&mut self.watchpoints),
+            ..DmgBus::default()
+        };
+        bus.ppu.video_sinks = std::mem::take(&mut self.ppu.video_sinks);
+        bus.apu.sink = self.apu.sink.take();
+        bus.apu.muted = self.apu.muted;
+        self.serial.reset();
+        std::mem::swap(&mut bus.serial, &mut self.serial);
+        bus.set_model(self.model);
+        *self = bus;
+    }
+
+    fn get_cartridge(&self) -> Option<&dyn Cartridge> {
+        self.cartridge.as_deref()
+    }
+
+    fn send_peripheral_event(&mut self, event: PeripheralEvent) -> bool {
+        self.cartridge
+            .as_mut()
+            .is_some_and(|cartridge| cartridge.handle_event(&event))
+    }
+
+    fn set_buttons(&mut self, pressed: u8) {
+        if let Some(irq) = self.joypad.set_pressed(pressed) {
+            self.interrupt_flags |= irq.mask();
+        }
+    }
+
+    fn set_link_device(&mut self, device: Box<dyn LinkDevice>) {
+        self.serial.set_device(device);
+    }
+
+    fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.apu.sink = Some(sink);
+    }
+
+    fn add_video_sink(&mut self, sink: Box<dyn VideoSink>) {
+        self.ppu.video_sinks.push(sink);
+    }
+
+    fn set_cpu_overclock(&mut self, factor: u8) {
+        self.overclock = factor.max(1);
+        self.overclock_cycle = 0;
+    }
+
+    fn oam_bug(&mut self, address: u16) {
+        // Fixed on CGB, even in DMG compatibility mode
+        if self.oam_bug && self.model != Model::Cgb && (0xFE00..=0xFEFF).contains(&address) {
+            self.sync(Component::Ppu);
+            self.ppu.corrupt_oam();
+        }
+    }
+
+    fn set_oam_bug(&mut self, enabled: bool) {
+        self.oam_bug = enabled;
+    }
+
+    fn set_ppu_access_locks(&mut self, enabled: bool) {
+        self.ppu_access_locks = enabled;
+    }
+
+    fn stub_ly(&mut self, value: Option<u8>) {
+        self.ly_stub = value;
+    }
+
+    fn model(&self) -> Model {
+        self.model
+    }
+
+    fn metadata(&self) -> Metadata {
+        let boot_rom = (!self.bootrom.is_empty()).then_some(self.bootrom.as_slice());
+        Metadata {
+            oam_bug: self.oam_bug,
+            ppu_access_locks: self.ppu_access_locks,
+            ..Metadata::current(self.model, boot_rom)
+        }
+    }
+
+    fn set_model(&mut self, model: Model) {
+        self.model = model;
+        self.ppu.cgb = model == Model::Cgb;
+        self.ppu.dmg_compat = false;
+        self.sync(Component::Serial);
+        self.serial.cgb = model == Model::Cgb;
+        self.reschedule(Component::Serial);
+        self.ppu.sgb = (model == Model::Sgb).then(|| Box::new(Sgb::new()));
+    }
+
+    fn get_ppu(&self) -> Option<&Ppu> {
+        Some(&self.ppu)
+    }
+
+    fn get_apu(&self) -> Option<&Apu> {
+        Some(&self.apu)
+    }
+
+    fn get_apu_mut(&mut self) -> Option<&mut Apu> {
+        Some(&mut self.apu)
+    }
+
+    fn watchpoints(&self) -> Option<&Watchpoints> {
+        Some(&self.watchpoints)
+    }
+
+    fn watchpoints_mut(&mut self) -> Option<&mut Watchpoints> {
+        Some(&mut self.watchpoints)
+    }
 }
@@ -1,101 +1,1047 @@
-pub trait Cartridge {
+use crate::header::CartridgeHeader;
+use crate::peripheral::PeripheralEvent;
+use crate::state::State;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Cartridges are saved in savestates without their ROM
+pub trait Cartridge: State {
     #[must_use]
     fn read_byte(&self, address: u16) -> u8;
     fn write_byte(&mut self, address: u16, value: u8);
+    /// Returns the ROM bank currently mapped at a ROM address (0x0000-0x7FFF)
+    fn rom_bank(&self, address: u16) -> usize {
+        usize::from(address >= 0x4000)
+    }
+    /// Tick one M-cycle, for cartridges with their own clocked hardware
+    fn tick(&mut self) {}
+    /// Returns the contents of battery-backed RAM, if the cartridge has a battery
+    fn save_data(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Restores battery-backed RAM from data previously returned by `save_data`
+    fn load_save_data(&mut self, _data: &[u8]) {}
+    /// Handles an event for hardware on the cartridge. Returns whether the event was handled.
+    fn handle_event(&mut self, _event: &PeripheralEvent) -> bool {
+        false
+    }
 }
 
-/// # Panics
-///
-/// Will panic if cartridge header is malformed or not present
+/// Whether the cartridge type in the header has a battery
 #[must_use]
-#[allow(clippy::similar_names)]
-pub fn from_rom(rom: Vec<u8>) -> Box<dyn Cartridge> {
-    let header_rom_size = rom
-        .get(0x0148)
-        .expect("Unable to find ROM size in cartridge header");
-    assert!(*header_rom_size <= 8);
-    let rom_size = (2_u32).pow(15 + u32::from(*header_rom_size)) as usize;
-    assert!(rom_size == rom.len());
-
-    let ram: Option<Vec<u8>> = if let Some(header_ram_size) = rom.get(0x0149) {
-        match header_ram_size {
-            0x00 => None,
-            0x02 => Some(Vec::with_capacity(0x2000)),
-            0x03 => Some(Vec::with_capacity(0x8000)),
-            0x04 => Some(Vec::with_capacity(0x20000)),
-            0x05 => Some(Vec::with_capacity(0x10000)),
-            _ => panic!("Unknown RAM size in cartridge header"),
-        }
-    } else {
-        panic!("Unable to find RAM size in cartridge header");
-    };
-    if let Some(header_mbc) = rom.get(0x0147) {
-        match header_mbc {
-            0x00 => Box::new(NoMbc { rom, ram }), // TODO assert that ROM is 32 KiB?
-            0x01 => Box::new(Mbc1 {
-                // TODO assert that RAM/ROM combination is correct?
-                rom,
-                ram,
-                ..Default::default()
-            }),
-            _ => panic!("Unknown MBC in cartridge header"),
+pub fn has_battery(header_mbc: u8) -> bool {
+    matches!(
+        header_mbc,
+        0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+    )
+}
+
+fn load_ram(ram: &mut [u8], data: &[u8]) {
+    let len = ram.len().min(data.len());
+    ram[..len].copy_from_slice(&data[..len]);
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CartridgeError {
+    /// The ROM is too small to contain a cartridge header
+    MissingHeader,
+    UnknownRomSize(u8),
+    /// The ROM size in the header doesn't match the size of the ROM
+    RomSizeMismatch {
+        header: usize,
+        actual: usize,
+    },
+    UnknownRamSize(u8),
+    UnknownMbc(u8),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::MissingHeader => write!(f, "ROM is too small to have a header"),
+            CartridgeError::UnknownRomSize(size) => {
+                write!(f, "unknown ROM size {size:02X} in cartridge header")
+            }
+            CartridgeError::RomSizeMismatch { header, actual } => write!(
+                f,
+                "cartridge header says the ROM is {header} bytes, but it's {actual} bytes"
+            ),
+            CartridgeError::UnknownRamSize(size) => {
+                write!(f, "unknown RAM size {size:02X} in cartridge header")
+            }
+            CartridgeError::UnknownMbc(mbc) => {
+                write!(f, "unknown cartridge type {mbc:02X} in cartridge header")
+            }
         }
-    } else {
-        panic!("Unable to find MBC in cartridge header")
     }
 }
 
+impl std::error::Error for CartridgeError {}
+
+/// Creates the cartridge for a ROM, along with its parsed header
+///
+/// # Errors
+///
+/// Will return `Err` if the cartridge header is malformed or not present
+pub fn load(rom: Vec<u8>) -> Result<(Box<dyn Cartridge>, CartridgeHeader), CartridgeError> {
+    let header = CartridgeHeader::parse(&rom).ok_or(CartridgeError::MissingHeader)?;
+    Ok((from_rom(rom)?, header))
+}
+
+/// Creates a fresh cartridge for a ROM that keeps the battery-backed RAM of the `previous` one,
+/// like switching the power off and on. Anything else in the mapper is reset.
+///
+/// # Errors
+///
+/// Will return `Err` if the cartridge header is malformed or not present
+pub fn power_cycle(
+    rom: Vec<u8>,
+    previous: Option<&dyn Cartridge>,
+) -> Result<Box<dyn Cartridge>, CartridgeError> {
+    let mut cartridge = from_rom(rom)?;
+    if let Some(data) = previous.and_then(Cartridge::save_data) {
+        cartridge.load_save_data(&data);
+    }
+    Ok(cartridge)
+}
+
+/// # Errors
+///
+/// Will return `Err` if the cartridge header is malformed or not present
+pub fn from_rom(rom: Vec<u8>) -> Result<Box<dyn Cartridge>, CartridgeError> {
+    if rom.len() < 0x0150 {
+        return Err(CartridgeError::MissingHeader);
+    }
+    let header_rom_size = rom[0x0148];
+    if header_rom_size > 8 {
+        return Err(CartridgeError::UnknownRomSize(header_rom_size));
+    }
+    let rom_size = 0x8000 << header_rom_size;
+    if rom_size != rom.len() {
+        return Err(CartridgeError::RomSizeMismatch {
+            header: rom_size,
+            actual: rom.len(),
+        });
+    }
+
+    let ram: Option<Vec<u8>> = match rom[0x0149] {
+        0x00 => None,
+        0x02 => Some(vec![0; 0x2000]),
+        0x03 => Some(vec![0; 0x8000]),
+        0x04 => Some(vec![0; 0x20000]),
+        0x05 => Some(vec![0; 0x10000]),
+        size => return Err(CartridgeError::UnknownRamSize(size)),
+    };
+    let header_mbc = rom[0x0147];
+    let battery = has_battery(header_mbc);
+    Ok(match header_mbc {
+        0x00 | 0x08 | 0x09 => Box::new(NoMbc { rom, ram, battery }), // TODO assert that ROM is 32 KiB?
+        0x01..=0x03 => Box::new(Mbc1 {
+            // TODO assert that RAM/ROM combination is correct?
+            multicart: is_mbc1_multicart(&rom),
+            rom,
+            ram,
+            battery,
+            ..Default::default()
+        }),
+        0x05 | 0x06 => Box::new(Mbc2 {
+            rom,
+            ram: vec![0; 0x200],
+            battery,
+            rom_bank: 1,
+            ram_enabled: false,
+        }),
+        0x0F..=0x13 => Box::new(Mbc3 {
+            rom,
+            ram,
+            battery,
+            rtc: if header_mbc <= 0x10 {
+                Some(Rtc::default())
+            } else {
+                None
+            },
+            ..Default::default()
+        }),
+        0x19..=0x1E => Box::new(Mbc5 {
+            rom,
+            ram,
+            battery,
+            rom_bank: 1,
+            has_rumble: header_mbc >= 0x1C,
+            ..Default::default()
+        }),
+        0x22 => Box::new(Mbc7 {
+            rom,
+            rom_bank: 1,
+            ..Default::default()
+        }),
+        0xFF => Box::new(Huc1 {
+            rom,
+            ram,
+            battery,
+            rom_bank: 1,
+            ..Default::default()
+        }),
+        _ => return Err(CartridgeError::UnknownMbc(header_mbc)),
+    })
+}
+
 pub struct NoMbc {
     pub rom: Vec<u8>,
     pub ram: Option<Vec<u8>>,
+    pub battery: bool,
 }
 
 impl Cartridge for NoMbc {
     fn read_byte(&self, address: u16) -> u8 {
-        self.rom[address as usize]
+        match (address, &self.ram) {
+            (0x0000..=0x7FFF, _) => self.rom[address as usize],
+            (0xA000..=0xBFFF, Some(ram)) => ram[(address as usize - 0xA000) % ram.len()],
+            _ => 0xFF,
+        }
     }
 
-    fn write_byte(&mut self, _address: u16, _value: u8) {}
+    fn write_byte(&mut self, address: u16, value: u8) {
+        if let (0xA000..=0xBFFF, Some(ram)) = (address, &mut self.ram) {
+            let len = ram.len();
+            ram[(address as usize - 0xA000) % len] = value;
+        }
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if self.battery {
+            self.ram.clone()
+        } else {
+            None
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if let Some(ram) = &mut self.ram {
+            load_ram(ram, data);
+        }
+    }
+}
+
+/// MBC1 multicarts (MBC1M) are 1 MiB collections of 256 KiB games, each with its own header
+/// and Nintendo logo. The first game's logo is repeated at the start of bank 0x10.
+fn is_mbc1_multicart(rom: &[u8]) -> bool {
+    rom.len() == 0x10_0000 && rom[0x0104..0x0134] == rom[0x4_0104..0x4_0134]
 }
 
 #[derive(Default)]
 pub struct Mbc1 {
     pub rom: Vec<u8>,
     pub ram: Option<Vec<u8>>,
-    pub active_bank: u8,
+    pub battery: bool,
+    /// 5-bit ROM bank register (0x2000-0x3FFF)
+    pub rom_bank: u8,
+    /// 2-bit register (0x4000-0x5FFF) used as the upper ROM bank bits or the RAM bank
+    pub bank2: u8,
+    /// Banking mode 1 applies `bank2` to the 0x0000-0x3FFF ROM area and RAM too
+    pub advanced_banking: bool,
     pub ram_enabled: bool,
+    /// On multicarts, bit 4 of the ROM bank register isn't connected, so `bank2` selects the
+    /// upper two bits of a 6-bit bank number instead of a 7-bit one
+    pub multicart: bool,
+}
+
+impl Mbc1 {
+    fn bank2_shift(&self) -> u8 {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.advanced_banking {
+            usize::from(self.bank2)
+        } else {
+            0
+        }
+    }
 }
 
 impl Cartridge for Mbc1 {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => {
+                self.rom[self.rom_bank(address) * 0x4000 + (address as usize & 0x3FFF)]
+            }
+            0xA000..=0xBFFF => match &self.ram {
+                Some(ram) if self.ram_enabled => {
+                    ram[(self.ram_bank() * 0x2000 + (address as usize - 0xA000)) % ram.len()]
+                }
+                _ => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        let bank = if address < 0x4000 {
+            if self.advanced_banking {
+                self.bank2 << self.bank2_shift()
+            } else {
+                0
+            }
+        } else {
+            // Bank 0 can't be selected here, so writing 0x00/0x20/0x40/0x60 selects the next
+            // bank. The check uses all five bits, even on multicarts.
+            let low_mask = (1 << self.bank2_shift()) - 1;
+            self.bank2 << self.bank2_shift() | (self.rom_bank.max(1) & low_mask)
+        };
+        usize::from(bank) % (self.rom.len() / 0x4000)
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x1F,
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            0x6000..=0x7FFF => self.advanced_banking = value & 0x01 != 0,
+            0xA000..=0xBFFF if self.ram_enabled => {
+                let bank = self.ram_bank();
+                if let Some(ram) = &mut self.ram {
+                    let len = ram.len();
+                    ram[(bank * 0x2000 + (address as usize - 0xA000)) % len] = value;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if self.battery {
+            self.ram.clone()
+        } else {
+            None
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if let Some(ram) = &mut self.ram {
+            load_ram(ram, data);
+        }
+    }
+}
+
+/// MBC2 has 512 half-bytes of RAM built in, instead of RAM specified by the cartridge header
+pub struct Mbc2 {
+    pub rom: Vec<u8>,
+    pub ram: Vec<u8>,
+    pub battery: bool,
+    pub rom_bank: u8,
+    pub ram_enabled: bool,
+}
+
+impl Cartridge for Mbc2 {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                self.rom[(usize::from(self.rom_bank) * 0x4000 + (address as usize - 0x4000))
+                    % self.rom.len()]
+            }
+            // Only the lower nibble is stored; the upper one reads as open bus
+            0xA000..=0xBFFF if self.ram_enabled => 0xF0 | self.ram[(address & 0x1FF) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            0
+        } else {
+            usize::from(self.rom_bank) % (self.rom.len() / 0x4000)
+        }
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            // Address bit 8 selects between the RAM enable and ROM bank registers
+            0x0000..=0x3FFF => {
+                if address & 0x100 == 0 {
+                    self.ram_enabled = value & 0x0F == 0x0A;
+                } else {
+                    self.rom_bank = (value & 0x0F).max(1);
+                }
+            }
+            0xA000..=0xBFFF if self.ram_enabled => {
+                self.ram[(address & 0x1FF) as usize] = value & 0x0F;
+            }
+            _ => (),
+        }
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if self.battery {
+            Some(self.ram.clone())
+        } else {
+            None
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_ram(&mut self.ram, data);
+    }
+}
+
+/// The MBC3's real-time clock
+#[derive(Default)]
+pub struct Rtc {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub days: u16,
+    pub halted: bool,
+    pub day_carry: bool,
+    /// Snapshot of the registers (08-0C) taken by the last latch, which is what the game reads
+    pub latched: [u8; 5],
+    pub(crate) cycles: u32,
+    pub(crate) latch_armed: bool,
+}
+
+impl Rtc {
+    /// T-cycles per second
+    const CLOCK_RATE: u32 = 4_194_304;
+
+    fn tick(&mut self) {
+        if self.halted {
+            return;
+        }
+        self.cycles += 4;
+        if self.cycles >= Self::CLOCK_RATE {
+            self.cycles -= Self::CLOCK_RATE;
+            self.advance(1);
+        }
+    }
+
+    /// Counts a number of seconds. Counters that have been written with out-of-range values
+    /// keep counting until they overflow their bit width, without carrying into the next counter.
+    fn advance(&mut self, seconds: u64) {
+        let minutes = Self::count(&mut self.seconds, seconds, 60, 0x40);
+        let hours = Self::count(&mut self.minutes, minutes, 60, 0x40);
+        let days = u64::from(self.days) + Self::count(&mut self.hours, hours, 24, 0x20);
+        self.days = (days % 0x200) as u16;
+        if days >= 0x200 {
+            self.day_carry = true;
+        }
+    }
+
+    /// Adds `amount` to a counter that carries at `limit`, and returns how many times it
+    /// carried. An out-of-range counter first counts up to `overflow`, where it wraps to 0
+    /// without carrying.
+    fn count(counter: &mut u8, mut amount: u64, limit: u8, overflow: u8) -> u64 {
+        if *counter >= limit {
+            let until_overflow = u64::from(overflow - *counter);
+            if amount < until_overflow {
+                *counter += amount as u8;
+                return 0;
+            }
+            amount -= until_overflow;
+            *counter = 0;
+        }
+        let total = u64::from(*counter) + amount;
+        *counter = (total % u64::from(limit)) as u8;
+        total / u64::from(limit)
+    }
+
+    fn registers(&self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            (self.days & 0xFF) as u8,
+            ((self.days >> 8) as u8)
+                | (u8::from(self.halted) << 6)
+                | (u8::from(self.day_carry) << 7),
+        ]
+    }
+
+    /// Latching happens when 0x00 and then 0x01 is written to 0x6000-0x7FFF
+    fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.latched = self.registers();
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    fn read_register(&self, register: u8) -> u8 {
+        self.latched[usize::from(register - 0x08)]
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => {
+                self.seconds = value & 0x3F;
+                self.cycles = 0;
+            }
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | u16::from(value),
+            0x0C => {
+                self.days = (self.days & 0xFF) | (u16::from(value & 0x01) << 8);
+                self.halted = value & 0x40 != 0;
+                self.day_carry = value & 0x80 != 0;
+            }
+            _ => unreachable!(),
+        }
+        self.latched[usize::from(register - 0x08)] = self.registers()[usize::from(register - 0x08)];
+    }
+
+    /// Size of the RTC footer appended to the save file, in the format used by VBA and BGB.
+    /// Older files use a 32-bit timestamp, making the footer 44 bytes.
+    const FOOTER_LENGTH: usize = 48;
+    const OLD_FOOTER_LENGTH: usize = 44;
+
+    /// The current and latched registers as 32-bit little endian values, followed by the
+    /// current UNIX time as a 64-bit value
+    fn footer(&self) -> Vec<u8> {
+        let mut footer = Vec::with_capacity(Self::FOOTER_LENGTH);
+        for register in self.registers().iter().chain(self.latched.iter()) {
+            footer.extend_from_slice(&u32::from(*register).to_le_bytes());
+        }
+        footer.extend_from_slice(&unix_time().to_le_bytes());
+        footer
+    }
+
+    /// Restores the registers from a save file footer, and advances the clock by the time that
+    /// has passed since it was saved
+    fn load_footer(&mut self, footer: &[u8]) {
+        let values: Vec<u32> = footer
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        for register in 0x08..=0x0C {
+            self.write_register(register, values[usize::from(register - 0x08)] as u8);
+        }
+        for (latched, value) in self.latched.iter_mut().zip(&values[5..10]) {
+            *latched = *value as u8;
+        }
+        let timestamp = if footer.len() == Self::FOOTER_LENGTH {
+            u64::from(values[10]) | u64::from(values[11]) << 32
+        } else {
+            u64::from(values[10])
+        };
+        if !self.halted {
+            self.advance(unix_time().saturating_sub(timestamp));
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[derive(Default)]
+pub struct Mbc3 {
+    pub rom: Vec<u8>,
+    pub ram: Option<Vec<u8>>,
+    pub battery: bool,
+    pub rtc: Option<Rtc>,
+    pub rom_bank: u8,
+    /// RAM bank 0x00-0x07, or RTC register 0x08-0x0C
+    pub ram_bank: u8,
+    pub ram_enabled: bool,
+}
+
+impl Cartridge for Mbc3 {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                let bank = usize::from(self.rom_bank.max(1));
+                self.rom[(bank * 0x4000 + (address as usize - 0x4000)) % self.rom.len()]
+            }
+            0xA000..=0xBFFF if self.ram_enabled => match (self.ram_bank, &self.ram, &self.rtc) {
+                (0x00..=0x07, Some(ram), _) => {
+                    ram[(usize::from(self.ram_bank) * 0x2000 + (address as usize - 0xA000))
+                        % ram.len()]
+                }
+                (0x08..=0x0C, _, Some(rtc)) => rtc.read_register(self.ram_bank),
+                _ => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            0
+        } else {
+            usize::from(self.rom_bank.max(1)) % (self.rom.len() / 0x4000)
+        }
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value,
+            0x4000..=0x5FFF => self.ram_bank = value,
+            0x6000..=0x7FFF => {
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_latch(value);
+                }
+            }
+            0xA000..=0xBFFF if self.ram_enabled => {
+                match (self.ram_bank, &mut self.ram, &mut self.rtc) {
+                    (0x00..=0x07, Some(ram), _) => {
+                        let len = ram.len();
+                        ram[(usize::from(self.ram_bank) * 0x2000 + (address as usize - 0xA000))
+                            % len] = value;
+                    }
+                    (0x08..=0x0C, _, Some(rtc)) => rtc.write_register(self.ram_bank, value),
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn tick(&mut self) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.tick();
+        }
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if !self.battery {
+            return None;
+        }
+        let mut data = self.ram.clone().unwrap_or_default();
+        if let Some(rtc) = &self.rtc {
+            data.extend(rtc.footer());
+        }
+        Some(data)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let ram_length = self.ram.as_ref().map_or(0, Vec::len);
+        if let Some(ram) = &mut self.ram {
+            load_ram(ram, data);
+        }
+        if let Some(rtc) = &mut self.rtc {
+            let footer = &data[ram_length.min(data.len())..];
+            if footer.len() == Rtc::FOOTER_LENGTH || footer.len() == Rtc::OLD_FOOTER_LENGTH {
+                rtc.load_footer(footer);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Mbc5 {
+    pub rom: Vec<u8>,
+    pub ram: Option<Vec<u8>>,
+    pub battery: bool,
+    /// 9-bit ROM bank number
+    pub rom_bank: u16,
+    pub ram_bank: u8,
+    pub ram_enabled: bool,
+    /// On rumble cartridges, bit 3 of the RAM bank register drives the motor instead
+    pub has_rumble: bool,
+    pub rumble_active: bool,
+}
+
+impl Cartridge for Mbc5 {
     fn read_byte(&self, address: u16) -> u8 {
         match address {
             0x0000..=0x3FFF => self.rom[address as usize],
             0x4000..=0x7FFF => {
-                let active_bank = match self.active_bank {
-                    0x00 | 0x20 | 0x40 | 0x60 => self.active_bank + 1,
-                    _ => self.active_bank,
-                };
-                self.rom[(address * u16::from(active_bank)) as usize]
+                self.rom[(usize::from(self.rom_bank) * 0x4000 + (address as usize - 0x4000))
+                    % self.rom.len()]
+            }
+            0xA000..=0xBFFF => match &self.ram {
+                Some(ram) if self.ram_enabled => {
+                    ram[(usize::from(self.ram_bank) * 0x2000 + (address as usize - 0xA000))
+                        % ram.len()]
+                }
+                _ => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            0
+        } else {
+            usize::from(self.rom_bank) % (self.rom.len() / 0x4000)
+        }
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | u16::from(value),
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | (u16::from(value & 0x01) << 8);
+            }
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.rumble_active = value & 0x08 != 0;
+                    self.ram_bank = value & 0x07;
+                } else {
+                    self.ram_bank = value & 0x0F;
+                }
             }
             0xA000..=0xBFFF => {
-                if !self.ram_enabled || self.ram.is_none() {
-                    0xFF
+                if let Some(ram) = &mut self.ram {
+                    if self.ram_enabled {
+                        let len = ram.len();
+                        ram[(usize::from(self.ram_bank) * 0x2000 + (address as usize - 0xA000))
+                            % len] = value;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if self.battery {
+            self.ram.clone()
+        } else {
+            None
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if let Some(ram) = &mut self.ram {
+            load_ram(ram, data);
+        }
+    }
+}
+
+/// The 93LC56 serial EEPROM on MBC7 cartridges, which holds 128 16-bit words. The game drives
+/// its chip select, clock and data in pins, and reads its data out pin. Writes finish
+/// instantly, so it's always ready.
+pub struct Eeprom {
+    /// The words as little endian bytes
+    pub data: [u8; 0x100],
+    pub write_enabled: bool,
+    pub(crate) chip_select: bool,
+    pub(crate) clock: bool,
+    pub(crate) data_in: bool,
+    pub(crate) data_out: bool,
+    /// Whether the start bit of a command has been clocked in
+    pub(crate) started: bool,
+    /// Bits clocked in after the start bit: 2 opcode bits, 8 address bits and 16 data bits
+    pub(crate) command: u32,
+    pub(crate) command_bits: u8,
+    /// Word being shifted out by a read, and how many of its bits have been
+    pub(crate) read_address: Option<u8>,
+    pub(crate) read_bit: u8,
+}
+
+impl Default for Eeprom {
+    fn default() -> Self {
+        Self {
+            data: [0xFF; 0x100],
+            write_enabled: false,
+            chip_select: false,
+            clock: false,
+            data_in: false,
+            data_out: true,
+            started: false,
+            command: 0,
+            command_bits: 0,
+            read_address: None,
+            read_bit: 0,
+        }
+    }
+}
+
+impl Eeprom {
+    /// The pins, as bit 7 (CS), 6 (CLK), 1 (DI) and 0 (DO)
+    fn read(&self) -> u8 {
+        u8::from(self.chip_select) << 7
+            | u8::from(self.clock) << 6
+            | u8::from(self.data_in) << 1
+            | u8::from(self.data_out)
+    }
+
+    fn write(&mut self, value: u8) {
+        let rising_clock = !self.clock && value & 0x40 != 0;
+        self.chip_select = value & 0x80 != 0;
+        self.clock = value & 0x40 != 0;
+        self.data_in = value & 0x02 != 0;
+        if !self.chip_select {
+            self.started = false;
+            self.read_address = None;
+            self.data_out = true;
+        } else if rising_clock {
+            self.clock_in();
+        }
+    }
+
+    fn word(&self, address: u8) -> u16 {
+        let index = usize::from(address) * 2;
+        u16::from_le_bytes([self.data[index], self.data[index + 1]])
+    }
+
+    fn set_word(&mut self, address: u8, word: u16) {
+        if self.write_enabled {
+            let index = usize::from(address) * 2;
+            self.data[index..index + 2].copy_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    fn clock_in(&mut self) {
+        if let Some(address) = self.read_address {
+            // Reads carry on into the next word
+            self.data_out = self.word(address) >> (15 - self.read_bit) & 1 != 0;
+            self.read_bit += 1;
+            if self.read_bit == 16 {
+                self.read_address = Some((address + 1) & 0x7F);
+                self.read_bit = 0;
+            }
+            return;
+        }
+        if !self.started {
+            self.started = self.data_in;
+            self.command = 0;
+            self.command_bits = 0;
+            return;
+        }
+        self.command = self.command << 1 | u32::from(self.data_in);
+        self.command_bits += 1;
+        let address = (self.command & 0x7F) as u8;
+        let opcode = self.command >> self.command_bits.saturating_sub(2) & 0x03;
+        match (self.command_bits, opcode) {
+            // READ starts with a dummy 0 bit
+            (10, 0b10) => {
+                self.read_address = Some(address);
+                self.read_bit = 0;
+                self.data_out = false;
+            }
+            (10, 0b11) => {
+                self.set_word(address, 0xFFFF);
+                self.started = false;
+            }
+            (10, 0b00) => {
+                match self.command >> 6 & 0x03 {
+                    0b00 => self.write_enabled = false,
+                    0b11 => self.write_enabled = true,
+                    0b10 => {
+                        for address in 0..0x80 {
+                            self.set_word(address, 0xFFFF);
+                        }
+                    }
+                    // WRAL takes a word of data
+                    _ => return,
+                }
+                self.started = false;
+            }
+            // WRITE and WRAL have their address bits before the data
+            (26, opcode) => {
+                let word = self.command as u16;
+                let address = (self.command >> 16 & 0x7F) as u8;
+                if opcode == 0b01 {
+                    self.set_word(address, word);
                 } else {
-                    self.ram.as_ref().unwrap()[(address - 0xA000) as usize]
+                    for address in 0..0x80 {
+                        self.set_word(address, word);
+                    }
+                }
+                self.started = false;
+                self.data_out = true;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The MBC7, used by games with a tilt sensor, which has an accelerometer and an EEPROM
+/// mapped in place of cartridge RAM
+#[derive(Default)]
+pub struct Mbc7 {
+    pub rom: Vec<u8>,
+    pub rom_bank: u8,
+    /// 0x0000-0x1FFF and 0x4000-0x5FFF both have to be written to enable the registers
+    pub ram_enabled: [bool; 2],
+    pub eeprom: Eeprom,
+    /// The current accelerometer reading in g, as sent by the frontend
+    pub tilt: (f32, f32),
+    /// Accelerometer values latched for the game to read
+    pub latched: (u16, u16),
+    /// Whether the latched values have been erased, which is needed before latching new ones
+    pub(crate) latch_armed: bool,
+}
+
+impl Mbc7 {
+    /// The accelerometer's value when level, and how much 1 g changes it
+    const LEVEL: f32 = 0x81D0 as f32;
+    const G: f32 = 0x70 as f32;
+
+    fn accelerometer(tilt: f32) -> u16 {
+        (Self::LEVEL + tilt * Self::G).clamp(0.0, f32::from(u16::MAX)) as u16
+    }
+}
+
+impl Cartridge for Mbc7 {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                self.rom[(usize::from(self.rom_bank) * 0x4000 + (address as usize - 0x4000))
+                    % self.rom.len()]
+            }
+            0xA000..=0xAFFF if self.ram_enabled == [true; 2] => {
+                let [x_low, x_high] = self.latched.0.to_le_bytes();
+                let [y_low, y_high] = self.latched.1.to_le_bytes();
+                match address >> 4 & 0x0F {
+                    2 => x_low,
+                    3 => x_high,
+                    4 => y_low,
+                    5 => y_high,
+                    6 => 0x00,
+                    8 => self.eeprom.read(),
+                    _ => 0xFF,
                 }
             }
             _ => 0xFF,
         }
     }
 
+    fn rom_bank(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            0
+        } else {
+            usize::from(self.rom_bank) % (self.rom.len() / 0x4000)
+        }
+    }
+
     fn write_byte(&mut self, address: u16, value: u8) {
         match address {
-            0x0000..=0x1FFF => self.ram_enabled = value & 0x0A > 0,
-            0x2000..=0x3FFF => match value & 0x1F {
-                0x00 | 0x20 | 0x40 | 0x60 => self.active_bank = value + 1,
-                _ => self.active_bank = value,
+            0x0000..=0x1FFF => self.ram_enabled[0] = value == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value,
+            0x4000..=0x5FFF => self.ram_enabled[1] = value == 0x40,
+            0xA000..=0xAFFF if self.ram_enabled == [true; 2] => match address >> 4 & 0x0F {
+                0 if value == 0x55 => {
+                    self.latched = (0x8000, 0x8000);
+                    self.latch_armed = true;
+                }
+                1 if value == 0xAA && self.latch_armed => {
+                    self.latched = (
+                        Self::accelerometer(self.tilt.0),
+                        Self::accelerometer(self.tilt.1),
+                    );
+                    self.latch_armed = false;
+                }
+                8 => self.eeprom.write(value),
+                _ => (),
             },
             _ => (),
         }
     }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.eeprom.data.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_ram(&mut self.eeprom.data, data);
+    }
+
+    fn handle_event(&mut self, event: &PeripheralEvent) -> bool {
+        match event {
+            PeripheralEvent::Tilt { x, y } => {
+                self.tilt = (*x, *y);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Hudson's HuC1 mapper, which has an infrared port mapped over cartridge RAM
+#[derive(Default)]
+pub struct Huc1 {
+    pub rom: Vec<u8>,
+    pub ram: Option<Vec<u8>>,
+    pub battery: bool,
+    /// 6-bit ROM bank number
+    pub rom_bank: u8,
+    pub ram_bank: u8,
+    /// When set, 0xA000-0xBFFF accesses the IR port instead of RAM
+    pub ir_mode: bool,
+    pub ir_led: bool,
+    /// Whether the IR receiver sees light from another device
+    pub ir_light: bool,
+}
+
+impl Cartridge for Huc1 {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                self.rom[(usize::from(self.rom_bank) * 0x4000 + (address as usize - 0x4000))
+                    % self.rom.len()]
+            }
+            0xA000..=0xBFFF if self.ir_mode => 0xC0 | u8::from(self.ir_light),
+            0xA000..=0xBFFF => match &self.ram {
+                Some(ram) => {
+                    ram[(usize::from(self.ram_bank) * 0x2000 + (address as usize - 0xA000))
+                        % ram.len()]
+                }
+                None => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            0
+        } else {
+            usize::from(self.rom_bank) % (self.rom.len() / 0x4000)
+        }
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ir_mode = value & 0x0F == 0x0E,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x3F,
+            0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+            0xA000..=0xBFFF if self.ir_mode => self.ir_led = value & 0x01 != 0,
+            0xA000..=0xBFFF => {
+                if let Some(ram) = &mut self.ram {
+                    let len = ram.len();
+                    ram[(usize::from(self.ram_bank) * 0x2000 + (address as usize - 0xA000))
+                        % len] = value;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if self.battery {
+            self.ram.clone()
+        } else {
+            None
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if let Some(ram) = &mut self.ram {
+            load_ram(ram, data);
+        }
+    }
+
+    fn handle_event(&mut self, event: &PeripheralEvent) -> bool {
+        match event {
+            PeripheralEvent::InfraredLight(light) => {
+                self.ir_light = *light;
+                true
+            }
+            _ => false,
+        }
+    }
 }
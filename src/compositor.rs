@@ -0,0 +1,249 @@
+//! Layers the Game Boy screen, a border, on-screen messages and debug overlays into the final
+//! image shown by a frontend, so every frontend presents frames the same way.
+
+use crate::palette::Palette;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::video::Frame;
+
+/// Size of a Super Game Boy border, which surrounds the Game Boy screen
+pub const BORDER_WIDTH: usize = 256;
+pub const BORDER_HEIGHT: usize = 224;
+
+/// Converts a CGB color to RGB24, scaling each 5-bit channel to the full 8-bit range
+#[must_use]
+pub fn rgb555_to_rgb24(color: u16) -> [u8; 3] {
+    let channel = |shift: u16| {
+        let value = ((color >> shift) & 0x1F) as u8;
+        value << 3 | value >> 2
+    };
+    [channel(0), channel(5), channel(10)]
+}
+
+/// A composed image, as RGB24
+pub struct Output {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    /// Position of the top left corner of the Game Boy screen in the image
+    pub screen_origin: (usize, usize),
+}
+
+impl Output {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height * 3],
+            screen_origin: (0, 0),
+        }
+    }
+
+    /// Sets a pixel, ignoring pixels outside the image
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < self.width && y < self.height {
+            let offset = (y * self.width + x) * 3;
+            self.pixels[offset..offset + 3].copy_from_slice(&color);
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        for y in y..y + height {
+            for x in x..x + width {
+                self.set_pixel(x, y, color);
+            }
+        }
+    }
+
+    /// Draws text in a small built-in font, 4 pixels per character and 5 pixels tall.
+    /// Lowercase letters are drawn as uppercase.
+    pub fn draw_text(&mut self, x: usize, y: usize, text: &str, color: [u8; 3]) {
+        for (index, c) in text.chars().enumerate() {
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) != 0 {
+                        self.set_pixel(x + index * 4 + column, y + row, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draws debugging information on top of the composed image
+pub trait Overlay {
+    fn draw(&mut self, output: &mut Output);
+}
+
+struct Message {
+    text: String,
+    /// Frame number after which the message is removed
+    until: u64,
+}
+
+/// Composes each frame into an `Output`
+pub struct Compositor {
+    /// Colors of the DMG shades, for frames without colors
+    pub palette: Palette,
+    border: Option<Vec<u8>>,
+    messages: Vec<Message>,
+    overlays: Vec<Box<dyn Overlay>>,
+    output: Output,
+    last_frame: u64,
+}
+
+impl Default for Compositor {
+    fn default() -> Self {
+        Self {
+            palette: Palette::default(),
+            border: None,
+            messages: Vec::new(),
+            overlays: Vec::new(),
+            output: Output::new(SCREEN_WIDTH, SCREEN_HEIGHT),
+            last_frame: 0,
+        }
+    }
+}
+
+impl Compositor {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a `BORDER_WIDTH`×`BORDER_HEIGHT` RGB24 border to surround the screen, or removes it
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the border has the wrong size
+    pub fn set_border(&mut self, border: Option<Vec<u8>>) {
+        if let Some(border) = &border {
+            assert_eq!(border.len(), BORDER_WIDTH * BORDER_HEIGHT * 3);
+        }
+        self.output = match border {
+            Some(_) => Output::new(BORDER_WIDTH, BORDER_HEIGHT),
+            None => Output::new(SCREEN_WIDTH, SCREEN_HEIGHT),
+        };
+        self.border = border;
+    }
+
+    /// Shows a message on screen for the given number of frames
+    pub fn show_message(&mut self, text: &str, frames: u64) {
+        self.messages.push(Message {
+            text: text.to_string(),
+            until: self.last_frame + frames,
+        });
+    }
+
+    /// Whether any messages are still shown, in which case frames should be composed and
+    /// presented even if the Game Boy screen hasn't changed
+    #[must_use]
+    pub fn has_messages(&self) -> bool {
+        !self.messages.is_empty()
+    }
+
+    pub fn add_overlay(&mut self, overlay: Box<dyn Overlay>) {
+        self.overlays.push(overlay);
+    }
+
+    /// Composes a frame with the border, messages and overlays
+    pub fn compose(&mut self, frame: &Frame) -> &Output {
+        self.last_frame = frame.number;
+        self.messages
+            .retain(|message| message.until >= frame.number);
+
+        let output = &mut self.output;
+        if let Some(border) = &self.border {
+            output.pixels.copy_from_slice(border);
+        }
+        let origin = (
+            (output.width - SCREEN_WIDTH) / 2,
+            (output.height - SCREEN_HEIGHT) / 2,
+        );
+        output.screen_origin = origin;
+        for (index, shade) in frame.pixels.iter().enumerate() {
+            let color = match frame.colors {
+                Some(colors) => rgb555_to_rgb24(colors[index]),
+                None => self.palette.color(*shade),
+            };
+            output.set_pixel(
+                origin.0 + index % SCREEN_WIDTH,
+                origin.1 + index / SCREEN_WIDTH,
+                color,
+            );
+        }
+
+        // Messages are stacked upwards from the bottom left of the screen, newest at the bottom
+        for (index, message) in self
+            .messages
+            .iter()
+            .rev()
+            .take(SCREEN_HEIGHT / 7)
+            .enumerate()
+        {
+            let y = origin.1 + SCREEN_HEIGHT - (index + 1) * 7;
+            let width = message.text.chars().count() * 4 + 1;
+            output.fill_rect(origin.0, y, width, 7, [0x00, 0x00, 0x00]);
+            output.draw_text(origin.0 + 1, y + 1, &message.text, [0xFF, 0xFF, 0xFF]);
+        }
+
+        for overlay in &mut self.overlays {
+            overlay.draw(output);
+        }
+        output
+    }
+}
+
+/// Rows of a 3×5 glyph, most significant bit on the left
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010],
+    }
+}
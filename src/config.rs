@@ -0,0 +1,120 @@
+//! Settings from a TOML configuration file, which fill in any options that aren't given on the
+//! command line
+
+use rgb_emu::joypad::Button;
+use rgb_emu::palette::Palette;
+use rgb_emu::scaler::Filter;
+use serde::{Deserialize, Deserializer};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// An example of every setting:
+///
+/// ```toml
+/// palette = "green"
+/// scale = 4
+/// filter = "scanlines"
+/// speed = 1.0
+/// turbo = 1
+/// bootrom = "/path/to/dmg_boot.bin"
+/// save_dir = "/path/to/saves"
+///
+/// [keys]
+/// a = "X"
+/// b = "Z"
+/// select = "Backspace"
+/// start = "Return"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+pub struct Config {
+    #[serde(deserialize_with = "parse")]
+    pub palette: Option<Palette>,
+    pub scale: Option<u8>,
+    #[serde(deserialize_with = "parse")]
+    pub filter: Option<Filter>,
+    pub speed: Option<f32>,
+    pub turbo: Option<u8>,
+    pub bootrom: Option<PathBuf>,
+    /// Directory for battery saves, instead of next to the ROM
+    pub save_dir: Option<PathBuf>,
+    pub keys: Keys,
+}
+
+/// Names of the keys the joypad buttons are mapped to, as SDL names them
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+pub struct Keys {
+    pub right: Option<String>,
+    pub left: Option<String>,
+    pub up: Option<String>,
+    pub down: Option<String>,
+    pub a: Option<String>,
+    pub b: Option<String>,
+    pub select: Option<String>,
+    pub start: Option<String>,
+}
+
+impl Keys {
+    /// The key name configured for a button, if any
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    pub fn get(&self, button: Button) -> Option<&str> {
+        match button {
+            Button::Right => &self.right,
+            Button::Left => &self.left,
+            Button::Up => &self.up,
+            Button::Down => &self.down,
+            Button::A => &self.a,
+            Button::B => &self.b,
+            Button::Select => &self.select,
+            Button::Start => &self.start,
+        }
+        .as_deref()
+    }
+}
+
+/// Deserializes a setting from a string with its `FromStr` implementation, like on the command
+/// line
+fn parse<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+impl Config {
+    /// The default location of the configuration file, `rgb/config.toml` in the platform's
+    /// configuration directory (like `~/.config` on Linux)
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("rgb").join("config.toml"))
+    }
+
+    /// Loads the configuration file at `path`, or the default location if it's `None`. A
+    /// missing file at the default location gives the default configuration.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be read or isn't valid
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|error| format!("{}: {error}", path.display()))
+            }
+            Err(error) if required || error.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("{}: {error}", path.display()))
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Records which ROM code has been executed, and exports it as an annotated disassembly.
+//!
+//! Only code that actually ran is disassembled, so data in the ROM isn't mistaken for code.
+
+use crate::bus::Bus;
+use crate::cpu::Cpu;
+use crate::opcodes;
+use crate::symbols::Symbols;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// The set of executed instructions in ROM, by bank and address
+#[derive(Default)]
+pub struct Coverage {
+    executed: BTreeSet<(usize, u16)>,
+}
+
+impl Coverage {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the instruction at PC, if it's in cartridge ROM
+    pub fn record<B: Bus + ?Sized>(&mut self, cpu: &Cpu<B>) {
+        let pc = cpu.registers.pc;
+        if pc < 0x8000 && !(pc < 0x0100 && cpu.bus.boot_rom_mapped()) {
+            if let Some(cartridge) = cpu.bus.get_cartridge() {
+                self.executed.insert((cartridge.rom_bank(pc), pc));
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn is_executed(&self, bank: usize, address: u16) -> bool {
+        self.executed.contains(&(bank, address))
+    }
+
+    /// Number of distinct instructions executed
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.executed.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.executed.is_empty()
+    }
+
+    /// Disassembles every executed instruction in `rom`, grouped by bank, with labels from
+    /// `symbols`. Gaps between runs of executed code are separated by a blank line.
+    #[must_use]
+    pub fn disassemble(&self, rom: &[u8], symbols: &Symbols) -> String {
+        let mut output = String::new();
+        let mut current_bank = None;
+        let mut next_address = None;
+        for &(bank, address) in &self.executed {
+            let offset = if address < 0x4000 {
+                usize::from(address)
+            } else {
+                bank * 0x4000 + usize::from(address - 0x4000)
+            };
+            let Some(bytes) = rom.get(offset..) else {
+                continue;
+            };
+
+            if current_bank != Some(bank) {
+                if current_bank.is_some() {
+                    output.push('\n');
+                }
+                let _ = writeln!(output, "; ROM bank ${bank:02X}");
+                current_bank = Some(bank);
+            } else if next_address != Some(address) {
+                output.push('\n');
+            }
+
+            if let Some(label) = symbols.label(bank, address) {
+                let _ = writeln!(output, "{label}:");
+            }
+            let label = |target| symbols.label_from(bank, target).map(String::from);
+            let instruction = opcodes::format_instruction(bytes, address, label);
+            let length = instruction
+                .as_ref()
+                .and_then(|_| opcodes::info(opcode(bytes)))
+                .map_or(1, |info| usize::from(info.length));
+            let hex = bytes[..length.min(bytes.len())]
+                .iter()
+                .map(|byte| format!("{byte:02X}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let _ = writeln!(
+                output,
+                "{bank:02X}:{address:04X}  {hex:<9} {}",
+                instruction.unwrap_or_else(|| String::from("; illegal opcode"))
+            );
+            next_address = u16::try_from(length)
+                .ok()
+                .map(|length| address.wrapping_add(length));
+        }
+        output
+    }
+}
+
+fn opcode(bytes: &[u8]) -> u16 {
+    match bytes {
+        [0xCB, opcode, ..] => 0xCB00 | u16::from(*opcode),
+        [opcode, ..] => u16::from(*opcode),
+        [] => 0,
+    }
+}
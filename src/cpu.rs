@@ -1,54 +1,164 @@
+use crate::block_cache::BlockCache;
 use crate::bus::{Bus, DmgBus};
+use crate::compat_palettes;
+use crate::interrupts::Interrupt;
+use crate::model::Model;
+use std::collections::BTreeSet;
 use std::ops::{Index, IndexMut};
 
-pub struct Cpu {
+/// M-cycles the CPU is paused for while the CGB switches speed
+const SPEED_SWITCH_CYCLES: u16 = 2050;
+
+/// The SM83 CPU, which owns the bus with the rest of the hardware. It's generic over the bus so
+/// a `Cpu<DmgBus>` can call it without dynamic dispatch. `Cpu` on its own holds a
+/// `Box<dyn Bus>`, which can be swapped out for another bus.
+pub struct Cpu<B: Bus + ?Sized = dyn Bus> {
     pub registers: Registers,
     pub flags: Flags,
     pub ime: bool,
     pub ime_delayed: bool,
     pub halted: bool,
-    pub bus: Box<dyn Bus>,
+    /// Set when HALT is executed with IME=0 and an interrupt pending, which makes the CPU fail
+    /// to increment PC after fetching the next opcode, so it's read twice
+    pub halt_bug: bool,
+    /// In STOP mode, all clocks are stopped until a button is pressed
+    pub stopped: bool,
+    /// M-cycles left of a CGB speed switch. The CPU stays `stopped` until they've run out, but
+    /// the rest of the system keeps running.
+    pub speed_switch_cycles: u16,
+    /// Set when an illegal opcode is executed, which hangs the CPU until it's reset. The rest
+    /// of the system keeps running, but interrupts are no longer serviced.
+    pub locked: bool,
+    pub bus: Box<B>,
+    /// Called by `step` before each instruction is executed
+    pub trace_hook: Option<TraceHook>,
+    /// Checked by `step` after each instruction
+    pub breakpoints: BTreeSet<Breakpoint>,
+    /// Set by `step` when PC reaches a breakpoint. Run loops should pause when they see it.
+    pub breakpoint_hit: Option<Breakpoint>,
+    /// Decoded code in ROM for `run_block`, or `None` to decode every instruction as it runs
+    pub block_cache: Option<BlockCache>,
+}
+
+/// Breaks execution when PC reaches an address, optionally only when a given ROM bank is mapped
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Breakpoint {
+    pub address: u16,
+    pub bank: Option<usize>,
+}
+
+/// An instruction about to be executed, as seen by a trace hook
+pub struct TraceEvent<'a> {
+    pub pc: u16,
+    /// The opcode, where prefixed opcodes are given as 0xCBxx like in `opcodes::info`
+    pub opcode: u16,
+    pub instruction: &'a Instruction,
+    /// Registers and flags before the instruction was fetched
+    pub registers: Registers,
+    pub flags: Flags,
 }
 
+pub type TraceHook = Box<dyn FnMut(&TraceEvent)>;
+
 impl Default for Cpu {
     fn default() -> Self {
+        Self::with_bus(Box::new(DmgBus::new()))
+    }
+}
+
+impl Cpu {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<B: Bus + ?Sized> Cpu<B> {
+    /// A CPU connected to `bus`, with all registers cleared
+    #[must_use]
+    pub fn with_bus(bus: Box<B>) -> Self {
         Self {
             registers: Registers::default(),
             flags: Flags::default(),
             ime: false,
             ime_delayed: false,
             halted: false,
-            bus: Box::new(DmgBus::new()),
+            halt_bug: false,
+            stopped: false,
+            speed_switch_cycles: 0,
+            locked: false,
+            bus,
+            trace_hook: None,
+            breakpoints: BTreeSet::new(),
+            breakpoint_hit: None,
+            block_cache: None,
         }
     }
-}
 
-impl Cpu {
-    #[must_use]
-    pub fn new() -> Self {
-        Self::default()
+    /// Resets the CPU and the rest of the hardware, like switching the power off and on. The boot
+    /// ROM runs again if there is one, and otherwise the post-boot state is set up. Breakpoints
+    /// and the trace hook are kept, and so is the cartridge, so insert a fresh one first to
+    /// reset its mapper too.
+    pub fn reset(&mut self) {
+        self.registers = Registers::default();
+        self.flags = Flags::default();
+        self.ime = false;
+        self.ime_delayed = false;
+        self.halted = false;
+        self.halt_bug = false;
+        self.stopped = false;
+        self.speed_switch_cycles = 0;
+        self.locked = false;
+        self.breakpoint_hit = None;
+        self.bus.reset();
+        if !self.bus.boot_rom_mapped() {
+            self.set_post_boot_state();
+        }
     }
 
+    /// Sets up the registers the way the model's boot ROM leaves them, along with the rest of
+    /// the hardware, as if the boot ROM had just finished
     pub fn set_post_boot_state(&mut self) {
         self.registers.pc = 0x100;
-        self.registers.a = 0x01;
-        self.registers.b = 0x00;
-        self.registers.c = 0x13;
-        self.registers.d = 0x00;
-        self.registers.e = 0xD8;
-        self.registers.h = 0x01;
-        self.registers.l = 0x4D;
-        self.flags.z = true;
-        self.flags.n = false;
-        self.flags.h = true;
-        self.flags.c = true;
         self.registers.sp = 0xFFFE;
+        // The DMG and MGB boot ROMs finish by comparing the header checksum they computed with
+        // the one in the header, which leaves H and C set unless the header checksum is 0
+        let checksum_flags = self.bus.peek_byte(0x014D) != 0;
+        let (af, bc, de, hl) = match self.bus.model() {
+            Model::Dmg0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+            Model::Dmg => (0x01B0, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFFB0, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+            // A = 0x11 is how games detect that they're running on a CGB
+            Model::Cgb if self.bus.peek_byte(0x0143) & 0x80 != 0 => {
+                (0x1180, 0x0000, 0xFF56, 0x000D)
+            }
+            Model::Cgb => {
+                // In DMG compatibility mode, the boot ROM leaves the sum of the title in B if
+                // the licensee is Nintendo, since it picks a palette based on it
+                let b = compat_palettes::title_checksum(|address| self.bus.peek_byte(address));
+                let hl = if b == 0x43 || b == 0x58 {
+                    0x991A
+                } else {
+                    0x007C
+                };
+                (0x1180, u16::from(b) << 8, 0x0008, hl)
+            }
+        };
+        self.set_register_pair(&RegisterPair::AF, af);
+        self.set_register_pair(&RegisterPair::BC, bc);
+        self.set_register_pair(&RegisterPair::DE, de);
+        self.set_register_pair(&RegisterPair::HL, hl);
+        if matches!(self.bus.model(), Model::Dmg | Model::Mgb) && !checksum_flags {
+            self.flags.h = false;
+            self.flags.c = false;
+        }
 
         self.bus.set_post_boot_state();
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Copy, Debug, Default)]
 #[allow(clippy::struct_excessive_bools)]
 pub struct Flags {
     pub z: bool,
@@ -57,7 +167,7 @@ pub struct Flags {
     pub h: bool,
 }
 
-#[derive(Default)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Registers {
     pub a: u8,
     pub b: u8,
@@ -102,7 +212,7 @@ impl IndexMut<&Register> for Registers {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Register {
     A,
     B,
@@ -117,7 +227,7 @@ pub enum Register {
     IndirectC,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum RegisterPair {
     BC,
     DE,
@@ -126,7 +236,7 @@ pub enum RegisterPair {
     AF,
 }
 
-fn inherent_condition_operand(opcode: u8) -> Condition {
+const fn inherent_condition_operand(opcode: u8) -> Condition {
     match opcode & 0o03 {
         0 => Condition::NonZero,
         1 => Condition::Zero,
@@ -136,7 +246,7 @@ fn inherent_condition_operand(opcode: u8) -> Condition {
     }
 }
 
-fn inherent_register_operand(opcode: u8) -> Register {
+const fn inherent_register_operand(opcode: u8) -> Register {
     match opcode & 0o07 {
         0 => Register::B,
         1 => Register::C,
@@ -150,7 +260,7 @@ fn inherent_register_operand(opcode: u8) -> Register {
     }
 }
 
-fn inherent_registerpair_operand(opcode: u8) -> RegisterPair {
+const fn inherent_registerpair_operand(opcode: u8) -> RegisterPair {
     match opcode & 0o07 {
         0 | 1 => RegisterPair::BC,
         2 | 3 => RegisterPair::DE,
@@ -160,7 +270,7 @@ fn inherent_registerpair_operand(opcode: u8) -> RegisterPair {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Condition {
     Always,
     Zero,
@@ -169,7 +279,7 @@ pub enum Condition {
     NonCarry,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Instruction {
     Ld(Operand, Operand),
     Xor(Operand),
@@ -214,9 +324,11 @@ pub enum Instruction {
     Cpl,
     Scf,
     Ccf,
+    /// One of the opcodes that have no instruction, which lock up the CPU
+    Illegal(u8),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Operand {
     Immediate8(u8),
     IndirectImmediate8(u8),
@@ -228,7 +340,50 @@ pub enum Operand {
     RegisterIndirect(RegisterPair),
 }
 
-impl Cpu {
+impl Operand {
+    /// Fills in the value of an immediate operand
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    fn with_immediate(self, value: u16) -> Self {
+        match self {
+            Operand::Immediate8(_) => Operand::Immediate8(value as u8),
+            Operand::IndirectImmediate8(_) => Operand::IndirectImmediate8(value as u8),
+            Operand::Immediate16(_) => Operand::Immediate16(value),
+            Operand::IndirectImmediate16(_) => Operand::IndirectImmediate16(value),
+            Operand::StackOffset(_) => Operand::StackOffset(value as i8),
+            operand => operand,
+        }
+    }
+}
+
+impl Instruction {
+    /// Fills in the value of the immediate operand in an instruction template
+    #[allow(clippy::cast_possible_wrap)]
+    fn with_immediate(self, value: u16) -> Self {
+        match self {
+            Instruction::Ld(target, source) => {
+                Instruction::Ld(target.with_immediate(value), source.with_immediate(value))
+            }
+            Instruction::Add(target, source) => {
+                Instruction::Add(target, source.with_immediate(value))
+            }
+            Instruction::Adc(source) => Instruction::Adc(source.with_immediate(value)),
+            Instruction::Sub(source) => Instruction::Sub(source.with_immediate(value)),
+            Instruction::Sbc(source) => Instruction::Sbc(source.with_immediate(value)),
+            Instruction::And(source) => Instruction::And(source.with_immediate(value)),
+            Instruction::Xor(source) => Instruction::Xor(source.with_immediate(value)),
+            Instruction::Or(source) => Instruction::Or(source.with_immediate(value)),
+            Instruction::Cp(source) => Instruction::Cp(source.with_immediate(value)),
+            Instruction::Jp(condition, target) => {
+                Instruction::Jp(condition, target.with_immediate(value))
+            }
+            Instruction::Jr(condition, _) => Instruction::Jr(condition, value as u8 as i8),
+            Instruction::Call(condition, _) => Instruction::Call(condition, value),
+            instruction => instruction,
+        }
+    }
+}
+
+impl<B: Bus + ?Sized> Cpu<B> {
     #[must_use]
     pub fn get_register_pair(&self, rp: &RegisterPair) -> u16 {
         match rp {
@@ -247,7 +402,7 @@ impl Cpu {
     }
 
     #[allow(clippy::cast_possible_truncation)]
-    fn set_register_pair(&mut self, rp: &RegisterPair, value: u16) {
+    pub fn set_register_pair(&mut self, rp: &RegisterPair, value: u16) {
         match rp {
             RegisterPair::AF => {
                 self.registers.a = (value >> 8) as u8;
@@ -278,23 +433,183 @@ impl Cpu {
         value
     }
 
-    fn fetch_imm16(&mut self) -> u16 {
-        let value = self.bus.read_word(self.registers.pc);
-        self.registers.pc = self.registers.pc.wrapping_add(2);
-        value
+    /// Fetches, decodes and executes one instruction, including servicing any interrupt
+    /// afterwards, and returns the number of M-cycles it took
+    pub fn step(&mut self) -> u32 {
+        let start = self.bus.cycles();
+        // Nothing is fetched while the CPU is halted, stopped or locked up
+        let idle = self.halted
+            || self.locked
+            || (self.stopped && (self.speed_switch_cycles > 0 || !self.joypad_line_low()));
+        let (registers, flags) = (self.registers, self.flags);
+        let opcode = self.fetch();
+        let instruction = self.decode(opcode);
+        if let Some(hook) = &mut self.trace_hook {
+            if !idle {
+                let opcode = match opcode {
+                    0xCB => 0xCB00 | u16::from(self.bus.peek_byte(registers.pc.wrapping_add(1))),
+                    _ => u16::from(opcode),
+                };
+                hook(&TraceEvent {
+                    pc: registers.pc,
+                    opcode,
+                    instruction: &instruction,
+                    registers,
+                    flags,
+                });
+            }
+        }
+        self.execute(instruction);
+        if !self.breakpoints.is_empty() && !self.halted && !self.locked {
+            self.breakpoint_hit = self.breakpoint_at_pc().or(self.breakpoint_hit);
+        }
+        u32::try_from(self.bus.cycles() - start).unwrap_or(u32::MAX)
+    }
+
+    /// Runs a block of straight-line code from the block cache and returns the number of
+    /// instructions run. The block ends early if an interrupt is dispatched, the CPU halts or
+    /// another ROM bank is mapped.
+    ///
+    /// This falls back to `step` for one instruction when the block cache is off or can't be
+    /// used: outside cartridge ROM, while the boot ROM is mapped, while halted, or with
+    /// breakpoints or a trace hook set. Cached instructions take the same cycles to fetch, but
+    /// aren't read from the bus, so watchpoints and OAM DMA bus conflicts don't see them.
+    ///
+    /// With the `jit` feature, blocks that the cache's JIT has compiled run as native code
+    /// instead, unless an EI has just been executed.
+    pub fn run_block(&mut self) -> usize {
+        let pc = self.registers.pc;
+        let usable = !self.halted
+            && !self.stopped
+            && !self.locked
+            && !self.halt_bug
+            && self.trace_hook.is_none()
+            && self.breakpoints.is_empty()
+            && pc < 0x8000
+            && !self.bus.boot_rom_mapped()
+            && !self.bus.oam_dma_pending();
+        let bank = self
+            .bus
+            .get_cartridge()
+            .map(|cartridge| cartridge.rom_bank(pc));
+        let (Some(cache), true, Some(bank)) = (&mut self.block_cache, usable, bank) else {
+            self.step();
+            return 1;
+        };
+        let block = cache.get(&*self.bus, bank, pc);
+        if block.is_empty() {
+            self.step();
+            return 1;
+        }
+        #[cfg(feature = "jit")]
+        if let Some(code) = cache
+            .jit
+            .as_mut()
+            .filter(|_| !self.ime_delayed)
+            .and_then(|jit| jit.get(bank, pc, &block))
+        {
+            let count = code.run(&mut self.registers, &mut self.flags, &mut self.bus);
+            self.handle_interrupts();
+            return count;
+        }
+
+        let mut count = 0;
+        for cached in block.iter() {
+            for _ in 0..cached.length {
+                self.bus.tick();
+            }
+            let next = self.registers.pc.wrapping_add(u16::from(cached.length));
+            self.registers.pc = next;
+            self.execute(cached.instruction);
+            count += 1;
+            let same_bank = self
+                .bus
+                .get_cartridge()
+                .is_some_and(|cartridge| cartridge.rom_bank(pc) == bank);
+            if self.registers.pc != next
+                || self.halted
+                || self.stopped
+                || !same_bank
+                || self.bus.oam_dma_pending()
+            {
+                break;
+            }
+        }
+        count
+    }
+
+    /// Whether the CPU is in STOP mode with every clock stopped, so no time passes until a button
+    /// is pressed. That isn't the case while the CGB switches speed.
+    #[must_use]
+    pub fn clocks_stopped(&self) -> bool {
+        self.stopped && self.speed_switch_cycles == 0
+    }
+
+    /// Whether a breakpoint or watchpoint was hit, so the run loop should pause
+    #[must_use]
+    pub fn should_pause(&self) -> bool {
+        self.breakpoint_hit.is_some()
+            || self
+                .bus
+                .watchpoints()
+                .is_some_and(|watchpoints| watchpoints.hit.is_some())
+    }
+
+    fn breakpoint_at_pc(&self) -> Option<Breakpoint> {
+        let address = self.registers.pc;
+        let bank = self
+            .bus
+            .get_cartridge()
+            .map_or(0, |cartridge| cartridge.rom_bank(address));
+        self.breakpoints
+            .range(
+                Breakpoint {
+                    address,
+                    bank: None,
+                }..,
+            )
+            .take_while(|breakpoint| breakpoint.address == address)
+            .find(|breakpoint| breakpoint.bank.is_none_or(|b| b == bank))
+            .copied()
     }
 
     pub fn fetch(&mut self) -> u8 {
-        if self.halted {
+        if self.speed_switch_cycles > 0 {
+            self.speed_switch_cycles -= 1;
+            self.stopped = self.speed_switch_cycles > 0;
             self.bus.tick();
             return 0x00;
         }
+        if self.stopped {
+            // Pressing a button in a selected row wakes the CPU up, whether the joypad interrupt
+            // is enabled or not. An old request in IF doesn't.
+            if !self.joypad_line_low() {
+                return 0x00;
+            }
+            self.stopped = false;
+        }
+        if self.halted || self.locked {
+            self.bus.tick();
+            return 0x00;
+        }
+        if self.halt_bug {
+            self.halt_bug = false;
+            return self.bus.read_byte(self.registers.pc);
+        }
         self.fetch_imm8()
     }
 
+    /// Whether a held button pulls one of P1's input lines low
+    fn joypad_line_low(&self) -> bool {
+        self.bus.peek_byte(0xFF00) & 0x0F != 0x0F
+    }
+
+    /// Pushes the high byte first, one M-cycle per byte, like the hardware does
     fn push(&mut self, value: u16) {
-        self.registers.sp = self.registers.sp.wrapping_sub(2);
-        self.bus.write_word(self.registers.sp, value);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.bus.write_byte(self.registers.sp, (value >> 8) as u8);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.bus.write_byte(self.registers.sp, (value & 0xFF) as u8);
     }
 
     fn pop(&mut self) -> u16 {
@@ -303,201 +618,13 @@ impl Cpu {
         result
     }
 
-    #[allow(clippy::too_many_lines)]
     pub fn decode(&mut self, opcode: u8) -> Instruction {
-        #[allow(clippy::match_overlapping_arm, clippy::cast_possible_wrap)]
-        match opcode {
-            0o00 => Instruction::Nop,
-            0o01 | 0o21 | 0o41 | 0o61 => Instruction::Ld(
-                Operand::RegisterPair(inherent_registerpair_operand(opcode >> 3)),
-                Operand::Immediate16(self.fetch_imm16()),
-            ),
-            0o07 => Instruction::Rlca,
-            0o17 => Instruction::Rrca,
-            0o27 => Instruction::Rla,
-            0o37 => Instruction::Rra,
-            0o47 => Instruction::Daa,
-            0o57 => Instruction::Cpl,
-            0o67 => Instruction::Scf,
-            0o77 => Instruction::Ccf,
-            0o11 | 0o31 | 0o51 | 0o71 => Instruction::Add(
-                Operand::RegisterPair(RegisterPair::HL),
-                Operand::RegisterPair(inherent_registerpair_operand(opcode >> 3)),
-            ),
-            0o10 => Instruction::Ld(
-                Operand::IndirectImmediate16(self.fetch_imm16()),
-                Operand::RegisterPair(RegisterPair::SP),
-            ),
-            0o02 | 0o22 => Instruction::Ld(
-                Operand::RegisterIndirect(inherent_registerpair_operand(opcode >> 3)),
-                Operand::Register(Register::A),
-            ),
-            0o12 | 0o32 => Instruction::Ld(
-                Operand::Register(Register::A),
-                Operand::RegisterIndirect(inherent_registerpair_operand(opcode >> 3)),
-            ),
-            0o42 => Instruction::Ld(
-                Operand::Register(Register::IncrementHL),
-                Operand::Register(Register::A),
-            ),
-            0o52 => Instruction::Ld(
-                Operand::Register(Register::A),
-                Operand::Register(Register::IncrementHL),
-            ),
-            0o62 => Instruction::Ld(
-                Operand::Register(Register::DecrementHL),
-                Operand::Register(Register::A),
-            ),
-            0o72 => Instruction::Ld(
-                Operand::Register(Register::A),
-                Operand::Register(Register::DecrementHL),
-            ),
-            0o20 => Instruction::Stop,
-            0o30 => Instruction::Jr(Condition::Always, self.fetch_imm8() as i8),
-            0o40 | 0o50 | 0o60 | 0o70 => Instruction::Jr(
-                inherent_condition_operand((opcode - 0o40) >> 3),
-                self.fetch_imm8() as i8,
-            ),
-            0o03 | 0o23 | 0o43 | 0o63 => Instruction::Inc(Operand::RegisterPair(
-                inherent_registerpair_operand(opcode >> 3),
-            )),
-            0o13 | 0o33 | 0o53 | 0o73 => Instruction::Dec(Operand::RegisterPair(
-                inherent_registerpair_operand(opcode >> 3),
-            )),
-            0o04 | 0o14 | 0o24 | 0o34 | 0o44 | 0o54 | 0o64 | 0o74 => Instruction::Inc(
-                Operand::Register(inherent_register_operand((opcode & 0o70) >> 3)),
-            ),
-            0o05 | 0o15 | 0o25 | 0o35 | 0o45 | 0o55 | 0o65 | 0o75 => Instruction::Dec(
-                Operand::Register(inherent_register_operand((opcode & 0o70) >> 3)),
-            ),
-            0o06 | 0o16 | 0o26 | 0o36 | 0o46 | 0o56 | 0o66 | 0o76 => Instruction::Ld(
-                Operand::Register(inherent_register_operand((opcode & 0o70) >> 3)),
-                Operand::Immediate8(self.fetch_imm8()),
-            ),
-            0o166 => Instruction::Halt,
-            0o100..=0o177 => Instruction::Ld(
-                Operand::Register(inherent_register_operand(opcode >> 3)),
-                Operand::Register(inherent_register_operand(opcode)),
-            ),
-            0o200..=0o207 => Instruction::Add(
-                Operand::Register(Register::A),
-                Operand::Register(inherent_register_operand(opcode)),
-            ),
-            0o210..=0o217 => Instruction::Adc(Operand::Register(inherent_register_operand(opcode))),
-            0o220..=0o227 => Instruction::Sub(Operand::Register(inherent_register_operand(opcode))),
-            0o230..=0o237 => Instruction::Sbc(Operand::Register(inherent_register_operand(opcode))),
-            0o240..=0o247 => Instruction::And(Operand::Register(inherent_register_operand(opcode))),
-            0o250..=0o257 => Instruction::Xor(Operand::Register(inherent_register_operand(opcode))),
-            0o260..=0o267 => Instruction::Or(Operand::Register(inherent_register_operand(opcode))),
-            0o270..=0o277 => Instruction::Cp(Operand::Register(inherent_register_operand(opcode))),
-            0o300 | 0o310 | 0o320 | 0o330 => {
-                Instruction::Ret(inherent_condition_operand(opcode >> 3))
-            }
-            0o301 | 0o321 | 0o341 => {
-                Instruction::Pop(inherent_registerpair_operand((opcode & 0o70) >> 3))
-            }
-            0o305 | 0o325 | 0o345 => {
-                Instruction::Push(inherent_registerpair_operand((opcode & 0o70) >> 3))
-            }
-            0o361 => Instruction::Pop(RegisterPair::AF),
-            0o365 => Instruction::Push(RegisterPair::AF),
-            0o311 => Instruction::Ret(Condition::Always),
-            0o303 => Instruction::Jp(Condition::Always, Operand::Immediate16(self.fetch_imm16())),
-            0o351 => Instruction::Jp(Condition::Always, Operand::RegisterPair(RegisterPair::HL)),
-            0o302 | 0o312 | 0o322 | 0o332 => Instruction::Jp(
-                inherent_condition_operand(opcode >> 3),
-                Operand::Immediate16(self.fetch_imm16()),
-            ),
-            0o304 | 0o314 | 0o324 | 0o334 => {
-                Instruction::Call(inherent_condition_operand(opcode >> 3), self.fetch_imm16())
-            }
-            0o315 => Instruction::Call(Condition::Always, self.fetch_imm16()),
-            0o313 => {
-                let opcode = self.fetch_imm8();
-                match opcode {
-                    0o00..=0o07 => Instruction::Rlc(inherent_register_operand(opcode)),
-                    0o10..=0o17 => Instruction::Rrc(inherent_register_operand(opcode)),
-                    0o20..=0o27 => Instruction::Rl(inherent_register_operand(opcode)),
-                    0o30..=0o37 => Instruction::Rr(inherent_register_operand(opcode)),
-                    0o40..=0o47 => Instruction::Sla(inherent_register_operand(opcode)),
-                    0o50..=0o57 => Instruction::Sra(inherent_register_operand(opcode)),
-                    0o60..=0o67 => Instruction::Swap(inherent_register_operand(opcode)),
-                    0o70..=0o77 => Instruction::Srl(inherent_register_operand(opcode)),
-                    0o100..=0o177 => {
-                        Instruction::Bit((opcode - 0o100) >> 3, inherent_register_operand(opcode))
-                    }
-                    0o200..=0o277 => {
-                        Instruction::Res((opcode - 0o200) >> 3, inherent_register_operand(opcode))
-                    }
-                    0o300..=0o377 => {
-                        Instruction::Set((opcode - 0o300) >> 3, inherent_register_operand(opcode))
-                    }
-                }
-            }
-            0o306 => Instruction::Add(
-                Operand::Register(Register::A),
-                Operand::Immediate8(self.fetch_imm8()),
-            ),
-            0o316 => Instruction::Adc(Operand::Immediate8(self.fetch_imm8())),
-            0o326 => Instruction::Sub(Operand::Immediate8(self.fetch_imm8())),
-            0o331 => Instruction::Reti,
-            0o336 => Instruction::Sbc(Operand::Immediate8(self.fetch_imm8())),
-            0o340 => Instruction::Ld(
-                Operand::IndirectImmediate8(self.fetch_imm8()),
-                Operand::Register(Register::A),
-            ),
-            0o342 => Instruction::Ld(
-                Operand::Register(Register::IndirectC),
-                Operand::Register(Register::A),
-            ),
-            0o346 => Instruction::And(Operand::Immediate8(self.fetch_imm8())),
-            0o350 => Instruction::Add(
-                Operand::RegisterPair(RegisterPair::SP),
-                Operand::Immediate8(self.fetch_imm8()),
-            ),
-            0o352 => Instruction::Ld(
-                Operand::IndirectImmediate16(self.fetch_imm16()),
-                Operand::Register(Register::A),
-            ),
-            0o356 => Instruction::Xor(Operand::Immediate8(self.fetch_imm8())),
-            0o360 => Instruction::Ld(
-                Operand::Register(Register::A),
-                Operand::IndirectImmediate8(self.fetch_imm8()),
-            ),
-            0o362 => Instruction::Ld(
-                Operand::Register(Register::A),
-                Operand::Register(Register::IndirectC),
-            ),
-            0o363 => Instruction::Di,
-            0o366 => Instruction::Or(Operand::Immediate8(self.fetch_imm8())),
-            0o370 => Instruction::Ld(
-                Operand::RegisterPair(RegisterPair::HL),
-                Operand::StackOffset(self.fetch_imm8() as i8),
-            ),
-            0o371 => Instruction::Ld(
-                Operand::RegisterPair(RegisterPair::SP),
-                Operand::RegisterPair(RegisterPair::HL),
-            ),
-            0o372 => Instruction::Ld(
-                Operand::Register(Register::A),
-                Operand::IndirectImmediate16(self.fetch_imm16()),
-            ),
-            0o373 => Instruction::Ei,
-            0o376 => Instruction::Cp(Operand::Immediate8(self.fetch_imm8())),
-            0o307 | 0o317 | 0o327 | 0o337 | 0o347 | 0o357 | 0o367 | 0o377 => {
-                Instruction::Rst(((opcode & 0o70) >> 3) * 8)
-            }
-            _ => {
-                panic!(
-                    "Unhandled opcode 0x{:02X} at 0x{:04X}",
-                    opcode, self.registers.pc
-                );
-            }
-        }
+        decode(opcode, &mut || self.fetch_imm8())
     }
 
     #[allow(clippy::too_many_lines)]
     pub fn execute(&mut self, instruction: Instruction) {
+        // EI takes effect once the following instruction has started
         if self.ime_delayed {
             self.ime = true;
             self.ime_delayed = false;
@@ -516,33 +643,22 @@ impl Cpu {
                     .bus
                     .write_byte(self.get_register_pair(&RegisterPair::HL), value),
                 (Operand::Register(Register::DecrementHL), Operand::Register(source)) => {
-                    self.bus.write_byte(
-                        self.get_register_pair(&RegisterPair::HL),
-                        self.registers[&source],
-                    );
-                    self.set_register_pair(
-                        &RegisterPair::HL,
-                        self.get_register_pair(&RegisterPair::HL).wrapping_sub(1),
-                    );
+                    let hl = self.get_register_pair(&RegisterPair::HL);
+                    self.bus.write_byte(hl, self.registers[&source]);
+                    self.bus.oam_bug(hl);
+                    self.set_register_pair(&RegisterPair::HL, hl.wrapping_sub(1));
                 }
                 (Operand::Register(Register::IncrementHL), Operand::Register(source)) => {
-                    self.bus.write_byte(
-                        self.get_register_pair(&RegisterPair::HL),
-                        self.registers[&source],
-                    );
-                    self.set_register_pair(
-                        &RegisterPair::HL,
-                        self.get_register_pair(&RegisterPair::HL).wrapping_add(1),
-                    );
+                    let hl = self.get_register_pair(&RegisterPair::HL);
+                    self.bus.write_byte(hl, self.registers[&source]);
+                    self.bus.oam_bug(hl);
+                    self.set_register_pair(&RegisterPair::HL, hl.wrapping_add(1));
                 }
                 (Operand::Register(source), Operand::Register(Register::IncrementHL)) => {
-                    self.registers[&source] = self
-                        .bus
-                        .read_byte(self.get_register_pair(&RegisterPair::HL));
-                    self.set_register_pair(
-                        &RegisterPair::HL,
-                        self.get_register_pair(&RegisterPair::HL).wrapping_add(1),
-                    );
+                    let hl = self.get_register_pair(&RegisterPair::HL);
+                    self.registers[&source] = self.bus.read_byte(hl);
+                    self.bus.oam_bug(hl);
+                    self.set_register_pair(&RegisterPair::HL, hl.wrapping_add(1));
                 }
                 (Operand::Register(Register::IndirectC), Operand::Register(Register::A)) => {
                     self.bus.write_byte(
@@ -584,6 +700,7 @@ impl Cpu {
                 (Operand::Register(target), Operand::Register(Register::DecrementHL)) => {
                     let value = self.get_register_pair(&RegisterPair::HL);
                     self.registers[&target] = self.bus.read_byte(value);
+                    self.bus.oam_bug(value);
                     let result = value.overflowing_sub(1);
                     self.set_register_pair(&RegisterPair::HL, result.0);
                 }
@@ -597,21 +714,18 @@ impl Cpu {
                     self.set_register_pair(&target, value);
                 }
                 (Operand::RegisterPair(target), Operand::RegisterPair(source)) => {
+                    self.bus.tick();
                     self.set_register_pair(&target, self.get_register_pair(&source));
                 }
                 (Operand::RegisterPair(_), Operand::StackOffset(value)) => {
-                    let result = self
-                        .get_register_pair(&RegisterPair::SP)
-                        .overflowing_add(value as u16);
+                    self.bus.tick();
+                    let sp = self.registers.sp;
+                    let value = value as u16;
                     self.flags.z = false;
                     self.flags.n = false;
-                    self.flags.h = (self.get_register_pair(&RegisterPair::SP) & 0x0F)
-                        + (value as u16 & 0x0F)
-                        > 0x0F;
-                    self.flags.c = (self.get_register_pair(&RegisterPair::SP) & 0xFF)
-                        + (value as u16 & 0xFF)
-                        > 0xFF;
-                    self.set_register_pair(&RegisterPair::HL, result.0);
+                    self.flags.h = (sp & 0x0F) + (value & 0x0F) > 0x0F;
+                    self.flags.c = (sp & 0xFF) + (value & 0xFF) > 0xFF;
+                    self.set_register_pair(&RegisterPair::HL, sp.wrapping_add(value));
                 }
                 _ => panic!("Illegal operand for LD"),
             },
@@ -636,27 +750,24 @@ impl Cpu {
                 }
                 Operand::RegisterPair(rp) => match source {
                     Operand::RegisterPair(source) => {
-                        let result = self
-                            .get_register_pair(&rp)
-                            .overflowing_add(self.get_register_pair(&source));
+                        self.bus.tick();
+                        let (target, source) =
+                            (self.get_register_pair(&rp), self.get_register_pair(&source));
+                        let (result, carry) = target.overflowing_add(source);
                         self.flags.n = false;
-                        self.flags.h = (self.get_register_pair(&rp) & 0x0FFF)
-                            + (self.get_register_pair(&source) & 0x0FFF)
-                            > 0x0FFF;
-                        self.flags.c = result.1;
-                        self.set_register_pair(&rp, result.0);
+                        self.flags.h = (target & 0x0FFF) + (source & 0x0FFF) > 0x0FFF;
+                        self.flags.c = carry;
+                        self.set_register_pair(&rp, result);
                     }
                     Operand::Immediate8(value) => {
-                        let result = self
-                            .get_register_pair(&rp)
-                            .overflowing_add((value as i8) as u16);
+                        self.bus.tick();
+                        self.bus.tick();
+                        let target = self.get_register_pair(&rp);
                         self.flags.z = false;
                         self.flags.n = false;
-                        self.flags.h =
-                            (self.get_register_pair(&rp) & 0x0F) + (u16::from(value) & 0x0F) > 0x0F;
-                        self.flags.c =
-                            (self.get_register_pair(&rp) & 0xFF) + (u16::from(value) & 0xFF) > 0xFF;
-                        self.set_register_pair(&rp, result.0);
+                        self.flags.h = (target & 0x0F) + (u16::from(value) & 0x0F) > 0x0F;
+                        self.flags.c = (target & 0xFF) + (u16::from(value) & 0xFF) > 0xFF;
+                        self.set_register_pair(&rp, target.wrapping_add((value as i8) as u16));
                     }
                     _ => panic!("Illegal operand for ADD"),
                 },
@@ -761,7 +872,9 @@ impl Cpu {
                 self.ime = false;
             }
             Instruction::Ei => {
-                self.ime_delayed = true; // TODO delay
+                // IME is set after the next instruction, so an interrupt can't be dispatched
+                // between EI and the instruction following it
+                self.ime_delayed = true;
             }
             Instruction::Bit(bit, register) => {
                 let value = match register {
@@ -799,6 +912,7 @@ impl Cpu {
                 _ => self.registers[&register] &= !(1 << bit),
             },
             Instruction::Push(rp) => {
+                self.bus.tick();
                 self.push(self.get_register_pair(&rp));
             }
             Instruction::Pop(rp) => {
@@ -806,6 +920,7 @@ impl Cpu {
                 self.set_register_pair(&rp, result);
             }
             Instruction::Rst(address) => {
+                self.bus.tick();
                 self.push(self.registers.pc);
                 self.registers.pc = u16::from(address);
             }
@@ -817,6 +932,7 @@ impl Cpu {
                     Condition::Zero => self.flags.z,
                     Condition::NonZero => !self.flags.z,
                 } {
+                    self.bus.tick();
                     self.push(self.registers.pc);
                     self.registers.pc = address;
                 }
@@ -833,7 +949,10 @@ impl Cpu {
                         Operand::RegisterPair(RegisterPair::HL) => {
                             self.registers.pc = self.get_register_pair(&RegisterPair::HL);
                         }
-                        Operand::Immediate16(address) => self.registers.pc = address,
+                        Operand::Immediate16(address) => {
+                            self.bus.tick();
+                            self.registers.pc = address;
+                        }
                         _ => panic!("Illegal operand"),
                     }
                 }
@@ -846,10 +965,15 @@ impl Cpu {
                     Condition::Zero => self.flags.z,
                     Condition::NonZero => !self.flags.z,
                 } {
+                    self.bus.tick();
                     self.registers.pc = self.registers.pc.wrapping_add(offset as u16);
                 }
             }
             Instruction::Ret(condition) => {
+                // Conditional returns spend a cycle checking the condition
+                if !matches!(condition, Condition::Always) {
+                    self.bus.tick();
+                }
                 if match condition {
                     Condition::Always => true,
                     Condition::Carry => self.flags.c,
@@ -858,16 +982,21 @@ impl Cpu {
                     Condition::NonZero => !self.flags.z,
                 } {
                     self.registers.pc = self.pop();
+                    self.bus.tick();
                 }
             }
             Instruction::Reti => {
                 self.registers.pc = self.pop();
+                self.bus.tick();
                 self.ime = true;
             }
             Instruction::Inc(operand) => {
                 match operand {
                     Operand::RegisterPair(rp) => {
-                        self.set_register_pair(&rp, self.get_register_pair(&rp).wrapping_add(1));
+                        self.bus.tick();
+                        let value = self.get_register_pair(&rp);
+                        self.bus.oam_bug(value);
+                        self.set_register_pair(&rp, value.wrapping_add(1));
                     }
                     Operand::Register(register) => {
                         let (value, result) = if let Register::IndirectHL = register {
@@ -894,7 +1023,10 @@ impl Cpu {
             Instruction::Dec(operand) => {
                 match operand {
                     Operand::RegisterPair(rp) => {
-                        self.set_register_pair(&rp, self.get_register_pair(&rp).wrapping_sub(1));
+                        self.bus.tick();
+                        let value = self.get_register_pair(&rp);
+                        self.bus.oam_bug(value);
+                        self.set_register_pair(&rp, value.wrapping_sub(1));
                     }
                     Operand::Register(register) => {
                         let result = if let Register::IndirectHL = register {
@@ -920,15 +1052,10 @@ impl Cpu {
             }
             Instruction::Rl(register) => {
                 let result = if let Register::IndirectHL = register {
-                    let result = (
-                        self.bus
-                            .read_byte(self.get_register_pair(&RegisterPair::HL))
-                            << 1,
-                        self.bus
-                            .read_byte(self.get_register_pair(&RegisterPair::HL))
-                            & 0x80
-                            != 0,
-                    );
+                    let value = self
+                        .bus
+                        .read_byte(self.get_register_pair(&RegisterPair::HL));
+                    let result = (value << 1, value & 0x80 != 0);
                     self.bus.write_byte(
                         self.get_register_pair(&RegisterPair::HL),
                         result.0 | u8::from(self.flags.c),
@@ -949,15 +1076,10 @@ impl Cpu {
             }
             Instruction::Rr(register) => {
                 let result = if let Register::IndirectHL = register {
-                    let result = (
-                        self.bus
-                            .read_byte(self.get_register_pair(&RegisterPair::HL))
-                            >> 1,
-                        self.bus
-                            .read_byte(self.get_register_pair(&RegisterPair::HL))
-                            & 0x01
-                            != 0,
-                    );
+                    let value = self
+                        .bus
+                        .read_byte(self.get_register_pair(&RegisterPair::HL));
+                    let result = (value >> 1, value & 0x01 != 0);
                     self.bus.write_byte(
                         self.get_register_pair(&RegisterPair::HL),
                         result.0 | if self.flags.c { 0x80 } else { 0 },
@@ -1047,15 +1169,10 @@ impl Cpu {
             }
             Instruction::Rrc(register) => {
                 let result = if let Register::IndirectHL = register {
-                    let result = (
-                        self.bus
-                            .read_byte(self.get_register_pair(&RegisterPair::HL))
-                            >> 1,
-                        self.bus
-                            .read_byte(self.get_register_pair(&RegisterPair::HL))
-                            & 0x01
-                            != 0,
-                    );
+                    let value = self
+                        .bus
+                        .read_byte(self.get_register_pair(&RegisterPair::HL));
+                    let result = (value >> 1, value & 0x01 != 0);
                     self.bus.write_byte(
                         self.get_register_pair(&RegisterPair::HL),
                         result.0 | if result.1 { 0x80 } else { 0 },
@@ -1132,15 +1249,10 @@ impl Cpu {
             }
             Instruction::Srl(register) => {
                 let result = if let Register::IndirectHL = register {
-                    let result = (
-                        self.bus
-                            .read_byte(self.get_register_pair(&RegisterPair::HL))
-                            >> 1,
-                        self.bus
-                            .read_byte(self.get_register_pair(&RegisterPair::HL))
-                            & 0x01
-                            != 0,
-                    );
+                    let value = self
+                        .bus
+                        .read_byte(self.get_register_pair(&RegisterPair::HL));
+                    let result = (value >> 1, value & 0x01 != 0);
                     self.bus
                         .write_byte(self.get_register_pair(&RegisterPair::HL), result.0);
                     result
@@ -1191,49 +1303,474 @@ impl Cpu {
                 self.flags.h = (self.registers.a & 0x0F) < (value & 0x0F);
                 self.flags.c = result.1;
             }
+            Instruction::Daa => {
+                // Adjusts A to be valid BCD after an addition or subtraction of BCD numbers,
+                // using N, H and C to tell which operation it was and which digits overflowed
+                let mut correction = 0;
+                if self.flags.h || (!self.flags.n && self.registers.a & 0x0F > 0x09) {
+                    correction |= 0x06;
+                }
+                if self.flags.c || (!self.flags.n && self.registers.a > 0x99) {
+                    correction |= 0x60;
+                    self.flags.c = true;
+                }
+                self.registers.a = if self.flags.n {
+                    self.registers.a.wrapping_sub(correction)
+                } else {
+                    self.registers.a.wrapping_add(correction)
+                };
+                self.flags.z = self.registers.a == 0;
+                self.flags.h = false;
+            }
             Instruction::Cpl => {
                 self.registers.a = !self.registers.a;
                 self.flags.n = true;
                 self.flags.h = true;
             }
             Instruction::Halt => {
-                self.halted = true;
+                if !self.ime
+                    && self.bus.get_interrupt_enable() & self.bus.get_interrupt_flags() & 0x1F != 0
+                {
+                    self.halt_bug = true;
+                } else {
+                    self.halted = true;
+                }
             }
             Instruction::Stop => {
-                if self.bus.get_interrupt_enable() & self.bus.get_interrupt_flags() != 0 {
-                    let _ = self.fetch();
-                    self.halted = true; // TODO
+                // STOP is followed by a byte that's skipped, unless an interrupt is pending
+                let interrupt_pending =
+                    self.bus.get_interrupt_enable() & self.bus.get_interrupt_flags() & 0x1F != 0;
+                if !interrupt_pending {
+                    let _ = self.fetch_imm8();
+                }
+                if self.joypad_line_low() {
+                    // With a button held, STOP enters HALT mode instead, or does nothing if an
+                    // interrupt is pending. DIV isn't reset and the speed doesn't switch.
+                    self.halted = !interrupt_pending;
+                } else {
+                    if self.bus.stop() {
+                        self.speed_switch_cycles = SPEED_SWITCH_CYCLES;
+                    }
+                    self.stopped = true;
                 }
             }
-            _ => panic!("Unhandled instruction {instruction:?}"),
+            Instruction::Illegal(_) => self.locked = true,
         }
 
-        // Check for pending interrupts
-        for i in 0..=4 {
-            if (1 << i) & self.bus.get_interrupt_enable() & self.bus.get_interrupt_flags() != 0 {
-                // Exit HALT state
-                self.halted = false;
+        if !self.stopped && !self.locked {
+            self.handle_interrupts();
+        }
+    }
 
-                // If IME, also service interrupt
-                if self.ime {
-                    // Two wait states (NOPs?)
-                    self.bus.tick();
-                    self.bus.tick();
+    /// Wakes the CPU from HALT if any enabled interrupt is requested, and if IME is set, calls
+    /// the handler for the highest priority one. Dispatching takes 5 M-cycles.
+    fn handle_interrupts(&mut self) {
+        if self.pending_interrupt().is_none() {
+            return;
+        }
 
-                    // Call interrupt handler
-                    self.push(self.registers.pc);
-                    self.registers.pc = 0x0040 + (i * 8);
-                    self.bus.tick();
+        self.halted = false;
+        if !self.ime {
+            return;
+        }
+        self.ime = false;
 
-                    // Disable interrupts
-                    self.ime = false;
-                    self.bus
-                        .set_interrupt_flags(self.bus.get_interrupt_flags() & !(1 << i));
-                    break;
-                } else {
-                    // HALT bug
-                }
-            }
+        // Two wait states
+        self.bus.tick();
+        self.bus.tick();
+
+        // The interrupt to dispatch is only chosen after the high byte of PC is pushed, so it
+        // can be a different one, or none if the push overwrote IE. Then the CPU jumps to
+        // 0x0000 instead.
+        let pc = self.registers.pc;
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.bus.write_byte(self.registers.sp, (pc >> 8) as u8);
+        let interrupt = self.pending_interrupt();
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.bus.write_byte(self.registers.sp, (pc & 0xFF) as u8);
+
+        self.registers.pc = interrupt.map_or(0x0000, Interrupt::vector);
+        if let Some(interrupt) = interrupt {
+            self.bus
+                .set_interrupt_flags(self.bus.get_interrupt_flags() & !interrupt.mask());
         }
+        self.bus.tick();
+    }
+
+    /// The highest priority interrupt that's both requested and enabled
+    fn pending_interrupt(&self) -> Option<Interrupt> {
+        let pending = self.bus.get_interrupt_enable() & self.bus.get_interrupt_flags() & 0x1F;
+        Interrupt::ALL
+            .into_iter()
+            .find(|interrupt| pending & interrupt.mask() != 0)
+    }
+}
+
+/// Decodes an opcode, reading any further bytes of the instruction with `next_byte`
+pub fn decode(opcode: u8, next_byte: &mut impl FnMut() -> u8) -> Instruction {
+    let Template {
+        instruction,
+        immediate,
+    } = TEMPLATES[usize::from(opcode)];
+    match immediate {
+        Immediate::None => instruction,
+        Immediate::Byte => instruction.with_immediate(u16::from(next_byte())),
+        Immediate::Word => {
+            instruction.with_immediate(u16::from_le_bytes([next_byte(), next_byte()]))
+        }
+        Immediate::Prefix => CB_TEMPLATES[usize::from(next_byte())],
+    }
+}
+
+/// The number of bytes in an instruction, from its opcode
+#[must_use]
+pub fn instruction_length(opcode: u8) -> u16 {
+    match TEMPLATES[usize::from(opcode)].immediate {
+        Immediate::None => 1,
+        Immediate::Byte | Immediate::Prefix => 2,
+        Immediate::Word => 3,
+    }
+}
+
+/// The bytes that follow an opcode
+#[derive(Clone, Copy)]
+enum Immediate {
+    None,
+    Byte,
+    Word,
+    /// The opcode is 0xCB, and the next byte is the opcode of a bit operation
+    Prefix,
+}
+
+/// An opcode's instruction, with any immediate operand as 0 until it's fetched
+#[derive(Clone, Copy)]
+struct Template {
+    instruction: Instruction,
+    immediate: Immediate,
+}
+
+impl Template {
+    const fn new(instruction: Instruction, immediate: Immediate) -> Self {
+        Self {
+            instruction,
+            immediate,
+        }
+    }
+}
+
+/// Every opcode decoded at compile time, so decoding at run time is a table lookup
+static TEMPLATES: [Template; 256] = {
+    let mut templates = [Template::new(Instruction::Nop, Immediate::None); 256];
+    let mut opcode = 0;
+    while opcode < 256 {
+        templates[opcode] = template(opcode as u8);
+        opcode += 1;
+    }
+    templates
+};
+
+/// The bit operations after the 0xCB prefix
+static CB_TEMPLATES: [Instruction; 256] = {
+    let mut templates = [Instruction::Nop; 256];
+    let mut opcode = 0;
+    while opcode < 256 {
+        templates[opcode] = cb_template(opcode as u8);
+        opcode += 1;
+    }
+    templates
+};
+
+#[allow(clippy::match_overlapping_arm)]
+const fn template(opcode: u8) -> Template {
+    match opcode {
+        0o00 => Template::new(Instruction::Nop, Immediate::None),
+        0o01 | 0o21 | 0o41 | 0o61 => Template::new(
+            Instruction::Ld(
+                Operand::RegisterPair(inherent_registerpair_operand(opcode >> 3)),
+                Operand::Immediate16(0),
+            ),
+            Immediate::Word,
+        ),
+        0o07 => Template::new(Instruction::Rlca, Immediate::None),
+        0o17 => Template::new(Instruction::Rrca, Immediate::None),
+        0o27 => Template::new(Instruction::Rla, Immediate::None),
+        0o37 => Template::new(Instruction::Rra, Immediate::None),
+        0o47 => Template::new(Instruction::Daa, Immediate::None),
+        0o57 => Template::new(Instruction::Cpl, Immediate::None),
+        0o67 => Template::new(Instruction::Scf, Immediate::None),
+        0o77 => Template::new(Instruction::Ccf, Immediate::None),
+        0o11 | 0o31 | 0o51 | 0o71 => Template::new(
+            Instruction::Add(
+                Operand::RegisterPair(RegisterPair::HL),
+                Operand::RegisterPair(inherent_registerpair_operand(opcode >> 3)),
+            ),
+            Immediate::None,
+        ),
+        0o10 => Template::new(
+            Instruction::Ld(
+                Operand::IndirectImmediate16(0),
+                Operand::RegisterPair(RegisterPair::SP),
+            ),
+            Immediate::Word,
+        ),
+        0o02 | 0o22 => Template::new(
+            Instruction::Ld(
+                Operand::RegisterIndirect(inherent_registerpair_operand(opcode >> 3)),
+                Operand::Register(Register::A),
+            ),
+            Immediate::None,
+        ),
+        0o12 | 0o32 => Template::new(
+            Instruction::Ld(
+                Operand::Register(Register::A),
+                Operand::RegisterIndirect(inherent_registerpair_operand(opcode >> 3)),
+            ),
+            Immediate::None,
+        ),
+        0o42 => Template::new(
+            Instruction::Ld(
+                Operand::Register(Register::IncrementHL),
+                Operand::Register(Register::A),
+            ),
+            Immediate::None,
+        ),
+        0o52 => Template::new(
+            Instruction::Ld(
+                Operand::Register(Register::A),
+                Operand::Register(Register::IncrementHL),
+            ),
+            Immediate::None,
+        ),
+        0o62 => Template::new(
+            Instruction::Ld(
+                Operand::Register(Register::DecrementHL),
+                Operand::Register(Register::A),
+            ),
+            Immediate::None,
+        ),
+        0o72 => Template::new(
+            Instruction::Ld(
+                Operand::Register(Register::A),
+                Operand::Register(Register::DecrementHL),
+            ),
+            Immediate::None,
+        ),
+        0o20 => Template::new(Instruction::Stop, Immediate::None),
+        0o30 => Template::new(Instruction::Jr(Condition::Always, 0), Immediate::Byte),
+        0o40 | 0o50 | 0o60 | 0o70 => Template::new(
+            Instruction::Jr(inherent_condition_operand((opcode - 0o40) >> 3), 0),
+            Immediate::Byte,
+        ),
+        0o03 | 0o23 | 0o43 | 0o63 => Template::new(
+            Instruction::Inc(Operand::RegisterPair(inherent_registerpair_operand(
+                opcode >> 3,
+            ))),
+            Immediate::None,
+        ),
+        0o13 | 0o33 | 0o53 | 0o73 => Template::new(
+            Instruction::Dec(Operand::RegisterPair(inherent_registerpair_operand(
+                opcode >> 3,
+            ))),
+            Immediate::None,
+        ),
+        0o04 | 0o14 | 0o24 | 0o34 | 0o44 | 0o54 | 0o64 | 0o74 => Template::new(
+            Instruction::Inc(Operand::Register(inherent_register_operand(
+                (opcode & 0o70) >> 3,
+            ))),
+            Immediate::None,
+        ),
+        0o05 | 0o15 | 0o25 | 0o35 | 0o45 | 0o55 | 0o65 | 0o75 => Template::new(
+            Instruction::Dec(Operand::Register(inherent_register_operand(
+                (opcode & 0o70) >> 3,
+            ))),
+            Immediate::None,
+        ),
+        0o06 | 0o16 | 0o26 | 0o36 | 0o46 | 0o56 | 0o66 | 0o76 => Template::new(
+            Instruction::Ld(
+                Operand::Register(inherent_register_operand((opcode & 0o70) >> 3)),
+                Operand::Immediate8(0),
+            ),
+            Immediate::Byte,
+        ),
+        0o166 => Template::new(Instruction::Halt, Immediate::None),
+        0o100..=0o177 => Template::new(
+            Instruction::Ld(
+                Operand::Register(inherent_register_operand(opcode >> 3)),
+                Operand::Register(inherent_register_operand(opcode)),
+            ),
+            Immediate::None,
+        ),
+        0o200..=0o207 => Template::new(
+            Instruction::Add(
+                Operand::Register(Register::A),
+                Operand::Register(inherent_register_operand(opcode)),
+            ),
+            Immediate::None,
+        ),
+        0o210..=0o217 => Template::new(
+            Instruction::Adc(Operand::Register(inherent_register_operand(opcode))),
+            Immediate::None,
+        ),
+        0o220..=0o227 => Template::new(
+            Instruction::Sub(Operand::Register(inherent_register_operand(opcode))),
+            Immediate::None,
+        ),
+        0o230..=0o237 => Template::new(
+            Instruction::Sbc(Operand::Register(inherent_register_operand(opcode))),
+            Immediate::None,
+        ),
+        0o240..=0o247 => Template::new(
+            Instruction::And(Operand::Register(inherent_register_operand(opcode))),
+            Immediate::None,
+        ),
+        0o250..=0o257 => Template::new(
+            Instruction::Xor(Operand::Register(inherent_register_operand(opcode))),
+            Immediate::None,
+        ),
+        0o260..=0o267 => Template::new(
+            Instruction::Or(Operand::Register(inherent_register_operand(opcode))),
+            Immediate::None,
+        ),
+        0o270..=0o277 => Template::new(
+            Instruction::Cp(Operand::Register(inherent_register_operand(opcode))),
+            Immediate::None,
+        ),
+        0o300 | 0o310 | 0o320 | 0o330 => Template::new(
+            Instruction::Ret(inherent_condition_operand(opcode >> 3)),
+            Immediate::None,
+        ),
+        0o301 | 0o321 | 0o341 => Template::new(
+            Instruction::Pop(inherent_registerpair_operand((opcode & 0o70) >> 3)),
+            Immediate::None,
+        ),
+        0o305 | 0o325 | 0o345 => Template::new(
+            Instruction::Push(inherent_registerpair_operand((opcode & 0o70) >> 3)),
+            Immediate::None,
+        ),
+        0o361 => Template::new(Instruction::Pop(RegisterPair::AF), Immediate::None),
+        0o365 => Template::new(Instruction::Push(RegisterPair::AF), Immediate::None),
+        0o311 => Template::new(Instruction::Ret(Condition::Always), Immediate::None),
+        0o303 => Template::new(
+            Instruction::Jp(Condition::Always, Operand::Immediate16(0)),
+            Immediate::Word,
+        ),
+        0o351 => Template::new(
+            Instruction::Jp(Condition::Always, Operand::RegisterPair(RegisterPair::HL)),
+            Immediate::None,
+        ),
+        0o302 | 0o312 | 0o322 | 0o332 => Template::new(
+            Instruction::Jp(
+                inherent_condition_operand(opcode >> 3),
+                Operand::Immediate16(0),
+            ),
+            Immediate::Word,
+        ),
+        0o304 | 0o314 | 0o324 | 0o334 => Template::new(
+            Instruction::Call(inherent_condition_operand(opcode >> 3), 0),
+            Immediate::Word,
+        ),
+        0o315 => Template::new(Instruction::Call(Condition::Always, 0), Immediate::Word),
+        0o313 => Template {
+            instruction: Instruction::Nop,
+            immediate: Immediate::Prefix,
+        },
+        0o306 => Template::new(
+            Instruction::Add(Operand::Register(Register::A), Operand::Immediate8(0)),
+            Immediate::Byte,
+        ),
+        0o316 => Template::new(Instruction::Adc(Operand::Immediate8(0)), Immediate::Byte),
+        0o326 => Template::new(Instruction::Sub(Operand::Immediate8(0)), Immediate::Byte),
+        0o331 => Template::new(Instruction::Reti, Immediate::None),
+        0o336 => Template::new(Instruction::Sbc(Operand::Immediate8(0)), Immediate::Byte),
+        0o340 => Template::new(
+            Instruction::Ld(
+                Operand::IndirectImmediate8(0),
+                Operand::Register(Register::A),
+            ),
+            Immediate::Byte,
+        ),
+        0o342 => Template::new(
+            Instruction::Ld(
+                Operand::Register(Register::IndirectC),
+                Operand::Register(Register::A),
+            ),
+            Immediate::None,
+        ),
+        0o346 => Template::new(Instruction::And(Operand::Immediate8(0)), Immediate::Byte),
+        0o350 => Template::new(
+            Instruction::Add(
+                Operand::RegisterPair(RegisterPair::SP),
+                Operand::Immediate8(0),
+            ),
+            Immediate::Byte,
+        ),
+        0o352 => Template::new(
+            Instruction::Ld(
+                Operand::IndirectImmediate16(0),
+                Operand::Register(Register::A),
+            ),
+            Immediate::Word,
+        ),
+        0o356 => Template::new(Instruction::Xor(Operand::Immediate8(0)), Immediate::Byte),
+        0o360 => Template::new(
+            Instruction::Ld(
+                Operand::Register(Register::A),
+                Operand::IndirectImmediate8(0),
+            ),
+            Immediate::Byte,
+        ),
+        0o362 => Template::new(
+            Instruction::Ld(
+                Operand::Register(Register::A),
+                Operand::Register(Register::IndirectC),
+            ),
+            Immediate::None,
+        ),
+        0o363 => Template::new(Instruction::Di, Immediate::None),
+        0o366 => Template::new(Instruction::Or(Operand::Immediate8(0)), Immediate::Byte),
+        0o370 => Template::new(
+            Instruction::Ld(
+                Operand::RegisterPair(RegisterPair::HL),
+                Operand::StackOffset(0),
+            ),
+            Immediate::Byte,
+        ),
+        0o371 => Template::new(
+            Instruction::Ld(
+                Operand::RegisterPair(RegisterPair::SP),
+                Operand::RegisterPair(RegisterPair::HL),
+            ),
+            Immediate::None,
+        ),
+        0o372 => Template::new(
+            Instruction::Ld(
+                Operand::Register(Register::A),
+                Operand::IndirectImmediate16(0),
+            ),
+            Immediate::Word,
+        ),
+        0o373 => Template::new(Instruction::Ei, Immediate::None),
+        0o376 => Template::new(Instruction::Cp(Operand::Immediate8(0)), Immediate::Byte),
+        0o307 | 0o317 | 0o327 | 0o337 | 0o347 | 0o357 | 0o367 | 0o377 => Template::new(
+            Instruction::Rst(((opcode & 0o70) >> 3) * 8),
+            Immediate::None,
+        ),
+        _ => Template::new(Instruction::Illegal(opcode), Immediate::None),
+    }
+}
+
+const fn cb_template(opcode: u8) -> Instruction {
+    match opcode {
+        0o00..=0o07 => Instruction::Rlc(inherent_register_operand(opcode)),
+        0o10..=0o17 => Instruction::Rrc(inherent_register_operand(opcode)),
+        0o20..=0o27 => Instruction::Rl(inherent_register_operand(opcode)),
+        0o30..=0o37 => Instruction::Rr(inherent_register_operand(opcode)),
+        0o40..=0o47 => Instruction::Sla(inherent_register_operand(opcode)),
+        0o50..=0o57 => Instruction::Sra(inherent_register_operand(opcode)),
+        0o60..=0o67 => Instruction::Swap(inherent_register_operand(opcode)),
+        0o70..=0o77 => Instruction::Srl(inherent_register_operand(opcode)),
+        0o100..=0o177 => Instruction::Bit((opcode - 0o100) >> 3, inherent_register_operand(opcode)),
+        0o200..=0o277 => Instruction::Res((opcode - 0o200) >> 3, inherent_register_operand(opcode)),
+        0o300..=0o377 => Instruction::Set((opcode - 0o300) >> 3, inherent_register_operand(opcode)),
     }
 }
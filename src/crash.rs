@@ -0,0 +1,74 @@
+use rgb_emu::bus::{Bus, DmgBus};
+use rgb_emu::cpu::Cpu;
+use rgb_emu::debug::TraceBuffer;
+use rgb_emu::disasm;
+use rgb_emu::state;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Number of instructions kept in the trace written to crash dumps
+pub const TRACE_LENGTH: usize = 1000;
+
+/// Writes a report with the CPU state, the instruction trace and a dump of the address space next
+/// to the ROM, along with a savestate that can be loaded in the debugger to inspect the failing
+/// situation after the emulator has crashed or the CPU has locked up.
+///
+/// Returns the paths of the report and the savestate.
+///
+/// # Errors
+///
+/// Will return `Err` if the files can't be written
+pub fn write_dump(
+    rom_path: &Path,
+    cpu: &Cpu<DmgBus>,
+    trace: &TraceBuffer,
+) -> std::io::Result<(PathBuf, PathBuf)> {
+    let report_path = rom_path.with_extension("crash.txt");
+    let state_path = rom_path.with_extension("crash.state");
+
+    let memory: Vec<u8> = (0..=0xFFFF)
+        .map(|address| cpu.bus.peek_byte(address))
+        .collect();
+
+    let mut report = String::new();
+    let _ = writeln!(report, "Crash dump for {}", rom_path.display());
+    let _ = writeln!(
+        report,
+        "\nRegisters:\nA:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} IME:{} HALT:{}",
+        cpu.registers.a,
+        u8::from(cpu.flags.z) << 7
+            | u8::from(cpu.flags.n) << 6
+            | u8::from(cpu.flags.h) << 5
+            | u8::from(cpu.flags.c) << 4,
+        cpu.registers.b,
+        cpu.registers.c,
+        cpu.registers.d,
+        cpu.registers.e,
+        cpu.registers.h,
+        cpu.registers.l,
+        cpu.registers.sp,
+        cpu.registers.pc,
+        u8::from(cpu.ime),
+        u8::from(cpu.halted),
+    );
+    let _ = writeln!(report, "\nNext instructions:");
+    for disassembly in disasm::disassemble_range(cpu.bus.as_ref(), cpu.registers.pc, 5) {
+        let _ = writeln!(report, "{disassembly}");
+    }
+    let _ = writeln!(report, "\nLast instructions (oldest first):");
+    for entry in trace.entries() {
+        let _ = writeln!(report, "{entry}");
+    }
+    let _ = writeln!(report, "\nMemory:");
+    for (row, bytes) in memory.chunks_exact(16).enumerate() {
+        let _ = write!(report, "{:04X}:", row * 16);
+        for byte in bytes {
+            let _ = write!(report, " {byte:02X}");
+        }
+        report.push('\n');
+    }
+
+    std::fs::write(&report_path, report)?;
+    std::fs::write(&state_path, state::save(cpu))?;
+    Ok((report_path, state_path))
+}
@@ -0,0 +1,371 @@
+//! Views of the emulator's internal state, for debuggers and other tools
+
+use crate::bus::Bus;
+use crate::cpu::{Cpu, RegisterPair};
+use crate::ppu::{Ppu, SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// An image of shades (0-3), one byte per pixel
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+}
+
+/// Renders all 384 tiles in VRAM, 16 tiles per row, using their raw color indices as shades. In
+/// CGB mode, the tiles in bank 1 are to the right of those in bank 0.
+///
+/// Each third of the rows is a block of 128 tiles. Objects use the first two blocks, and so do
+/// the background and window when LCDC bit 4 is set. Otherwise, they use the last two.
+#[must_use]
+pub fn tiles(ppu: &Ppu) -> Image {
+    let banks = if ppu.cgb { 2 } else { 1 };
+    let mut image = Image::new(banks * 16 * 8, 24 * 8);
+    for bank in 0..banks {
+        for tile in 0..384 {
+            let left = bank * 16 * 8 + (tile % 16) * 8;
+            for y in 0..8 {
+                for x in 0..8 {
+                    image.pixels[((tile / 16) * 8 + y) * image.width + left + x] =
+                        ppu.tile_pixel(bank * 0x2000 + tile * 16, x, y);
+                }
+            }
+        }
+    }
+    image
+}
+
+/// A rectangle of pixels in an [`Image`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Renders the full 256×256 background map currently selected by LCDC
+#[must_use]
+pub fn background_map(ppu: &Ppu) -> Image {
+    tile_map(ppu, if ppu.lcdc & 0x08 != 0 { 0x1C00 } else { 0x1800 })
+}
+
+/// Renders the full 256×256 window map currently selected by LCDC
+#[must_use]
+pub fn window_map(ppu: &Ppu) -> Image {
+    tile_map(ppu, if ppu.lcdc & 0x40 != 0 { 0x1C00 } else { 0x1800 })
+}
+
+/// The part of the background map that's on screen at the current SCX and SCY. The screen wraps
+/// around the edges of the map, so it can be split into up to four regions.
+#[must_use]
+pub fn viewport(ppu: &Ppu) -> Vec<Region> {
+    let split = |start: u8, length: usize| {
+        let start = usize::from(start);
+        if start + length <= 256 {
+            vec![(start, length)]
+        } else {
+            vec![(start, 256 - start), (0, start + length - 256)]
+        }
+    };
+    let mut regions = Vec::new();
+    for (y, height) in split(ppu.scy, SCREEN_HEIGHT) {
+        for &(x, width) in &split(ppu.scx, SCREEN_WIDTH) {
+            regions.push(Region {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
+    }
+    regions
+}
+
+/// The part of the window map that's on screen at the current WX and WY, or `None` if the window
+/// is disabled or entirely off screen
+#[must_use]
+pub fn window_viewport(ppu: &Ppu) -> Option<Region> {
+    let (wx, wy) = (usize::from(ppu.wx), usize::from(ppu.wy));
+    if ppu.lcdc & 0x20 == 0 || wx >= SCREEN_WIDTH + 7 || wy >= SCREEN_HEIGHT {
+        return None;
+    }
+    Some(Region {
+        x: 7usize.saturating_sub(wx),
+        y: 0,
+        width: SCREEN_WIDTH - wx.saturating_sub(7),
+        height: SCREEN_HEIGHT - wy,
+    })
+}
+
+/// Renders the 32×32 tile map at a VRAM offset, through BGP
+fn tile_map(ppu: &Ppu, map: usize) -> Image {
+    let mut image = Image::new(256, 256);
+    for y in 0..256 {
+        for x in 0..256 {
+            let tile_number = ppu.vram[map + (y / 8) * 32 + x / 8];
+            let color = ppu.tile_pixel(ppu.bg_tile_address(tile_number), x % 8, y % 8);
+            image.pixels[y * image.width + x] = (ppu.bgp >> (color * 2)) & 3;
+        }
+    }
+    image
+}
+
+/// Why a sprite isn't drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hidden {
+    /// Objects are disabled in LCDC
+    Disabled,
+    /// The sprite's position is outside the screen
+    OffScreen,
+    /// Ten sprites earlier in OAM are on each of the sprite's lines
+    LineLimit,
+}
+
+impl fmt::Display for Hidden {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Hidden::Disabled => "objects disabled",
+            Hidden::OffScreen => "off screen",
+            Hidden::LineLimit => "over 10 per line",
+        })
+    }
+}
+
+/// One of the 40 sprites in OAM. The position is as stored, so the sprite's top left corner is
+/// at (X - 8, Y - 16) on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OamEntry {
+    pub index: usize,
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub attributes: u8,
+    pub hidden: Option<Hidden>,
+}
+
+impl fmt::Display for OamEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:2}: X:{:02X} Y:{:02X} tile:{:02X} attributes:{:02X} (OBP{}",
+            self.index,
+            self.x,
+            self.y,
+            self.tile,
+            self.attributes,
+            (self.attributes >> 4) & 1
+        )?;
+        for (bit, flag) in [(5, "X flip"), (6, "Y flip"), (7, "behind BG")] {
+            if self.attributes & (1 << bit) != 0 {
+                write!(f, ", {flag}")?;
+            }
+        }
+        f.write_str(")")?;
+        match self.hidden {
+            Some(hidden) => write!(f, " hidden: {hidden}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Lists the 40 sprites in OAM, with the reason each hidden one isn't drawn
+#[must_use]
+pub fn oam_entries(ppu: &Ppu) -> Vec<OamEntry> {
+    let height = if ppu.lcdc & 0x04 != 0 { 16 } else { 8 };
+    let mut entries: Vec<OamEntry> = ppu
+        .oam
+        .chunks_exact(4)
+        .enumerate()
+        .map(|(index, sprite)| OamEntry {
+            index,
+            y: sprite[0],
+            x: sprite[1],
+            tile: sprite[2],
+            attributes: sprite[3],
+            hidden: Some(Hidden::LineLimit),
+        })
+        .collect();
+    // The PPU picks the first ten sprites on each line regardless of X, so a sprite is only
+    // drawn if it's among them on at least one of its lines
+    for line in 0..SCREEN_HEIGHT as i32 {
+        entries
+            .iter_mut()
+            .filter(|entry| (0..height).contains(&(line + 16 - i32::from(entry.y))))
+            .take(10)
+            .for_each(|entry| entry.hidden = None);
+    }
+    for entry in &mut entries {
+        let top = i32::from(entry.y) - 16;
+        if ppu.lcdc & 0x02 == 0 {
+            entry.hidden = Some(Hidden::Disabled);
+        } else if top + height <= 0
+            || top >= SCREEN_HEIGHT as i32
+            || entry.x == 0
+            || usize::from(entry.x) >= SCREEN_WIDTH + 8
+        {
+            entry.hidden = Some(Hidden::OffScreen);
+        }
+    }
+    entries
+}
+
+/// Renders the 40 sprites in OAM order, 10 per row, each in an 8×16 cell and flipped like on
+/// screen
+#[must_use]
+pub fn sprites(ppu: &Ppu) -> Image {
+    let mut image = Image::new(10 * 8, 4 * 16);
+    let height = if ppu.lcdc & 0x04 != 0 { 16 } else { 8 };
+    for (index, sprite) in ppu.oam.chunks_exact(4).enumerate() {
+        let tile_number = if height == 16 {
+            sprite[2] & 0xFE
+        } else {
+            sprite[2]
+        };
+        let palette = if sprite[3] & 0x10 != 0 {
+            ppu.obp1
+        } else {
+            ppu.obp0
+        };
+        let flip_x = sprite[3] & 0x20 != 0;
+        let flip_y = sprite[3] & 0x40 != 0;
+        for y in 0..height {
+            for x in 0..8 {
+                let color = ppu.tile_pixel(
+                    usize::from(tile_number) * 16,
+                    if flip_x { 7 - x } else { x },
+                    if flip_y { height - 1 - y } else { y },
+                );
+                image.pixels[((index / 10) * 16 + y) * image.width + (index % 10) * 8 + x] =
+                    (palette >> (color * 2)) & 3;
+            }
+        }
+    }
+    image
+}
+
+/// Returns the shades of the BGP, OBP0 and OBP1 palettes
+#[must_use]
+pub fn palettes(ppu: &Ppu) -> [[u8; 4]; 3] {
+    [ppu.bgp, ppu.obp0, ppu.obp1]
+        .map(|palette| [0, 1, 2, 3].map(|color| (palette >> (color * 2)) & 3))
+}
+
+/// Formats the CPU state before the instruction at PC is executed as a line in the format used by
+/// Gameboy Doctor (<https://github.com/robert/gameboy-doctor>)
+#[must_use]
+pub fn doctor_line<B: Bus + ?Sized>(cpu: &Cpu<B>) -> String {
+    let pc = cpu.registers.pc;
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+        cpu.registers.a,
+        cpu.get_register_pair(&RegisterPair::AF) & 0xFF,
+        cpu.registers.b,
+        cpu.registers.c,
+        cpu.registers.d,
+        cpu.registers.e,
+        cpu.registers.h,
+        cpu.registers.l,
+        cpu.registers.sp,
+        pc,
+        cpu.bus.peek_byte(pc),
+        cpu.bus.peek_byte(pc.wrapping_add(1)),
+        cpu.bus.peek_byte(pc.wrapping_add(2)),
+        cpu.bus.peek_byte(pc.wrapping_add(3)),
+    )
+}
+
+/// Formats memory as a hex dump of 16 bytes per line, each with the address of its first byte
+/// and the bytes as ASCII, where `bytes` starts at `start`
+#[must_use]
+pub fn hexdump(start: u16, bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (row, chunk) in bytes.chunks(16).enumerate() {
+        let address = start.wrapping_add((row * 16) as u16);
+        let hex: Vec<_> = chunk.iter().map(|byte| format!("{byte:02X}")).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    char::from(byte)
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        dump.push_str(&format!(
+            "{address:04X}: {:<47}  |{ascii}|\n",
+            hex.join(" ")
+        ));
+    }
+    dump
+}
+
+/// CPU state right before an instruction was executed
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u8,
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PC:{:04X} OP:{:02X} AF:{:04X} BC:{:04X} DE:{:04X} HL:{:04X} SP:{:04X}",
+            self.pc, self.opcode, self.af, self.bc, self.de, self.hl, self.sp
+        )
+    }
+}
+
+/// Ring buffer of the most recently executed instructions
+pub struct TraceBuffer {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl TraceBuffer {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Records the CPU state before the instruction at PC is executed
+    pub fn record<B: Bus + ?Sized>(&mut self, cpu: &Cpu<B>) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(TraceEntry {
+            pc: cpu.registers.pc,
+            opcode: cpu.bus.peek_byte(cpu.registers.pc),
+            af: cpu.get_register_pair(&RegisterPair::AF),
+            bc: cpu.get_register_pair(&RegisterPair::BC),
+            de: cpu.get_register_pair(&RegisterPair::DE),
+            hl: cpu.get_register_pair(&RegisterPair::HL),
+            sp: cpu.get_register_pair(&RegisterPair::SP),
+        });
+    }
+
+    /// Returns the recorded entries, oldest first
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+}
@@ -0,0 +1,321 @@
+//! Interactive debugger on the terminal, which is entered with the emulator paused at a prompt
+
+use crate::Tools;
+use rgb_emu::bus::{Bus, DmgBus};
+use rgb_emu::cpu::{Breakpoint, Cpu, RegisterPair};
+use rgb_emu::debug;
+use rgb_emu::disasm::{self, Disassembly};
+use rgb_emu::symbols::Symbols;
+use rgb_emu::watchpoints::{WatchKind, Watchpoint};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+Commands:
+  s, step [N]          Execute N instructions (default 1)
+  c, continue          Resume running until a breakpoint is hit
+  b, break [ADDRESS]   Set a breakpoint, or list breakpoints
+  d, delete ADDRESS    Delete a breakpoint
+  w, watch [ADDRESS [VALUE]]
+                       Break when ADDRESS is written, optionally only with VALUE,
+                       or list watchpoints
+  rwatch ADDRESS [VALUE]
+                       Break when ADDRESS is read
+  awatch ADDRESS [VALUE]
+                       Break when ADDRESS is read or written
+  unwatch ADDRESS      Delete the watchpoints on ADDRESS
+  r, regs              Show registers
+  x ADDRESS [LENGTH], x/LENGTH ADDRESS
+                       Show a hex dump of memory (default 16 bytes)
+  l, list [N]          Disassemble around PC (default 5 instructions ahead)
+  oam                  List the sprites in OAM, and why any of them are hidden
+  q, quit              Quit
+Addresses are hexadecimal, optionally prefixed with $ or 0x, or labels from the .sym file.
+Breakpoints can be limited to one ROM bank as BANK:ADDRESS, which labels in banked ROM are.
+An empty line repeats the last command.";
+
+/// Runs the debugger prompt. Returns `true` if emulation should continue, or `false` if the
+/// debugger was quit or stdin was closed.
+pub fn run(cpu: &mut Cpu<DmgBus>, tools: &mut Tools) -> bool {
+    let mut last_command = String::from("step");
+    if !report_hits(cpu, &tools.symbols) {
+        println!("Type \"help\" for a list of commands");
+    }
+    print_location(cpu, &tools.symbols);
+
+    let stdin = io::stdin();
+    loop {
+        print!("(rgb) ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return false;
+        }
+        let line = match line.trim() {
+            "" => last_command.clone(),
+            line => line.to_string(),
+        };
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            continue;
+        };
+        // x/LENGTH is the same as x with LENGTH after the address
+        let (command, suffix) = command.split_once('/').unwrap_or((command, ""));
+        let argument = words.next();
+        let symbols = &tools.symbols;
+
+        match command {
+            "s" | "step" => {
+                let count = argument.map_or(Some(1), |count| count.parse().ok());
+                match count {
+                    Some(count) => {
+                        for _ in 0..count {
+                            crate::step(cpu, tools);
+                            if cpu.should_pause() {
+                                break;
+                            }
+                        }
+                        report_hits(cpu, &tools.symbols);
+                        print_location(cpu, &tools.symbols);
+                    }
+                    None => println!("Invalid count"),
+                }
+            }
+            "c" | "continue" => return true,
+            "b" | "break" => match argument {
+                Some(argument) => match parse_breakpoint(argument, symbols) {
+                    Some(breakpoint) => {
+                        cpu.breakpoints.insert(breakpoint);
+                        println!("Breakpoint at {}", describe(&breakpoint, symbols));
+                    }
+                    None => println!("Unknown address {argument}"),
+                },
+                None => {
+                    for breakpoint in &cpu.breakpoints {
+                        println!("{}", describe(breakpoint, symbols));
+                    }
+                }
+            },
+            "d" | "delete" => {
+                match argument.and_then(|argument| parse_breakpoint(argument, symbols)) {
+                    Some(breakpoint) if cpu.breakpoints.remove(&breakpoint) => {
+                        println!("Deleted breakpoint at {}", describe(&breakpoint, symbols));
+                    }
+                    _ => println!("No such breakpoint"),
+                }
+            }
+            "w" | "watch" | "rwatch" | "awatch" => {
+                let kind = match command {
+                    "rwatch" => WatchKind::Read,
+                    "awatch" => WatchKind::Access,
+                    _ => WatchKind::Write,
+                };
+                let watchpoint = argument
+                    .and_then(|argument| parse_address(argument, symbols))
+                    .map(|address| Watchpoint {
+                        address,
+                        kind,
+                        value: words.next().and_then(parse_hex),
+                    });
+                match (cpu.bus.watchpoints_mut(), watchpoint) {
+                    (None, _) => println!("Watchpoints aren't supported"),
+                    (Some(watchpoints), Some(watchpoint)) => {
+                        watchpoints.insert(watchpoint);
+                        println!("Watchpoint {}", describe_watchpoint(&watchpoint));
+                    }
+                    (Some(watchpoints), None) if argument.is_none() => {
+                        for watchpoint in watchpoints.iter() {
+                            println!("{}", describe_watchpoint(watchpoint));
+                        }
+                    }
+                    (Some(_), None) => println!("Usage: {command} [ADDRESS [VALUE]]"),
+                }
+            }
+            "unwatch" => {
+                let address = argument.and_then(|argument| parse_address(argument, symbols));
+                let removed = address
+                    .zip(cpu.bus.watchpoints_mut())
+                    .is_some_and(|(address, watchpoints)| watchpoints.remove(address));
+                match address {
+                    Some(address) if removed => println!("Deleted watchpoints on ${address:04X}"),
+                    _ => println!("No such watchpoint"),
+                }
+            }
+            "r" | "regs" => print_registers(cpu),
+            "x" => match argument.and_then(|argument| parse_address(argument, symbols)) {
+                Some(address) => {
+                    let length = Some(suffix)
+                        .filter(|suffix| !suffix.is_empty())
+                        .or_else(|| words.next())
+                        .and_then(|length| length.parse().ok())
+                        .unwrap_or(16);
+                    print!(
+                        "{}",
+                        debug::hexdump(address, &cpu.bus.peek_range(address, length))
+                    );
+                }
+                None => println!("Usage: x ADDRESS [LENGTH] or x/LENGTH ADDRESS"),
+            },
+            "l" | "list" => {
+                let count = argument.and_then(|count| count.parse().ok()).unwrap_or(5);
+                print_listing(cpu, tools, count);
+            }
+            "oam" => match cpu.bus.get_ppu() {
+                Some(ppu) => debug::oam_entries(ppu)
+                    .iter()
+                    .for_each(|entry| println!("{entry}")),
+                None => println!("No PPU"),
+            },
+            "q" | "quit" => return false,
+            "h" | "help" => println!("{HELP}"),
+            _ => println!("Unknown command {command}; type \"help\" for a list of commands"),
+        }
+        last_command = line;
+    }
+}
+
+fn parse_address(argument: &str, symbols: &Symbols) -> Option<u16> {
+    parse_hex(argument).or_else(|| symbols.address(argument).map(|(_, address)| address))
+}
+
+fn parse_hex<T: TryFrom<u32>>(argument: &str) -> Option<T> {
+    let hex = argument
+        .strip_prefix('$')
+        .or_else(|| argument.strip_prefix("0x"))
+        .unwrap_or(argument);
+    T::try_from(u32::from_str_radix(hex, 16).ok()?).ok()
+}
+
+/// Parses `ADDRESS` or `BANK:ADDRESS`. Labels in banked ROM only break in their own bank.
+fn parse_breakpoint(argument: &str, symbols: &Symbols) -> Option<Breakpoint> {
+    if let Some((bank, address)) = argument.split_once(':') {
+        return Some(Breakpoint {
+            address: parse_hex(address)?,
+            bank: Some(parse_hex(bank)?),
+        });
+    }
+    match parse_hex(argument) {
+        Some(address) => Some(Breakpoint {
+            address,
+            bank: None,
+        }),
+        None => symbols.address(argument).map(|(bank, address)| Breakpoint {
+            address,
+            bank: (0x4000..0x8000).contains(&address).then_some(bank),
+        }),
+    }
+}
+
+/// Prints and clears breakpoint and watchpoint hits. Returns whether there were any.
+fn report_hits(cpu: &mut Cpu<DmgBus>, symbols: &Symbols) -> bool {
+    let breakpoint = cpu.breakpoint_hit.take();
+    if let Some(breakpoint) = &breakpoint {
+        println!("Breakpoint hit at {}", describe(breakpoint, symbols));
+    }
+    let watchpoint = cpu
+        .bus
+        .watchpoints_mut()
+        .and_then(|watchpoints| watchpoints.hit.take());
+    if let Some(hit) = &watchpoint {
+        let access = if hit.write { "written" } else { "read" };
+        println!(
+            "Watchpoint hit: ${:04X} {access} with ${:02X}",
+            hit.watchpoint.address, hit.value
+        );
+    }
+    breakpoint.is_some() || watchpoint.is_some()
+}
+
+fn describe_watchpoint(watchpoint: &Watchpoint) -> String {
+    let access = match watchpoint.kind {
+        WatchKind::Read => "reads",
+        WatchKind::Write => "writes",
+        WatchKind::Access => "reads and writes",
+    };
+    match watchpoint.value {
+        Some(value) => format!("on {access} of ${value:02X} at ${:04X}", watchpoint.address),
+        None => format!("on {access} at ${:04X}", watchpoint.address),
+    }
+}
+
+/// The breakpoint's address, and its label if it has one
+fn describe(breakpoint: &Breakpoint, symbols: &Symbols) -> String {
+    let Breakpoint { address, bank } = *breakpoint;
+    let location = match bank {
+        Some(bank) => format!("{bank:02X}:{address:04X}"),
+        None => format!("${address:04X}"),
+    };
+    match symbols.label_from(bank.unwrap_or(0), address) {
+        Some(label) => format!("{location} ({label})"),
+        None => location,
+    }
+}
+
+fn print_location(cpu: &Cpu<DmgBus>, symbols: &Symbols) {
+    print_instruction(
+        &disasm::disassemble_at(cpu.bus.as_ref(), cpu.registers.pc),
+        cpu,
+        symbols,
+    );
+}
+
+fn print_instruction(disassembly: &Disassembly, cpu: &Cpu<DmgBus>, symbols: &Symbols) {
+    let bank = cpu
+        .bus
+        .get_cartridge()
+        .map_or(0, |cartridge| cartridge.rom_bank(disassembly.address));
+    if let Some(label) = symbols.label_from(bank, disassembly.address) {
+        println!("{label}:");
+    }
+    let marker = if disassembly.address == cpu.registers.pc {
+        "=>"
+    } else {
+        "  "
+    };
+    println!("{marker} {disassembly}");
+}
+
+fn print_registers(cpu: &Cpu<DmgBus>) {
+    println!(
+        "AF:{:04X} BC:{:04X} DE:{:04X} HL:{:04X} SP:{:04X} PC:{:04X}",
+        cpu.get_register_pair(&RegisterPair::AF),
+        cpu.get_register_pair(&RegisterPair::BC),
+        cpu.get_register_pair(&RegisterPair::DE),
+        cpu.get_register_pair(&RegisterPair::HL),
+        cpu.registers.sp,
+        cpu.registers.pc
+    );
+    println!(
+        "Z:{} N:{} H:{} C:{} IME:{} HALT:{} IE:{:02X} IF:{:02X}",
+        u8::from(cpu.flags.z),
+        u8::from(cpu.flags.n),
+        u8::from(cpu.flags.h),
+        u8::from(cpu.flags.c),
+        u8::from(cpu.ime),
+        u8::from(cpu.halted),
+        cpu.bus.get_interrupt_enable(),
+        cpu.bus.get_interrupt_flags()
+    );
+}
+
+/// Disassembles the last few executed instructions from the trace, since disassembling
+/// backwards from PC is ambiguous, followed by `count` instructions from PC
+fn print_listing(cpu: &Cpu<DmgBus>, tools: &Tools, count: usize) {
+    let symbols = &tools.symbols;
+    let previous: Vec<u16> = tools
+        .trace
+        .entries()
+        .rev()
+        .take(3)
+        .map(|entry| entry.pc)
+        .collect();
+    for address in previous.iter().rev() {
+        print_instruction(
+            &disasm::disassemble_at(cpu.bus.as_ref(), *address),
+            cpu,
+            symbols,
+        );
+    }
+    for disassembly in disasm::disassemble_range(cpu.bus.as_ref(), cpu.registers.pc, count) {
+        print_instruction(&disassembly, cpu, symbols);
+    }
+}
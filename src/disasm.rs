@@ -0,0 +1,234 @@
+//! Disassembler, which decodes instructions with the CPU's own decoder without executing them
+
+use crate::bus::Bus;
+use crate::cpu::{
+    decode, instruction_length, Condition, Instruction, Operand, Register, RegisterPair,
+};
+use std::fmt;
+
+/// A decoded instruction and where it came from
+pub struct Disassembly {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub instruction: Instruction,
+}
+
+impl Disassembly {
+    /// Address of the next instruction
+    #[must_use]
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.bytes.len() as u16)
+    }
+}
+
+/// Decodes the instruction at the start of `bytes`, as if it were at `address`. Missing bytes at
+/// the end are read as 0x00.
+#[must_use]
+pub fn disassemble_bytes(bytes: &[u8], address: u16) -> Disassembly {
+    let mut offset = 0;
+    let mut next_byte = || {
+        let byte = bytes.get(offset).copied().unwrap_or(0x00);
+        offset += 1;
+        byte
+    };
+    let opcode = next_byte();
+    let instruction = decode(opcode, &mut next_byte);
+    Disassembly {
+        address,
+        bytes: bytes
+            .iter()
+            .copied()
+            .chain(std::iter::repeat(0))
+            .take(offset)
+            .collect(),
+        instruction,
+    }
+}
+
+/// Decodes the instruction at `address` without side effects on the bus
+#[must_use]
+pub fn disassemble_at(bus: &dyn Bus, address: u16) -> Disassembly {
+    let length = instruction_length(bus.peek_byte(address));
+    let bytes: Vec<u8> = (0..length)
+        .map(|offset| bus.peek_byte(address.wrapping_add(offset)))
+        .collect();
+    disassemble_bytes(&bytes, address)
+}
+
+/// Decodes `count` consecutive instructions starting at `address`
+#[must_use]
+pub fn disassemble_range(bus: &dyn Bus, address: u16, count: usize) -> Vec<Disassembly> {
+    let mut address = address;
+    (0..count)
+        .map(|_| {
+            let disassembly = disassemble_at(bus, address);
+            address = disassembly.next_address();
+            disassembly
+        })
+        .collect()
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        write!(f, "{:04X}  {bytes:<9} ", self.address)?;
+        // Relative jumps are shown with their target address
+        match &self.instruction {
+            Instruction::Jr(condition, offset) => {
+                let target = self.next_address().wrapping_add_signed(i16::from(*offset));
+                write!(f, "JR {}${target:04X}", with_comma(condition))
+            }
+            instruction => write!(f, "{instruction}"),
+        }
+    }
+}
+
+fn signed(value: i8) -> String {
+    if value < 0 {
+        format!("-${:02X}", value.unsigned_abs())
+    } else {
+        format!("${value:02X}")
+    }
+}
+
+/// The condition followed by a comma, or nothing if there's no condition
+fn with_comma(condition: &Condition) -> String {
+    match condition {
+        Condition::Always => String::new(),
+        condition => format!("{condition},"),
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Condition::Always => "",
+            Condition::Zero => "Z",
+            Condition::NonZero => "NZ",
+            Condition::Carry => "C",
+            Condition::NonCarry => "NC",
+        })
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Register::A => "A",
+            Register::B => "B",
+            Register::C => "C",
+            Register::D => "D",
+            Register::E => "E",
+            Register::H => "H",
+            Register::L => "L",
+            Register::IndirectHL => "(HL)",
+            Register::DecrementHL => "(HL-)",
+            Register::IncrementHL => "(HL+)",
+            Register::IndirectC => "(C)",
+        })
+    }
+}
+
+impl fmt::Display for RegisterPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            RegisterPair::BC => "BC",
+            RegisterPair::DE => "DE",
+            RegisterPair::HL => "HL",
+            RegisterPair::SP => "SP",
+            RegisterPair::AF => "AF",
+        })
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Immediate8(value) => write!(f, "${value:02X}"),
+            Operand::IndirectImmediate8(address) => write!(f, "($FF{address:02X})"),
+            Operand::Immediate16(value) => write!(f, "${value:04X}"),
+            Operand::IndirectImmediate16(address) => write!(f, "(${address:04X})"),
+            Operand::StackOffset(offset) if *offset < 0 => write!(f, "SP{}", signed(*offset)),
+            Operand::StackOffset(offset) => write!(f, "SP+{}", signed(*offset)),
+            Operand::Register(register) => write!(f, "{register}"),
+            Operand::RegisterPair(rp) => write!(f, "{rp}"),
+            Operand::RegisterIndirect(rp) => write!(f, "({rp})"),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    #[allow(clippy::cast_possible_wrap)]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Ld(
+                target @ (Operand::IndirectImmediate8(_) | Operand::Register(Register::IndirectC)),
+                source,
+            )
+            | Instruction::Ld(
+                target,
+                source @ (Operand::IndirectImmediate8(_) | Operand::Register(Register::IndirectC)),
+            ) => write!(f, "LDH {target},{source}"),
+            Instruction::Ld(target, source) => write!(f, "LD {target},{source}"),
+            Instruction::Add(
+                Operand::RegisterPair(RegisterPair::SP),
+                Operand::Immediate8(value),
+            ) => {
+                write!(f, "ADD SP,{}", signed(*value as i8))
+            }
+            Instruction::Add(target, source) => write!(f, "ADD {target},{source}"),
+            Instruction::Adc(source) => write!(f, "ADC A,{source}"),
+            Instruction::Sub(source) => write!(f, "SUB {source}"),
+            Instruction::Sbc(source) => write!(f, "SBC A,{source}"),
+            Instruction::And(source) => write!(f, "AND {source}"),
+            Instruction::Xor(source) => write!(f, "XOR {source}"),
+            Instruction::Or(source) => write!(f, "OR {source}"),
+            Instruction::Cp(source) => write!(f, "CP {source}"),
+            Instruction::Inc(operand) => write!(f, "INC {operand}"),
+            Instruction::Dec(operand) => write!(f, "DEC {operand}"),
+            Instruction::Rlc(register) => write!(f, "RLC {register}"),
+            Instruction::Rrc(register) => write!(f, "RRC {register}"),
+            Instruction::Rl(register) => write!(f, "RL {register}"),
+            Instruction::Rr(register) => write!(f, "RR {register}"),
+            Instruction::Sla(register) => write!(f, "SLA {register}"),
+            Instruction::Sra(register) => write!(f, "SRA {register}"),
+            Instruction::Swap(register) => write!(f, "SWAP {register}"),
+            Instruction::Srl(register) => write!(f, "SRL {register}"),
+            Instruction::Bit(bit, register) => write!(f, "BIT {bit},{register}"),
+            Instruction::Res(bit, register) => write!(f, "RES {bit},{register}"),
+            Instruction::Set(bit, register) => write!(f, "SET {bit},{register}"),
+            Instruction::Rla => f.write_str("RLA"),
+            Instruction::Rlca => f.write_str("RLCA"),
+            Instruction::Rra => f.write_str("RRA"),
+            Instruction::Rrca => f.write_str("RRCA"),
+            Instruction::Rst(address) => write!(f, "RST ${address:02X}"),
+            Instruction::Ret(Condition::Always) => f.write_str("RET"),
+            Instruction::Ret(condition) => write!(f, "RET {condition}"),
+            Instruction::Reti => f.write_str("RETI"),
+            Instruction::Jp(condition, target) => write!(f, "JP {}{target}", with_comma(condition)),
+            Instruction::Jr(condition, offset) => {
+                write!(f, "JR {}{}", with_comma(condition), signed(*offset))
+            }
+            Instruction::Call(condition, address) => {
+                write!(f, "CALL {}${address:04X}", with_comma(condition))
+            }
+            Instruction::Stop => f.write_str("STOP"),
+            Instruction::Nop => f.write_str("NOP"),
+            Instruction::Halt => f.write_str("HALT"),
+            Instruction::Ei => f.write_str("EI"),
+            Instruction::Di => f.write_str("DI"),
+            Instruction::Push(rp) => write!(f, "PUSH {rp}"),
+            Instruction::Pop(rp) => write!(f, "POP {rp}"),
+            Instruction::Daa => f.write_str("DAA"),
+            Instruction::Cpl => f.write_str("CPL"),
+            Instruction::Scf => f.write_str("SCF"),
+            Instruction::Ccf => f.write_str("CCF"),
+            Instruction::Illegal(opcode) => write!(f, "DB ${opcode:02X}"),
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! OAM DMA (0xFF46), which copies 160 bytes to OAM one M-cycle at a time. While it runs, the
+//! CPU can't use OAM or the bus the DMA reads from. The copying itself is done by the bus.
+
+/// M-cycles from writing the DMA register until the transfer starts
+const STARTUP_CYCLES: u8 = 2;
+
+#[derive(Default)]
+pub struct OamDma {
+    /// The DMA register, the high byte of the source address
+    pub register: u8,
+    /// Source of the running transfer
+    pub(crate) source: u16,
+    /// Number of bytes the running transfer has copied, if there is one
+    pub(crate) copied: Option<u8>,
+    /// Source of a transfer that starts when `delay` runs out, replacing any that's running
+    pub(crate) requested: Option<u16>,
+    pub(crate) delay: u8,
+}
+
+impl OamDma {
+    /// Requests a transfer, when the DMA register is written
+    pub fn write_byte(&mut self, value: u8) {
+        self.register = value;
+        // Sources above 0xDF00 read from the echo of WRAM, so 0xFE00 and 0xFF00 copy from
+        // 0xDE00 and 0xDF00 rather than from OAM and I/O
+        self.requested = Some(match u16::from(value) << 8 {
+            source @ 0xE000.. => source - 0x2000,
+            source => source,
+        });
+        self.delay = STARTUP_CYCLES;
+    }
+
+    /// Whether a transfer is running, keeping the CPU off OAM and the source's bus
+    #[must_use]
+    pub fn active(&self) -> bool {
+        self.copied.is_some()
+    }
+
+    /// Whether a transfer is running or has been requested
+    #[must_use]
+    pub fn pending(&self) -> bool {
+        self.active() || self.requested.is_some()
+    }
+
+    /// Advances one M-cycle. Returns the source address and OAM offset of a byte to copy.
+    pub(crate) fn tick(&mut self) -> Option<(u16, u16)> {
+        let transfer = self.copied.map(|copied| {
+            let offset = u16::from(copied);
+            self.copied = (copied < 0x9F).then_some(copied + 1);
+            (self.source + offset, offset)
+        });
+        if let Some(source) = self.requested {
+            self.delay -= 1;
+            if self.delay == 0 {
+                self.requested = None;
+                self.source = source;
+                self.copied = Some(0);
+            }
+        }
+        transfer
+    }
+
+    /// Whether the CPU is kept off an address by a running transfer. Besides OAM, the DMA uses
+    /// the bus it reads from: either VRAM's, or the external bus for the cartridge and WRAM.
+    #[must_use]
+    pub fn blocks(&self, address: u16) -> bool {
+        if !self.active() {
+            return false;
+        }
+        let vram = |address| (0x8000..=0x9FFF).contains(&address);
+        match address {
+            0xFE00..=0xFEFF => true,
+            0xFF00.. => false,
+            _ => vram(address) == vram(self.source),
+        }
+    }
+}
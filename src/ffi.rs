@@ -0,0 +1,168 @@
+//! A C API over `GameBoy`, for embedding the emulator in C and C++ frontends or other languages
+//! with a C FFI. The declarations are in `include/rgb.h`, which must be kept in sync with this
+//! file; tests/ffi.rs compiles a program against it and the shared library to catch drift.
+//!
+//! All functions take a handle from `rgb_create`, and must not be called with a null or
+//! destroyed handle.
+
+use crate::gameboy::{GameBoy, Options};
+use crate::state;
+use std::ptr;
+use std::slice;
+
+/// An opaque handle to a Game Boy, which may not have a ROM loaded yet
+pub struct RgbGameBoy {
+    gameboy: Option<GameBoy>,
+}
+
+/// Creates a Game Boy with no ROM loaded. It must be freed with `rgb_destroy`.
+#[no_mangle]
+pub extern "C" fn rgb_create() -> *mut RgbGameBoy {
+    Box::into_raw(Box::new(RgbGameBoy { gameboy: None }))
+}
+
+/// Frees a Game Boy. Does nothing if `handle` is null.
+///
+/// # Safety
+///
+/// `handle` must be null or come from `rgb_create`, and must not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn rgb_destroy(handle: *mut RgbGameBoy) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Loads a ROM and powers on, replacing any ROM that was loaded. The model is picked from the
+/// cartridge header. Returns `false` if the ROM can't be loaded.
+///
+/// # Safety
+///
+/// `handle` must be valid, and `rom` must point to `length` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn rgb_load_rom(
+    handle: *mut RgbGameBoy,
+    rom: *const u8,
+    length: usize,
+) -> bool {
+    if rom.is_null() {
+        return false;
+    }
+    let rom = slice::from_raw_parts(rom, length).to_vec();
+    let handle = &mut *handle;
+    handle.gameboy = GameBoy::new(rom, Options::default()).ok();
+    handle.gameboy.is_some()
+}
+
+/// Runs until the next frame is finished. Does nothing if no ROM is loaded.
+///
+/// # Safety
+///
+/// `handle` must be valid
+#[no_mangle]
+pub unsafe extern "C" fn rgb_run_frame(handle: *mut RgbGameBoy) {
+    if let Some(gameboy) = &mut (*handle).gameboy {
+        gameboy.run_frame();
+    }
+}
+
+/// Switches the power off and on, keeping battery-backed cartridge RAM. Does nothing if no ROM
+/// is loaded.
+///
+/// # Safety
+///
+/// `handle` must be valid
+#[no_mangle]
+pub unsafe extern "C" fn rgb_reset(handle: *mut RgbGameBoy) {
+    if let Some(gameboy) = &mut (*handle).gameboy {
+        gameboy.reset();
+    }
+}
+
+/// Sets the buttons that are held: bits 0-7 are right, left, up, down, A, B, select and start
+///
+/// # Safety
+///
+/// `handle` must be valid
+#[no_mangle]
+pub unsafe extern "C" fn rgb_set_buttons(handle: *mut RgbGameBoy, pressed: u8) {
+    if let Some(gameboy) = &mut (*handle).gameboy {
+        gameboy.set_buttons(pressed);
+    }
+}
+
+/// Returns the last completed frame as 160×144 shades (0-3), one byte per pixel, or null if no
+/// ROM is loaded. The pointer is valid until the next call that runs or changes the Game Boy.
+///
+/// # Safety
+///
+/// `handle` must be valid
+#[no_mangle]
+pub unsafe extern "C" fn rgb_framebuffer(handle: *const RgbGameBoy) -> *const u8 {
+    match &(*handle).gameboy {
+        Some(gameboy) => gameboy.frame().pixels.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Returns the last completed frame as 160×144 RGB555 colors, or null if the frame has no
+/// colors (on DMG) or no ROM is loaded. The pointer is valid until the next call that runs or
+/// changes the Game Boy.
+///
+/// # Safety
+///
+/// `handle` must be valid
+#[no_mangle]
+pub unsafe extern "C" fn rgb_color_framebuffer(handle: *const RgbGameBoy) -> *const u16 {
+    match (*handle)
+        .gameboy
+        .as_ref()
+        .and_then(|gameboy| gameboy.frame().colors)
+    {
+        Some(colors) => colors.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Saves the state into `buffer` if it's at least `capacity` bytes, and returns the size of
+/// the state either way, so it can be called with a null buffer first to get the size. Returns
+/// 0 if no ROM is loaded.
+///
+/// # Safety
+///
+/// `handle` must be valid, and `buffer` must be null or point to `capacity` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn rgb_save_state(
+    handle: *const RgbGameBoy,
+    buffer: *mut u8,
+    capacity: usize,
+) -> usize {
+    let Some(gameboy) = &(*handle).gameboy else {
+        return 0;
+    };
+    let data = state::save(&gameboy.cpu);
+    if !buffer.is_null() && data.len() <= capacity {
+        ptr::copy_nonoverlapping(data.as_ptr(), buffer, data.len());
+    }
+    data.len()
+}
+
+/// Loads a state saved by `rgb_save_state` for the same ROM. Returns `false` if no ROM is
+/// loaded or the state is corrupt.
+///
+/// # Safety
+///
+/// `handle` must be valid, and `data` must point to `length` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn rgb_load_state(
+    handle: *mut RgbGameBoy,
+    data: *const u8,
+    length: usize,
+) -> bool {
+    match &mut (*handle).gameboy {
+        Some(gameboy) if !data.is_null() => {
+            state::load(&mut gameboy.cpu, slice::from_raw_parts(data, length)).is_ok()
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,232 @@
+//! A high-level interface to the whole machine, for embedding the emulator without writing a
+//! run loop:
+//!
+//! ```no_run
+//! use rgb_emu::gameboy::{GameBoy, Options};
+//! use rgb_emu::joypad::Button;
+//!
+//! let rom = std::fs::read("game.gb").unwrap();
+//! let mut gameboy = GameBoy::new(rom, Options::default()).unwrap();
+//! gameboy.press(Button::Start);
+//! gameboy.run_frame();
+//! gameboy.reset();
+//! let pixels = gameboy.frame().pixels;
+//! let samples = gameboy.take_audio();
+//! ```
+
+use crate::audio::AudioSink;
+use crate::block_cache::BlockCache;
+use crate::bus::{Bus, DmgBus};
+use crate::cartridge::{self, CartridgeError};
+use crate::cpu::Cpu;
+#[cfg(feature = "jit")]
+use crate::jit::Jit;
+use crate::joypad::Button;
+use crate::model::Model;
+use crate::palette::Palette;
+use crate::png;
+use crate::ppu::{FRAME_CYCLES, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::video::Frame;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Default)]
+pub struct Options {
+    /// The model to emulate, or `None` to pick one from the cartridge header
+    pub model: Option<Model>,
+    /// A boot ROM to run first. Without one, the machine starts in the state the boot ROM
+    /// leaves it in.
+    pub boot_rom: Option<Vec<u8>>,
+    /// Runs frames with the block cache, which is faster but skips some edge cases, like
+    /// watchpoints on code fetches. See `Cpu::run_block`.
+    pub block_cache: bool,
+    /// Also compiles hot blocks in the block cache to native code. If the JIT can't run on this
+    /// host, only the block cache is used.
+    #[cfg(feature = "jit")]
+    pub jit: bool,
+}
+
+/// Collects the APU's samples until they're taken
+struct SampleBuffer(Rc<RefCell<Vec<(i16, i16)>>>);
+
+impl AudioSink for SampleBuffer {
+    fn push_sample(&mut self, left: i16, right: i16) {
+        self.0.borrow_mut().push((left, right));
+    }
+}
+
+pub struct GameBoy {
+    pub cpu: Cpu<DmgBus>,
+    /// Colors of the DMG shades in screenshots
+    pub palette: Palette,
+    /// Buttons held, as a mask of `Button::mask` bits
+    buttons: u8,
+    samples: Rc<RefCell<Vec<(i16, i16)>>>,
+    /// The ROM of the inserted cartridge, for a fresh cartridge on reset
+    rom: Option<Vec<u8>>,
+    paused: bool,
+}
+
+impl GameBoy {
+    /// Inserts a ROM and powers on
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the cartridge header is malformed or not present
+    pub fn new(rom: Vec<u8>, options: Options) -> Result<Self, CartridgeError> {
+        let (cartridge, header) = cartridge::load(rom.clone())?;
+        let mut cpu = Cpu::with_bus(Box::new(DmgBus::new()));
+        cpu.bus.insert_cartridge(cartridge);
+        cpu.bus
+            .set_model(options.model.unwrap_or_else(|| Model::for_header(&header)));
+        match options.boot_rom {
+            Some(boot_rom) => cpu.bus.set_boot_rom(boot_rom),
+            None => cpu.set_post_boot_state(),
+        }
+        if options.block_cache {
+            cpu.block_cache = Some(BlockCache::new());
+        }
+        #[cfg(feature = "jit")]
+        if options.jit {
+            cpu.block_cache =
+                Some(Jit::new().map_or_else(|_| BlockCache::new(), BlockCache::with_jit));
+        }
+        let samples = Rc::new(RefCell::new(Vec::new()));
+        cpu.bus
+            .set_audio_sink(Box::new(SampleBuffer(Rc::clone(&samples))));
+        Ok(Self {
+            cpu,
+            palette: Palette::default(),
+            buttons: 0,
+            samples,
+            rom: Some(rom),
+            paused: false,
+        })
+    }
+
+    /// Runs one instruction, returning the number of M-cycles it took, or 0 while paused
+    pub fn step(&mut self) -> u32 {
+        if self.paused {
+            return 0;
+        }
+        self.cpu.step()
+    }
+
+    /// Runs until the PPU has finished the next frame, or until STOP stops every clock, which
+    /// lasts until a button is pressed. Does nothing while paused.
+    pub fn run_frame(&mut self) {
+        if self.paused {
+            return;
+        }
+        let ppu = |cpu: &Cpu<DmgBus>| cpu.bus.get_ppu().map(|ppu| ppu.frame_count);
+        let frame = ppu(&self.cpu);
+        // In case the bus has no PPU, stop after a frame's worth of cycles (in double speed)
+        let start = self.cpu.bus.cycles();
+        while ppu(&self.cpu) == frame && self.cpu.bus.cycles() - start < u64::from(FRAME_CYCLES / 2)
+        {
+            if self.cpu.block_cache.is_some() {
+                self.cpu.run_block();
+            } else {
+                self.cpu.step();
+            }
+            // Nothing runs in STOP mode, so stepping again can't finish the frame. A stopped CPU
+            // still takes a step to notice a button press.
+            if self.cpu.clocks_stopped() {
+                break;
+            }
+        }
+    }
+
+    /// Stops `step` and `run_frame` from running anything until `resume` is called
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Switches the power off and on. The boot ROM runs again if there is one, and the
+    /// cartridge keeps its battery-backed RAM, but everything else starts over. Whether it's
+    /// paused and the buttons that are held are kept.
+    pub fn reset(&mut self) {
+        // The ROM loaded fine when it was inserted
+        if let Some(Ok(cartridge)) = self
+            .rom
+            .clone()
+            .map(|rom| cartridge::power_cycle(rom, self.cpu.bus.get_cartridge()))
+        {
+            self.cpu.bus.insert_cartridge(cartridge);
+        }
+        self.cpu.reset();
+        self.cpu.bus.set_buttons(self.buttons);
+    }
+
+    /// Swaps in a cartridge with another ROM without resetting, like pulling out the cartridge
+    /// and inserting another while the power is on. The model stays the same.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the cartridge header is malformed or not present, in which case the
+    /// inserted cartridge is kept
+    pub fn insert_cartridge(&mut self, rom: Vec<u8>) -> Result<(), CartridgeError> {
+        let cartridge = cartridge::from_rom(rom.clone())?;
+        self.cpu.bus.insert_cartridge(cartridge);
+        self.rom = Some(rom);
+        if let Some(cache) = &mut self.cpu.block_cache {
+            cache.clear();
+        }
+        Ok(())
+    }
+
+    /// Pulls out the cartridge while the power is on. Until another is inserted, the cartridge
+    /// ROM and RAM areas read as 0xFF, so the game most likely crashes.
+    pub fn remove_cartridge(&mut self) {
+        self.cpu.bus.remove_cartridge();
+        self.rom = None;
+    }
+
+    /// Sets all the buttons that are held, as a mask of `Button::mask` bits
+    pub fn set_buttons(&mut self, pressed: u8) {
+        self.buttons = pressed;
+        self.cpu.bus.set_buttons(pressed);
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.set_buttons(self.buttons | button.mask());
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.set_buttons(self.buttons & !button.mask());
+    }
+
+    /// The last completed frame
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the bus has no PPU
+    #[must_use]
+    pub fn frame(&self) -> Frame<'_> {
+        self.cpu.bus.get_ppu().expect("bus has no PPU").frame()
+    }
+
+    /// The last completed frame as a PNG image
+    #[must_use]
+    pub fn screenshot(&self) -> Vec<u8> {
+        png::encode(
+            SCREEN_WIDTH,
+            SCREEN_HEIGHT,
+            &self.frame().to_rgb24(&self.palette),
+        )
+    }
+
+    /// Takes the stereo samples the APU has mixed since the last call, at `audio::SAMPLE_RATE`
+    pub fn take_audio(&mut self) -> Vec<(i16, i16)> {
+        std::mem::take(&mut self.samples.borrow_mut())
+    }
+}
@@ -0,0 +1,299 @@
+//! Stub for the GDB remote serial protocol, so debuggers like GDB can attach to the emulator.
+//!
+//! SM83 isn't supported by upstream GDB, so registers are exposed in the order used by its Z80
+//! target, which SM83-capable forks also use: AF, BC, DE, HL, SP and PC, each 16 bits.
+
+use crate::bus::Bus;
+use crate::cpu::{Breakpoint, Cpu, RegisterPair};
+use crate::watchpoints::{WatchKind, Watchpoint};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// Stop reply when the debugger interrupted a running target (SIGINT)
+const INTERRUPTED: &str = "S02";
+
+/// Number of instructions between checks for an interrupt from the debugger while running
+const POLL_INTERVAL: usize = 10_000;
+
+const REGISTERS: [RegisterPair; 5] = [
+    RegisterPair::AF,
+    RegisterPair::BC,
+    RegisterPair::DE,
+    RegisterPair::HL,
+    RegisterPair::SP,
+];
+
+/// What the target should do after a packet has been handled
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    Reply(String),
+    Step,
+    Continue,
+    Detach,
+}
+
+/// Handles the contents of one packet
+pub fn handle_packet<B: Bus + ?Sized>(cpu: &mut Cpu<B>, packet: &str) -> Action {
+    let reply = |reply: &str| Action::Reply(reply.to_string());
+    let (command, arguments) = packet.split_at(packet.len().min(1));
+    match command {
+        "?" => reply("S05"),
+        "g" => {
+            let mut registers: String = REGISTERS
+                .iter()
+                .map(|rp| hex_u16(cpu.get_register_pair(rp)))
+                .collect();
+            registers.push_str(&hex_u16(cpu.registers.pc));
+            Action::Reply(registers)
+        }
+        "G" => {
+            let values: Vec<u16> = (0..arguments.len() / 4)
+                .filter_map(|i| parse_u16_le(&arguments[i * 4..i * 4 + 4]))
+                .collect();
+            for (index, value) in values.into_iter().enumerate() {
+                set_register(cpu, index, value);
+            }
+            reply("OK")
+        }
+        "p" => match usize::from_str_radix(arguments, 16) {
+            Ok(index @ 0..=4) => Action::Reply(hex_u16(cpu.get_register_pair(&REGISTERS[index]))),
+            Ok(5) => Action::Reply(hex_u16(cpu.registers.pc)),
+            _ => reply("E01"),
+        },
+        "P" => {
+            let parsed = arguments.split_once('=').and_then(|(index, value)| {
+                Some((usize::from_str_radix(index, 16).ok()?, parse_u16_le(value)?))
+            });
+            match parsed {
+                Some((index @ 0..=5, value)) => {
+                    set_register(cpu, index, value);
+                    reply("OK")
+                }
+                _ => reply("E01"),
+            }
+        }
+        "m" => match parse_address_length(arguments) {
+            Some((address, length)) => Action::Reply(
+                cpu.bus
+                    .peek_range(address, usize::from(length))
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect(),
+            ),
+            None => reply("E01"),
+        },
+        "M" => {
+            let parsed = arguments.split_once(':').and_then(|(location, data)| {
+                Some((parse_address_length(location)?, parse_bytes(data)?))
+            });
+            match parsed {
+                Some(((address, _), data)) => {
+                    for (offset, byte) in data.into_iter().enumerate() {
+                        cpu.bus
+                            .write_byte(address.wrapping_add(offset as u16), byte);
+                    }
+                    reply("OK")
+                }
+                None => reply("E01"),
+            }
+        }
+        // Software and hardware breakpoints are the same to the emulator
+        "Z" | "z" => {
+            let Some((kind, location)) = arguments.split_once(',') else {
+                return reply("E01");
+            };
+            let Some((address, length)) = parse_address_length(location) else {
+                return reply("E01");
+            };
+            let insert = command == "Z";
+            // Software and hardware breakpoints are the same to the emulator
+            if kind == "0" || kind == "1" {
+                let breakpoint = Breakpoint {
+                    address,
+                    bank: None,
+                };
+                if insert {
+                    cpu.breakpoints.insert(breakpoint);
+                } else {
+                    cpu.breakpoints.remove(&breakpoint);
+                }
+                return reply("OK");
+            }
+            let kind = match kind {
+                "2" => WatchKind::Write,
+                "3" => WatchKind::Read,
+                "4" => WatchKind::Access,
+                _ => return reply(""),
+            };
+            let Some(watchpoints) = cpu.bus.watchpoints_mut() else {
+                return reply("");
+            };
+            for offset in 0..length {
+                let address = address.wrapping_add(offset);
+                if insert {
+                    watchpoints.insert(Watchpoint {
+                        address,
+                        kind,
+                        value: None,
+                    });
+                } else {
+                    watchpoints.remove(address);
+                }
+            }
+            reply("OK")
+        }
+        "s" => Action::Step,
+        "c" => Action::Continue,
+        "D" | "k" => Action::Detach,
+        "H" => reply("OK"),
+        "q" if arguments.starts_with("Supported") => reply("PacketSize=4000"),
+        "q" if arguments == "Attached" => reply("1"),
+        _ => reply(""),
+    }
+}
+
+/// Stop reply after stepping or continuing (SIGTRAP), which tells the debugger which watchpoint
+/// was hit, if any. Clears the hits.
+fn stop_reply<B: Bus + ?Sized>(cpu: &mut Cpu<B>) -> String {
+    cpu.breakpoint_hit = None;
+    let hit = cpu
+        .bus
+        .watchpoints_mut()
+        .and_then(|watchpoints| watchpoints.hit.take());
+    match hit {
+        Some(hit) => {
+            let kind = match hit.watchpoint.kind {
+                WatchKind::Read => "rwatch",
+                WatchKind::Write => "watch",
+                WatchKind::Access => "awatch",
+            };
+            format!("T05{kind}:{:04x};", hit.watchpoint.address)
+        }
+        None => "S05".to_string(),
+    }
+}
+
+fn set_register<B: Bus + ?Sized>(cpu: &mut Cpu<B>, index: usize, value: u16) {
+    match index {
+        0..=4 => cpu.set_register_pair(&REGISTERS[index], value),
+        5 => cpu.registers.pc = value,
+        _ => (),
+    }
+}
+
+/// Registers are sent as little endian hex
+fn hex_u16(value: u16) -> String {
+    format!("{:02x}{:02x}", value & 0xFF, value >> 8)
+}
+
+fn parse_u16_le(hex: &str) -> Option<u16> {
+    let bytes = parse_bytes(hex)?;
+    Some(u16::from_le_bytes([*bytes.first()?, *bytes.get(1)?]))
+}
+
+fn parse_bytes(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len() / 2)
+        .map(|i| u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_address_length(arguments: &str) -> Option<(u16, u16)> {
+    let (address, length) = arguments.split_once(',')?;
+    Some((
+        u16::from_str_radix(address, 16).ok()?,
+        u16::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+fn send_packet(stream: &mut TcpStream, data: &str) -> io::Result<()> {
+    let checksum = data.bytes().fold(0_u8, u8::wrapping_add);
+    write!(stream, "${data}#{checksum:02x}")?;
+    stream.flush()
+}
+
+/// Reads the next packet, acknowledging it. Returns `None` if the debugger sent an interrupt.
+fn read_packet(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    let mut byte = [0];
+    loop {
+        stream.read_exact(&mut byte)?;
+        match byte[0] {
+            b'$' => break,
+            0x03 => return Ok(None),
+            // Acknowledgements from the debugger
+            _ => (),
+        }
+    }
+    let mut packet = Vec::new();
+    loop {
+        stream.read_exact(&mut byte)?;
+        if byte[0] == b'#' {
+            break;
+        }
+        packet.push(byte[0]);
+    }
+    let mut checksum = [0; 2];
+    stream.read_exact(&mut checksum)?;
+    stream.write_all(b"+")?;
+    Ok(Some(String::from_utf8_lossy(&packet).into_owned()))
+}
+
+/// Whether the debugger has sent an interrupt while the target is running
+fn interrupted(stream: &mut TcpStream) -> io::Result<bool> {
+    stream.set_nonblocking(true)?;
+    let mut byte = [0];
+    let result = match stream.read(&mut byte) {
+        Ok(1) => Ok(byte[0] == 0x03),
+        Ok(_) => Err(io::ErrorKind::UnexpectedEof.into()),
+        Err(error) if error.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        Err(error) => Err(error),
+    };
+    stream.set_nonblocking(false)?;
+    result
+}
+
+/// Waits for a debugger to connect on the listener, and serves it until it detaches. The
+/// target starts out stopped. `step` is called to execute each instruction.
+///
+/// # Errors
+///
+/// Will return `Err` if the connection fails
+pub fn serve<B: Bus + ?Sized>(
+    cpu: &mut Cpu<B>,
+    listener: &TcpListener,
+    mut step: impl FnMut(&mut Cpu<B>),
+) -> io::Result<()> {
+    let (mut stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    loop {
+        let Some(packet) = read_packet(&mut stream)? else {
+            send_packet(&mut stream, INTERRUPTED)?;
+            continue;
+        };
+        match handle_packet(cpu, &packet) {
+            Action::Reply(reply) => send_packet(&mut stream, &reply)?,
+            Action::Step => {
+                step(cpu);
+                send_packet(&mut stream, &stop_reply(cpu))?;
+            }
+            Action::Continue => {
+                let mut steps = 0;
+                loop {
+                    step(cpu);
+                    if cpu.should_pause() {
+                        send_packet(&mut stream, &stop_reply(cpu))?;
+                        break;
+                    }
+                    steps += 1;
+                    if steps % POLL_INTERVAL == 0 && interrupted(&mut stream)? {
+                        send_packet(&mut stream, INTERRUPTED)?;
+                        break;
+                    }
+                }
+            }
+            Action::Detach => {
+                send_packet(&mut stream, "OK")?;
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,760 @@
+use crate::config::Keys;
+use crate::pacing::{FrameTimer, PacingStats};
+use crate::saves::SaveFile;
+use crate::{Cli, Tools};
+use clap::ValueEnum;
+use rgb_emu::animation::{AnimationWriter, Format};
+use rgb_emu::apu::{Apu, Channel, FrameSequencerEvents};
+use rgb_emu::bus::{Bus, DmgBus};
+use rgb_emu::cartridge;
+use rgb_emu::compositor::{Compositor, BORDER_HEIGHT, BORDER_WIDTH};
+use rgb_emu::cpu::Cpu;
+use rgb_emu::debug::{self, Image};
+use rgb_emu::joypad::Button;
+use rgb_emu::palette::Palette;
+use rgb_emu::peripheral::PeripheralEvent;
+use rgb_emu::ppu::{Ppu, SCREEN_HEIGHT, SCREEN_WIDTH};
+use rgb_emu::scaler::{self, Filter};
+use rgb_emu::state;
+use rgb_emu::video::{Frame, VideoSink};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{KeyboardState, Keycode, Mod, Scancode};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::{Point, Rect};
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+use std::fs::File;
+use std::io::BufWriter;
+use std::time::Instant;
+
+/// Height of each channel's strip in the APU scope
+const CHANNEL_HEIGHT: u32 = 40;
+
+/// Auxiliary debug windows
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum View {
+    Tiles,
+    Map,
+    Oam,
+    Palettes,
+    Scope,
+}
+
+impl View {
+    const ALL: [View; 5] = [
+        View::Tiles,
+        View::Map,
+        View::Oam,
+        View::Palettes,
+        View::Scope,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            View::Tiles => "Tiles",
+            View::Map => "Background and window maps",
+            View::Oam => "OAM",
+            View::Palettes => "Palettes",
+            View::Scope => "APU scope",
+        }
+    }
+
+    fn size(self) -> (u32, u32) {
+        match self {
+            View::Tiles => (128 * 2, 192 * 2),
+            View::Map => (512 * 2, 256 * 2),
+            View::Oam => (80 * 4, 64 * 4),
+            View::Palettes => (4 * 32, 3 * 32),
+            View::Scope => (400, 200 + 4 * CHANNEL_HEIGHT),
+        }
+    }
+
+    fn hotkey(self) -> Keycode {
+        match self {
+            View::Tiles => Keycode::F1,
+            View::Map => Keycode::F2,
+            View::Oam => Keycode::F3,
+            View::Palettes => Keycode::F4,
+            View::Scope => Keycode::F5,
+        }
+    }
+}
+
+struct DebugWindow {
+    view: View,
+    canvas: Canvas<Window>,
+}
+
+impl DebugWindow {
+    fn open(video: &VideoSubsystem, view: View) -> Result<Self, String> {
+        let (width, height) = view.size();
+        let window = video
+            .window(view.title(), width, height)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        Ok(Self { view, canvas })
+    }
+
+    /// Resizes the window to the aspect ratio of an image, like when the tile view gets wider
+    /// for the second VRAM bank
+    fn fit_to(&mut self, image: &Image) -> Result<(), String> {
+        let (width, height) = self.canvas.window().size();
+        let fitted = height * image.width as u32 / image.height as u32;
+        if width != fitted {
+            self.canvas
+                .window_mut()
+                .set_size(fitted, height)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn refresh(&mut self, cpu: &Cpu<DmgBus>) -> Result<(), String> {
+        match self.view {
+            View::Tiles | View::Map | View::Oam => {
+                if let Some(ppu) = cpu.bus.get_ppu() {
+                    let image = match self.view {
+                        View::Tiles => debug::tiles(ppu),
+                        View::Map => beside(&debug::background_map(ppu), &debug::window_map(ppu)),
+                        _ => debug::sprites(ppu),
+                    };
+                    self.fit_to(&image)?;
+                    draw_image(&mut self.canvas, &image)?;
+                    match self.view {
+                        View::Tiles => draw_tile_blocks(&mut self.canvas, image.width / 128)?,
+                        View::Map => draw_viewports(&mut self.canvas, ppu)?,
+                        View::Oam => cross_out_hidden(&mut self.canvas, ppu)?,
+                        _ => (),
+                    }
+                }
+            }
+            View::Palettes => {
+                if let Some(ppu) = cpu.bus.get_ppu() {
+                    for (row, palette) in debug::palettes(ppu).iter().enumerate() {
+                        for (column, shade) in palette.iter().enumerate() {
+                            let [r, g, b] = Palette::GRAYSCALE.color(*shade);
+                            self.canvas.set_draw_color(Color::RGB(r, g, b));
+                            self.canvas.fill_rect(Rect::new(
+                                column as i32 * 32,
+                                row as i32 * 32,
+                                32,
+                                32,
+                            ))?;
+                        }
+                    }
+                }
+            }
+            View::Scope => {
+                self.canvas.set_draw_color(Color::BLACK);
+                self.canvas.clear();
+                if let Some(apu) = cpu.bus.get_apu() {
+                    let (width, _) = self.view.size();
+                    let height = 200;
+                    let samples = apu.history.len().max(1) as i32;
+                    let to_point = |(i, sample): (usize, i16)| {
+                        Point::new(
+                            i as i32 * width as i32 / samples,
+                            height as i32 / 2 - i32::from(sample) * height as i32 / 0x10000,
+                        )
+                    };
+                    let left: Vec<Point> = apu
+                        .history
+                        .iter()
+                        .map(|(left, _)| *left)
+                        .enumerate()
+                        .map(to_point)
+                        .collect();
+                    let right: Vec<Point> = apu
+                        .history
+                        .iter()
+                        .map(|(_, right)| *right)
+                        .enumerate()
+                        .map(to_point)
+                        .collect();
+                    self.canvas.set_draw_color(Color::GREEN);
+                    self.canvas.draw_lines(&left[..])?;
+                    self.canvas.set_draw_color(Color::RED);
+                    self.canvas.draw_lines(&right[..])?;
+                    draw_frame_sequencer(&mut self.canvas, apu, width)?;
+                    draw_channels(&mut self.canvas, apu, width, height)?;
+                }
+            }
+        }
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+/// Puts two images of the same height side by side
+fn beside(left: &Image, right: &Image) -> Image {
+    let pixels = left
+        .pixels
+        .chunks_exact(left.width)
+        .zip(right.pixels.chunks_exact(right.width))
+        .flat_map(|(left, right)| left.iter().chain(right))
+        .copied()
+        .collect();
+    Image {
+        width: left.width + right.width,
+        height: left.height,
+        pixels,
+    }
+}
+
+/// Outlines the screen on the background map to the left and the window map to the right
+fn draw_viewports(canvas: &mut Canvas<Window>, ppu: &Ppu) -> Result<(), String> {
+    let scale = canvas.window().size().1 / 256;
+    let outline = |region: debug::Region, left: usize| {
+        Rect::new(
+            ((left + region.x) as u32 * scale) as i32,
+            (region.y as u32 * scale) as i32,
+            region.width as u32 * scale,
+            region.height as u32 * scale,
+        )
+    };
+    canvas.set_draw_color(Color::RED);
+    for region in debug::viewport(ppu) {
+        canvas.draw_rect(outline(region, 0))?;
+    }
+    if let Some(region) = debug::window_viewport(ppu) {
+        canvas.draw_rect(outline(region, 256))?;
+    }
+    Ok(())
+}
+
+/// Crosses out the sprites in the OAM view that aren't drawn on screen
+fn cross_out_hidden(canvas: &mut Canvas<Window>, ppu: &Ppu) -> Result<(), String> {
+    let (width, height) = canvas.window().size();
+    let (cell_width, cell_height) = (width as i32 / 10, height as i32 / 4);
+    canvas.set_draw_color(Color::RED);
+    for entry in debug::oam_entries(ppu) {
+        if entry.hidden.is_some() {
+            let left = (entry.index % 10) as i32 * cell_width;
+            let top = (entry.index / 10) as i32 * cell_height;
+            let (right, bottom) = (left + cell_width - 1, top + cell_height - 1);
+            canvas.draw_line(Point::new(left, top), Point::new(right, bottom))?;
+            canvas.draw_line(Point::new(right, top), Point::new(left, bottom))?;
+        }
+    }
+    Ok(())
+}
+
+/// Separates the three blocks of tiles in the tile view, and the VRAM banks if there are two
+fn draw_tile_blocks(canvas: &mut Canvas<Window>, banks: usize) -> Result<(), String> {
+    let (width, height) = canvas.window().size();
+    let (width, height) = (width as i32, height as i32);
+    canvas.set_draw_color(Color::RED);
+    for block in 1..3 {
+        let y = height * block / 3;
+        canvas.draw_line(Point::new(0, y), Point::new(width, y))?;
+    }
+    for bank in 1..banks as i32 {
+        let x = width * bank / banks as i32;
+        canvas.draw_line(Point::new(x, 0), Point::new(x, height))?;
+    }
+    Ok(())
+}
+
+/// Draws the eight frame sequencer steps along the top, colored by which units they clock, with
+/// the current step filled in and a bar counting down to the next step below them
+/// Draws each channel's output in a strip below the mixed output, grayed out if it's muted
+fn draw_channels(
+    canvas: &mut Canvas<Window>,
+    apu: &Apu,
+    width: u32,
+    top: u32,
+) -> Result<(), String> {
+    for (index, channel) in Channel::ALL.into_iter().enumerate() {
+        let middle = (top + index as u32 * CHANNEL_HEIGHT + CHANNEL_HEIGHT / 2) as i32;
+        let history = &apu.channel_history[index];
+        let samples = history.len().max(1) as i32;
+        let points: Vec<Point> = history
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| {
+                Point::new(
+                    i as i32 * width as i32 / samples,
+                    middle - i32::from(*sample) * CHANNEL_HEIGHT as i32 / 0x4000,
+                )
+            })
+            .collect();
+        canvas.set_draw_color(if apu.is_muted(channel) {
+            Color::GRAY
+        } else {
+            Color::WHITE
+        });
+        canvas.draw_lines(&points[..])?;
+        let level = u32::from(apu.level(channel)) * CHANNEL_HEIGHT / 0x2000;
+        canvas.fill_rect(Rect::new(
+            width as i32 - 4,
+            middle + CHANNEL_HEIGHT as i32 / 2 - level as i32,
+            4,
+            level.max(1),
+        ))?;
+    }
+    Ok(())
+}
+
+fn draw_frame_sequencer(canvas: &mut Canvas<Window>, apu: &Apu, width: u32) -> Result<(), String> {
+    let step_width = width / 8;
+    for step in 0..8 {
+        let events = FrameSequencerEvents::for_step(step);
+        let color = if events.envelope {
+            Color::YELLOW
+        } else if events.sweep {
+            Color::CYAN
+        } else if events.length {
+            Color::BLUE
+        } else {
+            Color::GRAY
+        };
+        canvas.set_draw_color(color);
+        let rect = Rect::new(
+            (u32::from(step) * step_width) as i32 + 1,
+            1,
+            step_width - 2,
+            10,
+        );
+        if step == apu.frame_sequencer_step {
+            canvas.fill_rect(rect)?;
+        } else {
+            canvas.draw_rect(rect)?;
+        }
+    }
+    let remaining = u32::from(apu.cycles_until_frame_sequencer_step()) * width / 0x2000;
+    canvas.set_draw_color(Color::WHITE);
+    canvas.fill_rect(Rect::new(0, 13, remaining.max(1), 2))
+}
+
+/// Presents frames in the main window
+struct Presenter {
+    canvas: Canvas<Window>,
+    compositor: Compositor,
+    scale: usize,
+    filter: Filter,
+}
+
+impl VideoSink for Presenter {
+    fn push_frame(&mut self, frame: &Frame) {
+        let output = self.compositor.compose(frame);
+        let pixels = scaler::scale(
+            output.width,
+            output.height,
+            &output.pixels,
+            self.scale,
+            self.filter,
+        );
+        match draw_rgb(
+            &mut self.canvas,
+            output.width * self.scale,
+            output.height * self.scale,
+            &pixels,
+        ) {
+            Ok(()) => self.canvas.present(),
+            Err(error) => println!("Can't present frame: {error}"),
+        }
+    }
+}
+
+fn draw_image(canvas: &mut Canvas<Window>, image: &Image) -> Result<(), String> {
+    let pixels: Vec<u8> = image
+        .pixels
+        .iter()
+        .flat_map(|shade| Palette::GRAYSCALE.color(*shade))
+        .collect();
+    draw_rgb(canvas, image.width, image.height, &pixels)
+}
+
+/// Draws an RGB24 image scaled to fill the canvas
+fn draw_rgb(
+    canvas: &mut Canvas<Window>,
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+) -> Result<(), String> {
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+        .map_err(|e| e.to_string())?;
+    texture
+        .update(None, pixels, width * 3)
+        .map_err(|e| e.to_string())?;
+    canvas.copy(&texture, None, None)
+}
+
+/// Default keys for each joypad button
+const DEFAULT_KEYS: [(Scancode, Button); 8] = [
+    (Scancode::Right, Button::Right),
+    (Scancode::Left, Button::Left),
+    (Scancode::Up, Button::Up),
+    (Scancode::Down, Button::Down),
+    (Scancode::X, Button::A),
+    (Scancode::Z, Button::B),
+    (Scancode::Backspace, Button::Select),
+    (Scancode::Return, Button::Start),
+];
+
+/// Keys for each joypad button, with any keys from the configuration file replacing the defaults
+fn button_keys(keys: &Keys) -> Vec<(Scancode, Button)> {
+    DEFAULT_KEYS
+        .iter()
+        .map(|&(default, button)| match keys.get(button) {
+            Some(name) => match Scancode::from_name(name) {
+                Some(key) => (key, button),
+                None => {
+                    println!("Unknown key {name} in the config file, skipping...");
+                    (default, button)
+                }
+            },
+            None => (default, button),
+        })
+        .collect()
+}
+
+fn held_buttons(keyboard: &KeyboardState, button_keys: &[(Scancode, Button)]) -> u8 {
+    button_keys
+        .iter()
+        .filter(|(key, _)| keyboard.is_scancode_pressed(*key))
+        .fold(0, |buttons, (_, button)| buttons | button.mask())
+}
+
+/// A tilt of 1 g in the direction of the keypad's arrow keys (8, 4, 6 and 2) that are held, for
+/// cartridges with a tilt sensor
+fn keypad_tilt(keyboard: &KeyboardState) -> PeripheralEvent {
+    let axis = |negative, positive| {
+        f32::from(u8::from(keyboard.is_scancode_pressed(positive)))
+            - f32::from(u8::from(keyboard.is_scancode_pressed(negative)))
+    };
+    PeripheralEvent::Tilt {
+        x: axis(Scancode::Kp4, Scancode::Kp6),
+        y: axis(Scancode::Kp8, Scancode::Kp2),
+    }
+}
+
+/// Runs the emulator in a window, along with any debug views, until the window is closed.
+///
+/// The joypad is mapped to the arrow keys, X (A), Z (B), Backspace (Select) and Enter (Start),
+/// unless the configuration file maps them to other keys.
+/// The debug views can also be toggled with F1-F5, F6 and F7 save and load a state, F9 starts
+/// and stops recording a GIF, F8 resets, F12 saves a screenshot, P pauses and resumes, holding
+/// Tab fast-forwards, holding I shines an infrared light at the cartridge's IR port, and the
+/// keypad's arrow keys tilt cartridges with a tilt sensor.
+pub fn run(
+    cpu: &mut Cpu<DmgBus>,
+    cli: &Cli,
+    rom: &[u8],
+    tools: &mut Tools,
+    save_file: &mut SaveFile,
+) -> Result<(), String> {
+    let mut pacing = PacingStats::default();
+    let result = run_window(cpu, cli, rom, tools, save_file, &mut pacing);
+    if save_file.flush(cpu).is_err() {
+        println!("Can't write save file");
+    }
+    if cli.pacing_report {
+        println!("Frame pacing: {pacing}");
+    }
+    result
+}
+
+fn run_window(
+    cpu: &mut Cpu<DmgBus>,
+    cli: &Cli,
+    rom: &[u8],
+    tools: &mut Tools,
+    save_file: &mut SaveFile,
+    pacing: &mut PacingStats,
+) -> Result<(), String> {
+    let sdl = sdl2::init()?;
+    let video = sdl.video()?;
+    // The Super Game Boy draws a border around the screen
+    let sgb = cpu.bus.get_ppu().is_some_and(|ppu| ppu.sgb.is_some());
+    let (width, height) = if sgb {
+        (BORDER_WIDTH, BORDER_HEIGHT)
+    } else {
+        (SCREEN_WIDTH, SCREEN_HEIGHT)
+    };
+    let window = video
+        .window(
+            "RGB",
+            width as u32 * u32::from(cli.scale),
+            height as u32 * u32::from(cli.scale),
+        )
+        .position_centered()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut presenter = Presenter {
+        canvas: window.into_canvas().build().map_err(|e| e.to_string())?,
+        compositor: Compositor::new(),
+        scale: usize::from(cli.scale),
+        filter: cli.filter,
+    };
+    presenter.compositor.palette = cli.palette;
+    let mut debug_windows = cli
+        .view
+        .iter()
+        .map(|view| DebugWindow::open(&video, *view))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut event_pump = sdl.event_pump()?;
+    // The screen needs to be drawn even if the frame hasn't changed when the window is exposed
+    let mut redraw = true;
+    let mut frame_timer = FrameTimer::new(cli.power_save);
+    let mut locked = false;
+    let mut paused = false;
+    let state_path = cli.rom.with_extension("state");
+    let button_keys = button_keys(&cli.keys);
+    // The frame whose buttons have been set
+    let mut input_frame = None;
+    // The version of the SGB border that's shown
+    let mut border_version = None;
+    // The GIF being recorded with the hotkey
+    let mut recording: Option<AnimationWriter<BufWriter<File>>> = None;
+
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => return Ok(()),
+                Event::Window {
+                    win_event: WindowEvent::Exposed | WindowEvent::SizeChanged(..),
+                    ..
+                } => redraw = true,
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
+                    if window_id == presenter.canvas.window().id() {
+                        return Ok(());
+                    }
+                    debug_windows.retain(|window| window.canvas.window().id() != window_id);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::I),
+                    repeat: false,
+                    ..
+                } => {
+                    cpu.bus
+                        .send_peripheral_event(PeripheralEvent::InfraredLight(true));
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::I),
+                    ..
+                } => {
+                    cpu.bus
+                        .send_peripheral_event(PeripheralEvent::InfraredLight(false));
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    repeat: false,
+                    ..
+                } => {
+                    let message = match std::fs::write(&state_path, state::save(cpu)) {
+                        Ok(()) => "State saved".to_string(),
+                        Err(error) => format!("Can't save state: {error}"),
+                    };
+                    presenter.compositor.show_message(&message, 120);
+                }
+                Event::KeyDown {
+                    keycode:
+                        Some(
+                            keycode @ (Keycode::Num1
+                            | Keycode::Num2
+                            | Keycode::Num3
+                            | Keycode::Num4),
+                        ),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(apu) = cpu.bus.get_apu_mut() {
+                        let channel = Channel::ALL[keycode as usize - Keycode::Num1 as usize];
+                        let message = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            apu.solo(channel);
+                            if apu.is_muted(Channel::ALL[(channel as usize + 1) % 4]) {
+                                format!("{channel} solo")
+                            } else {
+                                "All channels playing".to_string()
+                            }
+                        } else {
+                            let muted = !apu.is_muted(channel);
+                            apu.set_muted(channel, muted);
+                            format!("{channel} {}", if muted { "muted" } else { "unmuted" })
+                        };
+                        presenter.compositor.show_message(&message, 120);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    repeat: false,
+                    ..
+                } => {
+                    paused = !paused;
+                    let message = if paused { "Paused" } else { "Resumed" };
+                    presenter.compositor.show_message(message, 120);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    repeat: false,
+                    ..
+                } => {
+                    let message = if tools.movie.is_some() {
+                        "Can't reset: a movie is active".to_string()
+                    } else {
+                        match cartridge::power_cycle(rom.to_vec(), cpu.bus.get_cartridge()) {
+                            Ok(cartridge) => {
+                                cpu.bus.insert_cartridge(cartridge);
+                                cpu.reset();
+                                input_frame = None;
+                                "Reset".to_string()
+                            }
+                            Err(error) => format!("Can't reset: {error}"),
+                        }
+                    };
+                    presenter.compositor.show_message(&message, 120);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    repeat: false,
+                    ..
+                } => {
+                    let message = match recording.take() {
+                        Some(mut animation) => match animation.finish() {
+                            Ok(()) => "Recording saved".to_string(),
+                            Err(error) => format!("Can't save recording: {error}"),
+                        },
+                        None => {
+                            let frame = cpu.bus.get_ppu().map_or(0, |ppu| ppu.frame_count);
+                            let path = crate::capture_path(&cli.rom, frame, "gif");
+                            match AnimationWriter::create(path, Format::Gif, cli.dedupe_frames) {
+                                Ok(mut animation) => {
+                                    animation.palette = cli.palette;
+                                    recording = Some(animation);
+                                    "Recording".to_string()
+                                }
+                                Err(error) => format!("Can't record: {error}"),
+                            }
+                        }
+                    };
+                    presenter.compositor.show_message(&message, 120);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(ppu) = cpu.bus.get_ppu() {
+                        let frame = ppu.frame();
+                        let path = crate::capture_path(&cli.rom, frame.number, "png");
+                        let message = match crate::save_screenshot(&path, &frame, &cli.palette) {
+                            Ok(()) => "Screenshot saved".to_string(),
+                            Err(error) => format!("Can't save screenshot: {error}"),
+                        };
+                        presenter.compositor.show_message(&message, 120);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    repeat: false,
+                    ..
+                } => {
+                    let result = if tools.movie.is_some() {
+                        Err("a movie is active".to_string())
+                    } else {
+                        std::fs::read(&state_path)
+                            .map_err(|error| error.to_string())
+                            .and_then(|data| crate::load_state(cpu, &data, cli.strict))
+                    };
+                    let message = match result {
+                        Ok(()) => "State loaded".to_string(),
+                        Err(error) => format!("Can't load state: {error}"),
+                    };
+                    presenter.compositor.show_message(&message, 120);
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(view) = View::ALL.iter().find(|view| view.hotkey() == keycode) {
+                        if debug_windows.iter().any(|window| window.view == *view) {
+                            debug_windows.retain(|window| window.view != *view);
+                        } else {
+                            debug_windows.push(DebugWindow::open(&video, *view)?);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if !paused {
+            let frame = cpu.bus.get_ppu().ok_or("No PPU on bus")?.frame_count;
+            if input_frame != Some(frame) {
+                input_frame = Some(frame);
+                if !crate::start_frame(
+                    cpu,
+                    tools,
+                    held_buttons(&event_pump.keyboard_state(), &button_keys),
+                ) {
+                    presenter.compositor.show_message("Movie ended", 120);
+                }
+                cpu.bus
+                    .send_peripheral_event(keypad_tilt(&event_pump.keyboard_state()));
+            }
+            while cpu.bus.get_ppu().ok_or("No PPU on bus")?.frame_count == frame {
+                crate::step(cpu, tools);
+                // The window isn't updated while the debugger prompt is open
+                if cpu.should_pause() && !crate::debugger::run(cpu, tools) {
+                    return Ok(());
+                }
+                // Nothing runs in STOP mode until a step finds a button held at a later frame
+                if cpu.clocks_stopped() {
+                    break;
+                }
+            }
+            if cpu.locked && !locked {
+                presenter.compositor.show_message("CPU locked up", 600);
+            }
+            locked = cpu.locked;
+        }
+
+        if let Some(ppu) = cpu.bus.get_ppu() {
+            if let (Some(animation), false) = (&mut recording, paused) {
+                animation.push_frame(&ppu.frame());
+            }
+            if let Some(sgb) = &ppu.sgb {
+                if border_version != Some(sgb.border_version) {
+                    border_version = Some(sgb.border_version);
+                    presenter.compositor.set_border(Some(sgb.border_image()));
+                    redraw = true;
+                }
+            }
+            if ppu.frame_changed || redraw || presenter.compositor.has_messages() {
+                presenter.push_frame(&ppu.frame());
+                redraw = false;
+            }
+            pacing.record_present(Instant::now(), frame_timer.next_deadline());
+            if pacing.frames.is_multiple_of(60) {
+                if save_file.flush(cpu).is_err() {
+                    println!("Can't write save file");
+                }
+                presenter
+                    .canvas
+                    .window_mut()
+                    .set_title(&format!("RGB - {pacing}"))
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        for window in &mut debug_windows {
+            window.refresh(cpu)?;
+        }
+
+        let fast_forward = event_pump
+            .keyboard_state()
+            .is_scancode_pressed(Scancode::Tab);
+        frame_timer.set_speed(if fast_forward { 0.0 } else { cli.speed });
+        frame_timer.wait();
+    }
+}
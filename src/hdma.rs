@@ -0,0 +1,74 @@
+//! CGB VRAM DMA registers (HDMA1-HDMA5), for copying data to VRAM in 16-byte blocks, either all
+//! at once (general-purpose DMA) or one block per HBlank (HBlank DMA). The copying itself is
+//! done by the bus.
+
+pub struct Hdma {
+    pub source: u16,
+    /// Offset into VRAM
+    pub destination: u16,
+    /// Number of blocks left to copy, minus one (HDMA5 bits 0-6)
+    pub remaining: u8,
+    /// Whether an HBlank DMA is in progress
+    pub hblank_active: bool,
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Self {
+            source: 0,
+            destination: 0,
+            remaining: 0x7F,
+            hblank_active: false,
+        }
+    }
+}
+
+impl Hdma {
+    #[must_use]
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            // The address registers are write-only
+            0xFF51..=0xFF54 => 0xFF,
+            // Bit 7 is set when no HBlank DMA is active
+            0xFF55 => u8::from(!self.hblank_active) << 7 | self.remaining,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Writes a register. Returns the number of blocks a general-purpose DMA should copy right
+    /// away, which is 0 for other writes.
+    pub fn write_byte(&mut self, address: u16, value: u8) -> u8 {
+        match address {
+            0xFF51 => self.source = u16::from(value) << 8 | self.source & 0x00F0,
+            0xFF52 => self.source = self.source & 0xFF00 | u16::from(value & 0xF0),
+            0xFF53 => self.destination = u16::from(value & 0x1F) << 8 | self.destination & 0x00F0,
+            0xFF54 => self.destination = self.destination & 0x1F00 | u16::from(value & 0xF0),
+            0xFF55 => {
+                // Clearing bit 7 during an HBlank DMA cancels it instead
+                if self.hblank_active && value & 0x80 == 0 {
+                    self.hblank_active = false;
+                    return 0;
+                }
+                self.remaining = value & 0x7F;
+                self.hblank_active = value & 0x80 != 0;
+                if !self.hblank_active {
+                    return self.remaining + 1;
+                }
+            }
+            _ => unreachable!(),
+        }
+        0
+    }
+
+    /// Advances the addresses past a copied block, and ends the transfer after the last one
+    pub fn finish_block(&mut self) {
+        self.source = self.source.wrapping_add(16);
+        self.destination = (self.destination + 16) & 0x1FF0;
+        if self.remaining == 0 {
+            self.remaining = 0x7F;
+            self.hblank_active = false;
+        } else {
+            self.remaining -= 1;
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! The cartridge header at 0x0100-0x014F
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CgbSupport {
+    /// DMG only
+    None,
+    /// Enhanced for CGB, but also runs on DMG
+    Compatible,
+    /// Only runs on CGB
+    Only,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub cgb: CgbSupport,
+    pub sgb: bool,
+    /// Cartridge type byte, which says which MBC and other hardware the cartridge has
+    pub cartridge_type: u8,
+    /// ROM size in bytes
+    pub rom_size: usize,
+    /// External RAM size in bytes
+    pub ram_size: usize,
+    /// Two-character licensee code. Old one-byte codes are written as two hex digits.
+    pub licensee: String,
+    pub destination: Destination,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    /// Parses the header of a ROM. Returns `None` if the ROM is too short to contain a header.
+    #[must_use]
+    pub fn parse(rom: &[u8]) -> Option<Self> {
+        let header = rom.get(0x0100..0x0150)?;
+        let byte = |address: usize| header[address - 0x0100];
+
+        let cgb = match byte(0x0143) {
+            0x80 => CgbSupport::Compatible,
+            0xC0 => CgbSupport::Only,
+            _ => CgbSupport::None,
+        };
+        // Newer cartridges use the end of the title area for a manufacturer code and CGB flag
+        let title_end = if cgb == CgbSupport::None {
+            0x0144
+        } else {
+            0x0143
+        };
+        let title = rom[0x0134..title_end]
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| char::from(c))
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+
+        let licensee = if byte(0x014B) == 0x33 {
+            rom[0x0144..0x0146].iter().map(|&c| char::from(c)).collect()
+        } else {
+            format!("{:02X}", byte(0x014B))
+        };
+
+        Some(Self {
+            title,
+            cgb,
+            sgb: byte(0x0146) == 0x03,
+            cartridge_type: byte(0x0147),
+            rom_size: match byte(0x0148) {
+                size @ 0x00..=0x08 => 0x8000 << size,
+                _ => 0,
+            },
+            ram_size: match byte(0x0149) {
+                0x02 => 0x2000,
+                0x03 => 0x8000,
+                0x04 => 0x2_0000,
+                0x05 => 0x1_0000,
+                _ => 0,
+            },
+            licensee,
+            destination: if byte(0x014A) == 0x00 {
+                Destination::Japan
+            } else {
+                Destination::Overseas
+            },
+            version: byte(0x014C),
+            header_checksum: byte(0x014D),
+            global_checksum: u16::from_be_bytes([byte(0x014E), byte(0x014F)]),
+        })
+    }
+}
+
+/// A checksum in the header that doesn't match the ROM contents, which usually means a bad dump
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumMismatch {
+    Header { expected: u8, computed: u8 },
+    Global { expected: u16, computed: u16 },
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChecksumMismatch::Header { expected, computed } => write!(
+                f,
+                "header checksum is {expected:02X}, but the header sums to {computed:02X}"
+            ),
+            ChecksumMismatch::Global { expected, computed } => write!(
+                f,
+                "global checksum is {expected:04X}, but the ROM sums to {computed:04X}"
+            ),
+        }
+    }
+}
+
+impl CartridgeHeader {
+    /// Verifies the header and global checksums against the ROM the header was parsed from.
+    /// The boot ROM refuses to run cartridges with a bad header checksum, while the global
+    /// checksum isn't checked by anything.
+    #[must_use]
+    pub fn checksum_mismatches(&self, rom: &[u8]) -> Vec<ChecksumMismatch> {
+        let mut mismatches = Vec::new();
+
+        let header_checksum = rom[0x0134..=0x014C]
+            .iter()
+            .fold(0_u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+        if header_checksum != self.header_checksum {
+            mismatches.push(ChecksumMismatch::Header {
+                expected: self.header_checksum,
+                computed: header_checksum,
+            });
+        }
+
+        let global_checksum = rom
+            .iter()
+            .enumerate()
+            .filter(|(address, _)| !matches!(address, 0x014E | 0x014F))
+            .fold(0_u16, |sum, (_, byte)| sum.wrapping_add(u16::from(*byte)));
+        if global_checksum != self.global_checksum {
+            mismatches.push(ChecksumMismatch::Global {
+                expected: self.global_checksum,
+                computed: global_checksum,
+            });
+        }
+
+        mismatches
+    }
+}
+
+impl fmt::Display for CartridgeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Title:           {}", self.title)?;
+        writeln!(f, "CGB:             {:?}", self.cgb)?;
+        writeln!(f, "SGB:             {}", self.sgb)?;
+        writeln!(f, "Cartridge type:  {:02X}", self.cartridge_type)?;
+        writeln!(f, "ROM size:        {} KiB", self.rom_size / 1024)?;
+        writeln!(f, "RAM size:        {} KiB", self.ram_size / 1024)?;
+        writeln!(f, "Licensee:        {}", self.licensee)?;
+        writeln!(f, "Destination:     {:?}", self.destination)?;
+        writeln!(f, "Version:         {}", self.version)?;
+        writeln!(f, "Header checksum: {:02X}", self.header_checksum)?;
+        write!(f, "Global checksum: {:04X}", self.global_checksum)
+    }
+}
@@ -0,0 +1,113 @@
+//! Running without video or audio output until an exit condition is met, for CI and scripted
+//! testing
+
+use crate::saves::SaveFile;
+use crate::{debugger, start_frame, step, Tools};
+use regex::Regex;
+use rgb_emu::bus::{Bus, DmgBus};
+use rgb_emu::cpu::Cpu;
+use rgb_emu::serial::LinkDevice;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Conditions that end a headless run. With none of them set, it runs until the ROM locks up
+/// the CPU or a movie ends. A lockup ends the run whether these are set or not, and so does STOP
+/// with no button held, since nothing would ever wake the CPU.
+#[derive(Default)]
+pub struct ExitConditions {
+    pub frames: Option<u64>,
+    pub instructions: Option<u64>,
+    /// Exits when the bytes sent over the serial port so far match this
+    pub serial: Option<Regex>,
+    /// Exits when the CPU reaches a `JR -2` instruction, which test ROMs loop on when done
+    pub infinite_loop: bool,
+}
+
+/// Records the bytes the game sends over the serial port, with no link cable connected
+struct SerialCapture(Rc<RefCell<Vec<u8>>>);
+
+impl LinkDevice for SerialCapture {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.0.borrow_mut().push(byte);
+        0xFF
+    }
+}
+
+/// Runs until an exit condition is met. Returns `false` if the run ended because the CPU
+/// locked up or stopped, so the emulator can exit with a failing status.
+pub fn run(
+    cpu: &mut Cpu<DmgBus>,
+    tools: &mut Tools,
+    save_file: &mut SaveFile,
+    exit: &ExitConditions,
+) -> bool {
+    let serial = Rc::new(RefCell::new(Vec::new()));
+    if exit.serial.is_some() {
+        cpu.bus
+            .set_link_device(Box::new(SerialCapture(Rc::clone(&serial))));
+    }
+    let playing = tools.movie.is_some();
+    let mut frame = cpu.bus.get_ppu().map_or(0, |ppu| ppu.frame_count);
+    let mut frames: u64 = 0;
+    let mut instructions: u64 = 0;
+    let mut serial_length = 0;
+    start_frame(cpu, tools, 0);
+    loop {
+        if exit.infinite_loop
+            && cpu.bus.peek_byte(cpu.registers.pc) == 0x18
+            && cpu.bus.peek_byte(cpu.registers.pc.wrapping_add(1)) == 0xFE
+        {
+            println!(
+                "Infinite loop at {:04X} after {instructions} instructions",
+                cpu.registers.pc
+            );
+            break;
+        }
+        instructions += step(cpu, tools);
+        if cpu.locked {
+            println!("CPU locked up after {instructions} instructions");
+            break;
+        }
+        if cpu.clocks_stopped() {
+            println!("CPU stopped with no button held after {instructions} instructions");
+            break;
+        }
+        if cpu.should_pause() && !debugger::run(cpu, tools) {
+            break;
+        }
+        if exit.instructions.is_some_and(|limit| instructions >= limit) {
+            println!("Ran {instructions} instructions");
+            break;
+        }
+        if let Some(pattern) = &exit.serial {
+            let serial = serial.borrow();
+            if serial.len() != serial_length {
+                serial_length = serial.len();
+                let output = String::from_utf8_lossy(&serial);
+                if pattern.is_match(&output) {
+                    println!("Serial output matched:\n{output}");
+                    break;
+                }
+            }
+        }
+        let frame_count = cpu.bus.get_ppu().map_or(0, |ppu| ppu.frame_count);
+        if frame_count != frame {
+            frame = frame_count;
+            frames += 1;
+            if frames.is_multiple_of(60) && save_file.flush(cpu).is_err() {
+                println!("Can't write save file");
+            }
+            if exit.frames == Some(frames) {
+                println!("Ran {frames} frames");
+                break;
+            }
+            if !start_frame(cpu, tools, 0) && playing {
+                break;
+            }
+        }
+    }
+    if save_file.flush(cpu).is_err() {
+        println!("Can't write save file");
+    }
+    !cpu.locked && !cpu.clocks_stopped()
+}
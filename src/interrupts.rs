@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Interrupt {
     VBlank = 0,
     Stat = 1,
@@ -6,3 +6,26 @@ pub enum Interrupt {
     Serial = 3,
     Joypad = 4,
 }
+
+impl Interrupt {
+    /// All interrupts, from highest to lowest priority
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::Stat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    /// The interrupt's bit in IE and IF
+    #[must_use]
+    pub fn mask(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// Address of the interrupt handler
+    #[must_use]
+    pub fn vector(self) -> u16 {
+        0x0040 + 8 * self as u16
+    }
+}
@@ -0,0 +1,1143 @@
+//! An experimental JIT compiler that turns hot blocks from the block cache into native code with
+//! Cranelift.
+//!
+//! Compiled code keeps the registers in host registers and calls back into the bus for every
+//! M-cycle and memory access, so MMIO, timers and the PPU see exactly what the interpreter would
+//! make them see. A compiled block returns to the interpreter after any instruction that left an
+//! interrupt pending, or that wrote to the cartridge's mapper registers, since that can map
+//! another bank over the code. Instructions that change the CPU's own state, like HALT, STOP, EI,
+//! DI and RETI, aren't compiled; a block stops right before them and the interpreter takes over.
+
+use crate::block_cache::CachedInstruction;
+use crate::bus::Bus;
+use crate::cpu::{Condition, Flags, Instruction, Operand, Register, RegisterPair, Registers};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{
+    types, AbiParam, Block, Inst, InstBuilder, MemFlags, SigRef, Type, Value,
+};
+use cranelift_codegen::Context;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+use std::collections::HashMap;
+use std::mem::offset_of;
+
+/// Blocks are compiled once the interpreter has run them this many times
+const HOT_RUNS: u32 = 16;
+
+type BlockFn = unsafe extern "C" fn(*mut Registers, *mut Flags, *mut u8, *const Callbacks) -> u32;
+
+/// A block compiled to native code
+#[derive(Clone, Copy)]
+pub struct CompiledBlock(BlockFn);
+
+impl CompiledBlock {
+    /// Runs the block and returns the number of instructions run. Interrupts aren't serviced,
+    /// so that's up to the caller afterwards.
+    pub fn run<B: Bus + ?Sized>(
+        self,
+        registers: &mut Registers,
+        flags: &mut Flags,
+        bus: &mut Box<B>,
+    ) -> usize {
+        let callbacks = Callbacks::new::<B>();
+        let bus: *mut Box<B> = bus;
+        // The code was generated by `Jit::compile` for exactly these types, and only passes the
+        // bus on to the callbacks for the same `B`
+        let count = unsafe { (self.0)(registers, flags, bus.cast(), &callbacks) };
+        count as usize
+    }
+}
+
+enum Entry {
+    /// Run this many times by the interpreter so far
+    Cold(u32),
+    Compiled(CompiledBlock),
+    /// Starts with an instruction the JIT can't compile
+    Interpreted,
+}
+
+/// Functions the compiled code calls to reach the bus, which is passed to them as a pointer to
+/// a `Box<B>`. Compiled blocks get these when they run, so they work with any type of bus.
+#[repr(C)]
+pub struct Callbacks {
+    tick: unsafe extern "C" fn(*mut u8, u32),
+    read: unsafe extern "C" fn(*mut u8, u32) -> u32,
+    write: unsafe extern "C" fn(*mut u8, u32, u32) -> u32,
+    oam_bug: unsafe extern "C" fn(*mut u8, u32),
+    pending: unsafe extern "C" fn(*mut u8) -> u32,
+}
+
+impl Callbacks {
+    fn new<B: Bus + ?Sized>() -> Self {
+        Self {
+            tick: jit_tick::<B>,
+            read: jit_read::<B>,
+            write: jit_write::<B>,
+            oam_bug: jit_oam_bug::<B>,
+            pending: jit_pending::<B>,
+        }
+    }
+}
+
+pub struct Jit {
+    /// Only `None` while it's being dropped
+    module: Option<JITModule>,
+    blocks: HashMap<(usize, u16), Entry>,
+    context: Context,
+    builder_context: FunctionBuilderContext,
+}
+
+impl Jit {
+    /// Sets up a compiler for the host CPU
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if Cranelift doesn't support the host
+    pub fn new() -> Result<Self, String> {
+        let builder = JITBuilder::with_flags(&[("opt_level", "speed")], default_libcall_names())
+            .map_err(|error| error.to_string())?;
+        let module = JITModule::new(builder);
+        Ok(Self {
+            context: module.make_context(),
+            module: Some(module),
+            blocks: HashMap::new(),
+            builder_context: FunctionBuilderContext::new(),
+        })
+    }
+
+    /// The compiled code for the block at `address` in ROM bank `bank`, if it's been run often
+    /// enough to be compiled and it could be. Blocks are compiled the first time they're asked
+    /// for after they've been asked for `HOT_RUNS` times.
+    pub fn get(
+        &mut self,
+        bank: usize,
+        address: u16,
+        block: &[CachedInstruction],
+    ) -> Option<CompiledBlock> {
+        let entry = self.blocks.entry((bank, address)).or_insert(Entry::Cold(0));
+        match entry {
+            Entry::Compiled(code) => return Some(*code),
+            Entry::Interpreted => return None,
+            Entry::Cold(runs) if *runs < HOT_RUNS => {
+                *runs += 1;
+                return None;
+            }
+            Entry::Cold(_) => (),
+        }
+        let code = self.compile(address, block);
+        self.blocks.insert(
+            (bank, address),
+            code.map_or(Entry::Interpreted, Entry::Compiled),
+        );
+        code
+    }
+
+    /// Forgets all compiled blocks, which is needed when another cartridge is inserted. The
+    /// memory for their code isn't freed until the JIT is dropped.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+
+    /// Number of compiled blocks
+    #[must_use]
+    pub fn compiled(&self) -> usize {
+        self.blocks
+            .values()
+            .filter(|entry| matches!(entry, Entry::Compiled(_)))
+            .count()
+    }
+
+    /// Compiles the instructions at the start of the block that the JIT supports, or returns
+    /// `None` if the first one isn't. A compiler error also gives `None`, so the block is left
+    /// to the interpreter.
+    fn compile(&mut self, address: u16, block: &[CachedInstruction]) -> Option<CompiledBlock> {
+        let supported = block
+            .iter()
+            .take_while(|cached| compiles(&cached.instruction))
+            .count();
+        if supported == 0 {
+            return None;
+        }
+        let module = self.module.as_mut()?;
+        let pointer = module.target_config().pointer_type();
+        self.context.func.signature.params = vec![AbiParam::new(pointer); 4];
+        self.context.func.signature.returns = vec![AbiParam::new(types::I32)];
+
+        let mut builder = FunctionBuilder::new(&mut self.context.func, &mut self.builder_context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+        let [registers, flags, bus, callbacks] = builder.block_params(entry) else {
+            unreachable!("The signature has four parameters");
+        };
+        let (registers, flags, bus, callbacks) = (*registers, *flags, *bus, *callbacks);
+        let helpers = CALLBACKS.map(|(offset, params, returns)| {
+            let mut signature = module.make_signature();
+            signature.params.push(AbiParam::new(pointer));
+            signature
+                .params
+                .extend((0..params).map(|_| AbiParam::new(types::I32)));
+            if returns {
+                signature.returns.push(AbiParam::new(types::I32));
+            }
+            let signature = builder.import_signature(signature);
+            let function =
+                builder
+                    .ins()
+                    .load(pointer, MemFlags::trusted(), callbacks, offset as i32);
+            (signature, function)
+        });
+        for (variable, ty, base, offset) in STATE {
+            let variable = Variable::from_u32(variable);
+            builder.declare_var(variable, ty);
+            let base = if base == Base::Registers {
+                registers
+            } else {
+                flags
+            };
+            let value = builder
+                .ins()
+                .load(ty, MemFlags::trusted(), base, offset as i32);
+            builder.def_var(variable, value);
+        }
+        let exit = builder.create_block();
+        builder.append_block_param(exit, types::I32);
+
+        let mut emitter = Emitter {
+            builder,
+            helpers,
+            bus,
+            exit,
+            stop: None,
+        };
+        let mut pc = address;
+        for (index, cached) in block[..supported].iter().enumerate() {
+            let count = index as u32 + 1;
+            let next = pc.wrapping_add(u16::from(cached.length));
+            emitter.tick(u32::from(cached.length));
+            emitter.set(PC, types::I16, i64::from(next));
+            let exited = emitter.instruction(cached.instruction, next, count);
+            if index + 1 == supported {
+                if !exited {
+                    emitter.exit(count);
+                }
+            } else {
+                // Give the interpreter a chance to dispatch an interrupt or pick up a new bank
+                let pending = emitter.call_value(PENDING, &[]);
+                let stop = match emitter.stop.take() {
+                    Some(stop) => emitter.builder.ins().bor(stop, pending),
+                    None => pending,
+                };
+                let resume = emitter.builder.create_block();
+                let count = emitter.builder.ins().iconst(types::I32, i64::from(count));
+                emitter
+                    .builder
+                    .ins()
+                    .brif(stop, emitter.exit, &[count], resume, &[]);
+                emitter.builder.seal_block(resume);
+                emitter.builder.switch_to_block(resume);
+            }
+            pc = next;
+        }
+
+        let mut builder = emitter.builder;
+        builder.seal_block(exit);
+        builder.switch_to_block(exit);
+        for (variable, _, base, offset) in STATE {
+            let base = if base == Base::Registers {
+                registers
+            } else {
+                flags
+            };
+            let value = builder.use_var(Variable::from_u32(variable));
+            builder
+                .ins()
+                .store(MemFlags::trusted(), value, base, offset as i32);
+        }
+        let count = builder.block_params(exit)[0];
+        builder.ins().return_(&[count]);
+        builder.finalize();
+
+        let id = module
+            .declare_anonymous_function(&self.context.func.signature)
+            .ok()
+            .filter(|&id| module.define_function(id, &mut self.context).is_ok());
+        module.clear_context(&mut self.context);
+        let id = id?;
+        module.finalize_definitions().ok()?;
+        let code = module.get_finalized_function(id);
+        // Cranelift compiled it with the signature of `BlockFn`
+        Some(CompiledBlock(unsafe {
+            std::mem::transmute::<*const u8, BlockFn>(code)
+        }))
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // Nothing can call the compiled code anymore, since that needs the `Jit`
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+/// Whether the JIT can compile an instruction
+fn compiles(instruction: &Instruction) -> bool {
+    !matches!(
+        instruction,
+        Instruction::Halt
+            | Instruction::Stop
+            | Instruction::Ei
+            | Instruction::Di
+            | Instruction::Reti
+            | Instruction::Illegal(_)
+            | Instruction::Ld(Operand::IndirectImmediate16(_), Operand::RegisterPair(_))
+    )
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Base {
+    Registers,
+    Flags,
+}
+
+// Indices of the Cranelift variables that hold the CPU state
+const A: u32 = 0;
+const B: u32 = 1;
+const C: u32 = 2;
+const D: u32 = 3;
+const E: u32 = 4;
+const H: u32 = 5;
+const L: u32 = 6;
+const SP: u32 = 7;
+const PC: u32 = 8;
+const FLAG_Z: u32 = 9;
+const FLAG_N: u32 = 10;
+const FLAG_H: u32 = 11;
+const FLAG_C: u32 = 12;
+
+/// The CPU state that's loaded into variables when a block starts, and stored when it exits.
+/// The flags are `bool`s, so they're always 0 or 1.
+const STATE: [(u32, Type, Base, usize); 13] = [
+    (A, types::I8, Base::Registers, offset_of!(Registers, a)),
+    (B, types::I8, Base::Registers, offset_of!(Registers, b)),
+    (C, types::I8, Base::Registers, offset_of!(Registers, c)),
+    (D, types::I8, Base::Registers, offset_of!(Registers, d)),
+    (E, types::I8, Base::Registers, offset_of!(Registers, e)),
+    (H, types::I8, Base::Registers, offset_of!(Registers, h)),
+    (L, types::I8, Base::Registers, offset_of!(Registers, l)),
+    (SP, types::I16, Base::Registers, offset_of!(Registers, sp)),
+    (PC, types::I16, Base::Registers, offset_of!(Registers, pc)),
+    (FLAG_Z, types::I8, Base::Flags, offset_of!(Flags, z)),
+    (FLAG_N, types::I8, Base::Flags, offset_of!(Flags, n)),
+    (FLAG_H, types::I8, Base::Flags, offset_of!(Flags, h)),
+    (FLAG_C, types::I8, Base::Flags, offset_of!(Flags, c)),
+];
+
+// Indices into `Emitter::helpers`
+const TICK: usize = 0;
+const READ: usize = 1;
+const WRITE: usize = 2;
+const OAM_BUG: usize = 3;
+const PENDING: usize = 4;
+
+/// Where each callback is in `Callbacks`, how many `u32` parameters it takes after the bus, and
+/// whether it returns a `u32`
+const CALLBACKS: [(usize, usize, bool); 5] = [
+    (offset_of!(Callbacks, tick), 1, false),
+    (offset_of!(Callbacks, read), 1, true),
+    (offset_of!(Callbacks, write), 2, true),
+    (offset_of!(Callbacks, oam_bug), 1, false),
+    (offset_of!(Callbacks, pending), 0, true),
+];
+
+fn variable(register: Register) -> u32 {
+    match register {
+        Register::A => A,
+        Register::B => B,
+        Register::C => C,
+        Register::D => D,
+        Register::E => E,
+        Register::H => H,
+        Register::L => L,
+        _ => panic!("Register {register:?} isn't stored in a register"),
+    }
+}
+
+/// The CB-prefixed shifts and rotates, and the unprefixed ones on A
+#[derive(Clone, Copy)]
+enum Shift {
+    Rlc,
+    Rrc,
+    Rl,
+    Rr,
+    Sla,
+    Sra,
+    Srl,
+    Swap,
+}
+
+/// Builds the IR for one block
+struct Emitter<'a> {
+    builder: FunctionBuilder<'a>,
+    /// The signature and address of each callback
+    helpers: [(SigRef, Value); 5],
+    bus: Value,
+    exit: Block,
+    /// Nonzero if a write in the current instruction may have mapped another ROM bank or
+    /// started OAM DMA
+    stop: Option<Value>,
+}
+
+impl Emitter<'_> {
+    fn call(&mut self, helper: usize, args: &[Value]) -> Inst {
+        let (signature, function) = self.helpers[helper];
+        let mut arguments = vec![self.bus];
+        arguments.extend_from_slice(args);
+        self.builder
+            .ins()
+            .call_indirect(signature, function, &arguments)
+    }
+
+    fn call_value(&mut self, helper: usize, args: &[Value]) -> Value {
+        let call = self.call(helper, args);
+        self.builder.inst_results(call)[0]
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        let cycles = self.builder.ins().iconst(types::I32, i64::from(cycles));
+        self.call(TICK, &[cycles]);
+    }
+
+    fn read(&mut self, address: Value) -> Value {
+        let address = self.builder.ins().uextend(types::I32, address);
+        let value = self.call_value(READ, &[address]);
+        self.builder.ins().ireduce(types::I8, value)
+    }
+
+    fn write(&mut self, address: Value, value: Value) {
+        let address = self.builder.ins().uextend(types::I32, address);
+        let value = self.builder.ins().uextend(types::I32, value);
+        let stop = self.call_value(WRITE, &[address, value]);
+        self.stop = Some(match self.stop {
+            Some(previous) => self.builder.ins().bor(previous, stop),
+            None => stop,
+        });
+    }
+
+    fn oam_bug(&mut self, address: Value) {
+        let address = self.builder.ins().uextend(types::I32, address);
+        self.call(OAM_BUG, &[address]);
+    }
+
+    /// Jumps to the exit with PC as it is
+    fn exit(&mut self, count: u32) {
+        let count = self.builder.ins().iconst(types::I32, i64::from(count));
+        self.builder.ins().jump(self.exit, &[count]);
+    }
+
+    fn constant(&mut self, ty: Type, value: i64) -> Value {
+        self.builder.ins().iconst(ty, value)
+    }
+
+    fn set(&mut self, variable: u32, ty: Type, value: i64) {
+        let value = self.constant(ty, value);
+        self.def(variable, value);
+    }
+
+    fn get(&mut self, variable: u32) -> Value {
+        self.builder.use_var(Variable::from_u32(variable))
+    }
+
+    fn def(&mut self, variable: u32, value: Value) {
+        self.builder.def_var(Variable::from_u32(variable), value);
+    }
+
+    fn pair(&mut self, rp: RegisterPair) -> Value {
+        let (high, low) = match rp {
+            RegisterPair::SP => return self.get(SP),
+            RegisterPair::BC => (self.get(B), self.get(C)),
+            RegisterPair::DE => (self.get(D), self.get(E)),
+            RegisterPair::HL => (self.get(H), self.get(L)),
+            RegisterPair::AF => {
+                let mut low = self.constant(types::I8, 0);
+                for (flag, bit) in [(FLAG_Z, 7), (FLAG_N, 6), (FLAG_H, 5), (FLAG_C, 4)] {
+                    let flag = self.get(flag);
+                    let flag = self.builder.ins().ishl_imm(flag, bit);
+                    low = self.builder.ins().bor(low, flag);
+                }
+                (self.get(A), low)
+            }
+        };
+        let high = self.builder.ins().uextend(types::I16, high);
+        let high = self.builder.ins().ishl_imm(high, 8);
+        let low = self.builder.ins().uextend(types::I16, low);
+        self.builder.ins().bor(high, low)
+    }
+
+    fn set_pair(&mut self, rp: RegisterPair, value: Value) {
+        let (high_variable, low_variable) = match rp {
+            RegisterPair::SP => {
+                self.def(SP, value);
+                return;
+            }
+            RegisterPair::BC => (B, C),
+            RegisterPair::DE => (D, E),
+            RegisterPair::HL => (H, L),
+            RegisterPair::AF => {
+                let low = self.builder.ins().ireduce(types::I8, value);
+                for (flag, bit) in [(FLAG_Z, 7), (FLAG_N, 6), (FLAG_H, 5), (FLAG_C, 4)] {
+                    let shifted = self.builder.ins().ushr_imm(low, bit);
+                    let flag_value = self.builder.ins().band_imm(shifted, 1);
+                    self.def(flag, flag_value);
+                }
+                (A, A)
+            }
+        };
+        let high = self.builder.ins().ushr_imm(value, 8);
+        let high = self.builder.ins().ireduce(types::I8, high);
+        self.def(high_variable, high);
+        if !matches!(rp, RegisterPair::AF) {
+            let low = self.builder.ins().ireduce(types::I8, value);
+            self.def(low_variable, low);
+        }
+    }
+
+    fn flag(&mut self, flag: u32, value: bool) {
+        self.set(flag, types::I8, i64::from(value));
+    }
+
+    /// Sets Z if an 8-bit value is zero
+    fn zero_flag(&mut self, value: Value) {
+        let zero = self.builder.ins().icmp_imm(IntCC::Equal, value, 0);
+        self.def(FLAG_Z, zero);
+    }
+
+    /// Reads an 8-bit register, or the byte at HL
+    fn register(&mut self, register: Register) -> Value {
+        match register {
+            Register::IndirectHL => {
+                let hl = self.pair(RegisterPair::HL);
+                self.read(hl)
+            }
+            _ => self.get(variable(register)),
+        }
+    }
+
+    fn set_register(&mut self, register: Register, value: Value) {
+        match register {
+            Register::IndirectHL => {
+                let hl = self.pair(RegisterPair::HL);
+                self.write(hl, value);
+            }
+            _ => self.def(variable(register), value),
+        }
+    }
+
+    /// The source operand of an 8-bit ALU instruction
+    fn source(&mut self, operand: Operand) -> Value {
+        match operand {
+            Operand::Register(register) => self.register(register),
+            Operand::Immediate8(value) => self.constant(types::I8, i64::from(value)),
+            _ => panic!("Illegal operand {operand:?}"),
+        }
+    }
+
+    /// Nonzero if the condition holds, or `None` for `Condition::Always`
+    fn condition(&mut self, condition: Condition) -> Option<Value> {
+        let (flag, set) = match condition {
+            Condition::Always => return None,
+            Condition::Zero => (FLAG_Z, true),
+            Condition::NonZero => (FLAG_Z, false),
+            Condition::Carry => (FLAG_C, true),
+            Condition::NonCarry => (FLAG_C, false),
+        };
+        let flag = self.get(flag);
+        Some(if set {
+            flag
+        } else {
+            self.builder.ins().bxor_imm(flag, 1)
+        })
+    }
+
+    fn push(&mut self, value: Value) {
+        let sp = self.get(SP);
+        let high_address = self.builder.ins().iadd_imm(sp, -1);
+        let high = self.builder.ins().ushr_imm(value, 8);
+        let high = self.builder.ins().ireduce(types::I8, high);
+        self.write(high_address, high);
+        let low_address = self.builder.ins().iadd_imm(sp, -2);
+        let low = self.builder.ins().ireduce(types::I8, value);
+        self.write(low_address, low);
+        self.def(SP, low_address);
+    }
+
+    fn pop(&mut self) -> Value {
+        let sp = self.get(SP);
+        let low = self.read(sp);
+        let high_address = self.builder.ins().iadd_imm(sp, 1);
+        let high = self.read(high_address);
+        let sp = self.builder.ins().iadd_imm(sp, 2);
+        self.def(SP, sp);
+        let low = self.builder.ins().uextend(types::I16, low);
+        let high = self.builder.ins().uextend(types::I16, high);
+        let high = self.builder.ins().ishl_imm(high, 8);
+        self.builder.ins().bor(high, low)
+    }
+
+    /// Emits code for the rest of a branch, if the condition holds, and jumps to the exit either
+    /// way
+    fn branch(&mut self, condition: Condition, count: u32, taken: impl FnOnce(&mut Self)) {
+        match self.condition(condition) {
+            None => {
+                taken(self);
+                self.exit(count);
+            }
+            Some(holds) => {
+                let taken_block = self.builder.create_block();
+                let skipped_block = self.builder.create_block();
+                self.builder
+                    .ins()
+                    .brif(holds, taken_block, &[], skipped_block, &[]);
+                self.builder.seal_block(taken_block);
+                self.builder.seal_block(skipped_block);
+                self.builder.switch_to_block(taken_block);
+                taken(self);
+                self.exit(count);
+                self.builder.switch_to_block(skipped_block);
+                self.exit(count);
+            }
+        }
+    }
+
+    /// Emits one instruction, with PC already pointing to the next one, and returns whether it
+    /// jumped to the exit, which instructions that can jump do
+    #[allow(clippy::too_many_lines)]
+    fn instruction(&mut self, instruction: Instruction, next: u16, count: u32) -> bool {
+        match instruction {
+            Instruction::Nop => (),
+            Instruction::Ld(target, source) => self.load(target, source),
+            Instruction::Add(Operand::Register(Register::A), source) => {
+                let value = self.source(source);
+                self.add(value, false);
+            }
+            Instruction::Adc(source) => {
+                let value = self.source(source);
+                self.add(value, true);
+            }
+            Instruction::Sub(source) => {
+                let value = self.source(source);
+                let result = self.subtract(value, false);
+                self.def(A, result);
+            }
+            Instruction::Sbc(source) => {
+                let value = self.source(source);
+                let result = self.subtract(value, true);
+                self.def(A, result);
+            }
+            Instruction::Cp(source) => {
+                let value = self.source(source);
+                self.subtract(value, false);
+            }
+            Instruction::And(source) | Instruction::Or(source) | Instruction::Xor(source) => {
+                let value = self.source(source);
+                let a = self.get(A);
+                let result = match instruction {
+                    Instruction::And(_) => self.builder.ins().band(a, value),
+                    Instruction::Or(_) => self.builder.ins().bor(a, value),
+                    _ => self.builder.ins().bxor(a, value),
+                };
+                self.def(A, result);
+                self.zero_flag(result);
+                self.flag(FLAG_N, false);
+                self.flag(FLAG_H, matches!(instruction, Instruction::And(_)));
+                self.flag(FLAG_C, false);
+            }
+            Instruction::Add(
+                Operand::RegisterPair(RegisterPair::HL),
+                Operand::RegisterPair(rp),
+            ) => {
+                self.tick(1);
+                let hl = self.pair(RegisterPair::HL);
+                let value = self.pair(rp);
+                let hl = self.builder.ins().uextend(types::I32, hl);
+                let value = self.builder.ins().uextend(types::I32, value);
+                let sum = self.builder.ins().iadd(hl, value);
+                let hl_low = self.builder.ins().band_imm(hl, 0x0FFF);
+                let value_low = self.builder.ins().band_imm(value, 0x0FFF);
+                let half = self.builder.ins().iadd(hl_low, value_low);
+                let half = self
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::UnsignedGreaterThan, half, 0x0FFF);
+                let carry = self
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::UnsignedGreaterThan, sum, 0xFFFF);
+                self.flag(FLAG_N, false);
+                self.def(FLAG_H, half);
+                self.def(FLAG_C, carry);
+                let result = self.builder.ins().ireduce(types::I16, sum);
+                self.set_pair(RegisterPair::HL, result);
+            }
+            Instruction::Add(Operand::RegisterPair(rp), Operand::Immediate8(value)) => {
+                self.tick(2);
+                let result = self.add_stack_offset(value);
+                self.set_pair(rp, result);
+            }
+            Instruction::Add(..) => panic!("Illegal operands for ADD"),
+            Instruction::Inc(Operand::RegisterPair(rp))
+            | Instruction::Dec(Operand::RegisterPair(rp)) => {
+                self.tick(1);
+                let value = self.pair(rp);
+                self.oam_bug(value);
+                let step = if matches!(instruction, Instruction::Inc(_)) {
+                    1
+                } else {
+                    -1
+                };
+                let result = self.builder.ins().iadd_imm(value, step);
+                self.set_pair(rp, result);
+            }
+            Instruction::Inc(Operand::Register(register)) => {
+                let value = self.register(register);
+                let result = self.builder.ins().iadd_imm(value, 1);
+                self.set_register(register, result);
+                self.zero_flag(result);
+                self.flag(FLAG_N, false);
+                let low = self.builder.ins().band_imm(value, 0x0F);
+                let half = self.builder.ins().icmp_imm(IntCC::Equal, low, 0x0F);
+                self.def(FLAG_H, half);
+            }
+            Instruction::Dec(Operand::Register(register)) => {
+                let value = self.register(register);
+                let result = self.builder.ins().iadd_imm(value, -1);
+                self.set_register(register, result);
+                self.zero_flag(result);
+                self.flag(FLAG_N, true);
+                let low = self.builder.ins().band_imm(result, 0x0F);
+                let half = self.builder.ins().icmp_imm(IntCC::Equal, low, 0x0F);
+                self.def(FLAG_H, half);
+            }
+            Instruction::Inc(_) | Instruction::Dec(_) => panic!("Illegal operand"),
+            Instruction::Rlca => self.shift_a(Shift::Rlc),
+            Instruction::Rrca => self.shift_a(Shift::Rrc),
+            Instruction::Rla => self.shift_a(Shift::Rl),
+            Instruction::Rra => self.shift_a(Shift::Rr),
+            Instruction::Rlc(register) => self.shift_register(Shift::Rlc, register),
+            Instruction::Rrc(register) => self.shift_register(Shift::Rrc, register),
+            Instruction::Rl(register) => self.shift_register(Shift::Rl, register),
+            Instruction::Rr(register) => self.shift_register(Shift::Rr, register),
+            Instruction::Sla(register) => self.shift_register(Shift::Sla, register),
+            Instruction::Sra(register) => self.shift_register(Shift::Sra, register),
+            Instruction::Srl(register) => self.shift_register(Shift::Srl, register),
+            Instruction::Swap(register) => self.shift_register(Shift::Swap, register),
+            Instruction::Bit(bit, register) => {
+                let value = self.register(register);
+                let masked = self.builder.ins().band_imm(value, 1 << bit);
+                self.zero_flag(masked);
+                self.flag(FLAG_N, false);
+                self.flag(FLAG_H, true);
+            }
+            Instruction::Set(bit, register) => {
+                let value = self.register(register);
+                let result = self.builder.ins().bor_imm(value, 1 << bit);
+                self.set_register(register, result);
+            }
+            Instruction::Res(bit, register) => {
+                let value = self.register(register);
+                let result = self.builder.ins().band_imm(value, !(1 << bit));
+                self.set_register(register, result);
+            }
+            Instruction::Daa => self.daa(),
+            Instruction::Cpl => {
+                let a = self.get(A);
+                let result = self.builder.ins().bnot(a);
+                self.def(A, result);
+                self.flag(FLAG_N, true);
+                self.flag(FLAG_H, true);
+            }
+            Instruction::Scf | Instruction::Ccf => {
+                self.flag(FLAG_N, false);
+                self.flag(FLAG_H, false);
+                if matches!(instruction, Instruction::Scf) {
+                    self.flag(FLAG_C, true);
+                } else {
+                    let carry = self.get(FLAG_C);
+                    let carry = self.builder.ins().bxor_imm(carry, 1);
+                    self.def(FLAG_C, carry);
+                }
+            }
+            Instruction::Push(rp) => {
+                self.tick(1);
+                let value = self.pair(rp);
+                self.push(value);
+            }
+            Instruction::Pop(rp) => {
+                let value = self.pop();
+                self.set_pair(rp, value);
+            }
+            Instruction::Jr(condition, offset) => {
+                let target = next.wrapping_add(offset as u16);
+                self.branch(condition, count, |emitter| {
+                    emitter.tick(1);
+                    emitter.set(PC, types::I16, i64::from(target));
+                });
+                return true;
+            }
+            Instruction::Jp(condition, Operand::Immediate16(target)) => {
+                self.branch(condition, count, |emitter| {
+                    emitter.tick(1);
+                    emitter.set(PC, types::I16, i64::from(target));
+                });
+                return true;
+            }
+            Instruction::Jp(_, _) => {
+                let hl = self.pair(RegisterPair::HL);
+                self.def(PC, hl);
+                self.exit(count);
+                return true;
+            }
+            Instruction::Call(condition, target) => {
+                self.branch(condition, count, |emitter| {
+                    emitter.tick(1);
+                    let pc = emitter.constant(types::I16, i64::from(next));
+                    emitter.push(pc);
+                    emitter.set(PC, types::I16, i64::from(target));
+                });
+                return true;
+            }
+            Instruction::Rst(target) => {
+                self.tick(1);
+                let pc = self.constant(types::I16, i64::from(next));
+                self.push(pc);
+                self.set(PC, types::I16, i64::from(target));
+                self.exit(count);
+                return true;
+            }
+            Instruction::Ret(condition) => {
+                // Conditional returns spend a cycle checking the condition
+                if !matches!(condition, Condition::Always) {
+                    self.tick(1);
+                }
+                self.branch(condition, count, |emitter| {
+                    let pc = emitter.pop();
+                    emitter.def(PC, pc);
+                    emitter.tick(1);
+                });
+                return true;
+            }
+            Instruction::Halt
+            | Instruction::Stop
+            | Instruction::Ei
+            | Instruction::Di
+            | Instruction::Reti
+            | Instruction::Illegal(_) => {
+                unreachable!("{instruction:?} isn't compiled")
+            }
+        }
+        false
+    }
+
+    fn load(&mut self, target: Operand, source: Operand) {
+        match (target, source) {
+            (Operand::Register(Register::IncrementHL | Register::DecrementHL), _)
+            | (_, Operand::Register(Register::IncrementHL | Register::DecrementHL)) => {
+                let hl = self.pair(RegisterPair::HL);
+                let step = match (target, source) {
+                    (Operand::Register(Register::IncrementHL), _)
+                    | (_, Operand::Register(Register::IncrementHL)) => 1,
+                    _ => -1,
+                };
+                match (target, source) {
+                    (
+                        Operand::Register(Register::IncrementHL | Register::DecrementHL),
+                        Operand::Register(source),
+                    ) => {
+                        let value = self.get(variable(source));
+                        self.write(hl, value);
+                    }
+                    (Operand::Register(target), _) => {
+                        let value = self.read(hl);
+                        self.def(variable(target), value);
+                    }
+                    _ => panic!("Illegal operands for LD"),
+                }
+                self.oam_bug(hl);
+                let hl = self.builder.ins().iadd_imm(hl, step);
+                self.set_pair(RegisterPair::HL, hl);
+            }
+            (Operand::Register(Register::IndirectC), Operand::Register(source)) => {
+                let address = self.high_page_c();
+                let value = self.get(variable(source));
+                self.write(address, value);
+            }
+            (Operand::Register(target), Operand::Register(Register::IndirectC)) => {
+                let address = self.high_page_c();
+                let value = self.read(address);
+                self.def(variable(target), value);
+            }
+            (Operand::Register(target), Operand::Register(_) | Operand::Immediate8(_)) => {
+                let value = self.source(source);
+                self.set_register(target, value);
+            }
+            (Operand::RegisterIndirect(rp), Operand::Register(source)) => {
+                let address = self.pair(rp);
+                let value = self.get(variable(source));
+                self.write(address, value);
+            }
+            (Operand::Register(target), Operand::RegisterIndirect(rp)) => {
+                let address = self.pair(rp);
+                let value = self.read(address);
+                self.def(variable(target), value);
+            }
+            (Operand::IndirectImmediate8(address), Operand::Register(source)) => {
+                let address = self.constant(types::I16, 0xFF00 + i64::from(address));
+                let value = self.get(variable(source));
+                self.write(address, value);
+            }
+            (Operand::Register(target), Operand::IndirectImmediate8(address)) => {
+                let address = self.constant(types::I16, 0xFF00 + i64::from(address));
+                let value = self.read(address);
+                self.def(variable(target), value);
+            }
+            (Operand::IndirectImmediate16(address), Operand::Register(source)) => {
+                let address = self.constant(types::I16, i64::from(address));
+                let value = self.get(variable(source));
+                self.write(address, value);
+            }
+            (Operand::Register(target), Operand::IndirectImmediate16(address)) => {
+                let address = self.constant(types::I16, i64::from(address));
+                let value = self.read(address);
+                self.def(variable(target), value);
+            }
+            (Operand::RegisterPair(target), Operand::Immediate16(value)) => {
+                let value = self.constant(types::I16, i64::from(value));
+                self.set_pair(target, value);
+            }
+            (Operand::RegisterPair(target), Operand::RegisterPair(source)) => {
+                self.tick(1);
+                let value = self.pair(source);
+                self.set_pair(target, value);
+            }
+            (Operand::RegisterPair(_), Operand::StackOffset(offset)) => {
+                self.tick(1);
+                let result = self.add_stack_offset(offset as u8);
+                self.set_pair(RegisterPair::HL, result);
+            }
+            _ => panic!("Illegal operands for LD"),
+        }
+    }
+
+    fn high_page_c(&mut self) -> Value {
+        let c = self.get(C);
+        let c = self.builder.ins().uextend(types::I16, c);
+        self.builder.ins().bor_imm(c, 0xFF00)
+    }
+
+    /// Adds to A, with the carry flag for ADC
+    fn add(&mut self, value: Value, with_carry: bool) {
+        let a = self.get(A);
+        let a_wide = self.builder.ins().uextend(types::I32, a);
+        let value_wide = self.builder.ins().uextend(types::I32, value);
+        let mut sum = self.builder.ins().iadd(a_wide, value_wide);
+        let a_low = self.builder.ins().band_imm(a_wide, 0x0F);
+        let value_low = self.builder.ins().band_imm(value_wide, 0x0F);
+        let mut half = self.builder.ins().iadd(a_low, value_low);
+        if with_carry {
+            let carry = self.get(FLAG_C);
+            let carry = self.builder.ins().uextend(types::I32, carry);
+            sum = self.builder.ins().iadd(sum, carry);
+            half = self.builder.ins().iadd(half, carry);
+        }
+        let result = self.builder.ins().ireduce(types::I8, sum);
+        self.def(A, result);
+        self.zero_flag(result);
+        self.flag(FLAG_N, false);
+        let half = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::UnsignedGreaterThan, half, 0x0F);
+        self.def(FLAG_H, half);
+        let carry = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::UnsignedGreaterThan, sum, 0xFF);
+        self.def(FLAG_C, carry);
+    }
+
+    /// Subtracts from A, with the carry flag for SBC, and sets the flags. Returns the result
+    /// without storing it, for CP.
+    fn subtract(&mut self, value: Value, with_carry: bool) -> Value {
+        let a = self.get(A);
+        let a_wide = self.builder.ins().uextend(types::I32, a);
+        let value_wide = self.builder.ins().uextend(types::I32, value);
+        let mut difference = self.builder.ins().isub(a_wide, value_wide);
+        let a_low = self.builder.ins().band_imm(a_wide, 0x0F);
+        let value_low = self.builder.ins().band_imm(value_wide, 0x0F);
+        let mut half = self.builder.ins().isub(a_low, value_low);
+        if with_carry {
+            let carry = self.get(FLAG_C);
+            let carry = self.builder.ins().uextend(types::I32, carry);
+            difference = self.builder.ins().isub(difference, carry);
+            half = self.builder.ins().isub(half, carry);
+        }
+        let result = self.builder.ins().ireduce(types::I8, difference);
+        self.zero_flag(result);
+        self.flag(FLAG_N, true);
+        let half = self.builder.ins().icmp_imm(IntCC::SignedLessThan, half, 0);
+        self.def(FLAG_H, half);
+        let carry = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::SignedLessThan, difference, 0);
+        self.def(FLAG_C, carry);
+        result
+    }
+
+    /// SP plus a signed offset, setting the flags like ADD SP,e and LD HL,SP+e do
+    fn add_stack_offset(&mut self, offset: u8) -> Value {
+        let sp = self.get(SP);
+        let offset = i64::from(offset as i8);
+        let sp_wide = self.builder.ins().uextend(types::I32, sp);
+        let sp_nibble = self.builder.ins().band_imm(sp_wide, 0x0F);
+        let half = self.builder.ins().iadd_imm(sp_nibble, offset & 0x0F);
+        let half = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::UnsignedGreaterThan, half, 0x0F);
+        let sp_byte = self.builder.ins().band_imm(sp_wide, 0xFF);
+        let carry = self.builder.ins().iadd_imm(sp_byte, offset & 0xFF);
+        let carry = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::UnsignedGreaterThan, carry, 0xFF);
+        self.flag(FLAG_Z, false);
+        self.flag(FLAG_N, false);
+        self.def(FLAG_H, half);
+        self.def(FLAG_C, carry);
+        self.builder.ins().iadd_imm(sp, offset)
+    }
+
+    /// Shifts or rotates a value, and returns the result and the bit shifted out
+    fn shift(&mut self, shift: Shift, value: Value) -> (Value, Value) {
+        let high_bit = self.builder.ins().ushr_imm(value, 7);
+        let low_bit = self.builder.ins().band_imm(value, 1);
+        let carry = self.get(FLAG_C);
+        match shift {
+            Shift::Rlc => (self.builder.ins().rotl_imm(value, 1), high_bit),
+            Shift::Rrc => (self.builder.ins().rotr_imm(value, 1), low_bit),
+            Shift::Rl => {
+                let shifted = self.builder.ins().ishl_imm(value, 1);
+                (self.builder.ins().bor(shifted, carry), high_bit)
+            }
+            Shift::Rr => {
+                let shifted = self.builder.ins().ushr_imm(value, 1);
+                let carry = self.builder.ins().ishl_imm(carry, 7);
+                (self.builder.ins().bor(shifted, carry), low_bit)
+            }
+            Shift::Sla => (self.builder.ins().ishl_imm(value, 1), high_bit),
+            Shift::Sra => (self.builder.ins().sshr_imm(value, 1), low_bit),
+            Shift::Srl => (self.builder.ins().ushr_imm(value, 1), low_bit),
+            Shift::Swap => (
+                self.builder.ins().rotr_imm(value, 4),
+                self.constant(types::I8, 0),
+            ),
+        }
+    }
+
+    /// The CB-prefixed shifts and rotates, which set Z from the result
+    fn shift_register(&mut self, shift: Shift, register: Register) {
+        let value = self.register(register);
+        let (result, carry) = self.shift(shift, value);
+        self.set_register(register, result);
+        self.zero_flag(result);
+        self.flag(FLAG_N, false);
+        self.flag(FLAG_H, false);
+        self.def(FLAG_C, carry);
+    }
+
+    /// RLCA, RRCA, RLA and RRA, which always clear Z
+    fn shift_a(&mut self, shift: Shift) {
+        let value = self.get(A);
+        let (result, carry) = self.shift(shift, value);
+        self.def(A, result);
+        self.flag(FLAG_Z, false);
+        self.flag(FLAG_N, false);
+        self.flag(FLAG_H, false);
+        self.def(FLAG_C, carry);
+    }
+
+    /// Adjusts A to be valid BCD after an addition or subtraction, like `Cpu::execute` does
+    fn daa(&mut self) {
+        let a = self.get(A);
+        let subtract = self.get(FLAG_N);
+        let half = self.get(FLAG_H);
+        let carry = self.get(FLAG_C);
+        let added = self.builder.ins().icmp_imm(IntCC::Equal, subtract, 0);
+
+        let low = self.builder.ins().band_imm(a, 0x0F);
+        let low_over = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::UnsignedGreaterThan, low, 0x09);
+        let low_over = self.builder.ins().band(added, low_over);
+        let low_adjust = self.builder.ins().bor(half, low_over);
+
+        let high_over = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::UnsignedGreaterThan, a, 0x99);
+        let high_over = self.builder.ins().band(added, high_over);
+        let high_adjust = self.builder.ins().bor(carry, high_over);
+
+        let six = self.constant(types::I8, 0x06);
+        let sixty = self.constant(types::I8, 0x60);
+        let zero = self.constant(types::I8, 0);
+        let low_correction = self.builder.ins().select(low_adjust, six, zero);
+        let high_correction = self.builder.ins().select(high_adjust, sixty, zero);
+        let correction = self.builder.ins().bor(low_correction, high_correction);
+        let sum = self.builder.ins().iadd(a, correction);
+        let difference = self.builder.ins().isub(a, correction);
+        let result = self.builder.ins().select(added, sum, difference);
+
+        self.def(A, result);
+        self.zero_flag(result);
+        self.flag(FLAG_H, false);
+        self.def(FLAG_C, high_adjust);
+    }
+}
+
+unsafe extern "C" fn jit_tick<B: Bus + ?Sized>(bus: *mut u8, cycles: u32) {
+    let bus = &mut *bus.cast::<Box<B>>();
+    for _ in 0..cycles {
+        bus.tick();
+    }
+}
+
+unsafe extern "C" fn jit_read<B: Bus + ?Sized>(bus: *mut u8, address: u32) -> u32 {
+    let bus = &mut *bus.cast::<Box<B>>();
+    u32::from(bus.read_byte(address as u16))
+}
+
+/// Writes a byte, and returns 1 if it went to the cartridge's mapper registers or started OAM
+/// DMA, since the block can't keep running from ROM after either
+unsafe extern "C" fn jit_write<B: Bus + ?Sized>(bus: *mut u8, address: u32, value: u32) -> u32 {
+    let bus = &mut *bus.cast::<Box<B>>();
+    bus.write_byte(address as u16, value as u8);
+    u32::from(address < 0x8000 || address == 0xFF46)
+}
+
+unsafe extern "C" fn jit_oam_bug<B: Bus + ?Sized>(bus: *mut u8, address: u32) {
+    let bus = &mut *bus.cast::<Box<B>>();
+    bus.oam_bug(address as u16);
+}
+
+/// Nonzero if an enabled interrupt is requested
+unsafe extern "C" fn jit_pending<B: Bus + ?Sized>(bus: *mut u8) -> u32 {
+    let bus = &*bus.cast::<Box<B>>();
+    u32::from(bus.get_interrupt_enable() & bus.get_interrupt_flags() & 0x1F)
+}
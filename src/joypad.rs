@@ -0,0 +1,82 @@
+//! The joypad register P1 (0xFF00), where the game selects a row of buttons and reads which of
+//! them are pressed
+
+use crate::interrupts::Interrupt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Right = 0,
+    Left = 1,
+    Up = 2,
+    Down = 3,
+    A = 4,
+    B = 5,
+    Select = 6,
+    Start = 7,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    /// The button's bit in a set of pressed buttons. The low nibble holds the directions, and
+    /// the high nibble the action buttons, in the order they're read from P1.
+    #[must_use]
+    pub fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+#[derive(Default)]
+pub struct Joypad {
+    /// Bits 4 and 5 of P1, which select the directions and the action buttons when 0
+    pub(crate) select: u8,
+    /// The buttons currently held, as a mask of `Button::mask` bits
+    pub pressed: u8,
+}
+
+impl Joypad {
+    /// Reads P1, where the low nibble has a 0 for each pressed button in the selected rows
+    #[must_use]
+    pub fn read_byte(&self) -> u8 {
+        0xC0 | self.select | self.lines()
+    }
+
+    /// Selects rows of buttons. Returns the joypad interrupt if this makes a line go low.
+    pub fn write_byte(&mut self, value: u8) -> Option<Interrupt> {
+        self.update(|joypad| joypad.select = value & 0x30)
+    }
+
+    /// Sets the buttons that are held. Returns the joypad interrupt if a button in a selected
+    /// row was pressed.
+    pub fn set_pressed(&mut self, pressed: u8) -> Option<Interrupt> {
+        self.update(|joypad| joypad.pressed = pressed)
+    }
+
+    /// The input lines in the low nibble of P1
+    fn lines(&self) -> u8 {
+        let mut lines = 0x0F;
+        if self.select & 0x10 == 0 {
+            lines &= !self.pressed & 0x0F;
+        }
+        if self.select & 0x20 == 0 {
+            lines &= !(self.pressed >> 4);
+        }
+        lines
+    }
+
+    /// Makes a change, requesting the interrupt if any input line went from high to low
+    fn update(&mut self, change: impl FnOnce(&mut Self)) -> Option<Interrupt> {
+        let lines = self.lines();
+        change(self);
+        (lines & !self.lines() != 0).then_some(Interrupt::Joypad)
+    }
+}
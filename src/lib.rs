@@ -1,6 +1,45 @@
+#[cfg(feature = "achievements")]
+pub mod achievements;
+pub mod animation;
+pub mod apu;
+pub mod audio;
+pub mod block_cache;
+#[cfg(feature = "bundled-bootrom")]
+pub mod boot_rom;
 pub mod bus;
 pub mod cartridge;
+pub mod compat_palettes;
+pub mod compositor;
+pub mod coverage;
 pub mod cpu;
+pub mod debug;
+pub mod disasm;
+pub mod dma;
+pub mod ffi;
+pub mod gameboy;
+pub mod gdb;
+pub mod hdma;
+pub mod header;
 pub mod interrupts;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod joypad;
+pub mod link;
+pub mod metadata;
+pub mod model;
+pub mod movie;
+pub mod opcodes;
+pub mod palette;
+pub mod peripheral;
+pub mod png;
 pub mod ppu;
+pub mod scaler;
+pub mod scheduler;
+pub mod serial;
+pub mod sgb;
+pub mod state;
+pub mod symbols;
 pub mod timer;
+pub mod video;
+pub mod watchpoints;
+pub mod websocket;
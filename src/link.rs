@@ -0,0 +1,126 @@
+//! Link cable emulation over TCP, so two instances can play together.
+//!
+//! Each serial transfer is sent as two bytes: a kind, and the byte shifted out. The side that
+//! provides the clock (the master) sends `TRANSFER` with its byte, and the other side answers
+//! with `REPLY` and its own byte. If both sides start a transfer at once, each one takes the
+//! other's `TRANSFER` as its answer, like two Game Boys clocking each other.
+
+use crate::serial::LinkDevice;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const TRANSFER: u8 = 0;
+const REPLY: u8 = 1;
+
+/// How long the master waits for the other side to answer before giving up on the transfer
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct TcpLink {
+    stream: TcpStream,
+    connected: bool,
+}
+
+impl TcpLink {
+    /// Waits for another instance to connect on a port
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the port can't be listened on or the connection fails
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let (stream, _) = TcpListener::bind(("0.0.0.0", port))?.accept()?;
+        Self::new(stream)
+    }
+
+    /// Connects to another instance that's listening
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the connection fails
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        Self::new(TcpStream::connect(address)?)
+    }
+
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            connected: true,
+        })
+    }
+
+    #[must_use]
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn send(&mut self, kind: u8, byte: u8) -> io::Result<()> {
+        self.stream.set_nonblocking(false)?;
+        let result = self.stream.write_all(&[kind, byte]);
+        self.stream.set_nonblocking(true)?;
+        result
+    }
+}
+
+impl LinkDevice for TcpLink {
+    /// As the master, sends a byte and waits for the other side's byte. Receives 0xFF if the
+    /// other side is disconnected or doesn't answer in time.
+    fn exchange(&mut self, byte: u8) -> u8 {
+        if !self.connected {
+            return 0xFF;
+        }
+        let result = self.send(TRANSFER, byte).and_then(|()| {
+            self.stream.set_nonblocking(false)?;
+            self.stream.set_read_timeout(Some(TIMEOUT))?;
+            let mut message = [0; 2];
+            let result = self.stream.read_exact(&mut message);
+            self.stream.set_nonblocking(true)?;
+            result.map(|()| message[1])
+        });
+        match result {
+            Ok(byte) => byte,
+            Err(error) => {
+                if error.kind() != ErrorKind::WouldBlock && error.kind() != ErrorKind::TimedOut {
+                    self.connected = false;
+                }
+                0xFF
+            }
+        }
+    }
+
+    /// As the slave, checks whether the other side has started a transfer, and if so answers
+    /// with `byte` and returns the other side's byte
+    fn poll(&mut self, byte: u8) -> Option<u8> {
+        if !self.connected {
+            return None;
+        }
+        let mut message = [0; 2];
+        match self.stream.peek(&mut message) {
+            Ok(2) => (),
+            Ok(0) => {
+                self.connected = false;
+                return None;
+            }
+            Ok(_) => return None,
+            Err(error) => {
+                if error.kind() != ErrorKind::WouldBlock {
+                    self.connected = false;
+                }
+                return None;
+            }
+        }
+        if self.stream.read_exact(&mut message).is_err() {
+            self.connected = false;
+            return None;
+        }
+        // A late answer to a transfer that timed out is dropped
+        if message[0] != TRANSFER {
+            return None;
+        }
+        if self.send(REPLY, byte).is_err() {
+            self.connected = false;
+        }
+        Some(message[1])
+    }
+}
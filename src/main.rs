@@ -1,14 +1,47 @@
-use clap::Parser;
-use std::path::PathBuf;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
+use regex::Regex;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 
-mod bus;
-mod cartridge;
-mod cpu;
-mod interrupts;
-mod ppu;
-mod timer;
+use config::Config;
+use rgb_emu::animation::{AnimationWriter, Format};
+use rgb_emu::audio::WavWriter;
+use rgb_emu::block_cache::BlockCache;
+use rgb_emu::bus::{Bus, DmgBus};
+use rgb_emu::cartridge;
+use rgb_emu::coverage::Coverage;
+use rgb_emu::cpu::Cpu;
+use rgb_emu::debug::{self, TraceBuffer};
+use rgb_emu::gdb;
+#[cfg(feature = "jit")]
+use rgb_emu::jit::Jit;
+use rgb_emu::link::TcpLink;
+use rgb_emu::metadata::Metadata;
+use rgb_emu::model::Model;
+use rgb_emu::movie::Movie;
+use rgb_emu::palette::Palette;
+use rgb_emu::png;
+use rgb_emu::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+#[cfg(feature = "gui")]
+use rgb_emu::scaler::Filter;
+use rgb_emu::state;
+use rgb_emu::symbols::Symbols;
+use rgb_emu::video::{Frame, FrameHashWriter, VideoSink};
+use rgb_emu::websocket::WebSocketStreamer;
+use saves::SaveFile;
 
-use cpu::{Cpu, RegisterPair};
+mod config;
+mod crash;
+mod debugger;
+#[cfg(feature = "gui")]
+mod gui;
+mod headless;
+#[cfg(feature = "gui")]
+mod pacing;
+mod saves;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -17,21 +50,399 @@ struct Cli {
     #[arg(index = 1, value_name = "ROM")]
     rom: PathBuf,
 
-    /// Game Boy Boot ROM file
+    /// Game Boy Boot ROM file. Without one, a free DMG boot ROM runs on DMG models if it's
+    /// compiled in, and the boot ROM's effects are emulated otherwise.
     #[arg(short, long, value_name = "FILE")]
     bootrom: Option<PathBuf>,
 
-    /// Log debugging information to stdout
+    /// Read settings from a TOML file instead of rgb/config.toml in the configuration directory.
+    /// Options given on the command line override the file.
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Directory for battery saves, instead of next to the ROM
+    #[arg(long, value_name = "DIR")]
+    save_dir: Option<PathBuf>,
+
+    /// Game Boy model to emulate: dmg, dmg0, mgb, sgb or cgb. Defaults to cgb for cartridges
+    /// with CGB support, and dmg otherwise.
+    #[arg(long, value_name = "MODEL")]
+    model: Option<Model>,
+
+    /// Start paused at an interactive debugger prompt in the terminal. Continuing resumes
+    /// emulation until a breakpoint is hit, which returns to the prompt.
+    #[arg(long)]
+    debugger: bool,
+
+    /// Start paused and wait for a debugger to attach with the GDB remote protocol on a port
+    #[arg(long, value_name = "PORT")]
+    gdb: Option<u16>,
+
+    /// Log the CPU state before each instruction to stdout
     #[arg(short, long)]
     debug: bool,
+
+    /// Log the CPU state before each instruction to a file in the Gameboy Doctor format, with
+    /// LY stubbed to 0x90 like it expects
+    #[arg(long, value_name = "FILE")]
+    doctor: Option<PathBuf>,
+
+    /// Overclock the CPU by this factor relative to the rest of the system, reducing slowdown
+    /// in games that lag on real hardware (not accurate)
+    #[arg(long, value_name = "FACTOR", default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=8))]
+    turbo: u8,
+
+    /// Emulate hardware quirks that games rarely depend on, like the DMG OAM corruption bug and
+    /// VRAM, OAM and CGB palettes being locked while the PPU uses them, at a small cost in speed
+    #[arg(long)]
+    accurate: bool,
+
+    /// Refuse to load savestates and play movies made with a different emulator version,
+    /// model, boot ROM or accuracy settings, instead of warning that they may desync
+    #[arg(long)]
+    strict: bool,
+
+    /// Start from a savestate. In the window, F6 saves a state next to the ROM and F7 loads it.
+    #[arg(long, value_name = "FILE")]
+    load_state: Option<PathBuf>,
+
+    /// Record the buttons held in each frame to a movie file, starting from the current state
+    #[arg(long, value_name = "FILE", conflicts_with = "play_movie")]
+    record_movie: Option<PathBuf>,
+
+    /// Play back a movie file. Without a window, the emulator exits when the movie ends.
+    #[arg(long, value_name = "FILE")]
+    play_movie: Option<PathBuf>,
+
+    /// Wait for another instance to connect a link cable on a port
+    #[arg(long, value_name = "PORT", conflicts_with = "link_connect")]
+    link_listen: Option<u16>,
+
+    /// Connect a link cable to another instance that's listening, at HOST:PORT
+    #[arg(long, value_name = "ADDRESS")]
+    link_connect: Option<String>,
+
+    /// Print the cartridge header and exit
+    #[arg(long)]
+    info: bool,
+
+    /// Run without video or audio, until an exit condition is met. If the CPU locks up, or
+    /// executes STOP with no button held, the emulator exits with a failing status instead.
+    #[arg(long)]
+    headless: bool,
+
+    /// In headless mode, exit after this many frames
+    #[arg(long, value_name = "N", requires = "headless")]
+    exit_after_frames: Option<u64>,
+
+    /// In headless mode, exit after this many instructions
+    #[arg(long, value_name = "N", requires = "headless")]
+    exit_after_instructions: Option<u64>,
+
+    /// In headless mode, exit when the output sent over the serial port matches a regular
+    /// expression. This replaces any link cable.
+    #[arg(long, value_name = "REGEX", requires = "headless")]
+    exit_on_serial: Option<Regex>,
+
+    /// In headless mode, exit when the CPU reaches an infinite `JR -2` loop, like test ROMs do
+    /// when they're done
+    #[arg(long, requires = "headless")]
+    exit_on_loop: bool,
+
+    /// In headless mode, run cached blocks of decoded ROM code, which is faster but skips some
+    /// edge cases. Instruction counts and loop detection only happen between blocks.
+    #[arg(
+        long,
+        requires = "headless",
+        conflicts_with_all = ["debug", "debugger", "gdb", "doctor", "export_disassembly"]
+    )]
+    block_cache: bool,
+
+    /// In headless mode, also compile hot blocks of ROM code to native code (experimental)
+    #[cfg(feature = "jit")]
+    #[arg(
+        long,
+        requires = "headless",
+        conflicts_with_all = ["debug", "debugger", "gdb", "doctor", "export_disassembly"]
+    )]
+    jit: bool,
+
+    /// Record audio output to a WAV file
+    #[arg(long, value_name = "FILE")]
+    record_audio: Option<PathBuf>,
+
+    /// Record a CRC32 hash of every frame to a text file
+    #[arg(long, value_name = "FILE")]
+    record_frame_hashes: Option<PathBuf>,
+
+    /// Colors of the four DMG shades: grayscale, green, or four hex colors from lightest to
+    /// darkest, like E0F8D0,88C070,346856,081820
+    #[arg(long, value_name = "PALETTE", default_value_t = Palette::default())]
+    palette: Palette,
+
+    /// Record gameplay to an animated GIF (.gif) or APNG (.png or .apng) file
+    #[arg(long, value_name = "FILE")]
+    record_animation: Option<PathBuf>,
+
+    /// Merge identical consecutive frames in recorded animations, which makes them smaller
+    #[arg(long)]
+    dedupe_frames: bool,
+
+    /// Stream every frame as RGB24 to WebSocket clients connecting to a local port
+    #[arg(long, value_name = "PORT")]
+    stream_websocket: Option<u16>,
+
+    /// Save a screenshot of frame N to a PNG file next to the ROM
+    #[arg(long, value_name = "N")]
+    screenshot_at_frame: Option<u64>,
+
+    /// On exit, write a disassembly of all ROM code that was executed to a text file, with
+    /// labels from a .sym file next to the ROM if there is one
+    #[arg(long, value_name = "FILE")]
+    export_disassembly: Option<PathBuf>,
+
+    /// Scale the screen up by this integer factor
+    #[cfg(feature = "gui")]
+    #[arg(long, value_name = "FACTOR", default_value_t = 3, value_parser = clap::value_parser!(u8).range(1..=8))]
+    scale: u8,
+
+    /// Filter to apply when scaling the screen up: none, scanlines, lcd-grid, or smooth (for
+    /// Scale2x and Scale3x)
+    #[cfg(feature = "gui")]
+    #[arg(long, value_name = "FILTER", default_value_t = Filter::default())]
+    filter: Filter,
+
+    /// Open a debug view window (can be repeated)
+    #[cfg(feature = "gui")]
+    #[arg(long, value_name = "VIEW")]
+    view: Vec<gui::View>,
+
+    /// Emulation speed as a multiple of real time, or 0 to run unthrottled. Holding Tab
+    /// fast-forwards without a limit.
+    #[cfg(feature = "gui")]
+    #[arg(long, value_name = "X", default_value_t = 1.0, value_parser = parse_speed)]
+    speed: f32,
+
+    /// Print frame presentation timing statistics on exit
+    #[cfg(feature = "gui")]
+    #[arg(long)]
+    pacing_report: bool,
+
+    /// Save power by sleeping until each frame is due without spinning, at the cost of some
+    /// timing jitter
+    #[cfg(feature = "gui")]
+    #[arg(long)]
+    power_save: bool,
+
+    /// Keys mapped to the joypad buttons, from the configuration file
+    #[cfg(feature = "gui")]
+    #[arg(skip)]
+    keys: config::Keys,
+}
+
+impl Cli {
+    /// Fills in the options that weren't given on the command line from a configuration file
+    fn apply_config(&mut self, config: Config, matches: &ArgMatches) {
+        let unset = |id| matches.value_source(id) != Some(ValueSource::CommandLine);
+        if let (true, Some(palette)) = (unset("palette"), config.palette) {
+            self.palette = palette;
+        }
+        if let (true, Some(turbo)) = (unset("turbo"), config.turbo) {
+            match turbo {
+                1..=8 => self.turbo = turbo,
+                _ => println!("Turbo in the config file must be 1-8, skipping..."),
+            }
+        }
+        self.bootrom = self.bootrom.take().or(config.bootrom);
+        self.save_dir = self.save_dir.take().or(config.save_dir);
+        #[cfg(feature = "gui")]
+        {
+            if let (true, Some(scale)) = (unset("scale"), config.scale) {
+                match scale {
+                    1..=8 => self.scale = scale,
+                    _ => println!("Scale in the config file must be 1-8, skipping..."),
+                }
+            }
+            if let (true, Some(filter)) = (unset("filter"), config.filter) {
+                self.filter = filter;
+            }
+            if let (true, Some(speed)) = (unset("speed"), config.speed) {
+                if speed >= 0.0 && speed.is_finite() {
+                    self.speed = speed;
+                } else {
+                    println!("Speed in the config file must be a non-negative number, skipping...");
+                }
+            }
+            self.keys = config.keys;
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+fn parse_speed(speed: &str) -> Result<f32, String> {
+    match speed.parse() {
+        Ok(speed) if speed >= 0.0 && f32::is_finite(speed) => Ok(speed),
+        _ => Err("must be a non-negative number".to_string()),
+    }
+}
+
+/// Where to save a screenshot or recording that starts at a frame: next to the ROM, named after
+/// it and the frame number
+fn capture_path(rom: &Path, frame: u64, extension: &str) -> PathBuf {
+    let stem = rom.file_stem().unwrap_or_default().to_string_lossy();
+    rom.with_file_name(format!("{stem}-{frame}.{extension}"))
+}
+
+/// Saves a frame as a PNG file
+fn save_screenshot(path: &Path, frame: &Frame, palette: &Palette) -> std::io::Result<()> {
+    std::fs::write(
+        path,
+        png::encode(SCREEN_WIDTH, SCREEN_HEIGHT, &frame.to_rgb24(palette)),
+    )
+}
+
+/// Saves a screenshot when a given frame is completed
+struct ScreenshotAtFrame {
+    frame: u64,
+    path: PathBuf,
+    palette: Palette,
+}
+
+impl VideoSink for ScreenshotAtFrame {
+    fn push_frame(&mut self, frame: &Frame) {
+        if frame.number == self.frame {
+            match save_screenshot(&self.path, frame, &self.palette) {
+                Ok(()) => println!("Screenshot saved to {}", self.path.display()),
+                Err(error) => println!("Can't save screenshot: {error}"),
+            }
+        }
+    }
+}
+
+/// Debugging tools that record the CPU state before each instruction
+struct Tools {
+    trace: TraceBuffer,
+    coverage: Option<Coverage>,
+    /// Where to log each instruction in the Gameboy Doctor format
+    log: Option<Box<dyn Write>>,
+    symbols: Symbols,
+    /// Movie being recorded or played back
+    movie: Option<Movie>,
+    /// The ROM file, which crash dumps are written next to
+    rom_path: PathBuf,
+}
+
+/// Sets the buttons for a new frame, from the movie if one is being played back, or from the
+/// `live` buttons held in the frontend otherwise. Returns `false` if playback has just ended.
+fn start_frame(cpu: &mut Cpu<DmgBus>, tools: &mut Tools, live: u8) -> bool {
+    let Some(movie) = &mut tools.movie else {
+        cpu.bus.set_buttons(live);
+        return true;
+    };
+    if movie.next_frame(cpu, live) {
+        return true;
+    }
+    println!("Movie ended after {} frames", movie.len());
+    tools.movie = None;
+    cpu.bus.set_buttons(live);
+    false
+}
+
+/// Runs an instruction, or a block of them with the block cache, and returns how many ran
+fn step(cpu: &mut Cpu<DmgBus>, tools: &mut Tools) -> u64 {
+    tools.trace.record(cpu);
+    if let Some(coverage) = &mut tools.coverage {
+        coverage.record(cpu);
+    }
+    if let Some(log) = &mut tools.log {
+        // Nothing is executed while halted
+        if !cpu.halted && writeln!(log, "{}", debug::doctor_line(cpu)).is_err() {
+            println!("Can't write log, stopping logging");
+            tools.log = None;
+        }
+    }
+    let pc = cpu.registers.pc;
+    let was_locked = cpu.locked;
+    let instructions = if cpu.block_cache.is_some() {
+        cpu.run_block() as u64
+    } else {
+        cpu.step();
+        1
+    };
+    if cpu.locked && !was_locked {
+        let opcode = cpu.bus.peek_byte(pc);
+        println!("Illegal opcode {opcode:02X} at {pc:04X}; the CPU has locked up");
+        write_crash_dump(cpu, tools);
+    }
+    instructions
+}
+
+/// Compares the settings a savestate or movie was made with to the current ones. Differences
+/// are printed as warnings, or refuse the load with `--strict`.
+fn check_metadata(
+    what: &str,
+    saved: &Metadata,
+    cpu: &Cpu<DmgBus>,
+    strict: bool,
+) -> Result<(), String> {
+    for mismatch in saved.mismatches(&cpu.bus.metadata()) {
+        if strict {
+            return Err(format!("{what} was {mismatch}"));
+        }
+        println!("Warning: {what} was {mismatch}; it may desync");
+    }
+    Ok(())
+}
+
+/// Loads a savestate, after checking the settings it was made with
+fn load_state(cpu: &mut Cpu<DmgBus>, data: &[u8], strict: bool) -> Result<(), String> {
+    let saved = state::metadata(data).map_err(|error| error.to_string())?;
+    check_metadata("state", &saved, cpu, strict)?;
+    state::load(cpu, data).map_err(|error| error.to_string())
+}
+
+fn write_crash_dump(cpu: &Cpu<DmgBus>, tools: &Tools) {
+    match crash::write_dump(&tools.rom_path, cpu, &tools.trace) {
+        Ok((report, state)) => println!(
+            "Crash dump written to {}; inspect it with --load-state {} --debugger",
+            report.display(),
+            state.display()
+        ),
+        Err(_) => println!("Can't write crash dump"),
+    }
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    match Config::load(cli.config.as_deref()) {
+        Ok(config) => cli.apply_config(config, &matches),
+        Err(error) => println!("Can't load config file {error}, skipping..."),
+    }
 
-    let mut cpu = Cpu::new();
+    let mut cpu = Cpu::with_bus(Box::new(DmgBus::new()));
 
-    if !match cli.bootrom {
+    let rom = std::fs::read(&cli.rom).expect("Unable to open ROM");
+    let (mut cartridge, header) = match cartridge::load(rom.clone()) {
+        Ok(cartridge) => cartridge,
+        Err(error) => {
+            println!("Can't load ROM: {error}");
+            std::process::exit(1);
+        }
+    };
+    for mismatch in header.checksum_mismatches(&rom) {
+        println!("Warning: {mismatch}; the ROM may be a bad dump");
+    }
+    if cli.info {
+        println!("{header}");
+        return;
+    }
+    let mut save_file = SaveFile::load(&cli.rom, cli.save_dir.as_deref(), cartridge.as_mut());
+    cpu.bus.insert_cartridge(cartridge);
+    cpu.bus
+        .set_model(cli.model.unwrap_or_else(|| Model::for_header(&header)));
+
+    if !match &cli.bootrom {
         Some(bootrom_file) => match std::fs::read(bootrom_file) {
             Ok(bootrom) => {
                 cpu.bus.set_boot_rom(bootrom);
@@ -42,36 +453,213 @@ fn main() {
                 false
             }
         },
+        #[cfg(feature = "bundled-bootrom")]
+        None if matches!(cpu.bus.model(), Model::Dmg | Model::Dmg0 | Model::Mgb) => {
+            cpu.bus.set_boot_rom(rgb_emu::boot_rom::DMG.to_vec());
+            true
+        }
         None => false,
     } {
         cpu.set_post_boot_state();
     };
 
-    let rom = std::fs::read(cli.rom).expect("Unable to open ROM");
-    cpu.bus.insert_cartridge(cartridge::from_rom(rom));
-
-    loop {
-        // gucci:
-        if cli.debug {
-            println!("A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
-                cpu.registers.a,
-                cpu.get_register_pair(&RegisterPair::AF) & 0xFF,
-                cpu.registers.b,
-                cpu.registers.c,
-                cpu.registers.d,
-                cpu.registers.e,
-                cpu.registers.h,
-                cpu.registers.l,
-                cpu.get_register_pair(&RegisterPair::SP),
-                cpu.registers.pc,
-                cpu.bus.read_byte(cpu.registers.pc),
-                cpu.bus.read_byte(cpu.registers.pc+1),
-                cpu.bus.read_byte(cpu.registers.pc+2),
-                cpu.bus.read_byte(cpu.registers.pc+3),
-            );
-        }
-        let opcode = cpu.fetch();
-        let instruction = cpu.decode(opcode);
-        cpu.execute(instruction);
+    cpu.bus.set_oam_bug(cli.accurate);
+    cpu.bus.set_ppu_access_locks(cli.accurate);
+
+    if let Some(state_file) = &cli.load_state {
+        match std::fs::read(state_file) {
+            Ok(data) => {
+                if let Err(error) = load_state(&mut cpu, &data, cli.strict) {
+                    println!("Can't load state: {error}");
+                }
+            }
+            Err(_) => println!("Can't open state file, skipping..."),
+        }
+    }
+
+    if let Some(wav_file) = &cli.record_audio {
+        match WavWriter::create(wav_file) {
+            Ok(wav) => cpu.bus.set_audio_sink(Box::new(wav)),
+            Err(_) => println!("Can't create WAV file, skipping..."),
+        }
+    }
+
+    let link = match (cli.link_listen, &cli.link_connect) {
+        (Some(port), _) => {
+            println!("Waiting for a link cable connection on port {port}");
+            Some(TcpLink::listen(port))
+        }
+        (None, Some(address)) => Some(TcpLink::connect(address.as_str())),
+        (None, None) => None,
+    };
+    match link {
+        Some(Ok(link)) => cpu.bus.set_link_device(Box::new(link)),
+        Some(Err(error)) => println!("Can't connect link cable: {error}"),
+        None => (),
+    }
+
+    if let Some(hash_file) = &cli.record_frame_hashes {
+        match FrameHashWriter::create(hash_file) {
+            Ok(hashes) => cpu.bus.add_video_sink(Box::new(hashes)),
+            Err(_) => println!("Can't create frame hash file, skipping..."),
+        }
+    }
+
+    if let Some(port) = cli.stream_websocket {
+        match WebSocketStreamer::bind(port) {
+            Ok(mut streamer) => {
+                streamer.palette = cli.palette;
+                println!("Streaming frames to WebSocket clients on port {port}");
+                cpu.bus.add_video_sink(Box::new(streamer));
+            }
+            Err(error) => println!("Can't stream frames on port {port}: {error}"),
+        }
+    }
+
+    if let Some(path) = &cli.record_animation {
+        match Format::from_path(path) {
+            Some(format) => match AnimationWriter::create(path, format, cli.dedupe_frames) {
+                Ok(mut animation) => {
+                    animation.palette = cli.palette;
+                    cpu.bus.add_video_sink(Box::new(animation));
+                }
+                Err(_) => println!("Can't create animation file, skipping..."),
+            },
+            None => println!("Animations must be .gif, .png or .apng files, skipping..."),
+        }
+    }
+
+    if let Some(frame) = cli.screenshot_at_frame {
+        cpu.bus.add_video_sink(Box::new(ScreenshotAtFrame {
+            frame,
+            path: capture_path(&cli.rom, frame, "png"),
+            palette: cli.palette,
+        }));
+    }
+
+    if cli.turbo > 1 {
+        println!("CPU overclocked {}x; emulation is not accurate", cli.turbo);
+        cpu.bus.set_cpu_overclock(cli.turbo);
+    }
+
+    if cli.block_cache {
+        cpu.block_cache = Some(BlockCache::new());
+    }
+    #[cfg(feature = "jit")]
+    if cli.jit {
+        match Jit::new() {
+            Ok(jit) => cpu.block_cache = Some(BlockCache::with_jit(jit)),
+            Err(error) => {
+                println!("Can't start the JIT: {error}");
+                cpu.block_cache = Some(BlockCache::new());
+            }
+        }
+    }
+
+    let log: Option<Box<dyn Write>> = match &cli.doctor {
+        Some(path) => match File::create(path) {
+            Ok(file) => {
+                cpu.bus.stub_ly(Some(0x90));
+                Some(Box::new(BufWriter::new(file)))
+            }
+            Err(_) => {
+                println!("Can't create log file, skipping...");
+                None
+            }
+        },
+        None if cli.debug => Some(Box::new(std::io::stdout())),
+        None => None,
+    };
+    let mut tools = Tools {
+        trace: TraceBuffer::new(crash::TRACE_LENGTH),
+        coverage: cli.export_disassembly.as_ref().map(|_| Coverage::new()),
+        log,
+        symbols: Symbols::load(&cli.rom.with_extension("sym")).unwrap_or_default(),
+        movie: None,
+        rom_path: cli.rom.clone(),
+    };
+
+    if let Some(movie_file) = &cli.play_movie {
+        let movie = std::fs::read(movie_file)
+            .map_err(|error| error.to_string())
+            .and_then(|data| Movie::from_bytes(&data).map_err(|error| error.to_string()));
+        match movie {
+            Ok(mut movie) => match check_metadata("movie", movie.metadata(), &cpu, cli.strict)
+                .and_then(|()| movie.start(&mut cpu).map_err(|error| error.to_string()))
+            {
+                Ok(()) => tools.movie = Some(movie),
+                Err(error) => println!("Can't play movie: {error}"),
+            },
+            Err(error) => println!("Can't open movie file: {error}"),
+        }
+    } else if cli.record_movie.is_some() {
+        tools.movie = Some(Movie::record(&cpu));
+    }
+
+    // Whether the emulator ran to completion, rather than stopping because the CPU locked up
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        if cli.debugger && !debugger::run(&mut cpu, &mut tools) {
+            if save_file.flush(&cpu).is_err() {
+                println!("Can't write save file");
+            }
+            return true;
+        }
+
+        if let Some(port) = cli.gdb {
+            match std::net::TcpListener::bind(("127.0.0.1", port)) {
+                Ok(listener) => {
+                    println!("Waiting for a debugger on port {port}");
+                    if let Err(error) = gdb::serve(&mut cpu, &listener, |cpu| {
+                        step(cpu, &mut tools);
+                    }) {
+                        println!("Debugger connection error: {error}");
+                    }
+                }
+                Err(error) => println!("Can't listen on port {port}: {error}"),
+            }
+            if save_file.flush(&cpu).is_err() {
+                println!("Can't write save file");
+            }
+            return true;
+        }
+
+        let exit = headless::ExitConditions {
+            frames: cli.exit_after_frames,
+            instructions: cli.exit_after_instructions,
+            serial: cli.exit_on_serial.clone(),
+            infinite_loop: cli.exit_on_loop,
+        };
+        if cli.headless || cfg!(not(feature = "gui")) {
+            return headless::run(&mut cpu, &mut tools, &mut save_file, &exit);
+        }
+        #[cfg(feature = "gui")]
+        if let Err(error) = gui::run(&mut cpu, &cli, &rom, &mut tools, &mut save_file) {
+            println!("GUI error: {error}");
+        }
+        true
+    }));
+    if let Some(log) = &mut tools.log {
+        let _ = log.flush();
+    }
+
+    if let (Some(path), Some(movie)) = (&cli.record_movie, &tools.movie) {
+        if std::fs::write(path, movie.to_bytes()).is_err() {
+            println!("Can't write movie file");
+        }
+    }
+
+    if let (Some(path), Some(coverage)) = (&cli.export_disassembly, &tools.coverage) {
+        if std::fs::write(path, coverage.disassemble(&rom, &tools.symbols)).is_err() {
+            println!("Can't write disassembly file");
+        }
+    }
+
+    match result {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(1),
+        Err(_) => {
+            write_crash_dump(&cpu, &tools);
+            std::process::exit(1);
+        }
     }
 }
@@ -0,0 +1,132 @@
+//! Information about the emulator setup that produced a savestate or movie.
+//!
+//! Loading a savestate or playing back a movie under different settings than it was made with
+//! can cause subtle desyncs, so the metadata is stored alongside them and compared on load.
+
+use std::fmt;
+
+use crate::model::Model;
+
+/// Emulator version, model, boot ROM and accuracy settings used when a savestate or movie was
+/// made
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    pub version: String,
+    pub model: Model,
+    /// CRC32 of the boot ROM, if one was used
+    pub boot_rom_crc: Option<u32>,
+    /// Whether the DMG OAM corruption bug was emulated
+    pub oam_bug: bool,
+    /// Whether the PPU locked the CPU out of VRAM and OAM
+    pub ppu_access_locks: bool,
+}
+
+impl Metadata {
+    /// Metadata for this build of the emulator, emulating the given model with the given boot
+    /// ROM and the accuracy settings off
+    #[must_use]
+    pub fn current(model: Model, boot_rom: Option<&[u8]>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            model,
+            boot_rom_crc: boot_rom.map(crc32),
+            oam_bug: false,
+            ppu_access_locks: false,
+        }
+    }
+
+    /// Serializes the metadata as `key=value` lines
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let boot_rom = self
+            .boot_rom_crc
+            .map_or_else(|| String::from("none"), |crc| format!("{crc:08X}"));
+        format!(
+            "version={}\nmodel={}\nboot_rom={boot_rom}\noam_bug={}\nppu_access_locks={}\n",
+            self.version, self.model, self.oam_bug, self.ppu_access_locks
+        )
+        .into_bytes()
+    }
+
+    /// Parses metadata written by `to_bytes`. Unknown keys are ignored.
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(bytes).ok()?;
+        let mut version = None;
+        let mut model = None;
+        let mut boot_rom_crc = None;
+        let mut oam_bug = None;
+        let mut ppu_access_locks = None;
+        for line in text.lines() {
+            match line.split_once('=')? {
+                ("version", value) => version = Some(value.to_string()),
+                ("model", value) => model = Some(value.parse().ok()?),
+                ("boot_rom", "none") => boot_rom_crc = Some(None),
+                ("boot_rom", value) => {
+                    boot_rom_crc = Some(Some(u32::from_str_radix(value, 16).ok()?));
+                }
+                ("oam_bug", value) => oam_bug = Some(value.parse().ok()?),
+                ("ppu_access_locks", value) => ppu_access_locks = Some(value.parse().ok()?),
+                _ => (),
+            }
+        }
+        Some(Self {
+            version: version?,
+            model: model?,
+            boot_rom_crc: boot_rom_crc?,
+            oam_bug: oam_bug?,
+            ppu_access_locks: ppu_access_locks?,
+        })
+    }
+
+    /// Describes each setting that differs from `other`
+    #[must_use]
+    pub fn mismatches(&self, other: &Metadata) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        if self.version != other.version {
+            mismatches.push(Mismatch::Version);
+        }
+        if self.model != other.model {
+            mismatches.push(Mismatch::Model);
+        }
+        if self.boot_rom_crc != other.boot_rom_crc {
+            mismatches.push(Mismatch::BootRom);
+        }
+        if self.oam_bug != other.oam_bug || self.ppu_access_locks != other.ppu_access_locks {
+            mismatches.push(Mismatch::Accuracy);
+        }
+        mismatches
+    }
+}
+
+/// A setting that differs between the saved and current metadata
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    Version,
+    Model,
+    BootRom,
+    Accuracy,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Version => write!(f, "made with a different emulator version"),
+            Mismatch::Model => write!(f, "made with a different Game Boy model"),
+            Mismatch::BootRom => write!(f, "made with a different boot ROM"),
+            Mismatch::Accuracy => write!(f, "made with different accuracy settings"),
+        }
+    }
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+            if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ 0xEDB8_8320
+            }
+        })
+    })
+}
@@ -0,0 +1,61 @@
+//! The Game Boy models that can be emulated
+
+use crate::header::{CartridgeHeader, CgbSupport};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Model {
+    /// The original Game Boy
+    #[default]
+    Dmg,
+    /// The earliest revision of the original Game Boy, whose boot ROM leaves different values
+    /// in the registers
+    Dmg0,
+    /// Game Boy Pocket, which has the same hardware as the DMG but a different boot ROM
+    Mgb,
+    /// Super Game Boy, which colorizes the screen and draws a border around it
+    Sgb,
+    /// Game Boy Color
+    Cgb,
+}
+
+impl Model {
+    /// The model a cartridge was made for: CGB for cartridges that support it, DMG otherwise
+    #[must_use]
+    pub fn for_header(header: &CartridgeHeader) -> Self {
+        match header.cgb {
+            CgbSupport::None => Model::Dmg,
+            CgbSupport::Compatible | CgbSupport::Only => Model::Cgb,
+        }
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dmg" => Ok(Model::Dmg),
+            "dmg0" => Ok(Model::Dmg0),
+            "mgb" => Ok(Model::Mgb),
+            "sgb" => Ok(Model::Sgb),
+            "cgb" => Ok(Model::Cgb),
+            _ => Err(format!(
+                "unknown model {s}; expected dmg, dmg0, mgb, sgb or cgb"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Model::Dmg => "DMG",
+            Model::Dmg0 => "DMG0",
+            Model::Mgb => "MGB",
+            Model::Sgb => "SGB",
+            Model::Cgb => "CGB",
+        })
+    }
+}
@@ -0,0 +1,123 @@
+//! Movies, which record the buttons held in each frame so a session can be replayed exactly.
+//!
+//! A movie starts from a savestate rather than from power on, so it also captures anything
+//! that isn't deterministic between runs, like battery-backed RAM and the real-time clock.
+
+use crate::bus::Bus;
+use crate::cpu::Cpu;
+use crate::metadata::Metadata;
+use crate::state::{self, StateError, StateReader, StateWriter};
+
+/// Identifies movie files, followed by the format version
+const MAGIC: &[u8; 4] = b"RGBM";
+const VERSION: u8 = 2;
+
+pub struct Movie {
+    /// Settings the movie was recorded with
+    metadata: Metadata,
+    initial_state: Vec<u8>,
+    /// Buttons held in each frame, as masks of `joypad::Button::mask` bits
+    inputs: Vec<u8>,
+    recording: bool,
+    /// Index of the next frame
+    frame: usize,
+}
+
+impl Movie {
+    /// Starts recording a movie from the machine's current state
+    #[must_use]
+    pub fn record<B: Bus + ?Sized>(cpu: &Cpu<B>) -> Self {
+        Self {
+            metadata: cpu.bus.metadata(),
+            initial_state: state::save(cpu),
+            inputs: Vec::new(),
+            recording: true,
+            frame: 0,
+        }
+    }
+
+    /// Reads a movie for playback
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the data isn't a movie or is corrupt
+    pub fn from_bytes(data: &[u8]) -> Result<Self, StateError> {
+        let data = data
+            .strip_prefix(MAGIC)
+            .and_then(|data| data.strip_prefix(&[VERSION]))
+            .ok_or(StateError::NotAState)?;
+        let mut reader = StateReader::new(data);
+        Ok(Self {
+            metadata: Metadata::from_bytes(reader.read_bytes()?).ok_or(StateError::InvalidValue)?,
+            initial_state: reader.read_bytes()?.to_vec(),
+            inputs: reader.read_bytes()?.to_vec(),
+            recording: false,
+            frame: 0,
+        })
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        for byte in MAGIC {
+            writer.write_u8(*byte);
+        }
+        writer.write_u8(VERSION);
+        writer.write_bytes(&self.metadata.to_bytes());
+        writer.write_bytes(&self.initial_state);
+        writer.write_bytes(&self.inputs);
+        writer.into_bytes()
+    }
+
+    /// Restores the state the movie starts from and rewinds to its first frame
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the movie was made with another ROM, or its state is corrupt
+    pub fn start<B: Bus + ?Sized>(&mut self, cpu: &mut Cpu<B>) -> Result<(), StateError> {
+        state::load(cpu, &self.initial_state)?;
+        self.frame = 0;
+        Ok(())
+    }
+
+    /// Sets the buttons for the next frame. When recording, these are the `live` buttons from
+    /// the frontend, which are added to the movie. When playing, they're the movie's, and
+    /// `false` is returned once the movie has ended.
+    pub fn next_frame<B: Bus + ?Sized>(&mut self, cpu: &mut Cpu<B>, live: u8) -> bool {
+        let buttons = if self.recording {
+            self.inputs.push(live);
+            live
+        } else {
+            match self.inputs.get(self.frame) {
+                Some(buttons) => *buttons,
+                None => return false,
+            }
+        };
+        self.frame += 1;
+        cpu.bus.set_buttons(buttons);
+        true
+    }
+
+    /// The settings the movie was recorded with, to compare to the current ones before playing
+    /// it back
+    #[must_use]
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    #[must_use]
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Number of frames in the movie
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
@@ -0,0 +1,601 @@
+//! Static metadata for every opcode, for disassemblers, tools and documentation
+
+/// Length, timing and mnemonic of an opcode.
+///
+/// Operands in the mnemonic are written as `d8`/`d16` (immediate data), `a8`/`a16` (immediate
+/// addresses; `a8` is relative to 0xFF00) and `r8` (signed offset).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub mnemonic: &'static str,
+    /// Length in bytes, including operands
+    pub length: u8,
+    /// T-cycles taken, or for conditional instructions, taken when the branch isn't taken
+    pub cycles: u8,
+    /// T-cycles taken by conditional instructions when the branch is taken
+    pub branch_cycles: Option<u8>,
+}
+
+const fn op(
+    mnemonic: &'static str,
+    length: u8,
+    cycles: u8,
+    branch_cycles: Option<u8>,
+) -> Option<OpcodeInfo> {
+    Some(OpcodeInfo {
+        mnemonic,
+        length,
+        cycles,
+        branch_cycles,
+    })
+}
+
+/// Returns the metadata for an opcode, where prefixed opcodes are given as 0xCBxx
+#[must_use]
+pub fn info(opcode: u16) -> Option<&'static OpcodeInfo> {
+    match opcode {
+        0x00..=0xFF => OPCODES[usize::from(opcode)].as_ref(),
+        0xCB00..=0xCBFF => CB_OPCODES[usize::from(opcode & 0xFF)].as_ref(),
+        _ => None,
+    }
+}
+
+/// Formats the instruction at the start of `bytes` with its operands filled in, as it would be
+/// executed from `address`. `label` is asked for a name for each address operand and relative
+/// jump target. Returns `None` for illegal opcodes or if `bytes` is too short.
+pub fn format_instruction(
+    bytes: &[u8],
+    address: u16,
+    label: impl Fn(u16) -> Option<String>,
+) -> Option<String> {
+    let info = match *bytes.first()? {
+        0xCB => info(0xCB00 | u16::from(*bytes.get(1)?))?,
+        opcode => info(u16::from(opcode))?,
+    };
+    let operands = bytes.get(1..usize::from(info.length))?;
+    let name = |target: u16| label(target).unwrap_or_else(|| format!("${target:04X}"));
+    let mnemonic = info.mnemonic;
+    Some(if mnemonic.contains("d16") || mnemonic.contains("a16") {
+        let value = u16::from_le_bytes([operands[0], operands[1]]);
+        mnemonic
+            .replace("d16", &format!("${value:04X}"))
+            .replace("a16", &name(value))
+    } else if mnemonic.contains("a8") {
+        mnemonic.replace("a8", &name(0xFF00 | u16::from(operands[0])))
+    } else if mnemonic.contains("d8") {
+        mnemonic.replace("d8", &format!("${:02X}", operands[0]))
+    } else if mnemonic.starts_with("JR") {
+        let offset = operands[0] as i8;
+        let target = address
+            .wrapping_add(u16::from(info.length))
+            .wrapping_add_signed(i16::from(offset));
+        mnemonic.replace("r8", &name(target))
+    } else if mnemonic.contains("r8") {
+        let offset = operands[0] as i8;
+        let (sign, plus) = if offset < 0 { ("-", "r8") } else { ("", "+r8") };
+        mnemonic
+            .replace("+r8", plus)
+            .replace("r8", &format!("{sign}${:02X}", offset.unsigned_abs()))
+    } else {
+        mnemonic.to_string()
+    })
+}
+
+/// Unprefixed opcodes. Illegal opcodes are `None`.
+pub static OPCODES: [Option<OpcodeInfo>; 256] = [
+    op("NOP", 1, 4, None),              // 00
+    op("LD BC,d16", 3, 12, None),       // 01
+    op("LD (BC),A", 1, 8, None),        // 02
+    op("INC BC", 1, 8, None),           // 03
+    op("INC B", 1, 4, None),            // 04
+    op("DEC B", 1, 4, None),            // 05
+    op("LD B,d8", 2, 8, None),          // 06
+    op("RLCA", 1, 4, None),             // 07
+    op("LD (a16),SP", 3, 20, None),     // 08
+    op("ADD HL,BC", 1, 8, None),        // 09
+    op("LD A,(BC)", 1, 8, None),        // 0A
+    op("DEC BC", 1, 8, None),           // 0B
+    op("INC C", 1, 4, None),            // 0C
+    op("DEC C", 1, 4, None),            // 0D
+    op("LD C,d8", 2, 8, None),          // 0E
+    op("RRCA", 1, 4, None),             // 0F
+    op("STOP 0", 2, 4, None),           // 10
+    op("LD DE,d16", 3, 12, None),       // 11
+    op("LD (DE),A", 1, 8, None),        // 12
+    op("INC DE", 1, 8, None),           // 13
+    op("INC D", 1, 4, None),            // 14
+    op("DEC D", 1, 4, None),            // 15
+    op("LD D,d8", 2, 8, None),          // 16
+    op("RLA", 1, 4, None),              // 17
+    op("JR r8", 2, 12, None),           // 18
+    op("ADD HL,DE", 1, 8, None),        // 19
+    op("LD A,(DE)", 1, 8, None),        // 1A
+    op("DEC DE", 1, 8, None),           // 1B
+    op("INC E", 1, 4, None),            // 1C
+    op("DEC E", 1, 4, None),            // 1D
+    op("LD E,d8", 2, 8, None),          // 1E
+    op("RRA", 1, 4, None),              // 1F
+    op("JR NZ,r8", 2, 8, Some(12)),     // 20
+    op("LD HL,d16", 3, 12, None),       // 21
+    op("LD (HL+),A", 1, 8, None),       // 22
+    op("INC HL", 1, 8, None),           // 23
+    op("INC H", 1, 4, None),            // 24
+    op("DEC H", 1, 4, None),            // 25
+    op("LD H,d8", 2, 8, None),          // 26
+    op("DAA", 1, 4, None),              // 27
+    op("JR Z,r8", 2, 8, Some(12)),      // 28
+    op("ADD HL,HL", 1, 8, None),        // 29
+    op("LD A,(HL+)", 1, 8, None),       // 2A
+    op("DEC HL", 1, 8, None),           // 2B
+    op("INC L", 1, 4, None),            // 2C
+    op("DEC L", 1, 4, None),            // 2D
+    op("LD L,d8", 2, 8, None),          // 2E
+    op("CPL", 1, 4, None),              // 2F
+    op("JR NC,r8", 2, 8, Some(12)),     // 30
+    op("LD SP,d16", 3, 12, None),       // 31
+    op("LD (HL-),A", 1, 8, None),       // 32
+    op("INC SP", 1, 8, None),           // 33
+    op("INC (HL)", 1, 12, None),        // 34
+    op("DEC (HL)", 1, 12, None),        // 35
+    op("LD (HL),d8", 2, 12, None),      // 36
+    op("SCF", 1, 4, None),              // 37
+    op("JR C,r8", 2, 8, Some(12)),      // 38
+    op("ADD HL,SP", 1, 8, None),        // 39
+    op("LD A,(HL-)", 1, 8, None),       // 3A
+    op("DEC SP", 1, 8, None),           // 3B
+    op("INC A", 1, 4, None),            // 3C
+    op("DEC A", 1, 4, None),            // 3D
+    op("LD A,d8", 2, 8, None),          // 3E
+    op("CCF", 1, 4, None),              // 3F
+    op("LD B,B", 1, 4, None),           // 40
+    op("LD B,C", 1, 4, None),           // 41
+    op("LD B,D", 1, 4, None),           // 42
+    op("LD B,E", 1, 4, None),           // 43
+    op("LD B,H", 1, 4, None),           // 44
+    op("LD B,L", 1, 4, None),           // 45
+    op("LD B,(HL)", 1, 8, None),        // 46
+    op("LD B,A", 1, 4, None),           // 47
+    op("LD C,B", 1, 4, None),           // 48
+    op("LD C,C", 1, 4, None),           // 49
+    op("LD C,D", 1, 4, None),           // 4A
+    op("LD C,E", 1, 4, None),           // 4B
+    op("LD C,H", 1, 4, None),           // 4C
+    op("LD C,L", 1, 4, None),           // 4D
+    op("LD C,(HL)", 1, 8, None),        // 4E
+    op("LD C,A", 1, 4, None),           // 4F
+    op("LD D,B", 1, 4, None),           // 50
+    op("LD D,C", 1, 4, None),           // 51
+    op("LD D,D", 1, 4, None),           // 52
+    op("LD D,E", 1, 4, None),           // 53
+    op("LD D,H", 1, 4, None),           // 54
+    op("LD D,L", 1, 4, None),           // 55
+    op("LD D,(HL)", 1, 8, None),        // 56
+    op("LD D,A", 1, 4, None),           // 57
+    op("LD E,B", 1, 4, None),           // 58
+    op("LD E,C", 1, 4, None),           // 59
+    op("LD E,D", 1, 4, None),           // 5A
+    op("LD E,E", 1, 4, None),           // 5B
+    op("LD E,H", 1, 4, None),           // 5C
+    op("LD E,L", 1, 4, None),           // 5D
+    op("LD E,(HL)", 1, 8, None),        // 5E
+    op("LD E,A", 1, 4, None),           // 5F
+    op("LD H,B", 1, 4, None),           // 60
+    op("LD H,C", 1, 4, None),           // 61
+    op("LD H,D", 1, 4, None),           // 62
+    op("LD H,E", 1, 4, None),           // 63
+    op("LD H,H", 1, 4, None),           // 64
+    op("LD H,L", 1, 4, None),           // 65
+    op("LD H,(HL)", 1, 8, None),        // 66
+    op("LD H,A", 1, 4, None),           // 67
+    op("LD L,B", 1, 4, None),           // 68
+    op("LD L,C", 1, 4, None),           // 69
+    op("LD L,D", 1, 4, None),           // 6A
+    op("LD L,E", 1, 4, None),           // 6B
+    op("LD L,H", 1, 4, None),           // 6C
+    op("LD L,L", 1, 4, None),           // 6D
+    op("LD L,(HL)", 1, 8, None),        // 6E
+    op("LD L,A", 1, 4, None),           // 6F
+    op("LD (HL),B", 1, 8, None),        // 70
+    op("LD (HL),C", 1, 8, None),        // 71
+    op("LD (HL),D", 1, 8, None),        // 72
+    op("LD (HL),E", 1, 8, None),        // 73
+    op("LD (HL),H", 1, 8, None),        // 74
+    op("LD (HL),L", 1, 8, None),        // 75
+    op("HALT", 1, 4, None),             // 76
+    op("LD (HL),A", 1, 8, None),        // 77
+    op("LD A,B", 1, 4, None),           // 78
+    op("LD A,C", 1, 4, None),           // 79
+    op("LD A,D", 1, 4, None),           // 7A
+    op("LD A,E", 1, 4, None),           // 7B
+    op("LD A,H", 1, 4, None),           // 7C
+    op("LD A,L", 1, 4, None),           // 7D
+    op("LD A,(HL)", 1, 8, None),        // 7E
+    op("LD A,A", 1, 4, None),           // 7F
+    op("ADD A,B", 1, 4, None),          // 80
+    op("ADD A,C", 1, 4, None),          // 81
+    op("ADD A,D", 1, 4, None),          // 82
+    op("ADD A,E", 1, 4, None),          // 83
+    op("ADD A,H", 1, 4, None),          // 84
+    op("ADD A,L", 1, 4, None),          // 85
+    op("ADD A,(HL)", 1, 8, None),       // 86
+    op("ADD A,A", 1, 4, None),          // 87
+    op("ADC A,B", 1, 4, None),          // 88
+    op("ADC A,C", 1, 4, None),          // 89
+    op("ADC A,D", 1, 4, None),          // 8A
+    op("ADC A,E", 1, 4, None),          // 8B
+    op("ADC A,H", 1, 4, None),          // 8C
+    op("ADC A,L", 1, 4, None),          // 8D
+    op("ADC A,(HL)", 1, 8, None),       // 8E
+    op("ADC A,A", 1, 4, None),          // 8F
+    op("SUB B", 1, 4, None),            // 90
+    op("SUB C", 1, 4, None),            // 91
+    op("SUB D", 1, 4, None),            // 92
+    op("SUB E", 1, 4, None),            // 93
+    op("SUB H", 1, 4, None),            // 94
+    op("SUB L", 1, 4, None),            // 95
+    op("SUB (HL)", 1, 8, None),         // 96
+    op("SUB A", 1, 4, None),            // 97
+    op("SBC A,B", 1, 4, None),          // 98
+    op("SBC A,C", 1, 4, None),          // 99
+    op("SBC A,D", 1, 4, None),          // 9A
+    op("SBC A,E", 1, 4, None),          // 9B
+    op("SBC A,H", 1, 4, None),          // 9C
+    op("SBC A,L", 1, 4, None),          // 9D
+    op("SBC A,(HL)", 1, 8, None),       // 9E
+    op("SBC A,A", 1, 4, None),          // 9F
+    op("AND B", 1, 4, None),            // A0
+    op("AND C", 1, 4, None),            // A1
+    op("AND D", 1, 4, None),            // A2
+    op("AND E", 1, 4, None),            // A3
+    op("AND H", 1, 4, None),            // A4
+    op("AND L", 1, 4, None),            // A5
+    op("AND (HL)", 1, 8, None),         // A6
+    op("AND A", 1, 4, None),            // A7
+    op("XOR B", 1, 4, None),            // A8
+    op("XOR C", 1, 4, None),            // A9
+    op("XOR D", 1, 4, None),            // AA
+    op("XOR E", 1, 4, None),            // AB
+    op("XOR H", 1, 4, None),            // AC
+    op("XOR L", 1, 4, None),            // AD
+    op("XOR (HL)", 1, 8, None),         // AE
+    op("XOR A", 1, 4, None),            // AF
+    op("OR B", 1, 4, None),             // B0
+    op("OR C", 1, 4, None),             // B1
+    op("OR D", 1, 4, None),             // B2
+    op("OR E", 1, 4, None),             // B3
+    op("OR H", 1, 4, None),             // B4
+    op("OR L", 1, 4, None),             // B5
+    op("OR (HL)", 1, 8, None),          // B6
+    op("OR A", 1, 4, None),             // B7
+    op("CP B", 1, 4, None),             // B8
+    op("CP C", 1, 4, None),             // B9
+    op("CP D", 1, 4, None),             // BA
+    op("CP E", 1, 4, None),             // BB
+    op("CP H", 1, 4, None),             // BC
+    op("CP L", 1, 4, None),             // BD
+    op("CP (HL)", 1, 8, None),          // BE
+    op("CP A", 1, 4, None),             // BF
+    op("RET NZ", 1, 8, Some(20)),       // C0
+    op("POP BC", 1, 12, None),          // C1
+    op("JP NZ,a16", 3, 12, Some(16)),   // C2
+    op("JP a16", 3, 16, None),          // C3
+    op("CALL NZ,a16", 3, 12, Some(24)), // C4
+    op("PUSH BC", 1, 16, None),         // C5
+    op("ADD A,d8", 2, 8, None),         // C6
+    op("RST 00H", 1, 16, None),         // C7
+    op("RET Z", 1, 8, Some(20)),        // C8
+    op("RET", 1, 16, None),             // C9
+    op("JP Z,a16", 3, 12, Some(16)),    // CA
+    op("PREFIX CB", 1, 4, None),        // CB
+    op("CALL Z,a16", 3, 12, Some(24)),  // CC
+    op("CALL a16", 3, 24, None),        // CD
+    op("ADC A,d8", 2, 8, None),         // CE
+    op("RST 08H", 1, 16, None),         // CF
+    op("RET NC", 1, 8, Some(20)),       // D0
+    op("POP DE", 1, 12, None),          // D1
+    op("JP NC,a16", 3, 12, Some(16)),   // D2
+    None,                               // D3
+    op("CALL NC,a16", 3, 12, Some(24)), // D4
+    op("PUSH DE", 1, 16, None),         // D5
+    op("SUB d8", 2, 8, None),           // D6
+    op("RST 10H", 1, 16, None),         // D7
+    op("RET C", 1, 8, Some(20)),        // D8
+    op("RETI", 1, 16, None),            // D9
+    op("JP C,a16", 3, 12, Some(16)),    // DA
+    None,                               // DB
+    op("CALL C,a16", 3, 12, Some(24)),  // DC
+    None,                               // DD
+    op("SBC A,d8", 2, 8, None),         // DE
+    op("RST 18H", 1, 16, None),         // DF
+    op("LDH (a8),A", 2, 12, None),      // E0
+    op("POP HL", 1, 12, None),          // E1
+    op("LD (C),A", 1, 8, None),         // E2
+    None,                               // E3
+    None,                               // E4
+    op("PUSH HL", 1, 16, None),         // E5
+    op("AND d8", 2, 8, None),           // E6
+    op("RST 20H", 1, 16, None),         // E7
+    op("ADD SP,r8", 2, 16, None),       // E8
+    op("JP (HL)", 1, 4, None),          // E9
+    op("LD (a16),A", 3, 16, None),      // EA
+    None,                               // EB
+    None,                               // EC
+    None,                               // ED
+    op("XOR d8", 2, 8, None),           // EE
+    op("RST 28H", 1, 16, None),         // EF
+    op("LDH A,(a8)", 2, 12, None),      // F0
+    op("POP AF", 1, 12, None),          // F1
+    op("LD A,(C)", 1, 8, None),         // F2
+    op("DI", 1, 4, None),               // F3
+    None,                               // F4
+    op("PUSH AF", 1, 16, None),         // F5
+    op("OR d8", 2, 8, None),            // F6
+    op("RST 30H", 1, 16, None),         // F7
+    op("LD HL,SP+r8", 2, 12, None),     // F8
+    op("LD SP,HL", 1, 8, None),         // F9
+    op("LD A,(a16)", 3, 16, None),      // FA
+    op("EI", 1, 4, None),               // FB
+    None,                               // FC
+    None,                               // FD
+    op("CP d8", 2, 8, None),            // FE
+    op("RST 38H", 1, 16, None),         // FF
+];
+
+/// Opcodes prefixed with 0xCB. The length and cycles include the prefix.
+pub static CB_OPCODES: [Option<OpcodeInfo>; 256] = [
+    op("RLC B", 2, 8, None),       // 00
+    op("RLC C", 2, 8, None),       // 01
+    op("RLC D", 2, 8, None),       // 02
+    op("RLC E", 2, 8, None),       // 03
+    op("RLC H", 2, 8, None),       // 04
+    op("RLC L", 2, 8, None),       // 05
+    op("RLC (HL)", 2, 16, None),   // 06
+    op("RLC A", 2, 8, None),       // 07
+    op("RRC B", 2, 8, None),       // 08
+    op("RRC C", 2, 8, None),       // 09
+    op("RRC D", 2, 8, None),       // 0A
+    op("RRC E", 2, 8, None),       // 0B
+    op("RRC H", 2, 8, None),       // 0C
+    op("RRC L", 2, 8, None),       // 0D
+    op("RRC (HL)", 2, 16, None),   // 0E
+    op("RRC A", 2, 8, None),       // 0F
+    op("RL B", 2, 8, None),        // 10
+    op("RL C", 2, 8, None),        // 11
+    op("RL D", 2, 8, None),        // 12
+    op("RL E", 2, 8, None),        // 13
+    op("RL H", 2, 8, None),        // 14
+    op("RL L", 2, 8, None),        // 15
+    op("RL (HL)", 2, 16, None),    // 16
+    op("RL A", 2, 8, None),        // 17
+    op("RR B", 2, 8, None),        // 18
+    op("RR C", 2, 8, None),        // 19
+    op("RR D", 2, 8, None),        // 1A
+    op("RR E", 2, 8, None),        // 1B
+    op("RR H", 2, 8, None),        // 1C
+    op("RR L", 2, 8, None),        // 1D
+    op("RR (HL)", 2, 16, None),    // 1E
+    op("RR A", 2, 8, None),        // 1F
+    op("SLA B", 2, 8, None),       // 20
+    op("SLA C", 2, 8, None),       // 21
+    op("SLA D", 2, 8, None),       // 22
+    op("SLA E", 2, 8, None),       // 23
+    op("SLA H", 2, 8, None),       // 24
+    op("SLA L", 2, 8, None),       // 25
+    op("SLA (HL)", 2, 16, None),   // 26
+    op("SLA A", 2, 8, None),       // 27
+    op("SRA B", 2, 8, None),       // 28
+    op("SRA C", 2, 8, None),       // 29
+    op("SRA D", 2, 8, None),       // 2A
+    op("SRA E", 2, 8, None),       // 2B
+    op("SRA H", 2, 8, None),       // 2C
+    op("SRA L", 2, 8, None),       // 2D
+    op("SRA (HL)", 2, 16, None),   // 2E
+    op("SRA A", 2, 8, None),       // 2F
+    op("SWAP B", 2, 8, None),      // 30
+    op("SWAP C", 2, 8, None),      // 31
+    op("SWAP D", 2, 8, None),      // 32
+    op("SWAP E", 2, 8, None),      // 33
+    op("SWAP H", 2, 8, None),      // 34
+    op("SWAP L", 2, 8, None),      // 35
+    op("SWAP (HL)", 2, 16, None),  // 36
+    op("SWAP A", 2, 8, None),      // 37
+    op("SRL B", 2, 8, None),       // 38
+    op("SRL C", 2, 8, None),       // 39
+    op("SRL D", 2, 8, None),       // 3A
+    op("SRL E", 2, 8, None),       // 3B
+    op("SRL H", 2, 8, None),       // 3C
+    op("SRL L", 2, 8, None),       // 3D
+    op("SRL (HL)", 2, 16, None),   // 3E
+    op("SRL A", 2, 8, None),       // 3F
+    op("BIT 0,B", 2, 8, None),     // 40
+    op("BIT 0,C", 2, 8, None),     // 41
+    op("BIT 0,D", 2, 8, None),     // 42
+    op("BIT 0,E", 2, 8, None),     // 43
+    op("BIT 0,H", 2, 8, None),     // 44
+    op("BIT 0,L", 2, 8, None),     // 45
+    op("BIT 0,(HL)", 2, 12, None), // 46
+    op("BIT 0,A", 2, 8, None),     // 47
+    op("BIT 1,B", 2, 8, None),     // 48
+    op("BIT 1,C", 2, 8, None),     // 49
+    op("BIT 1,D", 2, 8, None),     // 4A
+    op("BIT 1,E", 2, 8, None),     // 4B
+    op("BIT 1,H", 2, 8, None),     // 4C
+    op("BIT 1,L", 2, 8, None),     // 4D
+    op("BIT 1,(HL)", 2, 12, None), // 4E
+    op("BIT 1,A", 2, 8, None),     // 4F
+    op("BIT 2,B", 2, 8, None),     // 50
+    op("BIT 2,C", 2, 8, None),     // 51
+    op("BIT 2,D", 2, 8, None),     // 52
+    op("BIT 2,E", 2, 8, None),     // 53
+    op("BIT 2,H", 2, 8, None),     // 54
+    op("BIT 2,L", 2, 8, None),     // 55
+    op("BIT 2,(HL)", 2, 12, None), // 56
+    op("BIT 2,A", 2, 8, None),     // 57
+    op("BIT 3,B", 2, 8, None),     // 58
+    op("BIT 3,C", 2, 8, None),     // 59
+    op("BIT 3,D", 2, 8, None),     // 5A
+    op("BIT 3,E", 2, 8, None),     // 5B
+    op("BIT 3,H", 2, 8, None),     // 5C
+    op("BIT 3,L", 2, 8, None),     // 5D
+    op("BIT 3,(HL)", 2, 12, None), // 5E
+    op("BIT 3,A", 2, 8, None),     // 5F
+    op("BIT 4,B", 2, 8, None),     // 60
+    op("BIT 4,C", 2, 8, None),     // 61
+    op("BIT 4,D", 2, 8, None),     // 62
+    op("BIT 4,E", 2, 8, None),     // 63
+    op("BIT 4,H", 2, 8, None),     // 64
+    op("BIT 4,L", 2, 8, None),     // 65
+    op("BIT 4,(HL)", 2, 12, None), // 66
+    op("BIT 4,A", 2, 8, None),     // 67
+    op("BIT 5,B", 2, 8, None),     // 68
+    op("BIT 5,C", 2, 8, None),     // 69
+    op("BIT 5,D", 2, 8, None),     // 6A
+    op("BIT 5,E", 2, 8, None),     // 6B
+    op("BIT 5,H", 2, 8, None),     // 6C
+    op("BIT 5,L", 2, 8, None),     // 6D
+    op("BIT 5,(HL)", 2, 12, None), // 6E
+    op("BIT 5,A", 2, 8, None),     // 6F
+    op("BIT 6,B", 2, 8, None),     // 70
+    op("BIT 6,C", 2, 8, None),     // 71
+    op("BIT 6,D", 2, 8, None),     // 72
+    op("BIT 6,E", 2, 8, None),     // 73
+    op("BIT 6,H", 2, 8, None),     // 74
+    op("BIT 6,L", 2, 8, None),     // 75
+    op("BIT 6,(HL)", 2, 12, None), // 76
+    op("BIT 6,A", 2, 8, None),     // 77
+    op("BIT 7,B", 2, 8, None),     // 78
+    op("BIT 7,C", 2, 8, None),     // 79
+    op("BIT 7,D", 2, 8, None),     // 7A
+    op("BIT 7,E", 2, 8, None),     // 7B
+    op("BIT 7,H", 2, 8, None),     // 7C
+    op("BIT 7,L", 2, 8, None),     // 7D
+    op("BIT 7,(HL)", 2, 12, None), // 7E
+    op("BIT 7,A", 2, 8, None),     // 7F
+    op("RES 0,B", 2, 8, None),     // 80
+    op("RES 0,C", 2, 8, None),     // 81
+    op("RES 0,D", 2, 8, None),     // 82
+    op("RES 0,E", 2, 8, None),     // 83
+    op("RES 0,H", 2, 8, None),     // 84
+    op("RES 0,L", 2, 8, None),     // 85
+    op("RES 0,(HL)", 2, 16, None), // 86
+    op("RES 0,A", 2, 8, None),     // 87
+    op("RES 1,B", 2, 8, None),     // 88
+    op("RES 1,C", 2, 8, None),     // 89
+    op("RES 1,D", 2, 8, None),     // 8A
+    op("RES 1,E", 2, 8, None),     // 8B
+    op("RES 1,H", 2, 8, None),     // 8C
+    op("RES 1,L", 2, 8, None),     // 8D
+    op("RES 1,(HL)", 2, 16, None), // 8E
+    op("RES 1,A", 2, 8, None),     // 8F
+    op("RES 2,B", 2, 8, None),     // 90
+    op("RES 2,C", 2, 8, None),     // 91
+    op("RES 2,D", 2, 8, None),     // 92
+    op("RES 2,E", 2, 8, None),     // 93
+    op("RES 2,H", 2, 8, None),     // 94
+    op("RES 2,L", 2, 8, None),     // 95
+    op("RES 2,(HL)", 2, 16, None), // 96
+    op("RES 2,A", 2, 8, None),     // 97
+    op("RES 3,B", 2, 8, None),     // 98
+    op("RES 3,C", 2, 8, None),     // 99
+    op("RES 3,D", 2, 8, None),     // 9A
+    op("RES 3,E", 2, 8, None),     // 9B
+    op("RES 3,H", 2, 8, None),     // 9C
+    op("RES 3,L", 2, 8, None),     // 9D
+    op("RES 3,(HL)", 2, 16, None), // 9E
+    op("RES 3,A", 2, 8, None),     // 9F
+    op("RES 4,B", 2, 8, None),     // A0
+    op("RES 4,C", 2, 8, None),     // A1
+    op("RES 4,D", 2, 8, None),     // A2
+    op("RES 4,E", 2, 8, None),     // A3
+    op("RES 4,H", 2, 8, None),     // A4
+    op("RES 4,L", 2, 8, None),     // A5
+    op("RES 4,(HL)", 2, 16, None), // A6
+    op("RES 4,A", 2, 8, None),     // A7
+    op("RES 5,B", 2, 8, None),     // A8
+    op("RES 5,C", 2, 8, None),     // A9
+    op("RES 5,D", 2, 8, None),     // AA
+    op("RES 5,E", 2, 8, None),     // AB
+    op("RES 5,H", 2, 8, None),     // AC
+    op("RES 5,L", 2, 8, None),     // AD
+    op("RES 5,(HL)", 2, 16, None), // AE
+    op("RES 5,A", 2, 8, None),     // AF
+    op("RES 6,B", 2, 8, None),     // B0
+    op("RES 6,C", 2, 8, None),     // B1
+    op("RES 6,D", 2, 8, None),     // B2
+    op("RES 6,E", 2, 8, None),     // B3
+    op("RES 6,H", 2, 8, None),     // B4
+    op("RES 6,L", 2, 8, None),     // B5
+    op("RES 6,(HL)", 2, 16, None), // B6
+    op("RES 6,A", 2, 8, None),     // B7
+    op("RES 7,B", 2, 8, None),     // B8
+    op("RES 7,C", 2, 8, None),     // B9
+    op("RES 7,D", 2, 8, None),     // BA
+    op("RES 7,E", 2, 8, None),     // BB
+    op("RES 7,H", 2, 8, None),     // BC
+    op("RES 7,L", 2, 8, None),     // BD
+    op("RES 7,(HL)", 2, 16, None), // BE
+    op("RES 7,A", 2, 8, None),     // BF
+    op("SET 0,B", 2, 8, None),     // C0
+    op("SET 0,C", 2, 8, None),     // C1
+    op("SET 0,D", 2, 8, None),     // C2
+    op("SET 0,E", 2, 8, None),     // C3
+    op("SET 0,H", 2, 8, None),     // C4
+    op("SET 0,L", 2, 8, None),     // C5
+    op("SET 0,(HL)", 2, 16, None), // C6
+    op("SET 0,A", 2, 8, None),     // C7
+    op("SET 1,B", 2, 8, None),     // C8
+    op("SET 1,C", 2, 8, None),     // C9
+    op("SET 1,D", 2, 8, None),     // CA
+    op("SET 1,E", 2, 8, None),     // CB
+    op("SET 1,H", 2, 8, None),     // CC
+    op("SET 1,L", 2, 8, None),     // CD
+    op("SET 1,(HL)", 2, 16, None), // CE
+    op("SET 1,A", 2, 8, None),     // CF
+    op("SET 2,B", 2, 8, None),     // D0
+    op("SET 2,C", 2, 8, None),     // D1
+    op("SET 2,D", 2, 8, None),     // D2
+    op("SET 2,E", 2, 8, None),     // D3
+    op("SET 2,H", 2, 8, None),     // D4
+    op("SET 2,L", 2, 8, None),     // D5
+    op("SET 2,(HL)", 2, 16, None), // D6
+    op("SET 2,A", 2, 8, None),     // D7
+    op("SET 3,B", 2, 8, None),     // D8
+    op("SET 3,C", 2, 8, None),     // D9
+    op("SET 3,D", 2, 8, None),     // DA
+    op("SET 3,E", 2, 8, None),     // DB
+    op("SET 3,H", 2, 8, None),     // DC
+    op("SET 3,L", 2, 8, None),     // DD
+    op("SET 3,(HL)", 2, 16, None), // DE
+    op("SET 3,A", 2, 8, None),     // DF
+    op("SET 4,B", 2, 8, None),     // E0
+    op("SET 4,C", 2, 8, None),     // E1
+    op("SET 4,D", 2, 8, None),     // E2
+    op("SET 4,E", 2, 8, None),     // E3
+    op("SET 4,H", 2, 8, None),     // E4
+    op("SET 4,L", 2, 8, None),     // E5
+    op("SET 4,(HL)", 2, 16, None), // E6
+    op("SET 4,A", 2, 8, None),     // E7
+    op("SET 5,B", 2, 8, None),     // E8
+    op("SET 5,C", 2, 8, None),     // E9
+    op("SET 5,D", 2, 8, None),     // EA
+    op("SET 5,E", 2, 8, None),     // EB
+    op("SET 5,H", 2, 8, None),     // EC
+    op("SET 5,L", 2, 8, None),     // ED
+    op("SET 5,(HL)", 2, 16, None), // EE
+    op("SET 5,A", 2, 8, None),     // EF
+    op("SET 6,B", 2, 8, None),     // F0
+    op("SET 6,C", 2, 8, None),     // F1
+    op("SET 6,D", 2, 8, None),     // F2
+    op("SET 6,E", 2, 8, None),     // F3
+    op("SET 6,H", 2, 8, None),     // F4
+    op("SET 6,L", 2, 8, None),     // F5
+    op("SET 6,(HL)", 2, 16, None), // F6
+    op("SET 6,A", 2, 8, None),     // F7
+    op("SET 7,B", 2, 8, None),     // F8
+    op("SET 7,C", 2, 8, None),     // F9
+    op("SET 7,D", 2, 8, None),     // FA
+    op("SET 7,E", 2, 8, None),     // FB
+    op("SET 7,H", 2, 8, None),     // FC
+    op("SET 7,L", 2, 8, None),     // FD
+    op("SET 7,(HL)", 2, 16, None), // FE
+    op("SET 7,A", 2, 8, None),     // FF
+];
@@ -0,0 +1,158 @@
+use rgb_emu::ppu::FRAME_CYCLES;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// T-cycles per second
+const CLOCK_RATE: u32 = 4_194_304;
+
+/// Number of recent frame intervals used for the statistics
+const WINDOW: usize = 600;
+
+/// OS sleeps can overshoot by a millisecond or so, so the last part of each wait is spent
+/// spinning instead
+const SPIN_MARGIN: Duration = Duration::from_millis(2);
+
+/// How many frames emulation may fall behind real time and still catch up. Beyond this,
+/// timing starts over instead of running fast for a long time to make up for a stall.
+const MAX_LAG: u32 = 4;
+
+/// Throttles emulation to real time by waiting out the rest of each frame, sleeping as much as
+/// possible instead of spinning.
+///
+/// Deadlines are counted from a fixed starting point rather than from the previous frame, so
+/// frames that run a little late are made up for by the following ones, and rounding errors
+/// don't accumulate into drift.
+pub struct FrameTimer {
+    start: Instant,
+    /// Frames waited for since `start`
+    frames: u32,
+    /// Emulation speed as a multiple of real time, or 0 when unthrottled
+    speed: f32,
+    /// Sleep all the way to each deadline without spinning, which saves power at the cost of up
+    /// to a millisecond or so of jitter
+    power_save: bool,
+}
+
+impl FrameTimer {
+    pub fn new(power_save: bool) -> Self {
+        Self {
+            start: Instant::now(),
+            frames: 0,
+            speed: 1.0,
+            power_save,
+        }
+    }
+
+    /// Sets the emulation speed as a multiple of real time, where 0 means unthrottled
+    pub fn set_speed(&mut self, speed: f32) {
+        if speed != self.speed {
+            self.speed = speed;
+            self.reset();
+        }
+    }
+
+    /// Real-time duration of a number of frames at the current speed
+    fn duration(&self, frames: u32) -> Duration {
+        Duration::from_secs_f64(
+            f64::from(frames) * f64::from(FRAME_CYCLES)
+                / (f64::from(CLOCK_RATE) * f64::from(self.speed)),
+        )
+    }
+
+    /// When the next call to `wait` will return, or `None` when unthrottled
+    pub fn next_deadline(&self) -> Option<Instant> {
+        (self.speed != 0.0).then(|| self.start + self.duration(self.frames + 1))
+    }
+
+    /// Waits until the end of the next frame
+    pub fn wait(&mut self) {
+        if self.speed == 0.0 {
+            return;
+        }
+        self.frames += 1;
+        let deadline = self.start + self.duration(self.frames);
+        let now = Instant::now();
+        if now > deadline + self.duration(MAX_LAG) {
+            self.reset();
+            return;
+        }
+
+        let remaining = deadline.saturating_duration_since(now);
+        if self.power_save {
+            std::thread::sleep(remaining);
+            return;
+        }
+        if remaining > SPIN_MARGIN {
+            std::thread::sleep(remaining - SPIN_MARGIN);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Starts timing over from now
+    pub fn reset(&mut self) {
+        self.start = Instant::now();
+        self.frames = 0;
+    }
+}
+
+/// Timing statistics for presented frames
+#[derive(Default)]
+pub struct PacingStats {
+    last_present: Option<Instant>,
+    intervals: VecDeque<Duration>,
+    pub frames: u64,
+    /// Frames presented after the frame timer's deadline for them
+    pub missed_deadlines: u64,
+}
+
+impl PacingStats {
+    /// Records a frame presented at `now`, which was due by `deadline` unless emulation is
+    /// unthrottled
+    pub fn record_present(&mut self, now: Instant, deadline: Option<Instant>) {
+        if deadline.is_some_and(|deadline| now > deadline) {
+            self.missed_deadlines += 1;
+        }
+        if let Some(last_present) = self.last_present {
+            let interval = now - last_present;
+            if self.intervals.len() == WINDOW {
+                self.intervals.pop_front();
+            }
+            self.intervals.push_back(interval);
+        }
+        self.last_present = Some(now);
+        self.frames += 1;
+    }
+
+    /// Average interval between presented frames
+    pub fn average(&self) -> Duration {
+        if self.intervals.is_empty() {
+            return Duration::ZERO;
+        }
+        self.intervals.iter().sum::<Duration>() / self.intervals.len() as u32
+    }
+
+    /// The interval that 95% of recent frames were presented within
+    pub fn p95(&self) -> Duration {
+        let mut intervals: Vec<&Duration> = self.intervals.iter().collect();
+        intervals.sort();
+        intervals
+            .get((intervals.len() * 95 / 100).min(intervals.len().saturating_sub(1)))
+            .map_or(Duration::ZERO, |interval| **interval)
+    }
+}
+
+impl fmt::Display for PacingStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "avg {:.2} ms, p95 {:.2} ms, missed {}/{}",
+            self.average().as_secs_f64() * 1000.0,
+            self.p95().as_secs_f64() * 1000.0,
+            self.missed_deadlines,
+            self.frames
+        )
+    }
+}
@@ -0,0 +1,100 @@
+//! Colors for the four DMG shades, which are applied when frames are converted to RGB rather
+//! than by the PPU
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    /// RGB colors of shades 0-3, from lightest to darkest
+    pub shades: [[u8; 3]; 4],
+}
+
+impl Palette {
+    pub const GRAYSCALE: Palette = Palette {
+        shades: [
+            [0xFF, 0xFF, 0xFF],
+            [0xAA, 0xAA, 0xAA],
+            [0x55, 0x55, 0x55],
+            [0x00, 0x00, 0x00],
+        ],
+    };
+
+    /// The green tint of the original Game Boy's screen
+    pub const GREEN: Palette = Palette {
+        shades: [
+            [0x9B, 0xBC, 0x0F],
+            [0x8B, 0xAC, 0x0F],
+            [0x30, 0x62, 0x30],
+            [0x0F, 0x38, 0x0F],
+        ],
+    };
+
+    #[must_use]
+    pub fn color(&self, shade: u8) -> [u8; 3] {
+        self.shades[usize::from(shade & 3)]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::GRAYSCALE
+    }
+}
+
+/// Parses "grayscale", "green", or four hex colors from lightest to darkest, like
+/// "E0F8D0,88C070,346856,081820" (with or without `#`)
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "grayscale" | "greyscale" | "gray" | "grey" => return Ok(Palette::GRAYSCALE),
+            "green" => return Ok(Palette::GREEN),
+            _ => (),
+        }
+        let error = || {
+            format!(
+                "unknown palette {s}; expected grayscale, green or four hex colors like \
+                 E0F8D0,88C070,346856,081820"
+            )
+        };
+        let colors: Vec<_> = s
+            .split(',')
+            .map(|color| parse_color(color.trim()))
+            .collect();
+        let mut shades = [[0; 3]; 4];
+        if colors.len() != shades.len() {
+            return Err(error());
+        }
+        for (shade, color) in shades.iter_mut().zip(colors) {
+            *shade = color.ok_or_else(error)?;
+        }
+        Ok(Palette { shades })
+    }
+}
+
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let color = color.strip_prefix('#').unwrap_or(color);
+    if color.len() != 6 || !color.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u32::from_str_radix(color, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Palette::GRAYSCALE => return write!(f, "grayscale"),
+            Palette::GREEN => return write!(f, "green"),
+            _ => (),
+        }
+        let colors: Vec<_> = self
+            .shades
+            .iter()
+            .map(|[r, g, b]| format!("#{r:02X}{g:02X}{b:02X}"))
+            .collect();
+        write!(f, "{}", colors.join(","))
+    }
+}
@@ -0,0 +1,16 @@
+//! Events sent by the frontend to peripherals in the emulated system, like sensors on a
+//! cartridge. The bus routes each event to whichever device handles it, so frontends only need
+//! this one interface for all of them.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum PeripheralEvent {
+    /// Accelerometer reading in g, for cartridges with a tilt sensor
+    Tilt { x: f32, y: f32 },
+    /// Whether the infrared receiver sees light
+    InfraredLight(bool),
+    /// The frontend has applied the last change to the rumble motor
+    RumbleAck,
+    /// Feeds a blank line of paper through a printer
+    PaperFeed,
+}
@@ -0,0 +1,81 @@
+//! A minimal PNG encoder for screenshots. Image data is stored in uncompressed deflate blocks,
+//! which keeps the encoder small at the cost of file size.
+
+use crate::metadata::crc32;
+
+pub(crate) const SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+/// Largest amount of data in a stored deflate block
+const MAX_BLOCK: usize = 0xFFFF;
+
+/// Encodes an RGB24 image as a PNG file
+///
+/// # Panics
+///
+/// Will panic if `pixels` isn't `width * height * 3` bytes
+#[must_use]
+pub fn encode(width: usize, height: usize, pixels: &[u8]) -> Vec<u8> {
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header(width, height));
+    write_chunk(&mut png, b"IDAT", &image_data(width, height, pixels));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub(crate) fn header(width: usize, height: usize) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8 bits per channel, RGB, no interlacing
+    header
+}
+
+/// Compresses an RGB24 image into the contents of an IDAT chunk
+///
+/// # Panics
+///
+/// Will panic if `pixels` isn't `width * height * 3` bytes
+pub(crate) fn image_data(width: usize, height: usize, pixels: &[u8]) -> Vec<u8> {
+    assert_eq!(pixels.len(), width * height * 3, "wrong image size");
+    // Each scanline starts with its filter type, which is always none
+    let mut scanlines = Vec::with_capacity(height * (width * 3 + 1));
+    for row in pixels.chunks(width * 3) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+    zlib_stored(&scanlines)
+}
+
+pub(crate) fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps data in a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        stream.push(u8::from(blocks.peek().is_none()));
+        let length = block.len() as u16;
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1_u32, 0_u32), |(a, b), byte| {
+        let a = (a + u32::from(*byte)) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
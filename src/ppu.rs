@@ -1,23 +1,756 @@
 use crate::interrupts::Interrupt;
+use crate::scheduler::Clocked;
+use crate::sgb::Sgb;
+use crate::video::{Frame, VideoSink};
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+/// T-cycles per frame
+pub const FRAME_CYCLES: u32 = 70224;
+
+const DOTS_PER_LINE: u16 = 456;
+const LINES_PER_FRAME: u8 = 154;
+/// Dots into line 153 when LY already reads as 0
+const LINE_153_DOTS: u16 = 4;
+/// Length of mode 3 with no scrolling, window or sprites
+const MIN_DRAWING_DOTS: u16 = 172;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
 
 pub struct Ppu {
-    pub vram: [u8; 0x2000],
+    /// Both VRAM banks, with bank 1 (CGB only) at 0x2000
+    pub vram: [u8; 0x4000],
+    /// VRAM bank mapped for the CPU (VBK)
+    pub vram_bank: u8,
     pub oam: [u8; 0xA0],
+    pub lcdc: u8,
+    pub stat: u8,
     pub scy: u8,
+    pub scx: u8,
+    pub ly: u8,
+    pub lyc: u8,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    pub wy: u8,
+    pub wx: u8,
+    pub mode: Mode,
+    pub(crate) dot: u16,
+    pub(crate) stat_line: bool,
+    /// The dot where the current line's mode 3 ends, which depends on what's drawn on it
+    pub(crate) drawing_end: u16,
+    /// Dots since the LCD was turned off, or since the last blank frame it showed after that
+    pub(crate) off_dots: u32,
+    /// Whether this is the first frame since the LCD was turned on, which isn't shown
+    pub(crate) first_frame: bool,
+    /// The window's own line counter, which only advances on lines where the window is drawn
+    pub(crate) window_line: u8,
+    /// Whether LY has matched WY at the start of a line this frame, which lets the window show
+    /// for the rest of it even if WY changes
+    pub(crate) window_triggered: bool,
+    /// Whether the window reached WX=166 on the last line, which makes it cover the next one
+    pub(crate) window_wraps: bool,
+    /// Whether the PPU is in CGB mode, with color palettes and CGB object priority
+    pub cgb: bool,
+    /// Whether a CGB is running a DMG game. The DMG shades are colored with the first
+    /// background palette and the first two object palettes, which the boot ROM sets up.
+    pub dmg_compat: bool,
+    /// CGB palette RAM: 8 palettes of 4 little endian RGB555 colors each
+    pub bg_palettes: [u8; 64],
+    pub obj_palettes: [u8; 64],
+    /// CGB palette RAM index registers (BCPS/OCPS), with the address in bits 0-5 and
+    /// auto-increment after writes in bit 7
+    pub bcps: u8,
+    pub ocps: u8,
+    /// CGB object priority mode (OPRI). When bit 0 is set, objects are prioritized like on DMG.
+    pub opri: u8,
+    /// Shades (0-3) of the last rendered frame, one byte per pixel. In CGB mode, these are the
+    /// color numbers within each pixel's palette instead.
+    pub framebuffer: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    /// RGB555 colors of the last rendered frame, in CGB mode or when colorized by the SGB
+    pub color_framebuffer: [u16; SCREEN_WIDTH * SCREEN_HEIGHT],
+    /// The Super Game Boy, which colorizes each frame when it's finished
+    pub sgb: Option<Box<Sgb>>,
+    /// Number of frames completed, incremented when VBlank is entered
+    pub frame_count: u64,
+    /// Whether the last completed frame differs from the one before it, so frontends can skip
+    /// presenting identical frames
+    pub frame_changed: bool,
+    /// Whether any pixel has changed so far in the frame being drawn
+    frame_dirty: bool,
+    pub(crate) video_sinks: Vec<Box<dyn VideoSink>>,
 }
 
 impl Default for Ppu {
     fn default() -> Self {
         Self {
-            vram: [0; 0x2000],
+            vram: [0; 0x4000],
+            vram_bank: 0,
             oam: [0; 0xA0],
+            lcdc: 0,
+            stat: 0,
             scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            mode: Mode::HBlank,
+            dot: 0,
+            stat_line: false,
+            drawing_end: 80 + MIN_DRAWING_DOTS,
+            off_dots: 0,
+            first_frame: false,
+            window_line: 0,
+            window_triggered: false,
+            window_wraps: false,
+            cgb: false,
+            dmg_compat: false,
+            bg_palettes: [0; 64],
+            obj_palettes: [0; 64],
+            bcps: 0,
+            ocps: 0,
+            opri: 0,
+            framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            color_framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            sgb: None,
+            frame_count: 0,
+            frame_changed: true,
+            frame_dirty: true,
+            video_sinks: Vec::new(),
         }
     }
 }
 
 impl Ppu {
-    pub(crate) fn tick(&mut self) -> Option<Interrupt> {
-        None
+    fn step_dot(&mut self) -> Option<Interrupt> {
+        if !self.lcd_enabled() {
+            // The screen stays blank, but frames keep coming at the same rate for the frontend
+            self.off_dots += 1;
+            if self.off_dots == FRAME_CYCLES {
+                self.off_dots = 0;
+                self.finish_frame();
+            }
+            return None;
+        }
+        let mut interrupt = None;
+        self.dot += 1;
+        if self.dot == DOTS_PER_LINE {
+            self.dot = 0;
+            self.ly = (self.ly + 1) % LINES_PER_FRAME;
+            self.start_line();
+            if self.ly == 144 {
+                self.first_frame = false;
+                self.finish_sgb_frame();
+                self.finish_frame();
+                interrupt = Some(Interrupt::VBlank);
+            }
+        }
+
+        if self.dot == 80 && self.ly < 144 {
+            self.drawing_end = 80 + self.drawing_dots();
+        }
+        let mode = match (self.ly, self.dot) {
+            (144.., _) => Mode::VBlank,
+            // The first line after the LCD is turned on starts without scanning OAM
+            (0, 0..=79) if self.first_frame => Mode::HBlank,
+            (_, 0..=79) => Mode::OamScan,
+            (_, dot) if dot < self.drawing_end => Mode::Drawing,
+            _ => Mode::HBlank,
+        };
+        if mode != self.mode {
+            if mode == Mode::HBlank && !self.first_frame {
+                self.render_line();
+            }
+            self.mode = mode;
+        }
+        interrupt
+    }
+
+    /// Counts a completed frame and sends it to the video sinks
+    fn finish_frame(&mut self) {
+        self.frame_count = self.frame_count.wrapping_add(1);
+        self.frame_changed = self.frame_dirty;
+        self.frame_dirty = false;
+        let frame = Frame {
+            number: self.frame_count,
+            pixels: &self.framebuffer,
+            colors: self.colored().then_some(&self.color_framebuffer[..]),
+        };
+        for sink in &mut self.video_sinks {
+            sink.push_frame(&frame);
+        }
+    }
+
+    /// Turning the LCD off stops the PPU and blanks the screen. On DMG, this is supposed to be
+    /// done during VBlank, but it works the same at any time.
+    fn turn_off(&mut self) {
+        self.ly = 0;
+        self.dot = 0;
+        self.mode = Mode::HBlank;
+        self.off_dots = 0;
+        self.framebuffer.fill(0);
+        self.color_framebuffer.fill(0x7FFF);
+        self.frame_dirty = true;
+    }
+
+    /// Turning the LCD on starts a frame from the top, which is drawn but not shown
+    fn turn_on(&mut self) {
+        self.ly = 0;
+        self.dot = 0;
+        self.mode = Mode::HBlank;
+        self.first_frame = true;
+        self.start_line();
+    }
+
+    /// Length of mode 3 on the current line. The pixels SCX scrolls past within the first tile
+    /// are fetched and thrown away, starting the window restarts fetching, and each sprite
+    /// stalls the fetcher, for longer if the background tile under it hasn't been fetched yet.
+    fn drawing_dots(&self) -> u16 {
+        let window = self.window_visible();
+        let mut dots = MIN_DRAWING_DOTS + u16::from(self.scx & 7) + if window { 6 } else { 0 };
+        if self.lcdc & 0x02 == 0 {
+            return dots;
+        }
+        let window_start = i16::from(self.wx) - 7;
+        let mut sprites = self.line_sprites();
+        sprites.sort_by_key(|sprite| sprite[1]);
+        // The last tile a stall was counted for, as whether it's a window tile and its index
+        let mut stalled_tile = None;
+        for sprite in sprites {
+            // Sprites past the right edge of the screen aren't fetched
+            if sprite[1] >= 168 {
+                continue;
+            }
+            let x = i16::from(sprite[1]) - 8;
+            let (in_window, pixel) = if window && x >= window_start {
+                (true, x - window_start)
+            } else {
+                (false, x + i16::from(self.scx & 7))
+            };
+            let tile = Some((in_window, pixel.div_euclid(8)));
+            if tile != stalled_tile {
+                stalled_tile = tile;
+                dots += 5_u16.saturating_sub(pixel.rem_euclid(8) as u16);
+            }
+            dots += 6;
+        }
+        dots
+    }
+
+    /// Whether the window is drawn on the current line
+    fn window_visible(&self) -> bool {
+        self.lcdc & 0x20 != 0 && self.window_triggered && (self.wx <= 166 || self.window_wraps)
+    }
+
+    /// The first 10 sprites in OAM on the current line. OAM scan picks them by Y alone, so ones
+    /// that are off screen horizontally count too.
+    fn line_sprites(&self) -> Vec<[u8; 4]> {
+        let ly = i16::from(self.ly);
+        let height = if self.lcdc & 0x04 != 0 { 16 } else { 8 };
+        self.oam
+            .chunks_exact(4)
+            .filter(|sprite| {
+                let y = i16::from(sprite[0]) - 16;
+                (y..y + height).contains(&ly)
+            })
+            .take(10)
+            .map(|sprite| [sprite[0], sprite[1], sprite[2], sprite[3]])
+            .collect()
+    }
+
+    /// Resets the window at the start of a frame, and checks WY at the start of each line
+    fn start_line(&mut self) {
+        if self.ly == 0 {
+            self.window_line = 0;
+            self.window_triggered = false;
+            self.window_wraps = false;
+        }
+        if self.ly == self.wy {
+            self.window_triggered = true;
+        }
+    }
+
+    /// LY as the CPU sees it and compares with LYC. It already reads as 0 for most of the last
+    /// line of VBlank, so LYC=153 only matches briefly, and LYC=0 matches from then on.
+    #[must_use]
+    pub fn visible_ly(&self) -> u8 {
+        if self.ly == 153 && self.dot >= LINE_153_DOTS {
+            0
+        } else {
+            self.ly
+        }
+    }
+
+    fn stat_line(&self) -> bool {
+        if !self.lcd_enabled() {
+            return false;
+        }
+        (self.stat & 0x08 != 0 && self.mode == Mode::HBlank)
+            || (self.stat & 0x10 != 0 && self.mode == Mode::VBlank)
+            || (self.stat & 0x20 != 0 && self.mode == Mode::OamScan)
+            || (self.stat & 0x40 != 0 && self.visible_ly() == self.lyc)
+    }
+
+    /// The last completed frame
+    #[must_use]
+    pub fn frame(&self) -> Frame<'_> {
+        Frame {
+            number: self.frame_count,
+            pixels: &self.framebuffer,
+            colors: self.colored().then_some(&self.color_framebuffer[..]),
+        }
+    }
+
+    /// Whether frames have colors in `color_framebuffer`
+    fn colored(&self) -> bool {
+        self.cgb || self.dmg_compat || self.sgb.is_some()
+    }
+
+    /// Lets the SGB receive any data sent through VRAM, and colorize the finished frame
+    fn finish_sgb_frame(&mut self) {
+        let Some(sgb) = &self.sgb else {
+            return;
+        };
+        let data = sgb.transfer_pending().then(|| self.screen_tiles());
+        if let Some(sgb) = &mut self.sgb {
+            if sgb.finish_frame(
+                data.as_deref(),
+                &self.framebuffer,
+                &mut self.color_framebuffer,
+            ) {
+                self.frame_dirty = true;
+            }
+        }
+    }
+
+    /// The tile data of the first 256 tiles on screen, from left to right and top to bottom,
+    /// which is how data is sent to the SGB
+    fn screen_tiles(&self) -> Vec<u8> {
+        let map = if self.lcdc & 0x08 != 0 {
+            0x1C00
+        } else {
+            0x1800
+        };
+        (0..256)
+            .flat_map(|tile| {
+                let tile_number = self.vram[map + (tile / 20) * 32 + tile % 20];
+                let address = self.bg_tile_address(tile_number);
+                self.vram[address..address + 16].iter().copied()
+            })
+            .collect()
+    }
+
+    /// Whether the LCD is turned on (LCDC bit 7)
+    #[must_use]
+    pub fn lcd_enabled(&self) -> bool {
+        self.lcdc & 0x80 != 0
+    }
+
+    /// Emulates the DMG OAM corruption bug, which happens when the CPU puts an address in
+    /// 0xFE00-0xFEFF on the address bus while the PPU is scanning OAM, like when it increments
+    /// or decrements a pointer. The row of 8 bytes being scanned is mixed with the one before it.
+    pub(crate) fn corrupt_oam(&mut self) {
+        if !self.lcd_enabled() || self.mode != Mode::OamScan {
+            return;
+        }
+        // Two objects are scanned each M-cycle, and the first row is never corrupted
+        let row = usize::from(self.dot / 4) * 8;
+        if row == 0 || row >= self.oam.len() {
+            return;
+        }
+        let word = |oam: &[u8], index: usize| u16::from_le_bytes([oam[index], oam[index + 1]]);
+        let a = word(&self.oam, row);
+        let b = word(&self.oam, row - 8);
+        let c = word(&self.oam, row - 4);
+        let corrupted = ((a ^ c) & (b ^ c)) ^ c;
+        self.oam[row..row + 2].copy_from_slice(&corrupted.to_le_bytes());
+        self.oam.copy_within(row - 6..row, row + 2);
+    }
+
+    /// Returns the index into `vram` that the CPU accesses at an address in 0x8000-0x9FFF,
+    /// in the bank selected by VBK
+    #[must_use]
+    pub fn vram_index(&self, address: u16) -> usize {
+        usize::from(self.vram_bank) * 0x2000 + usize::from(address - 0x8000)
+    }
+
+    /// Returns the color index (0-3) of a pixel in the tile whose data starts at `address`
+    #[must_use]
+    pub fn tile_pixel(&self, address: usize, x: usize, y: usize) -> u8 {
+        let low = self.vram[address + y * 2];
+        let high = self.vram[address + y * 2 + 1];
+        (((high >> (7 - x)) & 1) << 1) | ((low >> (7 - x)) & 1)
+    }
+
+    /// Returns the VRAM address of a background/window tile, honoring LCDC bit 4
+    #[must_use]
+    pub fn bg_tile_address(&self, tile_number: u8) -> usize {
+        if self.lcdc & 0x10 != 0 {
+            usize::from(tile_number) * 16
+        } else {
+            (0x1000 + i32::from(tile_number as i8) * 16) as usize
+        }
+    }
+
+    fn render_line(&mut self) {
+        let ly = usize::from(self.ly);
+        let row = ly * SCREEN_WIDTH..(ly + 1) * SCREEN_WIDTH;
+        let previous_line: [u8; SCREEN_WIDTH] = self.framebuffer[row.clone()]
+            .try_into()
+            .expect("Framebuffer row has screen width");
+        let previous_colors: [u16; SCREEN_WIDTH] = self.color_framebuffer[row.clone()]
+            .try_into()
+            .expect("Framebuffer row has screen width");
+        let mut line = [0_u8; SCREEN_WIDTH];
+        // CGB background attributes of each pixel
+        let mut attributes = [0_u8; SCREEN_WIDTH];
+
+        let window_visible = self.window_visible();
+        // Screen X where the window starts, plus the number of its pixels that are cut off
+        let window_start = match self.wx {
+            _ if self.window_wraps => (0, 0),
+            // At WX=0, the background's fine scroll cuts off the start of the window instead
+            0 => (0, 7 + usize::from(self.scx & 7)),
+            1..=6 => (0, usize::from(7 - self.wx)),
+            wx => (usize::from(wx - 7), 0),
+        };
+        // In CGB mode, LCDC bit 0 doesn't hide the background, it only takes away its priority
+        // over objects
+        if self.lcdc & 0x01 != 0 || self.cgb {
+            for (x, (color, attributes)) in line.iter_mut().zip(&mut attributes).enumerate() {
+                let (map, map_x, map_y) = if window_visible && x >= window_start.0 {
+                    (
+                        if self.lcdc & 0x40 != 0 {
+                            0x1C00
+                        } else {
+                            0x1800
+                        },
+                        x - window_start.0 + window_start.1,
+                        usize::from(self.window_line),
+                    )
+                } else {
+                    (
+                        if self.lcdc & 0x08 != 0 {
+                            0x1C00
+                        } else {
+                            0x1800
+                        },
+                        (x + usize::from(self.scx)) & 0xFF,
+                        (ly + usize::from(self.scy)) & 0xFF,
+                    )
+                };
+                let map_index = map + (map_y / 8) * 32 + map_x / 8;
+                let tile_number = self.vram[map_index];
+                let (mut tile_x, mut tile_y) = (map_x % 8, map_y % 8);
+                let mut address = self.bg_tile_address(tile_number);
+                if self.cgb {
+                    // The attribute map is in bank 1, parallel to the tile map in bank 0
+                    *attributes = self.vram[0x2000 + map_index];
+                    if *attributes & 0x08 != 0 {
+                        address += 0x2000;
+                    }
+                    if *attributes & 0x20 != 0 {
+                        tile_x = 7 - tile_x;
+                    }
+                    if *attributes & 0x40 != 0 {
+                        tile_y = 7 - tile_y;
+                    }
+                }
+                *color = self.tile_pixel(address, tile_x, tile_y);
+            }
+        }
+
+        for (x, color) in line.iter().enumerate() {
+            if self.cgb {
+                self.framebuffer[ly * SCREEN_WIDTH + x] = *color;
+                self.color_framebuffer[ly * SCREEN_WIDTH + x] =
+                    palette_color(&self.bg_palettes, attributes[x] & 0x07, *color);
+            } else {
+                // On DMG, clearing LCDC bit 0 blanks the background and window to white rather
+                // than to BGP's shade for color 0
+                let shade = if self.lcdc & 0x01 != 0 {
+                    (self.bgp >> (color * 2)) & 3
+                } else {
+                    0
+                };
+                self.framebuffer[ly * SCREEN_WIDTH + x] = shade;
+                if self.dmg_compat {
+                    self.color_framebuffer[ly * SCREEN_WIDTH + x] =
+                        palette_color(&self.bg_palettes, 0, shade);
+                }
+            }
+        }
+
+        if window_visible {
+            self.window_line = self.window_line.wrapping_add(1);
+        }
+        self.window_wraps = window_visible && self.wx == 166;
+
+        if self.lcdc & 0x02 != 0 {
+            self.render_sprites(&line, &attributes);
+        }
+
+        if self.framebuffer[row.clone()] != previous_line
+            || self.color_framebuffer[row] != previous_colors
+        {
+            self.frame_dirty = true;
+        }
+    }
+
+    /// Draws the sprites on the current line on top of the background, whose color indices are
+    /// given in `bg_line` and CGB attributes in `bg_attributes`. Only the first 10 sprites in
+    /// OAM on the line are drawn. Where they overlap, the one with the lowest X is on top on
+    /// DMG, and the one earliest in OAM in CGB mode.
+    fn render_sprites(&mut self, bg_line: &[u8; SCREEN_WIDTH], bg_attributes: &[u8; SCREEN_WIDTH]) {
+        let ly = i16::from(self.ly);
+        let height = if self.lcdc & 0x04 != 0 { 16 } else { 8 };
+        // In CGB mode, clearing LCDC bit 0 puts objects on top of the background regardless of
+        // their attributes
+        let bg_priority = !self.cgb || self.lcdc & 0x01 != 0;
+
+        let mut sprites = self.line_sprites();
+        if !self.cgb || self.opri & 0x01 != 0 {
+            // The sort is stable, so sprites with the same X stay in OAM order
+            sprites.sort_by_key(|sprite| sprite[1]);
+        }
+        // Pixels that already have a sprite on top, even if the background hides it
+        let mut covered = [false; SCREEN_WIDTH];
+
+        for sprite in sprites {
+            let y = i16::from(sprite[0]) - 16;
+            let x = i16::from(sprite[1]) - 8;
+            let attributes = sprite[3];
+
+            let mut row = (ly - y) as usize;
+            if attributes & 0x40 != 0 {
+                row = height as usize - 1 - row;
+            }
+            let tile_number = if height == 16 {
+                sprite[2] & 0xFE
+            } else {
+                sprite[2]
+            };
+            let mut tile_address = usize::from(tile_number) * 16;
+            if self.cgb && attributes & 0x08 != 0 {
+                tile_address += 0x2000;
+            }
+            let palette = if attributes & 0x10 != 0 {
+                self.obp1
+            } else {
+                self.obp0
+            };
+
+            for column in 0..8 {
+                let screen_x = x + column;
+                if !(0..SCREEN_WIDTH as i16).contains(&screen_x) {
+                    continue;
+                }
+                let screen_x = screen_x as usize;
+                let pixel_x = if attributes & 0x20 != 0 {
+                    7 - column as usize
+                } else {
+                    column as usize
+                };
+                let color = self.tile_pixel(tile_address, pixel_x, row);
+                if color == 0 || covered[screen_x] {
+                    continue;
+                }
+                covered[screen_x] = true;
+                // Either the object or the background tile can give the background priority
+                let behind_bg = (attributes | bg_attributes[screen_x]) & 0x80 != 0;
+                if bg_priority && behind_bg && bg_line[screen_x] != 0 {
+                    continue;
+                }
+                let offset = usize::from(self.ly) * SCREEN_WIDTH + screen_x;
+                if self.cgb {
+                    self.framebuffer[offset] = color;
+                    self.color_framebuffer[offset] =
+                        palette_color(&self.obj_palettes, attributes & 0x07, color);
+                } else {
+                    let shade = (palette >> (color * 2)) & 3;
+                    self.framebuffer[offset] = shade;
+                    if self.dmg_compat {
+                        self.color_framebuffer[offset] =
+                            palette_color(&self.obj_palettes, (attributes >> 4) & 1, shade);
+                    }
+                }
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0xFF40 => self.lcdc,
+            0xFF41 => {
+                0x80 | self.stat | (u8::from(self.visible_ly() == self.lyc) << 2) | self.mode as u8
+            }
+            0xFF42 => self.scy,
+            0xFF43 => self.scx,
+            0xFF44 => self.visible_ly(),
+            0xFF45 => self.lyc,
+            0xFF47 => self.bgp,
+            0xFF48 => self.obp0,
+            0xFF49 => self.obp1,
+            0xFF4A => self.wy,
+            0xFF4B => self.wx,
+            0xFF68 => 0x40 | self.bcps,
+            0xFF69 => self.bg_palettes[usize::from(self.bcps & 0x3F)],
+            0xFF6A => 0x40 | self.ocps,
+            0xFF6B => self.obj_palettes[usize::from(self.ocps & 0x3F)],
+            0xFF6C => 0xFE | self.opri,
+            0xFF4F => 0xFE | self.vram_bank,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF40 => {
+                let enabled = self.lcd_enabled();
+                self.lcdc = value;
+                match (enabled, self.lcd_enabled()) {
+                    (true, false) => self.turn_off(),
+                    (false, true) => self.turn_on(),
+                    _ => (),
+                }
+            }
+            0xFF41 => self.stat = value & 0x78,
+            0xFF42 => self.scy = value,
+            0xFF43 => self.scx = value,
+            0xFF44 => (),
+            0xFF45 => self.lyc = value,
+            0xFF47 => self.bgp = value,
+            0xFF48 => self.obp0 = value,
+            0xFF49 => self.obp1 = value,
+            0xFF4A => self.wy = value,
+            0xFF4B => self.wx = value,
+            0xFF68 => self.bcps = value & 0xBF,
+            0xFF69 => {
+                self.bg_palettes[usize::from(self.bcps & 0x3F)] = value;
+                self.advance_palette_index(address);
+            }
+            0xFF6A => self.ocps = value & 0xBF,
+            0xFF6B => {
+                self.obj_palettes[usize::from(self.ocps & 0x3F)] = value;
+                self.advance_palette_index(address);
+            }
+            0xFF6C => self.opri = value & 0x01,
+            0xFF4F => self.vram_bank = value & 0x01,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Sets up palette RAM for a DMG game like the CGB boot ROM does, with the background and
+    /// object palettes from `compat_palettes::palettes`
+    pub(crate) fn load_compat_palettes(&mut self, [bg, obj0, obj1]: [[u16; 4]; 3]) {
+        let colors = |palette: [u16; 4]| palette.map(u16::to_le_bytes).concat();
+        self.bg_palettes[0..8].copy_from_slice(&colors(bg));
+        self.obj_palettes[0..8].copy_from_slice(&colors(obj0));
+        self.obj_palettes[8..16].copy_from_slice(&colors(obj1));
+    }
+
+    /// Advances the BCPS or OCPS index, if it auto-increments, after a write to the palette
+    /// data register at `address`. This happens even when the write itself is dropped.
+    pub(crate) fn advance_palette_index(&mut self, address: u16) {
+        match address {
+            0xFF69 => self.bcps = increment_palette_index(self.bcps),
+            _ => self.ocps = increment_palette_index(self.ocps),
+        }
+    }
+
+    /// Whether the CPU can access CGB palette RAM, which the PPU uses while drawing
+    #[must_use]
+    pub fn palettes_accessible(&self) -> bool {
+        self.vram_accessible()
+    }
+
+    /// Whether the CPU can access VRAM, which the PPU uses while drawing
+    #[must_use]
+    pub fn vram_accessible(&self) -> bool {
+        !self.lcd_enabled() || self.mode != Mode::Drawing
+    }
+
+    /// Whether the CPU can access OAM, which the PPU uses while scanning OAM and drawing
+    #[must_use]
+    pub fn oam_accessible(&self) -> bool {
+        !self.lcd_enabled() || !matches!(self.mode, Mode::OamScan | Mode::Drawing)
+    }
+}
+
+impl Clocked for Ppu {
+    /// Tick one M-cycle (4 dots)
+    fn tick(&mut self) -> Option<Interrupt> {
+        let mut interrupt = None;
+        for _ in 0..4 {
+            if let Some(irq) = self.step_dot() {
+                interrupt = Some(irq);
+            }
+        }
+
+        // The STAT interrupt fires on the rising edge of the OR-ed STAT sources. If VBlank was
+        // requested this cycle, the edge is left pending until the next one.
+        let stat_line = self.stat_line();
+        if stat_line && !self.stat_line {
+            if interrupt.is_none() {
+                self.stat_line = true;
+                return Some(Interrupt::Stat);
+            }
+        } else {
+            self.stat_line = stat_line;
+        }
+        interrupt
+    }
+
+    fn skip(&mut self, cycles: u64) {
+        if self.lcd_enabled() {
+            self.dot += 4 * cycles as u16;
+        } else {
+            self.off_dots += 4 * cycles as u32;
+        }
+    }
+
+    fn cycles_until_event(&self) -> u64 {
+        // A STAT edge that's been held back, or a source that was turned off
+        if self.stat_line() != self.stat_line {
+            return 1;
+        }
+        if !self.lcd_enabled() {
+            return u64::from((FRAME_CYCLES - self.off_dots).div_ceil(4).max(1));
+        }
+        // Otherwise, the next thing to happen is a mode change or the end of the line
+        let boundary = match self.mode {
+            Mode::OamScan => 80,
+            Mode::HBlank if self.dot < 80 => 80,
+            Mode::Drawing => self.drawing_end,
+            Mode::VBlank if self.ly == 153 && self.dot < LINE_153_DOTS => LINE_153_DOTS,
+            Mode::HBlank | Mode::VBlank => DOTS_PER_LINE,
+        };
+        u64::from(boundary.saturating_sub(self.dot).div_ceil(4).max(1))
     }
 }
+
+/// Advances a palette index register after a write to palette data, if auto-increment is on
+fn increment_palette_index(index: u8) -> u8 {
+    if index & 0x80 != 0 {
+        0x80 | (index.wrapping_add(1) & 0x3F)
+    } else {
+        index
+    }
+}
+
+/// Looks up a color in CGB palette RAM
+fn palette_color(palettes: &[u8; 64], palette: u8, color: u8) -> u16 {
+    let offset = usize::from(palette * 8 + color * 2);
+    u16::from_le_bytes([palettes[offset], palettes[offset + 1]]) & 0x7FFF
+}
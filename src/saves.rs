@@ -0,0 +1,50 @@
+use rgb_emu::bus::{Bus, DmgBus};
+use rgb_emu::cartridge::Cartridge;
+use rgb_emu::cpu::Cpu;
+use std::path::{Path, PathBuf};
+
+/// Battery-backed cartridge RAM persisted in a `.sav` file next to the ROM or in a save directory
+pub struct SaveFile {
+    path: PathBuf,
+    last_saved: Option<Vec<u8>>,
+}
+
+impl SaveFile {
+    /// Loads the save file for the ROM into the cartridge, if the cartridge has a battery and
+    /// the file exists. The file is in `save_dir` if given, and next to the ROM otherwise.
+    pub fn load(rom_path: &Path, save_dir: Option<&Path>, cartridge: &mut dyn Cartridge) -> Self {
+        let path = match save_dir {
+            Some(dir) => dir.join(rom_path.file_name().unwrap_or_default()),
+            None => rom_path.to_path_buf(),
+        }
+        .with_extension("sav");
+        if cartridge.save_data().is_some() {
+            if let Ok(data) = std::fs::read(&path) {
+                cartridge.load_save_data(&data);
+            }
+        }
+        Self {
+            last_saved: cartridge.save_data(),
+            path,
+        }
+    }
+
+    /// Writes the cartridge's battery-backed RAM to disk, if it has changed since last time.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be written
+    pub fn flush(&mut self, cpu: &Cpu<DmgBus>) -> std::io::Result<()> {
+        let data = cpu
+            .bus
+            .get_cartridge()
+            .and_then(|cartridge| cartridge.save_data());
+        if data.is_some() && data != self.last_saved {
+            if let Some(data) = &data {
+                std::fs::write(&self.path, data)?;
+            }
+            self.last_saved = data;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+//! Scaling composed RGB24 images up by an integer factor for modern displays, with optional
+//! filters that mimic a CRT or the Game Boy's LCD, or smooth out pixel art
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// Plain nearest-neighbor scaling
+    #[default]
+    None,
+    /// Darkens the last row of each scaled pixel, like the gaps between CRT scanlines
+    Scanlines,
+    /// Darkens the last row and column of each scaled pixel, like the grid between LCD pixels
+    LcdGrid,
+    /// Smooths diagonal edges with the Scale2x and Scale3x algorithms
+    Smooth,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Filter::None),
+            "scanlines" => Ok(Filter::Scanlines),
+            "lcd-grid" => Ok(Filter::LcdGrid),
+            "smooth" => Ok(Filter::Smooth),
+            _ => Err(format!(
+                "unknown filter {s}; expected none, scanlines, lcd-grid or smooth"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Filter::None => "none",
+            Filter::Scanlines => "scanlines",
+            Filter::LcdGrid => "lcd-grid",
+            Filter::Smooth => "smooth",
+        };
+        write!(f, "{name}")
+    }
+}
+
+type Image = Vec<[u8; 3]>;
+
+/// Scales an RGB24 image up by `factor` in both directions, returning the scaled RGB24 image.
+/// Filters other than smoothing have no effect at a factor of 1.
+///
+/// # Panics
+///
+/// Will panic if `pixels` isn't `width * height * 3` bytes
+#[must_use]
+pub fn scale(width: usize, height: usize, pixels: &[u8], factor: usize, filter: Filter) -> Vec<u8> {
+    assert_eq!(pixels.len(), width * height * 3, "wrong image size");
+    let factor = factor.max(1);
+    let mut image: Image = pixels
+        .chunks(3)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+    let (mut width, mut height) = (width, height);
+    let mut remaining = factor;
+    if filter == Filter::Smooth {
+        while remaining > 1 {
+            let step = if remaining.is_multiple_of(3) {
+                3
+            } else if remaining.is_multiple_of(2) {
+                2
+            } else {
+                break;
+            };
+            image = if step == 3 {
+                scale3x(width, height, &image)
+            } else {
+                scale2x(width, height, &image)
+            };
+            (width, height) = (width * step, height * step);
+            remaining /= step;
+        }
+    }
+
+    let mut scaled = Vec::with_capacity(width * height * remaining * remaining * 3);
+    for y in 0..height * remaining {
+        for x in 0..width * remaining {
+            let mut color = image[(y / remaining) * width + x / remaining];
+            let last_row = y % factor == factor - 1;
+            let last_column = x % factor == factor - 1;
+            let darken = factor > 1
+                && match filter {
+                    Filter::Scanlines => last_row,
+                    Filter::LcdGrid => last_row || last_column,
+                    Filter::None | Filter::Smooth => false,
+                };
+            if darken {
+                color = color.map(|channel| (u16::from(channel) * 3 / 4) as u8);
+            }
+            scaled.extend_from_slice(&color);
+        }
+    }
+    scaled
+}
+
+/// Returns a pixel, clamping the coordinates to the edges of the image
+fn pixel(width: usize, height: usize, image: &[[u8; 3]], x: isize, y: isize) -> [u8; 3] {
+    let x = x.clamp(0, width as isize - 1) as usize;
+    let y = y.clamp(0, height as isize - 1) as usize;
+    image[y * width + x]
+}
+
+fn scale2x(width: usize, height: usize, image: &[[u8; 3]]) -> Image {
+    let mut scaled = vec![[0; 3]; width * height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            let get = |dx: isize, dy: isize| {
+                pixel(width, height, image, x as isize + dx, y as isize + dy)
+            };
+            let (a, b, c, d, p) = (get(0, -1), get(1, 0), get(-1, 0), get(0, 1), get(0, 0));
+            let output = [
+                if c == a && c != d && a != b { a } else { p },
+                if a == b && a != c && b != d { b } else { p },
+                if d == c && d != b && c != a { c } else { p },
+                if b == d && b != a && d != c { d } else { p },
+            ];
+            for (i, color) in output.into_iter().enumerate() {
+                scaled[(y * 2 + i / 2) * width * 2 + x * 2 + i % 2] = color;
+            }
+        }
+    }
+    scaled
+}
+
+fn scale3x(width: usize, height: usize, image: &[[u8; 3]]) -> Image {
+    let mut scaled = vec![[0; 3]; width * height * 9];
+    for y in 0..height {
+        for x in 0..width {
+            let get = |dx: isize, dy: isize| {
+                pixel(width, height, image, x as isize + dx, y as isize + dy)
+            };
+            let (a, b, c) = (get(-1, -1), get(0, -1), get(1, -1));
+            let (d, e, f) = (get(-1, 0), get(0, 0), get(1, 0));
+            let (g, h, i) = (get(-1, 1), get(0, 1), get(1, 1));
+            let output = [
+                if d == b && d != h && b != f { d } else { e },
+                if (d == b && d != h && b != f && e != c) || (b == f && b != d && f != h && e != a)
+                {
+                    b
+                } else {
+                    e
+                },
+                if b == f && b != d && f != h { f } else { e },
+                if (h == d && h != f && d != b && e != a) || (d == b && d != h && b != f && e != g)
+                {
+                    d
+                } else {
+                    e
+                },
+                e,
+                if (b == f && b != d && f != h && e != i) || (f == h && f != b && h != d && e != c)
+                {
+                    f
+                } else {
+                    e
+                },
+                if h == d && h != f && d != b { d } else { e },
+                if (f == h && f != b && h != d && e != g) || (h == d && h != f && d != b && e != i)
+                {
+                    h
+                } else {
+                    e
+                },
+                if f == h && f != b && h != d { f } else { e },
+            ];
+            for (index, color) in output.into_iter().enumerate() {
+                scaled[(y * 3 + index / 3) * width * 3 + x * 3 + index % 3] = color;
+            }
+        }
+    }
+    scaled
+}
@@ -0,0 +1,107 @@
+//! Runs components lazily. Instead of being ticked every M-cycle, each component tells the
+//! scheduler how long it can go before something happens that the rest of the system can see,
+//! like an interrupt or a PPU mode change. The bus runs it up to that point in one go when the
+//! time comes, or earlier when the CPU accesses it.
+
+use crate::interrupts::Interrupt;
+
+/// A component that can be run lazily by the scheduler
+pub(crate) trait Clocked {
+    /// Tick one M-cycle
+    fn tick(&mut self) -> Option<Interrupt>;
+    /// Runs `cycles` M-cycles at once, which must be fewer than `cycles_until_event`, so
+    /// nothing but internal counters change
+    fn skip(&mut self, cycles: u64);
+    /// M-cycles until the next one that has to be run with `tick`, counting that one.
+    /// `u64::MAX` if nothing will happen until the component is accessed.
+    fn cycles_until_event(&self) -> u64;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    /// The CPU's M-cycles, which are twice as fast in CGB double-speed mode
+    Cpu = 0,
+    /// M-cycles at normal speed, which the PPU, APU and cartridge keep in double-speed mode
+    System = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    Timer = 0,
+    Serial = 1,
+    Ppu = 2,
+}
+
+impl Component {
+    pub const ALL: [Component; 3] = [Component::Timer, Component::Serial, Component::Ppu];
+
+    /// The clock the component runs on
+    #[must_use]
+    pub fn clock(self) -> Clock {
+        match self {
+            Component::Timer | Component::Serial => Clock::Cpu,
+            Component::Ppu => Clock::System,
+        }
+    }
+}
+
+/// Keeps track of how far each component has been run, and when it has to run next
+#[derive(Default)]
+pub struct Scheduler {
+    /// M-cycles each clock has run since power on
+    pub(crate) now: [u64; 2],
+    /// Time each component has been run up to, on its clock
+    pub(crate) synced: [u64; 3],
+    /// Time each component has to be run at next, at the latest
+    deadlines: [u64; 3],
+    /// The earliest deadline on each clock
+    next: [u64; 2],
+}
+
+impl Scheduler {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances a clock by one M-cycle. Returns whether a component on it is due to run.
+    pub fn advance(&mut self, clock: Clock) -> bool {
+        let clock = clock as usize;
+        self.now[clock] += 1;
+        self.now[clock] >= self.next[clock]
+    }
+
+    #[must_use]
+    pub fn now(&self, clock: Clock) -> u64 {
+        self.now[clock as usize]
+    }
+
+    /// M-cycles the component has to be run to catch up
+    #[must_use]
+    pub fn owed(&self, component: Component) -> u64 {
+        self.now(component.clock()) - self.synced[component as usize]
+    }
+
+    /// Whether the component's next event has come
+    #[must_use]
+    pub fn due(&self, component: Component) -> bool {
+        self.now(component.clock()) >= self.deadlines[component as usize]
+    }
+
+    /// Records that the component has caught up
+    pub fn sync(&mut self, component: Component) {
+        self.synced[component as usize] = self.now(component.clock());
+    }
+
+    /// Sets the component's next event to `cycles` M-cycles after the time it has been run to
+    pub fn schedule(&mut self, component: Component, cycles: u64) {
+        self.deadlines[component as usize] = self.synced[component as usize].saturating_add(cycles);
+        let clock = component.clock();
+        self.next[clock as usize] = Component::ALL
+            .into_iter()
+            .filter(|component| component.clock() == clock)
+            .map(|component| self.deadlines[component as usize])
+            .min()
+            .unwrap_or(u64::MAX);
+    }
+}
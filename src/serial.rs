@@ -0,0 +1,154 @@
+//! The serial port (SB and SC), which shifts a byte out to a link cable device while shifting
+//! the device's byte in, one bit at a time
+
+use crate::interrupts::Interrupt;
+use crate::scheduler::Clocked;
+
+/// M-cycles per bit with the internal clock, which runs at 8192 Hz
+const CYCLES_PER_BIT: u16 = 128;
+/// M-cycles per bit with the CGB's fast internal clock, which runs at 262144 Hz
+const FAST_CYCLES_PER_BIT: u16 = 4;
+
+/// Something plugged into the serial port
+pub trait LinkDevice {
+    /// Called when this Game Boy starts a transfer with its internal clock, with the byte it
+    /// shifts out. Returns the byte the device shifts in.
+    fn exchange(&mut self, byte: u8) -> u8;
+    /// Called regularly to check whether the device has clocked a transfer itself, with the
+    /// byte this Game Boy would shift out. Returns the byte the device shifted in, if any.
+    fn poll(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// No link cable, so all bits shifted in are 1
+pub struct Disconnected;
+
+impl LinkDevice for Disconnected {
+    fn exchange(&mut self, _byte: u8) -> u8 {
+        0xFF
+    }
+}
+
+pub struct Serial {
+    /// SB, the byte being shifted out and in
+    pub data: u8,
+    /// SC: bit 7 starts a transfer and stays set until it's done, bit 1 selects the fast clock
+    /// on CGB, and bit 0 selects the internal clock
+    pub control: u8,
+    /// Whether the CGB's fast clock can be selected
+    pub cgb: bool,
+    /// The byte being shifted in during a transfer, and how many bits are left
+    pub(crate) incoming: u8,
+    pub(crate) bits_left: u8,
+    /// M-cycles since power on, which drive the internal clock
+    pub(crate) clock: u16,
+    device: Box<dyn LinkDevice>,
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self {
+            data: 0,
+            control: 0,
+            cgb: false,
+            incoming: 0xFF,
+            bits_left: 0,
+            clock: 0,
+            device: Box::new(Disconnected),
+        }
+    }
+}
+
+impl Serial {
+    pub fn set_device(&mut self, device: Box<dyn LinkDevice>) {
+        self.device = device;
+    }
+
+    /// Resets the registers to their power-on state, keeping the device that's plugged in
+    pub(crate) fn reset(&mut self) {
+        let device = std::mem::replace(&mut self.device, Box::new(Disconnected));
+        *self = Self {
+            cgb: self.cgb,
+            device,
+            ..Self::default()
+        };
+    }
+
+    /// M-cycles per bit with the internal clock
+    fn period(&self) -> u16 {
+        if self.cgb && self.control & 0x02 != 0 {
+            FAST_CYCLES_PER_BIT
+        } else {
+            CYCLES_PER_BIT
+        }
+    }
+
+    #[must_use]
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0xFF01 => self.data,
+            0xFF02 if self.cgb => 0x7C | self.control,
+            0xFF02 => 0x7E | self.control,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF01 => self.data = value,
+            0xFF02 => {
+                self.control = value & if self.cgb { 0x83 } else { 0x81 };
+                if value & 0x81 == 0x81 {
+                    self.incoming = self.device.exchange(self.data);
+                    self.bits_left = 8;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Runs at the CPU's speed
+impl Clocked for Serial {
+    fn tick(&mut self) -> Option<Interrupt> {
+        self.clock = self.clock.wrapping_add(1);
+        if self.control & 0x81 == 0x81 {
+            if self.clock.is_multiple_of(self.period()) && self.bits_left > 0 {
+                self.bits_left -= 1;
+                self.data = self.data << 1 | (self.incoming >> self.bits_left) & 1;
+                if self.bits_left == 0 {
+                    self.control &= 0x7F;
+                    return Some(Interrupt::Serial);
+                }
+            }
+        } else if self.clock.is_multiple_of(CYCLES_PER_BIT) {
+            // The device answers transfers it clocks even when this side isn't waiting for one
+            if let Some(byte) = self.device.poll(self.data) {
+                if self.control & 0x81 == 0x80 {
+                    self.data = byte;
+                    self.control &= 0x7F;
+                    return Some(Interrupt::Serial);
+                }
+            }
+        }
+        None
+    }
+
+    fn skip(&mut self, cycles: u64) {
+        self.clock = self.clock.wrapping_add(cycles as u16);
+    }
+
+    fn cycles_until_event(&self) -> u64 {
+        // Bits are shifted, or the device is polled, when the clock reaches a multiple of the
+        // period
+        let period = if self.control & 0x81 != 0x81 {
+            CYCLES_PER_BIT
+        } else if self.bits_left == 0 {
+            return u64::MAX;
+        } else {
+            self.period()
+        };
+        u64::from(period - self.clock % period)
+    }
+}
@@ -0,0 +1,397 @@
+//! Super Game Boy: command packets that the game sends through the joypad register, which
+//! colorize the screen with a palette per 8×8 cell and draw a border around it.
+//!
+//! Sound commands and SNES programs aren't emulated.
+
+use crate::compositor::{rgb555_to_rgb24, BORDER_HEIGHT, BORDER_WIDTH};
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::cmp::Ordering;
+
+/// The screen is colorized in cells of 8×8 pixels
+pub const COLUMNS: usize = SCREEN_WIDTH / 8;
+pub const ROWS: usize = SCREEN_HEIGHT / 8;
+
+/// Number of attribute files that ATTR_TRN sends, of 90 bytes each
+const ATTRIBUTE_FILES: usize = 45;
+
+/// The palette the SGB starts with, before the game sends any
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+/// Data that the game sends through VRAM, by displaying it on screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transfer {
+    /// System palettes for PAL_SET (PAL_TRN)
+    Palettes,
+    /// Attribute files for ATTR_SET (ATTR_TRN)
+    AttributeFiles,
+    /// The first or second half of the border tiles (CHR_TRN)
+    BorderTiles(u8),
+    /// The border map and its palettes (PCT_TRN)
+    BorderMap,
+}
+
+pub struct Sgb {
+    /// The packet being received, and the number of bits received so far
+    pub(crate) packet: [u8; 16],
+    pub(crate) bit: usize,
+    /// Whether a packet is being received, after a reset pulse
+    pub(crate) receiving: bool,
+    /// Bits 4-5 of the previous write to P1
+    pub(crate) last_write: u8,
+    /// The packets received so far of a command, which can span up to 7 packets
+    pub(crate) command: Vec<u8>,
+    /// Number of controllers (MLT_REQ), and the one currently read
+    pub players: u8,
+    pub player: u8,
+    /// The four palettes on screen. Color 0 of palette 0 is used by all of them.
+    pub palettes: [[u16; 4]; 4],
+    /// 512 palettes that PAL_SET selects from
+    pub system_palettes: Vec<[u16; 4]>,
+    /// Palette number of each 8×8 cell on screen
+    pub attributes: [u8; COLUMNS * ROWS],
+    /// Attribute maps that ATTR_SET selects from, with 2 bits per cell
+    pub attribute_files: Vec<u8>,
+    /// MASK_EN: 0 shows the screen, 1 freezes it, 2 blanks it to black, and 3 to color 0
+    pub mask: u8,
+    /// 256 border tiles in the SNES format, with 4 bits per pixel
+    pub border_tiles: Vec<u8>,
+    /// 32×32 border map entries followed by four palettes of 16 colors
+    pub border_map: Vec<u8>,
+    /// Incremented whenever the border changes, so frontends know to redraw it
+    pub border_version: u32,
+    pub(crate) pending_transfer: Option<Transfer>,
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Self {
+            packet: [0; 16],
+            bit: 0,
+            receiving: false,
+            last_write: 0x30,
+            command: Vec::new(),
+            players: 1,
+            player: 0,
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: vec![[0; 4]; 512],
+            attributes: [0; COLUMNS * ROWS],
+            attribute_files: vec![0; ATTRIBUTE_FILES * 90],
+            mask: 0,
+            border_tiles: vec![0; 0x2000],
+            border_map: vec![0; 0x880],
+            border_version: 0,
+            pending_transfer: None,
+        }
+    }
+}
+
+impl Sgb {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receives a write to P1. A packet starts with both P14 and P15 low, and then each bit is
+    /// sent by pulsing P14 low for 0 or P15 low for 1, least significant bit first.
+    pub fn write_joypad(&mut self, value: u8) {
+        let value = value & 0x30;
+        let last = std::mem::replace(&mut self.last_write, value);
+        match value {
+            0x00 => {
+                self.receiving = true;
+                self.bit = 0;
+                self.packet = [0; 16];
+            }
+            0x10 | 0x20 if last == 0x30 && self.receiving => {
+                if self.bit == 128 {
+                    // The stop bit
+                    self.receiving = false;
+                    self.receive_packet();
+                } else {
+                    if value == 0x10 {
+                        self.packet[self.bit / 8] |= 1 << (self.bit % 8);
+                    }
+                    self.bit += 1;
+                }
+            }
+            // With more than one controller, the next one is selected when P15 goes high
+            0x30 if last & 0x20 == 0 && !self.receiving && self.players > 1 => {
+                self.player = (self.player + 1) % self.players;
+            }
+            _ => (),
+        }
+    }
+
+    /// With more than one controller, reading P1 with no buttons selected returns the
+    /// controller number in the low nibble, as 0xF for the first and counting down
+    #[must_use]
+    pub fn joypad_id(&self) -> Option<u8> {
+        (self.players > 1).then_some(0x0F - self.player)
+    }
+
+    fn receive_packet(&mut self) {
+        if self.command.is_empty() && self.packet[0] & 0x07 == 0 {
+            return;
+        }
+        self.command.extend_from_slice(&self.packet);
+        if self.command.len() >= usize::from(self.command[0] & 0x07) * 16 {
+            let command = std::mem::take(&mut self.command);
+            self.run_command(&command);
+        }
+    }
+
+    fn run_command(&mut self, command: &[u8]) {
+        let word = |index: usize| u16::from_le_bytes([command[index], command[index + 1]]);
+        match command[0] >> 3 {
+            // PAL01, PAL23, PAL03 and PAL12
+            code @ 0x00..=0x03 => {
+                let (first, second) = [(0, 1), (2, 3), (0, 3), (1, 2)][usize::from(code)];
+                for palette in &mut self.palettes {
+                    palette[0] = word(1) & 0x7FFF;
+                }
+                for color in 1..4 {
+                    self.palettes[first][color] = word(1 + color * 2) & 0x7FFF;
+                    self.palettes[second][color] = word(7 + color * 2) & 0x7FFF;
+                }
+            }
+            // ATTR_BLK
+            0x04 => {
+                for block in command[2..]
+                    .chunks_exact(6)
+                    .take(usize::from(command[1] & 0x1F))
+                {
+                    self.attribute_block(block);
+                }
+            }
+            // ATTR_LIN
+            0x05 => {
+                for line in command[2..].iter().take(usize::from(command[1])) {
+                    let number = usize::from(line & 0x1F);
+                    let palette = (line >> 5) & 0x03;
+                    if line & 0x80 != 0 && number < ROWS {
+                        self.attributes[number * COLUMNS..(number + 1) * COLUMNS].fill(palette);
+                    } else if line & 0x80 == 0 && number < COLUMNS {
+                        for row in 0..ROWS {
+                            self.attributes[row * COLUMNS + number] = palette;
+                        }
+                    }
+                }
+            }
+            // ATTR_DIV
+            0x06 => {
+                let split = usize::from(command[2] & 0x1F);
+                for (index, attribute) in self.attributes.iter_mut().enumerate() {
+                    let position = if command[1] & 0x40 != 0 {
+                        index / COLUMNS
+                    } else {
+                        index % COLUMNS
+                    };
+                    *attribute = match position.cmp(&split) {
+                        Ordering::Less => (command[1] >> 2) & 0x03,
+                        Ordering::Equal => (command[1] >> 4) & 0x03,
+                        Ordering::Greater => command[1] & 0x03,
+                    };
+                }
+            }
+            // ATTR_CHR
+            0x07 => {
+                let (mut x, mut y) = (
+                    usize::from(command[1]).min(COLUMNS - 1),
+                    usize::from(command[2]).min(ROWS - 1),
+                );
+                let count = usize::from(word(3)).min(COLUMNS * ROWS);
+                for index in 0..count {
+                    let Some(byte) = command.get(6 + index / 4) else {
+                        break;
+                    };
+                    self.attributes[y * COLUMNS + x] = (byte >> (6 - (index % 4) * 2)) & 0x03;
+                    if command[5] & 0x01 == 0 {
+                        x += 1;
+                        if x == COLUMNS {
+                            x = 0;
+                            y = (y + 1) % ROWS;
+                        }
+                    } else {
+                        y += 1;
+                        if y == ROWS {
+                            y = 0;
+                            x = (x + 1) % COLUMNS;
+                        }
+                    }
+                }
+            }
+            // PAL_SET
+            0x0A => {
+                for (index, palette) in self.palettes.iter_mut().enumerate() {
+                    *palette = self.system_palettes[usize::from(word(1 + index * 2) & 0x1FF)];
+                }
+                if command[9] & 0x80 != 0 {
+                    self.set_attribute_file(command[9] & 0x3F);
+                }
+                if command[9] & 0x40 != 0 {
+                    self.mask = 0;
+                }
+            }
+            0x0B => self.pending_transfer = Some(Transfer::Palettes),
+            // MLT_REQ
+            0x11 => {
+                self.players = match command[1] & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            0x13 => self.pending_transfer = Some(Transfer::BorderTiles(command[1] & 0x01)),
+            0x14 => self.pending_transfer = Some(Transfer::BorderMap),
+            0x15 => self.pending_transfer = Some(Transfer::AttributeFiles),
+            // ATTR_SET
+            0x16 => {
+                self.set_attribute_file(command[1] & 0x3F);
+                if command[1] & 0x40 != 0 {
+                    self.mask = 0;
+                }
+            }
+            // MASK_EN
+            0x17 => self.mask = command[1] & 0x03,
+            _ => (),
+        }
+    }
+
+    /// Sets the palettes inside, on the edge of and outside a rectangle of cells, as selected by
+    /// the control bits. If only the inside or outside is set, the edge is set with it.
+    fn attribute_block(&mut self, block: &[u8]) {
+        let control = block[0] & 0x07;
+        let inside = block[1] & 0x03;
+        let outside = (block[1] >> 4) & 0x03;
+        let edge = match control {
+            1 => Some(inside),
+            4 => Some(outside),
+            _ => (control & 0x02 != 0).then_some((block[1] >> 2) & 0x03),
+        };
+        let [left, top, right, bottom] = [block[2], block[3], block[4], block[5]]
+            .map(|coordinate| usize::from(coordinate & 0x1F));
+        for (index, attribute) in self.attributes.iter_mut().enumerate() {
+            let (x, y) = (index % COLUMNS, index / COLUMNS);
+            let palette = if x > left && x < right && y > top && y < bottom {
+                (control & 0x01 != 0).then_some(inside)
+            } else if x < left || x > right || y < top || y > bottom {
+                (control & 0x04 != 0).then_some(outside)
+            } else {
+                edge
+            };
+            if let Some(palette) = palette {
+                *attribute = palette;
+            }
+        }
+    }
+
+    fn set_attribute_file(&mut self, file: u8) {
+        let file = usize::from(file);
+        if file >= ATTRIBUTE_FILES {
+            return;
+        }
+        for (index, attribute) in self.attributes.iter_mut().enumerate() {
+            let byte = self.attribute_files[file * 90 + index / 4];
+            *attribute = (byte >> (6 - (index % 4) * 2)) & 0x03;
+        }
+    }
+
+    /// Whether the game is sending data through VRAM, which the PPU should then pass to
+    /// `finish_frame`
+    #[must_use]
+    pub fn transfer_pending(&self) -> bool {
+        self.pending_transfer.is_some()
+    }
+
+    /// Called at the end of each frame with the 4 KiB of tile data displayed on screen if a
+    /// transfer is pending, and colorizes the frame. Returns whether the colors changed.
+    pub fn finish_frame(&mut self, data: Option<&[u8]>, shades: &[u8], colors: &mut [u16]) -> bool {
+        if let (Some(transfer), Some(data)) = (self.pending_transfer.take(), data) {
+            self.receive_transfer(transfer, data);
+        }
+
+        let mut changed = false;
+        for (index, (shade, color)) in shades.iter().zip(colors.iter_mut()).enumerate() {
+            let palette = usize::from(
+                self.attributes[(index / SCREEN_WIDTH / 8) * COLUMNS + index % SCREEN_WIDTH / 8],
+            );
+            let new_color = match self.mask {
+                1 => *color,
+                2 => 0,
+                3 => self.palettes[0][0],
+                _ if *shade == 0 => self.palettes[0][0],
+                _ => self.palettes[palette][usize::from(*shade)],
+            };
+            changed |= new_color != *color;
+            *color = new_color;
+        }
+        changed
+    }
+
+    fn receive_transfer(&mut self, transfer: Transfer, data: &[u8]) {
+        match transfer {
+            Transfer::Palettes => {
+                for (palette, data) in self.system_palettes.iter_mut().zip(data.chunks_exact(8)) {
+                    for (color, data) in palette.iter_mut().zip(data.chunks_exact(2)) {
+                        *color = u16::from_le_bytes([data[0], data[1]]) & 0x7FFF;
+                    }
+                }
+            }
+            Transfer::AttributeFiles => {
+                let length = self.attribute_files.len();
+                self.attribute_files.copy_from_slice(&data[..length]);
+            }
+            Transfer::BorderTiles(half) => {
+                let start = usize::from(half) * 0x1000;
+                self.border_tiles[start..start + 0x1000].copy_from_slice(&data[..0x1000]);
+                self.border_version = self.border_version.wrapping_add(1);
+            }
+            Transfer::BorderMap => {
+                let length = self.border_map.len();
+                self.border_map.copy_from_slice(&data[..length]);
+                self.border_version = self.border_version.wrapping_add(1);
+            }
+        }
+    }
+
+    /// Draws the border as an RGB24 image of `BORDER_WIDTH`×`BORDER_HEIGHT`, with transparent
+    /// pixels in the backdrop color (color 0 of palette 0)
+    #[must_use]
+    pub fn border_image(&self) -> Vec<u8> {
+        let mut image = Vec::with_capacity(BORDER_WIDTH * BORDER_HEIGHT * 3);
+        for y in 0..BORDER_HEIGHT {
+            for x in 0..BORDER_WIDTH {
+                let offset = ((y / 8) * 32 + x / 8) * 2;
+                let entry =
+                    u16::from_le_bytes([self.border_map[offset], self.border_map[offset + 1]]);
+                let tile = &self.border_tiles[usize::from(entry & 0xFF) * 32..];
+                let column = if entry & 0x4000 != 0 {
+                    x % 8
+                } else {
+                    7 - x % 8
+                };
+                let row = if entry & 0x8000 != 0 {
+                    7 - y % 8
+                } else {
+                    y % 8
+                };
+                // Each row has bit planes 0 and 1 interleaved, and 2 and 3 after the first 16 bytes
+                let color = (0..4).fold(0, |color, plane| {
+                    let byte = tile[row * 2 + (plane & 1) + (plane >> 1) * 16];
+                    color | usize::from((byte >> column) & 1) << plane
+                });
+                // The border uses SNES palettes 4-7
+                let palette = usize::from((entry >> 10) & 0x07).saturating_sub(4);
+                let rgb555 = if color == 0 {
+                    self.palettes[0][0]
+                } else {
+                    let offset = 0x800 + (palette * 16 + color) * 2;
+                    u16::from_le_bytes([self.border_map[offset], self.border_map[offset + 1]])
+                };
+                image.extend(rgb555_to_rgb24(rgb555));
+            }
+        }
+        image
+    }
+}
@@ -0,0 +1,927 @@
+//! Platform-independent serialization of emulator state, for savestates and netplay rollback.
+//!
+//! All values are written as fixed-width little endian integers, and lengths as `u32`, so
+//! state saved on one architecture can be loaded on any other.
+
+use crate::apu::Apu;
+use crate::bus::{Bus, DmgBus};
+use crate::cartridge::{Eeprom, Huc1, Mbc1, Mbc2, Mbc3, Mbc5, Mbc7, NoMbc, Rtc};
+use crate::cpu::{Cpu, Flags, Registers};
+use crate::metadata::Metadata;
+use crate::model::Model;
+use crate::ppu::{Mode, Ppu, FRAME_CYCLES};
+use crate::scheduler::{Component, Scheduler};
+use crate::sgb::{Sgb, Transfer};
+use crate::timer::Timer;
+use std::fmt;
+
+/// Identifies savestate files, followed by the format version
+const MAGIC: &[u8; 4] = b"RGBS";
+const VERSION: u8 = 5;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The state ended before all values were read
+    UnexpectedEnd,
+    /// A value was out of range for the field it was read into
+    InvalidValue,
+    /// The data isn't a savestate or movie, or is from an unsupported version
+    NotAState,
+    /// The savestate was made with a different ROM
+    WrongRom,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::UnexpectedEnd => write!(f, "state data ended unexpectedly"),
+            StateError::InvalidValue => write!(f, "state data contains an invalid value"),
+            StateError::NotAState => write!(f, "not a savestate or movie from this version of RGB"),
+            StateError::WrongRom => write!(f, "savestate was made with a different ROM"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(u8::from(value));
+    }
+
+    /// Writes the length as a `u32`, followed by the bytes
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the slice is longer than `u32::MAX`
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(u32::try_from(bytes.len()).expect("State field too large"));
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    #[must_use]
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        if self.data.len() < N {
+            return Err(StateError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.data.split_at(N);
+        self.data = rest;
+        Ok(bytes.try_into().expect("Slice has the requested length"))
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the state has ended
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the state has ended
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the state has ended
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the state has ended
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the state has ended or the value isn't 0 or 1
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidValue),
+        }
+    }
+
+    /// Reads bytes written by `StateWriter::write_bytes`
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the state has ended
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let len = self.read_u32()? as usize;
+        if self.data.len() < len {
+            return Err(StateError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Reads bytes written by `StateWriter::write_bytes` into a fixed-size buffer
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the state has ended or the length doesn't match
+    pub fn read_bytes_into(&mut self, buffer: &mut [u8]) -> Result<(), StateError> {
+        let bytes = self.read_bytes()?;
+        if bytes.len() != buffer.len() {
+            return Err(StateError::InvalidValue);
+        }
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Emulator components that can be saved and restored
+pub trait State {
+    fn save_state(&self, writer: &mut StateWriter);
+    /// # Errors
+    ///
+    /// Will return `Err` if the state is truncated or invalid
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError>;
+}
+
+impl State for Registers {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for register in [self.a, self.b, self.c, self.d, self.e, self.h, self.l] {
+            writer.write_u8(register);
+        }
+        writer.write_u16(self.pc);
+        writer.write_u16(self.sp);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        for register in [
+            &mut self.a,
+            &mut self.b,
+            &mut self.c,
+            &mut self.d,
+            &mut self.e,
+            &mut self.h,
+            &mut self.l,
+        ] {
+            *register = reader.read_u8()?;
+        }
+        self.pc = reader.read_u16()?;
+        self.sp = reader.read_u16()?;
+        Ok(())
+    }
+}
+
+impl State for Flags {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(
+            u8::from(self.z) << 7
+                | u8::from(self.n) << 6
+                | u8::from(self.h) << 5
+                | u8::from(self.c) << 4,
+        );
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let f = reader.read_u8()?;
+        self.z = f & 0x80 != 0;
+        self.n = f & 0x40 != 0;
+        self.h = f & 0x20 != 0;
+        self.c = f & 0x10 != 0;
+        Ok(())
+    }
+}
+
+impl State for Timer {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.sysclock);
+        writer.write_u8(self.tima);
+        writer.write_u8(self.tma);
+        writer.write_bool(self.edge);
+        writer.write_bool(self.tima_enable);
+        writer.write_u8(self.clock_select);
+        writer.write_bool(self.overflowed);
+        writer.write_bool(self.reloaded);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.sysclock = reader.read_u16()?;
+        self.tima = reader.read_u8()?;
+        self.tma = reader.read_u8()?;
+        self.edge = reader.read_bool()?;
+        self.tima_enable = reader.read_bool()?;
+        self.clock_select = reader.read_u8()?;
+        self.overflowed = reader.read_bool()?;
+        self.reloaded = reader.read_bool()?;
+        if self.clock_select > 3 {
+            return Err(StateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+impl State for Scheduler {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for time in self.now.iter().chain(&self.synced) {
+            writer.write_u64(*time);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        for time in self.now.iter_mut().chain(&mut self.synced) {
+            *time = reader.read_u64()?;
+        }
+        // Deadlines are scheduled by the bus once the components are loaded
+        if Component::ALL
+            .into_iter()
+            .any(|component| self.synced[component as usize] > self.now(component.clock()))
+        {
+            return Err(StateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+/// Saves the state of the whole machine
+#[must_use]
+pub fn save<B: Bus + ?Sized>(cpu: &Cpu<B>) -> Vec<u8> {
+    let mut writer = StateWriter::new();
+    writer.data.extend_from_slice(MAGIC);
+    writer.write_u8(VERSION);
+    writer.write_bytes(&cpu.bus.metadata().to_bytes());
+    writer.write_bytes(&rom_fingerprint(cpu));
+    cpu.save_state(&mut writer);
+    writer.into_bytes()
+}
+
+/// Reads the settings a savestate was made with, so the frontend can compare them to the
+/// current ones before loading it
+///
+/// # Errors
+///
+/// Will return `Err` if the data isn't a savestate or its header is corrupt
+pub fn metadata(data: &[u8]) -> Result<Metadata, StateError> {
+    read_header(data).map(|(metadata, _)| metadata)
+}
+
+/// Restores the state of the whole machine from data returned by `save`. On failure, the
+/// machine is left as it was. Differences from the settings the state was made with aren't
+/// checked here; see `metadata`.
+///
+/// # Errors
+///
+/// Will return `Err` if the data isn't a savestate, is for another ROM, or is corrupt
+pub fn load<B: Bus + ?Sized>(cpu: &mut Cpu<B>, data: &[u8]) -> Result<(), StateError> {
+    let (_, mut reader) = read_header(data)?;
+    if reader.read_bytes()? != rom_fingerprint(cpu) {
+        return Err(StateError::WrongRom);
+    }
+    let backup = save(cpu);
+    let result = cpu.load_state(&mut reader);
+    if result.is_err() {
+        let (_, mut reader) = read_header(&backup)?;
+        reader.read_bytes()?;
+        cpu.load_state(&mut reader)?;
+    }
+    result
+}
+
+/// Checks the magic number and version, and reads the metadata that follows them
+fn read_header(data: &[u8]) -> Result<(Metadata, StateReader<'_>), StateError> {
+    let data = data
+        .strip_prefix(MAGIC)
+        .and_then(|data| data.strip_prefix(&[VERSION]))
+        .ok_or(StateError::NotAState)?;
+    let mut reader = StateReader::new(data);
+    let metadata = Metadata::from_bytes(reader.read_bytes()?).ok_or(StateError::InvalidValue)?;
+    Ok((metadata, reader))
+}
+
+/// The title and checksums from the cartridge header, to tell ROMs apart
+fn rom_fingerprint<B: Bus + ?Sized>(cpu: &Cpu<B>) -> Vec<u8> {
+    cpu.bus.get_cartridge().map_or_else(Vec::new, |cartridge| {
+        (0x0134..0x0150)
+            .map(|address| cartridge.read_byte(address))
+            .collect()
+    })
+}
+
+fn save_ram(ram: Option<&Vec<u8>>, writer: &mut StateWriter) {
+    writer.write_bool(ram.is_some());
+    if let Some(ram) = ram {
+        writer.write_bytes(ram);
+    }
+}
+
+fn load_ram(ram: Option<&mut Vec<u8>>, reader: &mut StateReader) -> Result<(), StateError> {
+    match (ram, reader.read_bool()?) {
+        (Some(ram), true) => reader.read_bytes_into(ram),
+        (None, false) => Ok(()),
+        _ => Err(StateError::InvalidValue),
+    }
+}
+
+impl<B: Bus + ?Sized> State for Cpu<B> {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.registers.save_state(writer);
+        self.flags.save_state(writer);
+        for value in [
+            self.ime,
+            self.ime_delayed,
+            self.halted,
+            self.halt_bug,
+            self.stopped,
+            self.locked,
+        ] {
+            writer.write_bool(value);
+        }
+        writer.write_u16(self.speed_switch_cycles);
+        self.bus.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.registers.load_state(reader)?;
+        self.flags.load_state(reader)?;
+        for value in [
+            &mut self.ime,
+            &mut self.ime_delayed,
+            &mut self.halted,
+            &mut self.halt_bug,
+            &mut self.stopped,
+            &mut self.locked,
+        ] {
+            *value = reader.read_bool()?;
+        }
+        self.speed_switch_cycles = reader.read_u16()?;
+        self.bus.load_state(reader)
+    }
+}
+
+impl State for DmgBus {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(match self.model {
+            Model::Dmg => 0,
+            Model::Sgb => 1,
+            Model::Cgb => 2,
+            Model::Dmg0 => 3,
+            Model::Mgb => 4,
+        });
+        writer.write_bytes(&self.wram);
+        writer.write_u8(self.wram_bank);
+        writer.write_bytes(&self.hram);
+        writer.write_bool(self.bootrom_enabled);
+        writer.write_u8(self.interrupt_enable);
+        writer.write_u8(self.interrupt_flags);
+        writer.write_u8(self.serial.data);
+        writer.write_u8(self.serial.control);
+        writer.write_u8(self.serial.incoming);
+        writer.write_u8(self.serial.bits_left);
+        writer.write_u16(self.serial.clock);
+        writer.write_u64(self.cycles);
+        writer.write_u8(self.joypad.select);
+        writer.write_u8(self.joypad.pressed);
+        writer.write_bool(self.double_speed);
+        writer.write_bool(self.speed_switch_armed);
+        writer.write_bool(self.half_cycle);
+        writer.write_u16(self.hdma.source);
+        writer.write_u16(self.hdma.destination);
+        writer.write_u8(self.hdma.remaining);
+        writer.write_bool(self.hdma.hblank_active);
+        writer.write_u8(self.dma.register);
+        writer.write_u16(self.dma.source);
+        writer.write_u8(self.dma.copied.unwrap_or(0xFF));
+        writer.write_u16(self.dma.requested.unwrap_or(0xFFFF));
+        writer.write_u8(self.dma.delay);
+        self.scheduler.save_state(writer);
+        self.timer.save_state(writer);
+        self.ppu.save_state(writer);
+        self.apu.save_state(writer);
+        writer.write_bool(self.cartridge.is_some());
+        if let Some(cartridge) = &self.cartridge {
+            cartridge.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.model = match reader.read_u8()? {
+            0 => Model::Dmg,
+            1 => Model::Sgb,
+            2 => Model::Cgb,
+            3 => Model::Dmg0,
+            4 => Model::Mgb,
+            _ => return Err(StateError::InvalidValue),
+        };
+        reader.read_bytes_into(&mut self.wram)?;
+        self.wram_bank = reader.read_u8()? & 0x07;
+        reader.read_bytes_into(&mut self.hram)?;
+        self.bootrom_enabled = reader.read_bool()?;
+        self.interrupt_enable = reader.read_u8()?;
+        self.interrupt_flags = reader.read_u8()?;
+        self.serial.data = reader.read_u8()?;
+        self.serial.control = reader.read_u8()? & 0x83;
+        self.serial.incoming = reader.read_u8()?;
+        self.serial.bits_left = reader.read_u8()?.min(8);
+        self.serial.clock = reader.read_u16()?;
+        self.cycles = reader.read_u64()?;
+        self.joypad.select = reader.read_u8()? & 0x30;
+        self.joypad.pressed = reader.read_u8()?;
+        self.double_speed = reader.read_bool()?;
+        self.speed_switch_armed = reader.read_bool()?;
+        self.half_cycle = reader.read_bool()?;
+        self.hdma.source = reader.read_u16()?;
+        self.hdma.destination = reader.read_u16()? & 0x1FF0;
+        self.hdma.remaining = reader.read_u8()? & 0x7F;
+        self.hdma.hblank_active = reader.read_bool()?;
+        self.dma.register = reader.read_u8()?;
+        self.dma.source = reader.read_u16()? & 0xDF00;
+        self.dma.copied = match reader.read_u8()? {
+            0xFF => None,
+            copied @ ..=0x9F => Some(copied),
+            _ => return Err(StateError::InvalidValue),
+        };
+        self.dma.requested = match reader.read_u16()? {
+            0xFFFF => None,
+            source => Some(source & 0xDF00),
+        };
+        self.dma.delay = reader.read_u8()?;
+        if self.dma.requested.is_some() && !(1..=2).contains(&self.dma.delay) {
+            return Err(StateError::InvalidValue);
+        }
+        self.scheduler.load_state(reader)?;
+        self.timer.load_state(reader)?;
+        self.ppu.load_state(reader)?;
+        self.apu.load_state(reader)?;
+        for component in Component::ALL {
+            self.reschedule(component);
+        }
+        match (&mut self.cartridge, reader.read_bool()?) {
+            (Some(cartridge), true) => cartridge.load_state(reader),
+            (None, false) => Ok(()),
+            _ => Err(StateError::InvalidValue),
+        }
+    }
+}
+
+impl State for Ppu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.vram);
+        writer.write_u8(self.vram_bank);
+        writer.write_bytes(&self.oam);
+        for register in [
+            self.lcdc, self.stat, self.scy, self.scx, self.ly, self.lyc, self.bgp, self.obp0,
+            self.obp1, self.wy, self.wx,
+        ] {
+            writer.write_u8(register);
+        }
+        writer.write_u8(self.mode as u8);
+        writer.write_u16(self.dot);
+        writer.write_bool(self.stat_line);
+        writer.write_u16(self.drawing_end);
+        writer.write_u32(self.off_dots);
+        writer.write_bool(self.first_frame);
+        writer.write_u8(self.window_line);
+        writer.write_bool(self.window_triggered);
+        writer.write_bool(self.window_wraps);
+        writer.write_bytes(&self.framebuffer);
+        writer.write_bool(self.cgb);
+        writer.write_bool(self.dmg_compat);
+        writer.write_bytes(&self.bg_palettes);
+        writer.write_bytes(&self.obj_palettes);
+        writer.write_u8(self.bcps);
+        writer.write_u8(self.ocps);
+        writer.write_u8(self.opri);
+        for color in self.color_framebuffer {
+            writer.write_u16(color);
+        }
+        writer.write_bool(self.sgb.is_some());
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes_into(&mut self.vram)?;
+        self.vram_bank = reader.read_u8()? & 0x01;
+        reader.read_bytes_into(&mut self.oam)?;
+        for register in [
+            &mut self.lcdc,
+            &mut self.stat,
+            &mut self.scy,
+            &mut self.scx,
+            &mut self.ly,
+            &mut self.lyc,
+            &mut self.bgp,
+            &mut self.obp0,
+            &mut self.obp1,
+            &mut self.wy,
+            &mut self.wx,
+        ] {
+            *register = reader.read_u8()?;
+        }
+        self.mode = match reader.read_u8()? {
+            0 => Mode::HBlank,
+            1 => Mode::VBlank,
+            2 => Mode::OamScan,
+            3 => Mode::Drawing,
+            _ => return Err(StateError::InvalidValue),
+        };
+        self.dot = reader.read_u16()?;
+        self.stat_line = reader.read_bool()?;
+        self.drawing_end = reader.read_u16()?;
+        if !(252..456).contains(&self.drawing_end) {
+            return Err(StateError::InvalidValue);
+        }
+        self.off_dots = reader.read_u32()?;
+        if self.off_dots >= FRAME_CYCLES {
+            return Err(StateError::InvalidValue);
+        }
+        self.first_frame = reader.read_bool()?;
+        self.window_line = reader.read_u8()?;
+        self.window_triggered = reader.read_bool()?;
+        self.window_wraps = reader.read_bool()?;
+        reader.read_bytes_into(&mut self.framebuffer)?;
+        self.cgb = reader.read_bool()?;
+        self.dmg_compat = reader.read_bool()?;
+        reader.read_bytes_into(&mut self.bg_palettes)?;
+        reader.read_bytes_into(&mut self.obj_palettes)?;
+        self.bcps = reader.read_u8()?;
+        self.ocps = reader.read_u8()?;
+        self.opri = reader.read_u8()?;
+        for color in &mut self.color_framebuffer {
+            *color = reader.read_u16()?;
+        }
+        self.sgb = if reader.read_bool()? {
+            let mut sgb = Box::new(Sgb::new());
+            sgb.load_state(reader)?;
+            Some(sgb)
+        } else {
+            None
+        };
+        // The restored frame needs to be presented
+        self.frame_changed = true;
+        Ok(())
+    }
+}
+
+impl State for Sgb {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.packet);
+        writer.write_u8(self.bit as u8);
+        writer.write_bool(self.receiving);
+        writer.write_u8(self.last_write);
+        writer.write_bytes(&self.command);
+        writer.write_u8(self.players);
+        writer.write_u8(self.player);
+        for color in self.palettes.iter().chain(&self.system_palettes).flatten() {
+            writer.write_u16(*color);
+        }
+        writer.write_bytes(&self.attributes);
+        writer.write_bytes(&self.attribute_files);
+        writer.write_u8(self.mask);
+        writer.write_bytes(&self.border_tiles);
+        writer.write_bytes(&self.border_map);
+        writer.write_u8(match self.pending_transfer {
+            None => 0,
+            Some(Transfer::Palettes) => 1,
+            Some(Transfer::AttributeFiles) => 2,
+            Some(Transfer::BorderTiles(half)) => 3 + half,
+            Some(Transfer::BorderMap) => 5,
+        });
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes_into(&mut self.packet)?;
+        self.bit = usize::from(reader.read_u8()?).min(128);
+        self.receiving = reader.read_bool()?;
+        self.last_write = reader.read_u8()? & 0x30;
+        self.command = reader.read_bytes()?.to_vec();
+        self.players = reader.read_u8()?;
+        self.player = reader.read_u8()?;
+        if ![1, 2, 4].contains(&self.players) || self.player >= self.players {
+            return Err(StateError::InvalidValue);
+        }
+        for color in self
+            .palettes
+            .iter_mut()
+            .chain(&mut self.system_palettes)
+            .flatten()
+        {
+            *color = reader.read_u16()? & 0x7FFF;
+        }
+        reader.read_bytes_into(&mut self.attributes)?;
+        reader.read_bytes_into(&mut self.attribute_files)?;
+        self.mask = reader.read_u8()? & 0x03;
+        reader.read_bytes_into(&mut self.border_tiles)?;
+        reader.read_bytes_into(&mut self.border_map)?;
+        self.pending_transfer = match reader.read_u8()? {
+            0 => None,
+            1 => Some(Transfer::Palettes),
+            2 => Some(Transfer::AttributeFiles),
+            half @ 3..=4 => Some(Transfer::BorderTiles(half - 3)),
+            5 => Some(Transfer::BorderMap),
+            _ => return Err(StateError::InvalidValue),
+        };
+        // The restored border needs to be presented
+        self.border_version = self.border_version.wrapping_add(1);
+        Ok(())
+    }
+}
+
+impl State for Apu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.registers);
+        writer.write_bytes(&self.wave_ram);
+        writer.write_bool(self.enabled);
+        writer.write_u32(self.sample_clock);
+        writer.write_u8(self.frame_sequencer_step);
+        writer.write_u16(self.div);
+        for channel in &self.channels {
+            writer.write_bool(channel.enabled);
+            writer.write_u16(channel.length);
+            writer.write_u32(channel.timer);
+            writer.write_u8(channel.position);
+            writer.write_u8(channel.volume);
+            writer.write_u8(channel.envelope_timer);
+        }
+        writer.write_bool(self.sweep_enabled);
+        writer.write_u8(self.sweep_timer);
+        writer.write_u16(self.shadow_frequency);
+        writer.write_bool(self.sweep_negated);
+        writer.write_u16(self.lfsr);
+        writer.write_u8(self.wave_sample);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes_into(&mut self.registers)?;
+        reader.read_bytes_into(&mut self.wave_ram)?;
+        self.enabled = reader.read_bool()?;
+        self.sample_clock = reader.read_u32()?;
+        self.frame_sequencer_step = reader.read_u8()?;
+        self.div = reader.read_u16()?;
+        for channel in &mut self.channels {
+            channel.enabled = reader.read_bool()?;
+            channel.length = reader.read_u16()?;
+            channel.timer = reader.read_u32()?;
+            channel.position = reader.read_u8()?;
+            channel.volume = reader.read_u8()?;
+            channel.envelope_timer = reader.read_u8()?;
+            if channel.length > 256 || channel.position > 31 || channel.volume > 15 {
+                return Err(StateError::InvalidValue);
+            }
+        }
+        self.sweep_enabled = reader.read_bool()?;
+        self.sweep_timer = reader.read_u8()?;
+        self.shadow_frequency = reader.read_u16()?;
+        self.sweep_negated = reader.read_bool()?;
+        self.lfsr = reader.read_u16()?;
+        self.wave_sample = reader.read_u8()?;
+        if self.frame_sequencer_step > 7 || self.shadow_frequency > 2047 {
+            return Err(StateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+impl State for NoMbc {
+    fn save_state(&self, writer: &mut StateWriter) {
+        save_ram(self.ram.as_ref(), writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        load_ram(self.ram.as_mut(), reader)
+    }
+}
+
+impl State for Mbc1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        save_ram(self.ram.as_ref(), writer);
+        writer.write_u8(self.rom_bank);
+        writer.write_u8(self.bank2);
+        writer.write_bool(self.advanced_banking);
+        writer.write_bool(self.ram_enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        load_ram(self.ram.as_mut(), reader)?;
+        self.rom_bank = reader.read_u8()?;
+        self.bank2 = reader.read_u8()?;
+        self.advanced_banking = reader.read_bool()?;
+        self.ram_enabled = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl State for Mbc2 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_u8(self.rom_bank);
+        writer.write_bool(self.ram_enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes_into(&mut self.ram)?;
+        self.rom_bank = reader.read_u8()?;
+        self.ram_enabled = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl State for Rtc {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.seconds);
+        writer.write_u8(self.minutes);
+        writer.write_u8(self.hours);
+        writer.write_u16(self.days);
+        writer.write_bool(self.halted);
+        writer.write_bool(self.day_carry);
+        writer.write_bytes(&self.latched);
+        writer.write_u32(self.cycles);
+        writer.write_bool(self.latch_armed);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.seconds = reader.read_u8()?;
+        self.minutes = reader.read_u8()?;
+        self.hours = reader.read_u8()?;
+        self.days = reader.read_u16()?;
+        self.halted = reader.read_bool()?;
+        self.day_carry = reader.read_bool()?;
+        reader.read_bytes_into(&mut self.latched)?;
+        self.cycles = reader.read_u32()?;
+        self.latch_armed = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl State for Mbc3 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        save_ram(self.ram.as_ref(), writer);
+        writer.write_bool(self.rtc.is_some());
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(writer);
+        }
+        writer.write_u8(self.rom_bank);
+        writer.write_u8(self.ram_bank);
+        writer.write_bool(self.ram_enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        load_ram(self.ram.as_mut(), reader)?;
+        match (&mut self.rtc, reader.read_bool()?) {
+            (Some(rtc), true) => rtc.load_state(reader)?,
+            (None, false) => (),
+            _ => return Err(StateError::InvalidValue),
+        }
+        self.rom_bank = reader.read_u8()?;
+        self.ram_bank = reader.read_u8()?;
+        self.ram_enabled = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl State for Mbc5 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        save_ram(self.ram.as_ref(), writer);
+        writer.write_u16(self.rom_bank);
+        writer.write_u8(self.ram_bank);
+        writer.write_bool(self.ram_enabled);
+        writer.write_bool(self.rumble_active);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        load_ram(self.ram.as_mut(), reader)?;
+        self.rom_bank = reader.read_u16()?;
+        self.ram_bank = reader.read_u8()?;
+        self.ram_enabled = reader.read_bool()?;
+        self.rumble_active = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl State for Eeprom {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.data);
+        for bit in [
+            self.write_enabled,
+            self.chip_select,
+            self.clock,
+            self.data_in,
+            self.data_out,
+            self.started,
+        ] {
+            writer.write_bool(bit);
+        }
+        writer.write_u32(self.command);
+        writer.write_u8(self.command_bits);
+        writer.write_u8(self.read_address.unwrap_or(0xFF));
+        writer.write_u8(self.read_bit);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes_into(&mut self.data)?;
+        for bit in [
+            &mut self.write_enabled,
+            &mut self.chip_select,
+            &mut self.clock,
+            &mut self.data_in,
+            &mut self.data_out,
+            &mut self.started,
+        ] {
+            *bit = reader.read_bool()?;
+        }
+        self.command = reader.read_u32()?;
+        self.command_bits = reader.read_u8()?;
+        self.read_address = match reader.read_u8()? {
+            0xFF => None,
+            address => Some(address & 0x7F),
+        };
+        self.read_bit = reader.read_u8()?;
+        if self.command_bits > 26 || self.read_bit > 15 {
+            return Err(StateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+impl State for Mbc7 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.eeprom.save_state(writer);
+        writer.write_u8(self.rom_bank);
+        writer.write_bool(self.ram_enabled[0]);
+        writer.write_bool(self.ram_enabled[1]);
+        writer.write_u16(self.latched.0);
+        writer.write_u16(self.latched.1);
+        writer.write_bool(self.latch_armed);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.eeprom.load_state(reader)?;
+        self.rom_bank = reader.read_u8()?;
+        self.ram_enabled = [reader.read_bool()?, reader.read_bool()?];
+        self.latched = (reader.read_u16()?, reader.read_u16()?);
+        self.latch_armed = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl State for Huc1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        save_ram(self.ram.as_ref(), writer);
+        writer.write_u8(self.rom_bank);
+        writer.write_u8(self.ram_bank);
+        writer.write_bool(self.ir_mode);
+        writer.write_bool(self.ir_led);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        load_ram(self.ram.as_mut(), reader)?;
+        self.rom_bank = reader.read_u8()?;
+        self.ram_bank = reader.read_u8()?;
+        self.ir_mode = reader.read_bool()?;
+        self.ir_led = reader.read_bool()?;
+        Ok(())
+    }
+}
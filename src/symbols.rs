@@ -0,0 +1,78 @@
+//! Symbol files (`.sym`) as written by RGBDS and read by BGB and other emulators.
+//!
+//! Each line is `BB:AAAA Label`, where BB is the bank and AAAA the address, both in hex.
+//! Anything after a `;` is a comment.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Default)]
+pub struct Symbols {
+    labels: HashMap<(usize, u16), String>,
+}
+
+impl Symbols {
+    /// Parses a symbol file, skipping lines that can't be parsed
+    #[must_use]
+    pub fn parse(text: &str) -> Self {
+        let labels = text
+            .lines()
+            .filter_map(|line| {
+                let line = line.split(';').next()?.trim();
+                let (location, label) = line.split_once(char::is_whitespace)?;
+                let (bank, address) = location.split_once(':')?;
+                Some((
+                    (
+                        usize::from_str_radix(bank, 16).ok()?,
+                        u16::from_str_radix(address, 16).ok()?,
+                    ),
+                    label.trim().to_string(),
+                ))
+            })
+            .collect();
+        Self { labels }
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be read
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    #[must_use]
+    pub fn label(&self, bank: usize, address: u16) -> Option<&str> {
+        self.labels.get(&(bank, address)).map(String::as_str)
+    }
+
+    /// Looks up the label for an address as seen from code in `bank`. Addresses in the
+    /// switchable ROM area are assumed to be in the same bank, and others are looked up in
+    /// bank 0 if they're not found in any other bank.
+    #[must_use]
+    pub fn label_from(&self, bank: usize, address: u16) -> Option<&str> {
+        match address {
+            0x0000..=0x3FFF => self.label(0, address),
+            0x4000..=0x7FFF => self.label(bank, address),
+            _ => self.label(0, address).or_else(|| {
+                self.labels
+                    .iter()
+                    .find(|((_, label_address), _)| *label_address == address)
+                    .map(|(_, label)| label.as_str())
+            }),
+        }
+    }
+
+    /// Finds the bank and address of a label
+    #[must_use]
+    pub fn address(&self, label: &str) -> Option<(usize, u16)> {
+        self.labels
+            .iter()
+            .find(|(_, name)| *name == label)
+            .map(|(location, _)| *location)
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
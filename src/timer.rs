@@ -1,43 +1,54 @@
 use crate::interrupts::Interrupt;
+use crate::scheduler::Clocked;
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct Timer {
     pub(crate) sysclock: u16,
     pub(crate) tima: u8,
     pub(crate) tma: u8,
-    edge: bool,
+    pub(crate) edge: bool,
     pub(crate) tima_enable: bool,
     pub(crate) clock_select: u8,
+    /// Whether TIMA overflowed in the last M-cycle. It reads 0 until it's reloaded from TMA in
+    /// the next one, and writing it in the meantime cancels the reload.
+    pub(crate) overflowed: bool,
+    /// Whether TIMA was reloaded from TMA in the last M-cycle, in which case writes to TIMA are
+    /// ignored and writes to TMA are copied to TIMA too
+    pub(crate) reloaded: bool,
 }
 
 impl Timer {
-    pub fn tick(&mut self) -> Option<Interrupt> {
-        self.sysclock = self.sysclock.wrapping_add(4);
+    /// The bit of the system clock whose falling edge increments TIMA
+    fn selected_bit(&self) -> u16 {
+        match self.clock_select {
+            0 => 9,
+            1 => 3,
+            2 => 5,
+            3 => 7,
+            _ => unreachable!(),
+        }
+    }
 
-        if self.tima_enable {
-            let old_edge = self.edge;
-            self.edge = (self.sysclock
-                >> match self.clock_select {
-                    0 => 9,
-                    1 => 3,
-                    2 => 5,
-                    3 => 7,
-                    _ => unreachable!(),
-                }
-                & 1)
-                != 0;
-            if !self.edge && old_edge {
-                let increment = self.tima.overflowing_add(1);
-                if increment.1 {
-                    self.tima = self.tma;
-                    return Some(Interrupt::Timer);
-                }
-                self.tima = increment.0;
-            }
+    /// The selected bit of the system clock when the timer is enabled, which TIMA counts the
+    /// falling edges of
+    fn input(&self) -> bool {
+        self.tima_enable && self.sysclock >> self.selected_bit() & 1 != 0
+    }
 
-            //self.tima = increment.0;
+    /// Updates the edge detector after the input may have changed, incrementing TIMA if it fell
+    fn detect_edge(&mut self) {
+        let input = self.input();
+        if self.edge && !input {
+            (self.tima, self.overflowed) = self.tima.overflowing_add(1);
         }
-        None
+        self.edge = input;
+    }
+
+    /// Resets the system clock, like writing DIV or executing STOP does. This is a falling edge
+    /// if the selected bit was set, so TIMA can be incremented.
+    pub(crate) fn reset_clock(&mut self) {
+        self.sysclock = 0;
+        self.detect_edge();
     }
 
     #[must_use]
@@ -53,14 +64,81 @@ impl Timer {
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
         match address {
-            0xFF04 => self.sysclock = 0,
-            0xFF05 => self.tima = value,
-            0xFF06 => self.tma = value,
+            0xFF04 => self.reset_clock(),
+            0xFF05 if self.reloaded => (),
+            0xFF05 => {
+                self.tima = value;
+                self.overflowed = false;
+            }
+            0xFF06 => {
+                self.tma = value;
+                if self.reloaded {
+                    self.tima = value;
+                }
+            }
             0xFF07 => {
                 self.tima_enable = value & 4 != 0;
                 self.clock_select = value & 3;
+                // Disabling the timer or selecting another bit can make the input fall
+                self.detect_edge();
             }
             _ => unreachable!(),
         }
     }
 }
+
+impl Clocked for Timer {
+    fn tick(&mut self) -> Option<Interrupt> {
+        let mut interrupt = None;
+        self.reloaded = false;
+        if self.overflowed {
+            self.overflowed = false;
+            self.reloaded = true;
+            self.tima = self.tma;
+            interrupt = Some(Interrupt::Timer);
+        }
+
+        self.sysclock = self.sysclock.wrapping_add(4);
+        self.detect_edge();
+        interrupt
+    }
+
+    fn skip(&mut self, cycles: u64) {
+        if cycles == 0 {
+            return;
+        }
+        let start = u64::from(self.sysclock);
+        let end = start + 4 * cycles;
+        self.sysclock = end as u16;
+        if self.tima_enable {
+            // TIMA is incremented each time the selected bit falls, which is when the system
+            // clock passes a multiple of twice its value
+            let period = 2_u64 << self.selected_bit();
+            let increments = end / period - start / period;
+            // Skips stop short of `cycles_until_event`, which is the edge that overflows TIMA
+            debug_assert!(
+                increments <= u64::from(0xFF - self.tima),
+                "skipped past a TIMA overflow"
+            );
+            self.tima = self.tima.wrapping_add(increments as u8);
+            self.edge = self.input();
+        }
+    }
+
+    fn cycles_until_event(&self) -> u64 {
+        if self.overflowed || self.reloaded {
+            return 1;
+        }
+        // The edge detector only catches up with its input in a tick
+        if self.edge != self.input() {
+            return 1;
+        }
+        if !self.tima_enable {
+            return u64::MAX;
+        }
+        // The interesting edge is the one that overflows TIMA
+        let period = 2 << self.selected_bit();
+        let first = (period - self.sysclock % period) / 4;
+        u64::from(first) + u64::from(0xFF - self.tima) * u64::from(period / 4)
+    }
+}
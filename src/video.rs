@@ -0,0 +1,81 @@
+use crate::compositor::rgb555_to_rgb24;
+use crate::metadata::crc32;
+use crate::palette::Palette;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// A completed frame of shades (0-3), one byte per pixel
+pub struct Frame<'a> {
+    /// The PPU's frame count when this frame was completed
+    pub number: u64,
+    pub pixels: &'a [u8],
+    /// RGB555 colors of each pixel, in CGB mode
+    pub colors: Option<&'a [u16]>,
+}
+
+impl Frame<'_> {
+    /// Converts the frame to RGB24, coloring the shades with `palette` if it has no colors
+    #[must_use]
+    pub fn to_rgb24(&self, palette: &Palette) -> Vec<u8> {
+        match self.colors {
+            Some(colors) => colors
+                .iter()
+                .flat_map(|color| rgb555_to_rgb24(*color))
+                .collect(),
+            None => self
+                .pixels
+                .iter()
+                .flat_map(|shade| palette.color(*shade))
+                .collect(),
+        }
+    }
+}
+
+/// Receives each frame when the PPU has finished drawing it. Any number of sinks can be attached
+/// at once, for example to play while recording.
+pub trait VideoSink {
+    fn push_frame(&mut self, frame: &Frame);
+}
+
+/// Writes the frame number and CRC32 of each frame's pixels as a line of text, so runs can be
+/// compared frame by frame without storing every frame
+pub struct FrameHashWriter<W: Write> {
+    writer: W,
+}
+
+impl FrameHashWriter<BufWriter<File>> {
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be created
+    pub fn create<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> FrameHashWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> VideoSink for FrameHashWriter<W> {
+    fn push_frame(&mut self, frame: &Frame) {
+        let hash = match frame.colors {
+            Some(colors) => crc32(
+                &colors
+                    .iter()
+                    .flat_map(|color| color.to_le_bytes())
+                    .collect::<Vec<_>>(),
+            ),
+            None => crc32(frame.pixels),
+        };
+        let _ = writeln!(self.writer, "{} {hash:08X}", frame.number);
+    }
+}
+
+impl<W: Write> Drop for FrameHashWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
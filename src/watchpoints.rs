@@ -0,0 +1,82 @@
+//! Data watchpoints, which break when the CPU reads or writes an address
+
+use std::collections::BTreeSet;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Both reads and writes
+    Access,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Watchpoint {
+    pub address: u16,
+    pub kind: WatchKind,
+    /// Only break when this value is read or written
+    pub value: Option<u8>,
+}
+
+/// A watchpoint that was triggered, and the access that triggered it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchpointHit {
+    pub watchpoint: Watchpoint,
+    pub value: u8,
+    pub write: bool,
+}
+
+#[derive(Default)]
+pub struct Watchpoints {
+    watchpoints: BTreeSet<Watchpoint>,
+    /// Set when a watchpoint is triggered. Run loops should pause when they see it.
+    pub hit: Option<WatchpointHit>,
+}
+
+impl Watchpoints {
+    pub fn insert(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.insert(watchpoint);
+    }
+
+    /// Removes all watchpoints on an address. Returns whether there were any.
+    pub fn remove(&mut self, address: u16) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| watchpoint.address != address);
+        self.watchpoints.len() != count
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.watchpoints.iter()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.watchpoints.is_empty()
+    }
+
+    /// Records a hit if a watchpoint matches the access. The first hit is kept until it's
+    /// taken.
+    pub fn check(&mut self, address: u16, value: u8, write: bool) {
+        if self.hit.is_some() {
+            return;
+        }
+        let watchpoint = self
+            .watchpoints
+            .iter()
+            .filter(|watchpoint| watchpoint.address == address)
+            .find(|watchpoint| {
+                let kind = match watchpoint.kind {
+                    WatchKind::Read => !write,
+                    WatchKind::Write => write,
+                    WatchKind::Access => true,
+                };
+                kind && watchpoint.value.is_none_or(|v| v == value)
+            });
+        self.hit = watchpoint.map(|watchpoint| WatchpointHit {
+            watchpoint: *watchpoint,
+            value,
+            write,
+        });
+    }
+}
@@ -0,0 +1,205 @@
+//! Streams frames to WebSocket clients, like a browser page drawing them to a canvas. Each frame
+//! is sent as a binary message: the frame number as 8 little-endian bytes, followed by the
+//! frame's pixels in RGB24.
+
+use crate::palette::Palette;
+use crate::video::{Frame, VideoSink};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+/// Appended to the client's key before hashing it to accept the connection
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// How long a client gets to finish the handshake or receive a frame before it's dropped, so a
+/// stalled client can't hold up emulation for long
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct WebSocketStreamer {
+    listener: TcpListener,
+    clients: Vec<TcpStream>,
+    /// Colors of the DMG shades, for frames without colors of their own
+    pub palette: Palette,
+}
+
+impl WebSocketStreamer {
+    /// Listens for clients on a local port. Clients that connect are accepted between frames.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the port can't be listened on
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+            palette: Palette::default(),
+        })
+    }
+
+    /// The port the streamer is listening on
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the socket's address can't be looked up
+    pub fn port(&self) -> io::Result<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    /// Accepts the clients waiting to connect, dropping any that fail the handshake
+    fn accept_clients(&mut self) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if let Ok(stream) = handshake(stream) {
+                self.clients.push(stream);
+            }
+        }
+    }
+}
+
+impl VideoSink for WebSocketStreamer {
+    fn push_frame(&mut self, frame: &Frame) {
+        self.accept_clients();
+        if self.clients.is_empty() {
+            return;
+        }
+        let mut payload = frame.number.to_le_bytes().to_vec();
+        payload.extend(frame.to_rgb24(&self.palette));
+        let message = binary_message(&payload);
+        self.clients
+            .retain_mut(|client| client.write_all(&message).is_ok());
+    }
+}
+
+/// Reads the client's opening HTTP request and switches the connection to the WebSocket protocol
+fn handshake(stream: TcpStream) -> io::Result<TcpStream> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut key = None;
+    let mut reader = BufReader::new(&stream);
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Sec-WebSocket-Key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let key = key.ok_or(io::ErrorKind::InvalidData)?;
+    write!(
+        &stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    )?;
+    Ok(stream)
+}
+
+/// The Sec-WebSocket-Accept value that answers a client's Sec-WebSocket-Key
+#[must_use]
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{key}{HANDSHAKE_GUID}").as_bytes()))
+}
+
+/// Frames `payload` as a single unmasked binary message, as servers send them
+#[must_use]
+pub fn binary_message(payload: &[u8]) -> Vec<u8> {
+    let mut message = vec![0x82];
+    match payload.len() {
+        length @ 0..=125 => message.push(length as u8),
+        length @ 126..=0xFFFF => {
+            message.push(126);
+            message.extend((length as u16).to_be_bytes());
+        }
+        length => {
+            message.push(127);
+            message.extend((length as u64).to_be_bytes());
+        }
+    }
+    message.extend_from_slice(payload);
+    message
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [
+        0x6745_2301,
+        0xEFCD_AB89,
+        0x98BA_DCFE,
+        0x1032_5476,
+        0xC3D2_E1F0,
+    ];
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend((data.len() as u64 * 8).to_be_bytes());
+    for block in padded.chunks(64) {
+        let mut words = [0; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, new) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(new);
+        }
+    }
+    let mut hash = [0; 20];
+    for (bytes, value) in hash.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    hash
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(
+                    ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize],
+                ));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
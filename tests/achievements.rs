@@ -0,0 +1,36 @@
+#![cfg(feature = "achievements")]
+use rgb_emu::achievements::{rom_hash, Achievements, Memory};
+use rgb_emu::cpu::Cpu;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[test]
+fn rom_hash_is_md5() {
+    assert_eq!(rom_hash(b""), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(
+        rom_hash(b"The quick brown fox jumps over the lazy dog"),
+        "9e107d9d372bb6826bd81d3542a419d6"
+    );
+    assert_eq!(rom_hash(&[0; 0x8000]), "bb7df04e1b0a2570657527a7e108ae23");
+}
+
+#[test]
+fn frame_callbacks_read_memory() {
+    let mut cpu = Cpu::new();
+    cpu.bus.write_byte(0xC000, 0x42);
+
+    let reads = Rc::new(RefCell::new(Vec::new()));
+    let mut achievements = Achievements::new();
+    let callback_reads = reads.clone();
+    achievements.on_frame(move |frame, memory: &Memory| {
+        callback_reads
+            .borrow_mut()
+            .push((frame, memory.read(0xC000), memory.read(Memory::SIZE)));
+    });
+    achievements.frame(&cpu);
+    achievements.frame(&cpu);
+    assert_eq!(
+        *reads.borrow(),
+        [(1, Some(0x42), None), (2, Some(0x42), None)]
+    );
+}
@@ -0,0 +1,92 @@
+use std::io::Cursor;
+
+use rgb_emu::animation::{AnimationWriter, Format};
+use rgb_emu::video::{Frame, VideoSink};
+
+/// Records frames where each pixel has the given shade, numbered from 1
+fn record(format: Format, deduplicate: bool, shades: &[u8]) -> Vec<u8> {
+    let mut buffer = Cursor::new(Vec::new());
+    {
+        let mut animation = AnimationWriter::new(&mut buffer, format, deduplicate).unwrap();
+        for (number, shade) in shades.iter().enumerate() {
+            let pixels = vec![*shade; 160 * 144];
+            animation.push_frame(&Frame {
+                number: number as u64 + 1,
+                pixels: &pixels,
+                colors: None,
+            });
+        }
+    }
+    buffer.into_inner()
+}
+
+/// Returns the type and contents of each chunk in a PNG file
+fn chunks(png: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut offset = 8;
+    while offset < png.len() {
+        let length = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+        chunks.push((
+            &png[offset + 4..offset + 8],
+            &png[offset + 8..offset + 8 + length],
+        ));
+        offset += length + 12;
+    }
+    chunks
+}
+
+#[test]
+fn apng_frames_and_delays() {
+    let apng = record(Format::Apng, false, &[0, 1, 2]);
+    let chunks = chunks(&apng);
+    let kinds: Vec<_> = chunks.iter().map(|(kind, _)| *kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            &b"IHDR"[..],
+            b"acTL",
+            b"fcTL",
+            b"IDAT",
+            b"fcTL",
+            b"fdAT",
+            b"fcTL",
+            b"fdAT",
+            b"IEND"
+        ]
+    );
+    assert_eq!(chunks[1].1[..4], 3_u32.to_be_bytes());
+    // Frames at 59.7 fps are 16.74 ms each, and are rounded from power on so they add up
+    let delays: Vec<_> = chunks
+        .iter()
+        .filter(|(kind, _)| *kind == b"fcTL")
+        .map(|(_, data)| u16::from_be_bytes([data[20], data[21]]))
+        .collect();
+    assert_eq!(delays, [16, 17, 17]);
+}
+
+#[test]
+fn deduplicated_frames_are_merged() {
+    let apng = record(Format::Apng, true, &[0, 0, 0, 3]);
+    let chunks = chunks(&apng);
+    assert_eq!(chunks[1].1[..4], 2_u32.to_be_bytes());
+    let delays: Vec<_> = chunks
+        .iter()
+        .filter(|(kind, _)| *kind == b"fcTL")
+        .map(|(_, data)| u16::from_be_bytes([data[20], data[21]]))
+        .collect();
+    assert_eq!(delays, [50, 17]);
+}
+
+#[test]
+fn gif_frames_are_at_least_two_hundredths() {
+    let gif = record(Format::Gif, false, &[0, 1, 2, 3]);
+    assert_eq!(&gif[..6], b"GIF89a");
+    assert_eq!(gif[gif.len() - 1], 0x3B);
+    let delays: Vec<_> = gif
+        .windows(4)
+        .enumerate()
+        .filter(|(_, window)| *window == [0x21, 0xF9, 0x04, 0x04])
+        .map(|(offset, _)| u16::from_le_bytes([gif[offset + 4], gif[offset + 5]]))
+        .collect();
+    assert_eq!(delays, [3, 2, 2]);
+}
@@ -0,0 +1,202 @@
+use rgb_emu::apu::{Channel, FrameSequencerEvents, HISTORY_LENGTH};
+use rgb_emu::bus::{Bus, DmgBus};
+use rgb_emu::model::Model;
+
+#[test]
+fn frame_sequencer_steps_on_div_apu() {
+    let mut bus = DmgBus::new();
+    bus.write_byte(0xFF26, 0x80);
+
+    // DIV bit 4 (bit 12 of the system clock) falls every 8192 T-cycles
+    for _ in 0..bus.apu.cycles_until_frame_sequencer_step() / 4 - 1 {
+        bus.tick();
+    }
+    assert_eq!(bus.apu.frame_sequencer_step, 7);
+    bus.tick();
+    assert_eq!(bus.apu.frame_sequencer_step, 0);
+    for _ in 0..0x2000 / 4 * 2 {
+        bus.tick();
+    }
+    assert_eq!(bus.apu.frame_sequencer_step, 2);
+    assert_eq!(
+        FrameSequencerEvents::for_step(2),
+        FrameSequencerEvents {
+            length: true,
+            sweep: true,
+            envelope: false
+        }
+    );
+    assert!(!bus.apu.next_frame_sequencer_events().length);
+}
+
+#[test]
+fn mute_and_solo_channels() {
+    let mut bus = DmgBus::new();
+    let apu = bus.get_apu_mut().unwrap();
+    apu.set_muted(Channel::Wave, true);
+    assert!(apu.is_muted(Channel::Wave));
+    assert!(!apu.is_muted(Channel::Noise));
+
+    apu.solo(Channel::Square2);
+    assert_eq!(
+        Channel::ALL.map(|channel| apu.is_muted(channel)),
+        [true, false, true, true]
+    );
+    apu.solo(Channel::Square2);
+    assert!(Channel::ALL.iter().all(|channel| !apu.is_muted(*channel)));
+
+    apu.set_muted(Channel::Noise, true);
+    bus.reset();
+    assert!(bus.apu.is_muted(Channel::Noise));
+}
+
+#[test]
+fn channel_history_for_visualizers() {
+    let mut bus = DmgBus::new();
+    bus.write_byte(0xFF26, 0x80);
+    for _ in 0..70224 {
+        bus.tick();
+    }
+    for history in &bus.apu.channel_history {
+        assert_eq!(history.len(), HISTORY_LENGTH);
+    }
+    assert_eq!(bus.apu.level(Channel::Square1), 0);
+}
+
+/// A bus with the APU on and every channel sent to both sides at full volume
+fn apu_bus() -> DmgBus {
+    let mut bus = DmgBus::new();
+    bus.write_byte(0xFF26, 0x80);
+    bus.write_byte(0xFF24, 0x77);
+    bus.write_byte(0xFF25, 0xFF);
+    bus
+}
+
+fn tick_samples(bus: &mut DmgBus, samples: usize) {
+    for _ in 0..samples * 88 / 4 {
+        bus.tick();
+    }
+}
+
+#[test]
+fn square_channel_plays_its_duty_cycle() {
+    let mut bus = apu_bus();
+    bus.write_byte(0xFF16, 0x80); // 50% duty
+    bus.write_byte(0xFF17, 0xF0); // Volume 15, no envelope
+    bus.write_byte(0xFF18, 0x00);
+    bus.write_byte(0xFF19, 0x87); // Trigger at 131072 / (2048 - 1792) = 512 Hz
+    assert_eq!(bus.read_byte(0xFF26), 0xF2);
+    tick_samples(&mut bus, HISTORY_LENGTH);
+
+    let history = &bus.apu.channel_history[Channel::Square2 as usize];
+    let high = history.iter().filter(|&&sample| sample == 0x2000).count();
+    let low = history.iter().filter(|&&sample| sample == -0x2000).count();
+    assert_eq!(high + low, HISTORY_LENGTH);
+    assert!(high.abs_diff(low) < HISTORY_LENGTH / 10);
+    assert_eq!(bus.apu.level(Channel::Square2), 0x2000);
+    assert_eq!(bus.apu.level(Channel::Square1), 0);
+
+    // Turning the DAC off turns the channel off
+    bus.write_byte(0xFF17, 0x00);
+    assert_eq!(bus.read_byte(0xFF26), 0xF0);
+}
+
+/// Peak-to-peak amplitude of each side of the recently mixed output
+fn mixed_swing(bus: &DmgBus) -> (i32, i32) {
+    let swing = |side: fn(&(i16, i16)) -> i16| {
+        let samples = bus.apu.history.iter().map(|sample| i32::from(side(sample)));
+        samples.clone().max().unwrap() - samples.min().unwrap()
+    };
+    (swing(|(left, _)| *left), swing(|(_, right)| *right))
+}
+
+#[test]
+fn muted_channels_are_left_out_of_the_mix() {
+    let mut bus = apu_bus();
+    bus.write_byte(0xFF16, 0x80);
+    bus.write_byte(0xFF17, 0xF0);
+    bus.write_byte(0xFF19, 0x87);
+    // Square 2 on the right side only
+    bus.write_byte(0xFF25, 0x02);
+    tick_samples(&mut bus, HISTORY_LENGTH);
+    let (left, playing) = mixed_swing(&bus);
+    assert_eq!(left, 0);
+    assert!(playing > 0x3000);
+
+    // Once muted, all that's left is the filter capacitor discharging
+    bus.apu.set_muted(Channel::Square2, true);
+    tick_samples(&mut bus, HISTORY_LENGTH);
+    assert!(mixed_swing(&bus).1 < playing / 16);
+    // Muted channels are still in the channel history, for visualizers
+    assert_eq!(bus.apu.level(Channel::Square2), 0x2000);
+
+    bus.apu.solo(Channel::Square2);
+    tick_samples(&mut bus, HISTORY_LENGTH);
+    assert!(mixed_swing(&bus).1 > 0x3000);
+    bus.apu.solo(Channel::Square1);
+    tick_samples(&mut bus, HISTORY_LENGTH);
+    assert!(mixed_swing(&bus).1 < playing / 16);
+}
+
+#[test]
+fn length_counter_turns_channel_off() {
+    let mut bus = apu_bus();
+    bus.write_byte(0xFF1A, 0x80);
+    bus.write_byte(0xFF1B, 0xFE); // 2 length clocks
+    bus.write_byte(0xFF1E, 0xC0);
+    assert_eq!(bus.read_byte(0xFF26) & 0x04, 0x04);
+    // Length is clocked at 256 Hz
+    for _ in 0..0x2000 / 4 * 3 {
+        bus.tick();
+    }
+    assert_eq!(bus.read_byte(0xFF26) & 0x04, 0x00);
+}
+
+#[test]
+fn envelope_fades_out() {
+    let mut bus = apu_bus();
+    bus.write_byte(0xFF21, 0x21); // Volume 2, decreasing every 64 Hz tick
+    bus.write_byte(0xFF22, 0x00);
+    bus.write_byte(0xFF23, 0x80);
+    tick_samples(&mut bus, HISTORY_LENGTH);
+    assert!(bus.apu.level(Channel::Noise) > 0);
+    for _ in 0..0x2000 / 4 * 8 * 2 {
+        bus.tick();
+    }
+    tick_samples(&mut bus, HISTORY_LENGTH);
+    assert_eq!(bus.apu.level(Channel::Noise), 0);
+    // The channel is still on, just silent
+    assert_eq!(bus.read_byte(0xFF26) & 0x08, 0x08);
+}
+
+#[test]
+fn sweep_overflow_turns_square_1_off() {
+    let mut bus = apu_bus();
+    bus.write_byte(0xFF12, 0xF0);
+    bus.write_byte(0xFF10, 0x11); // Add a half every 128 Hz tick
+    bus.write_byte(0xFF13, 0x00);
+    bus.write_byte(0xFF14, 0x84); // 0x400
+    assert_eq!(bus.read_byte(0xFF26) & 0x01, 0x01);
+    for _ in 0..0x2000 / 4 * 4 {
+        bus.tick();
+    }
+    // 0x400 became 0x600, and the overflow check right after that found 0x900
+    assert_eq!(bus.read_byte(0xFF26) & 0x01, 0x00);
+}
+
+#[test]
+fn pcm_registers_read_channel_outputs() {
+    let mut bus = apu_bus();
+    bus.set_model(Model::Cgb);
+    bus.write_byte(0xFF16, 0x80);
+    bus.write_byte(0xFF17, 0xA0); // Volume 10
+    bus.write_byte(0xFF19, 0x87);
+    let mut pcm12 = Vec::new();
+    for _ in 0..0x1000 {
+        bus.tick();
+        pcm12.push(bus.read_byte(0xFF76));
+    }
+    assert!(pcm12.contains(&0xA0) && pcm12.contains(&0x00));
+    assert!(pcm12.iter().all(|&value| value == 0xA0 || value == 0x00));
+    assert_eq!(bus.read_byte(0xFF77), 0x00);
+}
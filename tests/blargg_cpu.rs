@@ -62,3 +62,8 @@ fn blargg_cpu_10() -> Result<(), String> {
 fn blargg_cpu_11() -> Result<(), String> {
     run_blargg_test("cpu_instrs/individual/11-op a,(hl).gb")
 }
+
+#[test]
+fn blargg_halt_bug() -> Result<(), String> {
+    run_blargg_test("halt_bug.gb")
+}
@@ -0,0 +1,17 @@
+mod blargg;
+use blargg::{run_blargg_memory_test, run_blargg_test};
+
+#[test]
+fn blargg_instr_timing() -> Result<(), String> {
+    run_blargg_test("instr_timing/instr_timing.gb")
+}
+
+#[test]
+fn blargg_mem_timing() -> Result<(), String> {
+    run_blargg_test("mem_timing/mem_timing.gb")
+}
+
+#[test]
+fn blargg_mem_timing_2() -> Result<(), String> {
+    run_blargg_memory_test("mem_timing-2/mem_timing.gb")
+}
@@ -0,0 +1,98 @@
+use rgb_emu::block_cache::BlockCache;
+use rgb_emu::cartridge;
+use rgb_emu::cpu::{Cpu, RegisterPair};
+
+/// Address of the `JR -2` the test ROM ends on
+const END: u16 = 0x016B;
+
+/// An MBC1 ROM that sums into WRAM in a loop with a VBlank handler counting in HRAM, then calls
+/// code in bank 1 that switches to bank 2 in the middle of a block
+fn test_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x10000];
+    rom[0x0147] = 0x01; // MBC1
+    rom[0x0148] = 0x01; // 4 banks
+    rom[0x0040..0x0048].copy_from_slice(&[
+        0xF5, // PUSH AF
+        0xF0, 0x80, // LDH A, [0xFF80]
+        0x3C, // INC A
+        0xE0, 0x80, // LDH [0xFF80], A
+        0xF1, // POP AF
+        0xD9, // RETI
+    ]);
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // NOP; JP 0x0150
+    rom[0x0150..0x016D].copy_from_slice(&[
+        0x3E, 0x01, // LD A, 1
+        0xE0, 0xFF, // LDH [IE], A
+        0xFB, // EI
+        0x21, 0x00, 0xC0, // LD HL, 0xC000
+        0x01, 0x00, 0x08, // LD BC, 0x0800
+        0xCD, 0x00, 0x02, // .loop: CALL 0x0200
+        0x0B, // DEC BC
+        0x78, // LD A, B
+        0xB1, // OR C
+        0x20, 0xF8, // JR NZ, .loop
+        0x3E, 0x01, // LD A, 1
+        0xEA, 0x00, 0x20, // LD [0x2000], A
+        0xCD, 0x00, 0x40, // CALL 0x4000
+        0x18, 0xFE, // JR -2
+    ]);
+    rom[0x0200..0x0204].copy_from_slice(&[
+        0x7E, // LD A, [HL]
+        0x81, // ADD A, C
+        0x22, // LD [HL+], A
+        0xC9, // RET
+    ]);
+    rom[0x4000..0x4008].copy_from_slice(&[
+        0x3E, 0x02, // LD A, 2
+        0xEA, 0x00, 0x20, // LD [0x2000], A
+        0x06, 0x01, // LD B, 1
+        0xC9, // RET
+    ]);
+    // Bank 2 at the same address as the LD B, 1 in bank 1
+    rom[0x8005..0x8008].copy_from_slice(&[0x06, 0x02, 0xC9]); // LD B, 2; RET
+    rom
+}
+
+fn run_to_end(block_cache: bool) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.bus
+        .insert_cartridge(cartridge::from_rom(test_rom()).unwrap());
+    cpu.set_post_boot_state();
+    if block_cache {
+        cpu.block_cache = Some(BlockCache::new());
+    }
+    while cpu.registers.pc != END {
+        assert!(cpu.bus.cycles() < 10_000_000, "didn't reach the end");
+        cpu.run_block();
+    }
+    cpu
+}
+
+#[test]
+fn blocks_run_like_single_steps() {
+    let stepped = run_to_end(false);
+    let cached = run_to_end(true);
+    assert!(!cached.block_cache.as_ref().unwrap().is_empty());
+
+    assert_eq!(cached.bus.cycles(), stepped.bus.cycles());
+    for rp in [
+        RegisterPair::AF,
+        RegisterPair::BC,
+        RegisterPair::DE,
+        RegisterPair::HL,
+        RegisterPair::SP,
+    ] {
+        assert_eq!(
+            cached.get_register_pair(&rp),
+            stepped.get_register_pair(&rp)
+        );
+    }
+    assert_eq!(
+        cached.bus.peek_range(0xC000, 0x800),
+        stepped.bus.peek_range(0xC000, 0x800)
+    );
+    // The VBlank handler ran, and the block was cut short when bank 2 was mapped
+    assert!(cached.bus.peek_byte(0xFF80) > 0);
+    assert_eq!(cached.bus.peek_byte(0xFF80), stepped.bus.peek_byte(0xFF80));
+    assert_eq!(cached.registers.b, 2);
+}
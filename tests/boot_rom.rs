@@ -0,0 +1,56 @@
+#![cfg(feature = "bundled-bootrom")]
+use rgb_emu::boot_rom;
+use rgb_emu::cartridge;
+use rgb_emu::cpu::{Cpu, RegisterPair};
+
+/// A ROM with a logo in the header, which doesn't have to be Nintendo's
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    for (offset, byte) in rom[0x0104..0x0134].iter_mut().enumerate() {
+        *byte = (offset as u8).wrapping_mul(37) ^ 0x5A;
+    }
+    rom[0x014D] = 0x42;
+    rom
+}
+
+#[test]
+fn leaves_same_state_as_emulated_boot() {
+    let mut booted = Cpu::new();
+    booted
+        .bus
+        .insert_cartridge(cartridge::from_rom(rom()).unwrap());
+    booted.bus.set_boot_rom(boot_rom::DMG.to_vec());
+    let mut cycles = 0;
+    while booted.registers.pc != 0x0100 {
+        cycles += booted.step();
+        assert!(cycles < 5_000_000, "boot ROM didn't finish");
+    }
+    assert!(!booted.bus.boot_rom_mapped());
+
+    let mut emulated = Cpu::new();
+    emulated
+        .bus
+        .insert_cartridge(cartridge::from_rom(rom()).unwrap());
+    emulated.set_post_boot_state();
+
+    for pair in [
+        RegisterPair::AF,
+        RegisterPair::BC,
+        RegisterPair::DE,
+        RegisterPair::HL,
+        RegisterPair::SP,
+    ] {
+        assert_eq!(
+            booted.get_register_pair(&pair),
+            emulated.get_register_pair(&pair),
+            "{pair:?}"
+        );
+    }
+    for address in (0x8000..=0x9FFF).chain([0xFF40, 0xFF42, 0xFF43, 0xFF47]) {
+        assert_eq!(
+            booted.bus.peek_byte(address),
+            emulated.bus.peek_byte(address),
+            "{address:04X}"
+        );
+    }
+}
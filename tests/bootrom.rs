@@ -10,7 +10,8 @@ fn test_initial_state_bootrom() {
 
     let testrom = std::fs::read("gb-test-roms/cpu_instrs/individual/06-ld r,r.gb")
         .expect("Test requires cartridge");
-    cpu.bus.insert_cartridge(cartridge::from_rom(testrom));
+    cpu.bus
+        .insert_cartridge(cartridge::from_rom(testrom).unwrap());
 
     loop {
         println!("PC: {:04X}, AF: {:04X}, BC: {:04X}, DE: {:04X}, HL: {:04X}, SP: {:04X} ({:02X}{:02X}), ({:02X} {:02X} {:02X} {:02X})",
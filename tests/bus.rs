@@ -0,0 +1,238 @@
+use rgb_emu::bus::{Bus, DmgBus};
+use rgb_emu::cartridge;
+
+#[test]
+fn words_wrap_around_address_space() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0000] = 0x12;
+    let mut bus = DmgBus::new();
+    bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+
+    bus.write_byte(0xFFFF, 0x1F);
+    assert_eq!(bus.read_word(0xFFFF), 0x12FF);
+
+    bus.write_word(0xFFFF, 0x3401);
+    assert_eq!(bus.get_interrupt_enable(), 0xE1);
+}
+
+#[test]
+fn oam_dma_from_top_of_address_space() {
+    let mut bus = DmgBus::new();
+    bus.write_byte(0xDE00, 0xAB);
+    bus.write_byte(0xDF9F, 0xCD);
+    bus.write_byte(0xFF80, 0x99);
+
+    bus.write_byte(0xFF46, 0xFE);
+    run_dma(&mut bus);
+    assert_eq!(bus.peek_byte(0xFE00), 0xAB);
+
+    bus.write_byte(0xFF46, 0xFF);
+    run_dma(&mut bus);
+    assert_eq!(bus.peek_byte(0xFE9F), 0xCD);
+}
+
+/// Waits out an OAM DMA transfer started by the last access
+fn run_dma(bus: &mut DmgBus) {
+    for _ in 0..161 {
+        bus.tick();
+    }
+    assert!(!bus.dma.active());
+}
+
+#[test]
+fn oam_dma_keeps_cpu_off_its_buses() {
+    let mut bus = DmgBus::new();
+    bus.write_byte(0xC000, 0x12);
+    bus.write_byte(0x8000, 0x34);
+    bus.write_byte(0xFF80, 0x56);
+
+    bus.write_byte(0xFF46, 0xC0);
+    // The transfer starts after a cycle of setup
+    assert_eq!(bus.read_byte(0xC000), 0x12);
+    assert_eq!(bus.read_byte(0xC000), 0xFF);
+    assert_eq!(bus.read_byte(0xFE00), 0xFF);
+    assert_eq!(bus.read_byte(0xFF80), 0x56);
+    // VRAM is on its own bus, unlike WRAM
+    assert_eq!(bus.read_byte(0x8000), 0x34);
+    bus.write_byte(0xC001, 0x78);
+    assert_eq!(bus.peek_byte(0xC001), 0x00);
+
+    run_dma(&mut bus);
+    assert_eq!(bus.read_byte(0xC000), 0x12);
+    assert_eq!(bus.peek_byte(0xFE00), 0x12);
+}
+
+#[test]
+fn overclocked_cpu() {
+    let mut bus = DmgBus::new();
+    bus.set_post_boot_state();
+    bus.set_cpu_overclock(2);
+    for _ in 0..456 / 4 * 2 {
+        bus.tick();
+    }
+    assert_eq!(bus.ppu.ly, 1);
+}
+
+#[test]
+fn post_boot_state_has_logo() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0104] = 0xCE;
+    rom[0x0105] = 0xED;
+    let mut bus = DmgBus::new();
+    bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+    bus.set_post_boot_state();
+
+    assert_eq!(bus.peek_byte(0xFF40), 0x91);
+    assert_eq!(bus.peek_byte(0xFF47), 0xFC);
+    assert_eq!(
+        [0x8010, 0x8012, 0x8014, 0x8016, 0x8018, 0x801E].map(|address| bus.peek_byte(address)),
+        [0xF0, 0xF0, 0xFC, 0xFC, 0xFC, 0xF3]
+    );
+    assert_eq!(bus.peek_byte(0x8011), 0x00);
+    assert_eq!(bus.peek_byte(0x8190), 0x3C);
+    assert_eq!(bus.peek_byte(0x9904), 0x01);
+    assert_eq!(bus.peek_byte(0x992F), 0x18);
+    assert_eq!(bus.peek_byte(0x9910), 0x19);
+}
+
+#[test]
+fn serial_transfer_without_link_cable() {
+    let mut bus = DmgBus::new();
+    bus.write_byte(0xFF01, 0x42);
+    bus.write_byte(0xFF02, 0x81);
+    for _ in 0..7 * 128 {
+        bus.tick();
+    }
+    assert_eq!(bus.read_byte(0xFF02) & 0x80, 0x80);
+    assert_eq!(bus.get_interrupt_flags() & 0x08, 0);
+
+    for _ in 0..128 {
+        bus.tick();
+    }
+    assert_eq!(bus.read_byte(0xFF01), 0xFF);
+    assert_eq!(bus.read_byte(0xFF02), 0x7F);
+    assert_eq!(bus.get_interrupt_flags() & 0x08, 0x08);
+}
+
+#[test]
+fn peek_range_and_hexdump() {
+    let mut bus = DmgBus::new();
+    bus.write_byte(0xFFFE, 0x1F);
+    for (offset, byte) in b"Hi!\x00".iter().enumerate() {
+        bus.write_byte(0xC000 + offset as u16, *byte);
+    }
+    // Wraps around to the start of ROM, which reads as open bus without a cartridge
+    assert_eq!(
+        bus.peek_range(0xFFFE, 3),
+        [0x1F, bus.peek_byte(0xFFFF), 0xFF]
+    );
+    assert_eq!(
+        rgb_emu::debug::hexdump(0xC000, &bus.peek_range(0xC000, 20)),
+        "C000: 48 69 21 00 00 00 00 00 00 00 00 00 00 00 00 00  |Hi!.............|\n\
+         C010: 00 00 00 00                                      |....|\n"
+    );
+}
+
+#[test]
+fn timer_overflows_between_accesses() {
+    let mut bus = DmgBus::new();
+    // TIMA counts every 4 M-cycles
+    bus.write_byte(0xFF07, 0x05);
+    bus.write_byte(0xFF04, 0x00);
+    bus.write_byte(0xFF05, 0xFE);
+    bus.set_interrupt_flags(0);
+    for _ in 0..5 {
+        bus.tick();
+    }
+    assert_eq!(bus.peek_byte(0xFF05), 0xFF);
+    bus.tick();
+    // TIMA reads 0 for an M-cycle before it's reloaded
+    assert_eq!(bus.peek_byte(0xFF05), 0x00);
+    assert_eq!(bus.get_interrupt_flags(), 0);
+    bus.tick();
+    assert_eq!(bus.get_interrupt_flags(), 0x04);
+
+    // DIV keeps counting without being accessed
+    for _ in 0..64 * 3 {
+        bus.tick();
+    }
+    assert_eq!(bus.read_byte(0xFF04), 3);
+}
+
+/// Ticks until TIMA has just overflowed, with TMA set to 0x42
+fn overflow_timer(bus: &mut DmgBus) {
+    bus.write_byte(0xFF07, 0x05);
+    bus.write_byte(0xFF06, 0x42);
+    bus.write_byte(0xFF05, 0xFF);
+    while bus.peek_byte(0xFF05) != 0 {
+        bus.tick();
+    }
+    bus.set_interrupt_flags(0);
+}
+
+#[test]
+fn writing_tima_while_overflowed_cancels_reload() {
+    let mut bus = DmgBus::new();
+    overflow_timer(&mut bus);
+    bus.write_byte(0xFF05, 0x10);
+    bus.tick();
+    assert_eq!(bus.peek_byte(0xFF05), 0x10);
+    assert_eq!(bus.get_interrupt_flags(), 0);
+}
+
+#[test]
+fn writes_while_reloading() {
+    let mut bus = DmgBus::new();
+    overflow_timer(&mut bus);
+    // Reloaded during the read
+    assert_eq!(bus.read_byte(0xFF05), 0x00);
+    assert_eq!(bus.get_interrupt_flags(), 0x04);
+    // TIMA can't be written in the M-cycle after the reload, but TMA goes through to it
+    bus.write_byte(0xFF05, 0x10);
+    assert_eq!(bus.peek_byte(0xFF05), 0x42);
+
+    overflow_timer(&mut bus);
+    bus.tick();
+    bus.write_byte(0xFF06, 0x20);
+    assert_eq!(bus.peek_byte(0xFF05), 0x20);
+}
+
+#[test]
+fn div_reset_is_a_falling_edge() {
+    let mut bus = DmgBus::new();
+    bus.write_byte(0xFF07, 0x05);
+    bus.write_byte(0xFF04, 0x00);
+    bus.write_byte(0xFF05, 0x00);
+    // The system clock is at 8, with bit 3 set
+    bus.write_byte(0xFF04, 0x00);
+    assert_eq!(bus.peek_byte(0xFF05), 0x01);
+    // And now at 4, with it cleared
+    bus.write_byte(0xFF04, 0x00);
+    assert_eq!(bus.peek_byte(0xFF05), 0x01);
+
+    // An overflow from the reset reloads TIMA in the same M-cycle
+    bus.write_byte(0xFF06, 0x42);
+    bus.write_byte(0xFF05, 0xFF);
+    bus.set_interrupt_flags(0);
+    bus.write_byte(0xFF04, 0x00);
+    assert_eq!(bus.peek_byte(0xFF05), 0x42);
+    assert_eq!(bus.get_interrupt_flags(), 0x04);
+}
+
+#[test]
+fn tac_write_can_increment_tima() {
+    let mut bus = DmgBus::new();
+    bus.write_byte(0xFF07, 0x05);
+    bus.write_byte(0xFF04, 0x00);
+    bus.write_byte(0xFF05, 0x00);
+    // With the system clock at 8, bit 3 is set but bit 9 isn't
+    bus.write_byte(0xFF07, 0x04);
+    assert_eq!(bus.peek_byte(0xFF05), 0x01);
+
+    bus.write_byte(0xFF07, 0x05);
+    bus.write_byte(0xFF04, 0x00);
+    bus.write_byte(0xFF05, 0x00);
+    // Disabling the timer is a falling edge too
+    bus.write_byte(0xFF07, 0x01);
+    assert_eq!(bus.peek_byte(0xFF05), 0x01);
+}
@@ -0,0 +1,353 @@
+use rgb_emu::cartridge::{self, CartridgeError};
+use rgb_emu::peripheral::PeripheralEvent;
+
+/// Builds a ROM with the given header values, where each bank is filled with its bank number
+fn make_rom(mbc: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+    let banks = 2 << rom_size;
+    let mut rom: Vec<u8> = (0..banks)
+        .flat_map(|bank| std::iter::repeat_n(bank as u8, 0x4000))
+        .collect();
+    rom[0x0147] = mbc;
+    rom[0x0148] = rom_size;
+    rom[0x0149] = ram_size;
+    rom
+}
+
+#[test]
+fn mbc1_rom_and_ram_banking() {
+    // 2 MiB ROM, 32 KiB RAM
+    let mut cartridge = cartridge::from_rom(make_rom(0x03, 0x06, 0x03)).unwrap();
+
+    assert_eq!(cartridge.read_byte(0x4000), 1);
+    cartridge.write_byte(0x2000, 0x05);
+    assert_eq!(cartridge.read_byte(0x4000), 5);
+    assert_eq!(cartridge.read_byte(0x7FFF), 5);
+
+    // Bank 0x20 isn't reachable in the switchable area
+    cartridge.write_byte(0x2000, 0x00);
+    cartridge.write_byte(0x4000, 0x01);
+    assert_eq!(cartridge.read_byte(0x4000), 0x21);
+    assert_eq!(cartridge.read_byte(0x0000), 0);
+
+    // ...but mode 1 maps it to the fixed area
+    cartridge.write_byte(0x6000, 0x01);
+    assert_eq!(cartridge.read_byte(0x0000), 0x20);
+
+    cartridge.write_byte(0x0000, 0x0A);
+    cartridge.write_byte(0x4000, 0x02);
+    cartridge.write_byte(0xA000, 0x42);
+    cartridge.write_byte(0x4000, 0x00);
+    assert_eq!(cartridge.read_byte(0xA000), 0x00);
+    cartridge.write_byte(0x4000, 0x02);
+    assert_eq!(cartridge.read_byte(0xA000), 0x42);
+
+    // Mode 0 always uses RAM bank 0
+    cartridge.write_byte(0x6000, 0x00);
+    assert_eq!(cartridge.read_byte(0xA000), 0x00);
+
+    cartridge.write_byte(0x0000, 0x00);
+    assert_eq!(cartridge.read_byte(0xA000), 0xFF);
+}
+
+#[test]
+fn mbc1_multicart() {
+    let mut rom = make_rom(0x01, 0x05, 0x00);
+    for game in 0..4 {
+        rom[game * 0x4_0000 + 0x0104..game * 0x4_0000 + 0x0134].fill(0xCE);
+    }
+    let mut cartridge = cartridge::from_rom(rom).unwrap();
+
+    cartridge.write_byte(0x4000, 0x01);
+    cartridge.write_byte(0x2000, 0x12);
+    assert_eq!(cartridge.read_byte(0x4000), 0x12);
+    cartridge.write_byte(0x2000, 0x10);
+    assert_eq!(cartridge.read_byte(0x4000), 0x10);
+    cartridge.write_byte(0x6000, 0x01);
+    assert_eq!(cartridge.read_byte(0x0000), 0x10);
+}
+
+#[test]
+fn mbc2_registers_and_ram() {
+    let mut cartridge = cartridge::from_rom(make_rom(0x06, 0x03, 0x00)).unwrap();
+
+    assert_eq!(cartridge.read_byte(0x4000), 1);
+    // Address bit 8 set: ROM bank register
+    cartridge.write_byte(0x2100, 0x05);
+    assert_eq!(cartridge.read_byte(0x4000), 5);
+    cartridge.write_byte(0x0100, 0x00);
+    assert_eq!(cartridge.read_byte(0x4000), 1);
+
+    // Address bit 8 clear: RAM enable register
+    assert_eq!(cartridge.read_byte(0xA000), 0xFF);
+    cartridge.write_byte(0x2000, 0x0A);
+    assert_eq!(cartridge.read_byte(0x4000), 1);
+    cartridge.write_byte(0xA000, 0x5A);
+    assert_eq!(cartridge.read_byte(0xA000), 0xFA);
+    // The 512 half-bytes are echoed through the whole RAM area
+    assert_eq!(cartridge.read_byte(0xA200), 0xFA);
+    assert_eq!(cartridge.read_byte(0xBE00), 0xFA);
+}
+
+#[test]
+fn mbc3_rom_and_ram_banking() {
+    let mut cartridge = cartridge::from_rom(make_rom(0x13, 0x06, 0x03)).unwrap();
+
+    assert_eq!(cartridge.read_byte(0x4000), 1);
+    cartridge.write_byte(0x2000, 0x00);
+    assert_eq!(cartridge.read_byte(0x4000), 1);
+    cartridge.write_byte(0x2000, 0x7F);
+    assert_eq!(cartridge.read_byte(0x4000), 0x7F);
+
+    assert_eq!(cartridge.read_byte(0xA000), 0xFF);
+    cartridge.write_byte(0x0000, 0x0A);
+    for bank in 0..4 {
+        cartridge.write_byte(0x4000, bank);
+        cartridge.write_byte(0xA000, bank + 0x10);
+    }
+    for bank in 0..4 {
+        cartridge.write_byte(0x4000, bank);
+        assert_eq!(cartridge.read_byte(0xA000), bank + 0x10);
+    }
+}
+
+#[test]
+fn mbc3_rtc_latch() {
+    let mut cartridge = cartridge::from_rom(make_rom(0x10, 0x00, 0x02)).unwrap();
+    cartridge.write_byte(0x0000, 0x0A);
+
+    // Set the clock to 23:59:59 on day 511
+    for (register, value) in [
+        (0x08, 59),
+        (0x09, 59),
+        (0x0A, 23),
+        (0x0B, 0xFF),
+        (0x0C, 0x01),
+    ] {
+        cartridge.write_byte(0x4000, register);
+        cartridge.write_byte(0xA000, value);
+    }
+
+    for _ in 0..4_194_304 / 4 {
+        cartridge.tick();
+    }
+
+    // Not latched yet
+    cartridge.write_byte(0x4000, 0x08);
+    assert_eq!(cartridge.read_byte(0xA000), 59);
+
+    cartridge.write_byte(0x6000, 0x00);
+    cartridge.write_byte(0x6000, 0x01);
+    for (register, value) in [(0x08, 0), (0x09, 0), (0x0A, 0), (0x0B, 0), (0x0C, 0x80)] {
+        cartridge.write_byte(0x4000, register);
+        assert_eq!(cartridge.read_byte(0xA000), value);
+    }
+}
+
+#[test]
+fn mbc3_rtc_save_footer() {
+    let mut cartridge = cartridge::from_rom(make_rom(0x10, 0x00, 0x02)).unwrap();
+    cartridge.write_byte(0x0000, 0x0A);
+    cartridge.write_byte(0x4000, 0x09);
+    cartridge.write_byte(0xA000, 42);
+
+    let data = cartridge.save_data().unwrap();
+    assert_eq!(data.len(), 0x2000 + 48);
+    assert_eq!(data[0x2000 + 4..0x2000 + 8], [42, 0, 0, 0]);
+
+    // Pretend the save was made an hour ago
+    let mut data = data;
+    let timestamp = u64::from_le_bytes(data[0x2000 + 40..].try_into().unwrap()) - 3600;
+    data[0x2000 + 40..].copy_from_slice(&timestamp.to_le_bytes());
+
+    let mut cartridge = cartridge::from_rom(make_rom(0x10, 0x00, 0x02)).unwrap();
+    cartridge.load_save_data(&data);
+    cartridge.write_byte(0x0000, 0x0A);
+    cartridge.write_byte(0x6000, 0x00);
+    cartridge.write_byte(0x6000, 0x01);
+    cartridge.write_byte(0x4000, 0x09);
+    assert_eq!(cartridge.read_byte(0xA000), 42);
+    cartridge.write_byte(0x4000, 0x0A);
+    assert_eq!(cartridge.read_byte(0xA000), 1);
+}
+
+#[test]
+fn mbc3_rtc_catches_up_on_old_saves() {
+    let cartridge = cartridge::from_rom(make_rom(0x10, 0x00, 0x02)).unwrap();
+    let mut data = cartridge.save_data().unwrap();
+    // 600 days, 2 hours, 3 minutes and 4 seconds ago, which wraps the day counter
+    let timestamp = u64::from_le_bytes(data[0x2000 + 40..].try_into().unwrap())
+        - (((600 * 24 + 2) * 60 + 3) * 60 + 4);
+    data[0x2000 + 40..].copy_from_slice(&timestamp.to_le_bytes());
+
+    let read_clock = |data: &[u8]| {
+        let mut cartridge = cartridge::from_rom(make_rom(0x10, 0x00, 0x02)).unwrap();
+        cartridge.load_save_data(data);
+        cartridge.write_byte(0x0000, 0x0A);
+        cartridge.write_byte(0x6000, 0x00);
+        cartridge.write_byte(0x6000, 0x01);
+        (0x08..=0x0C)
+            .map(|register| {
+                cartridge.write_byte(0x4000, register);
+                cartridge.read_byte(0xA000)
+            })
+            .collect::<Vec<u8>>()
+    };
+    let clock = read_clock(&data);
+    assert!((4..=5).contains(&clock[0]));
+    // Day 600 is day 88 with the carry bit set
+    assert_eq!(clock[1..], [3, 2, 88, 0x80]);
+
+    // A halted clock doesn't count while the emulator isn't running
+    data[0x2000 + 16] = 0x40;
+    assert_eq!(read_clock(&data), [0, 0, 0, 0, 0x40]);
+}
+
+#[test]
+fn mbc5_rom_and_ram_banking() {
+    let mut cartridge = cartridge::from_rom(make_rom(0x1B, 0x08, 0x04)).unwrap();
+
+    assert_eq!(cartridge.read_byte(0x4000), 1);
+    cartridge.write_byte(0x2000, 0x00);
+    assert_eq!(cartridge.read_byte(0x4000), 0);
+    cartridge.write_byte(0x2000, 0x2A);
+    cartridge.write_byte(0x3000, 0x01);
+    // Bank 0x12A; every bank is filled with the low byte of its number
+    assert_eq!(cartridge.read_byte(0x4000), 0x2A);
+    assert_eq!(cartridge.read_byte(0x0000), 0);
+
+    cartridge.write_byte(0x0000, 0x0A);
+    for bank in 0..16 {
+        cartridge.write_byte(0x4000, bank);
+        cartridge.write_byte(0xBFFF, bank + 0x20);
+    }
+    for bank in 0..16 {
+        cartridge.write_byte(0x4000, bank);
+        assert_eq!(cartridge.read_byte(0xBFFF), bank + 0x20);
+    }
+    cartridge.write_byte(0x0000, 0x00);
+    assert_eq!(cartridge.read_byte(0xBFFF), 0xFF);
+}
+
+#[test]
+fn huc1_banking_and_ir() {
+    let mut cartridge = cartridge::from_rom(make_rom(0xFF, 0x05, 0x03)).unwrap();
+
+    assert_eq!(cartridge.read_byte(0x4000), 1);
+    cartridge.write_byte(0x2000, 0x3F);
+    assert_eq!(cartridge.read_byte(0x4000), 0x3F);
+
+    cartridge.write_byte(0x4000, 0x01);
+    cartridge.write_byte(0xA000, 0x42);
+    assert_eq!(cartridge.read_byte(0xA000), 0x42);
+
+    // IR mode: no light seen until the frontend sends some
+    cartridge.write_byte(0x0000, 0x0E);
+    assert_eq!(cartridge.read_byte(0xA000), 0xC0);
+    assert!(cartridge.handle_event(&PeripheralEvent::InfraredLight(true)));
+    assert_eq!(cartridge.read_byte(0xA000), 0xC1);
+    assert!(!cartridge.handle_event(&PeripheralEvent::PaperFeed));
+    cartridge.write_byte(0xA000, 0x01);
+    cartridge.write_byte(0x0000, 0x00);
+    assert_eq!(cartridge.read_byte(0xA000), 0x42);
+}
+
+#[test]
+fn mbc7_accelerometer() {
+    let mut cartridge = cartridge::from_rom(make_rom(0x22, 0x05, 0x00)).unwrap();
+    cartridge.write_byte(0x2000, 0x3F);
+    assert_eq!(cartridge.read_byte(0x4000), 0x3F);
+
+    assert!(cartridge.handle_event(&PeripheralEvent::Tilt { x: 1.0, y: -0.5 }));
+    assert_eq!(cartridge.read_byte(0xA020), 0xFF);
+    cartridge.write_byte(0x0000, 0x0A);
+    cartridge.write_byte(0x4000, 0x40);
+    // Nothing is latched until the old values are erased
+    cartridge.write_byte(0xA010, 0xAA);
+    assert_eq!(cartridge.read_byte(0xA020), 0x00);
+    cartridge.write_byte(0xA000, 0x55);
+    assert_eq!(cartridge.read_byte(0xA030), 0x80);
+    cartridge.write_byte(0xA010, 0xAA);
+    let x = u16::from_le_bytes([cartridge.read_byte(0xA020), cartridge.read_byte(0xA030)]);
+    let y = u16::from_le_bytes([cartridge.read_byte(0xA040), cartridge.read_byte(0xA050)]);
+    assert_eq!((x, y), (0x81D0 + 0x70, 0x81D0 - 0x38));
+}
+
+/// Clocks bits into the MBC7's EEPROM, returning what it puts out on each clock
+fn eeprom_bits(cartridge: &mut Box<dyn cartridge::Cartridge>, bits: &[u8]) -> Vec<u8> {
+    bits.iter()
+        .map(|&bit| {
+            cartridge.write_byte(0xA080, 0x80 | bit << 1);
+            cartridge.write_byte(0xA080, 0xC0 | bit << 1);
+            cartridge.read_byte(0xA080) & 0x01
+        })
+        .collect()
+}
+
+fn eeprom_command(cartridge: &mut Box<dyn cartridge::Cartridge>, bits: &[u8]) -> Vec<u8> {
+    cartridge.write_byte(0xA080, 0x00);
+    eeprom_bits(cartridge, bits)
+}
+
+#[test]
+fn mbc7_eeprom() {
+    let mut cartridge = cartridge::from_rom(make_rom(0x22, 0x05, 0x00)).unwrap();
+    cartridge.write_byte(0x0000, 0x0A);
+    cartridge.write_byte(0x4000, 0x40);
+    let word = [1, 0, 1, 0, 0, 1, 0, 1, 1, 1, 0, 0, 0, 0, 1, 1];
+
+    // Writes are ignored until they're enabled with EWEN
+    let write = [&[1, 0, 1, 0, 0, 0, 0, 0, 1, 0, 1][..], &word].concat();
+    eeprom_command(&mut cartridge, &write);
+    eeprom_command(&mut cartridge, &[1, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(cartridge.save_data().unwrap()[10..12], [0xFF, 0xFF]);
+    eeprom_command(&mut cartridge, &write);
+    assert_eq!(cartridge.save_data().unwrap()[10..12], [0xC3, 0xA5]);
+
+    // A read puts out a dummy 0 bit after the address, and then the word
+    let read = eeprom_command(&mut cartridge, &[1, 1, 0, 0, 0, 0, 0, 0, 1, 0, 1]);
+    assert_eq!(read[10], 0);
+    assert_eq!(eeprom_bits(&mut cartridge, &[0; 16]), word);
+}
+
+#[test]
+fn battery_save_data() {
+    let cartridge = cartridge::from_rom(make_rom(0x1A, 0x01, 0x02)).unwrap();
+    assert_eq!(cartridge.save_data(), None);
+
+    let mut cartridge = cartridge::from_rom(make_rom(0x1B, 0x01, 0x02)).unwrap();
+    cartridge.write_byte(0x0000, 0x0A);
+    cartridge.write_byte(0xA123, 0x42);
+    let save_data = cartridge.save_data().unwrap();
+    assert_eq!(save_data.len(), 0x2000);
+    assert_eq!(save_data[0x123], 0x42);
+
+    let mut cartridge = cartridge::from_rom(make_rom(0x1B, 0x01, 0x02)).unwrap();
+    cartridge.load_save_data(&save_data);
+    cartridge.write_byte(0x0000, 0x0A);
+    assert_eq!(cartridge.read_byte(0xA123), 0x42);
+}
+
+#[test]
+fn malformed_headers() {
+    assert_eq!(
+        cartridge::from_rom(vec![0; 0x100]).err(),
+        Some(CartridgeError::MissingHeader)
+    );
+    assert_eq!(
+        cartridge::from_rom(make_rom(0x42, 0x00, 0x00)).err(),
+        Some(CartridgeError::UnknownMbc(0x42))
+    );
+    assert_eq!(
+        cartridge::from_rom(make_rom(0x00, 0x00, 0x01)).err(),
+        Some(CartridgeError::UnknownRamSize(0x01))
+    );
+    let mut rom = make_rom(0x00, 0x00, 0x00);
+    rom[0x0148] = 0x01;
+    assert_eq!(
+        cartridge::from_rom(rom).err(),
+        Some(CartridgeError::RomSizeMismatch {
+            header: 0x10000,
+            actual: 0x8000
+        })
+    );
+}
@@ -0,0 +1,273 @@
+use rgb_emu::cartridge;
+use rgb_emu::cpu::Cpu;
+use rgb_emu::model::Model;
+use rgb_emu::ppu::Mode;
+
+/// A CGB running a CGB-only ROM that loops forever at 0x0100
+fn cgb_cpu() -> Cpu {
+    cgb_cpu_with_code(&[0x18, 0xFE]) // JR -2
+}
+
+fn cgb_cpu_with_code(code: &[u8]) -> Cpu {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + code.len()].copy_from_slice(code);
+    rom[0x0143] = 0xC0;
+    let mut cpu = Cpu::new();
+    cpu.bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+    cpu.bus.set_model(Model::Cgb);
+    cpu.set_post_boot_state();
+    cpu
+}
+
+fn run_frame(cpu: &mut Cpu) {
+    let frame = cpu.bus.get_ppu().unwrap().frame_count;
+    while cpu.bus.get_ppu().unwrap().frame_count == frame {
+        cpu.step();
+    }
+}
+
+#[test]
+fn post_boot_state() {
+    let cpu = cgb_cpu();
+    assert_eq!(cpu.registers.a, 0x11);
+    assert!(cpu.bus.get_ppu().unwrap().cgb);
+}
+
+#[test]
+fn dmg_cartridge_runs_in_compatibility_mode() {
+    let mut cpu = Cpu::new();
+    cpu.bus
+        .insert_cartridge(cartridge::from_rom(vec![0; 0x8000]).unwrap());
+    cpu.bus.set_model(Model::Cgb);
+    cpu.set_post_boot_state();
+    assert_eq!(cpu.registers.a, 0x11);
+    assert!(!cpu.bus.get_ppu().unwrap().cgb);
+}
+
+#[test]
+fn dmg_cartridge_gets_compatibility_colors() {
+    let mut cpu = Cpu::new();
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]); // JR -2
+    cpu.bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+    cpu.bus.set_model(Model::Cgb);
+    cpu.set_post_boot_state();
+    cpu.bus.write_byte(0xFF47, 0xE4);
+    for address in (0x8000..0x8010).step_by(2) {
+        cpu.bus.write_byte(address, 0xFF);
+    }
+    run_frame(&mut cpu);
+    run_frame(&mut cpu);
+
+    // Shade 1 of the background is green, as the CGB boot ROM sets it up by default
+    let frame = cpu.bus.get_ppu().unwrap().frame();
+    assert_eq!(frame.pixels[0], 1);
+    assert_eq!(
+        frame.colors.expect("compatibility mode has colors")[0],
+        0x1BEF
+    );
+}
+
+#[test]
+fn recognized_dmg_cartridge_gets_its_own_colors() {
+    let mut cpu = Cpu::new();
+    let mut rom = vec![0; 0x8000];
+    rom[0x0134..0x013A].copy_from_slice(b"TETRIS");
+    rom[0x014B] = 0x01;
+    cpu.bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+    cpu.bus.set_model(Model::Cgb);
+    cpu.set_post_boot_state();
+    assert_eq!(cpu.registers.b, 0xDB);
+    // Shade 1 of the background is yellow
+    let ppu = cpu.bus.get_ppu().unwrap();
+    assert_eq!(ppu.bg_palettes[2..4], 0x03FF_u16.to_le_bytes());
+}
+
+#[test]
+fn palette_auto_increment() {
+    let mut cpu = cgb_cpu();
+    cpu.bus.write_byte(0xFF40, 0x00);
+    cpu.bus.write_byte(0xFF68, 0xBE);
+    cpu.bus.write_byte(0xFF69, 0x12);
+    cpu.bus.write_byte(0xFF69, 0x34);
+    // The index wraps around within palette RAM
+    assert_eq!(cpu.bus.read_byte(0xFF68), 0xC0);
+    cpu.bus.write_byte(0xFF68, 0x3E);
+    assert_eq!(cpu.bus.read_byte(0xFF69), 0x12);
+    cpu.bus.write_byte(0xFF69, 0x56);
+    assert_eq!(cpu.bus.read_byte(0xFF68), 0x7E);
+    assert_eq!(cpu.bus.read_byte(0xFF69), 0x56);
+}
+
+#[test]
+fn palettes_locked_while_drawing() {
+    let mut cpu = cgb_cpu();
+    let drawing = |cpu: &mut Cpu| {
+        while cpu.bus.get_ppu().unwrap().mode != Mode::Drawing {
+            cpu.step();
+        }
+    };
+    cpu.bus.write_byte(0xFF68, 0x00);
+    drawing(&mut cpu);
+    cpu.bus.write_byte(0xFF69, 0x12);
+    assert_eq!(
+        cpu.bus.peek_byte(0xFF69),
+        0x12,
+        "the locks are off by default"
+    );
+
+    cpu.bus.set_ppu_access_locks(true);
+    cpu.bus.write_byte(0xFF68, 0x80);
+    drawing(&mut cpu);
+    assert_eq!(cpu.bus.read_byte(0xFF69), 0xFF);
+    cpu.bus.write_byte(0xFF69, 0x34);
+    // The write is dropped, but the index still advances
+    assert_eq!(cpu.bus.peek_byte(0xFF68), 0xC1);
+    cpu.bus.write_byte(0xFF40, 0x00);
+    cpu.bus.write_byte(0xFF68, 0x00);
+    assert_eq!(cpu.bus.read_byte(0xFF69), 0x12);
+}
+
+#[test]
+fn background_colors() {
+    let mut cpu = cgb_cpu();
+    cpu.bus.write_byte(0xFF40, 0x00);
+    // Color 3 of background palette 0 is pure red
+    cpu.bus.write_byte(0xFF68, 0x86);
+    cpu.bus.write_byte(0xFF69, 0x1F);
+    cpu.bus.write_byte(0xFF69, 0x00);
+    for address in 0x8000..0x8010 {
+        cpu.bus.write_byte(address, 0xFF);
+    }
+    cpu.bus.write_byte(0xFF40, 0x91);
+    run_frame(&mut cpu);
+    run_frame(&mut cpu);
+
+    let frame = cpu.bus.get_ppu().unwrap().frame();
+    let colors = frame.colors.expect("CGB frames have colors");
+    assert_eq!(colors[0], 0x001F);
+    assert_eq!(frame.pixels[0], 3);
+}
+
+#[test]
+fn double_speed() {
+    // STOP, then loop
+    let mut cpu = cgb_cpu_with_code(&[0x10, 0x00, 0x18, 0xFE]);
+    assert_eq!(cpu.bus.read_byte(0xFF4D), 0x7E);
+    cpu.bus.write_byte(0xFF4D, 0x01);
+    assert_eq!(cpu.bus.read_byte(0xFF4D), 0x7F);
+    cpu.step();
+    assert_eq!(cpu.bus.read_byte(0xFF4D), 0xFE);
+
+    // The CPU is paused while the speed switches, but the rest of the system keeps running
+    assert!(cpu.stopped && !cpu.clocks_stopped());
+    let start = cpu.bus.cycles();
+    while cpu.stopped {
+        cpu.step();
+    }
+    assert_eq!(cpu.bus.cycles() - start, 2050);
+    assert_eq!(cpu.registers.pc, 0x0102);
+
+    // The PPU keeps its rate, so a frame takes twice as many CPU cycles
+    run_frame(&mut cpu);
+    let start = cpu.bus.cycles();
+    run_frame(&mut cpu);
+    assert_eq!(cpu.bus.cycles() - start, 2 * 17556);
+}
+
+#[test]
+fn background_attributes() {
+    let mut cpu = cgb_cpu();
+    cpu.bus.write_byte(0xFF40, 0x00);
+    // Color 1 of background palette 2 is pure blue
+    cpu.bus.write_byte(0xFF68, 0x92);
+    cpu.bus.write_byte(0xFF69, 0x00);
+    cpu.bus.write_byte(0xFF69, 0x7C);
+    // Tile 0 in bank 1 has color 1 in its leftmost column only
+    cpu.bus.write_byte(0xFF4F, 0x01);
+    assert_eq!(cpu.bus.read_byte(0xFF4F), 0xFF);
+    for row in 0..8 {
+        cpu.bus.write_byte(0x8000 + row * 2, 0x80);
+    }
+    // The first map entry uses bank 1 and palette 2, flipped horizontally
+    cpu.bus.write_byte(0x9800, 0x2A);
+    cpu.bus.write_byte(0xFF4F, 0x00);
+    assert_eq!(cpu.bus.read_byte(0x9800), 0x00);
+    cpu.bus.write_byte(0xFF40, 0x91);
+    run_frame(&mut cpu);
+    run_frame(&mut cpu);
+
+    let frame = cpu.bus.get_ppu().unwrap().frame();
+    let colors = frame.colors.unwrap();
+    assert_eq!(frame.pixels[7], 1);
+    assert_eq!(colors[7], 0x7C00);
+    assert_eq!(frame.pixels[0], 0);
+}
+
+/// Fills WRAM at 0xC000 with 0x00, 0x01, 0x02… and points VRAM DMA from there to 0x8000
+fn set_up_hdma(cpu: &mut Cpu) {
+    for offset in 0..0x100 {
+        cpu.bus.write_byte(0xC000 + offset, offset as u8);
+    }
+    for (address, value) in [
+        (0xFF51, 0xC0),
+        (0xFF52, 0x00),
+        (0xFF53, 0x80),
+        (0xFF54, 0x00),
+    ] {
+        cpu.bus.write_byte(address, value);
+    }
+}
+
+#[test]
+fn general_purpose_dma() {
+    let mut cpu = cgb_cpu();
+    set_up_hdma(&mut cpu);
+    let start = cpu.bus.cycles();
+    cpu.bus.write_byte(0xFF55, 0x01);
+    // The CPU is stalled for 8 M-cycles per block
+    assert_eq!(cpu.bus.cycles() - start, 1 + 2 * 8);
+    assert_eq!(cpu.bus.read_byte(0xFF55), 0xFF);
+    let ppu = cpu.bus.get_ppu().unwrap();
+    assert_eq!(ppu.vram[0x1F], 0x1F);
+    assert_eq!(ppu.vram[0x20], 0x00);
+}
+
+#[test]
+fn hblank_dma() {
+    let mut cpu = cgb_cpu();
+    set_up_hdma(&mut cpu);
+    cpu.bus.write_byte(0xFF55, 0x81);
+    assert_eq!(cpu.bus.read_byte(0xFF55), 0x01);
+    // One block is copied per HBlank
+    while cpu.bus.read_byte(0xFF55) == 0x01 {
+        cpu.step();
+    }
+    assert_eq!(cpu.bus.read_byte(0xFF55), 0x00);
+    assert_eq!(cpu.bus.get_ppu().unwrap().vram[0x0F], 0x0F);
+    assert_eq!(cpu.bus.get_ppu().unwrap().vram[0x10], 0x00);
+    while cpu.bus.read_byte(0xFF55) != 0xFF {
+        cpu.step();
+    }
+    assert_eq!(cpu.bus.get_ppu().unwrap().vram[0x1F], 0x1F);
+}
+
+#[test]
+fn wram_banks() {
+    let mut cpu = cgb_cpu();
+    assert_eq!(cpu.bus.read_byte(0xFF70), 0xF8);
+    for bank in 0..8 {
+        cpu.bus.write_byte(0xFF70, bank);
+        cpu.bus.write_byte(0xD000, 0x10 + bank);
+    }
+    cpu.bus.write_byte(0xFF70, 0x03);
+    assert_eq!(cpu.bus.read_byte(0xFF70), 0xFB);
+    assert_eq!(cpu.bus.read_byte(0xD000), 0x13);
+    // The echo follows the selected bank
+    assert_eq!(cpu.bus.read_byte(0xF000), 0x13);
+    // Bank 0 selects bank 1
+    cpu.bus.write_byte(0xFF70, 0x00);
+    assert_eq!(cpu.bus.read_byte(0xD000), 0x11);
+    cpu.bus.write_byte(0xC000, 0xAA);
+    assert_eq!(cpu.bus.read_byte(0xE000), 0xAA);
+}
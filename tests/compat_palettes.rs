@@ -0,0 +1,79 @@
+use rgb_emu::compat_palettes::{palettes, title_checksum};
+
+/// A header with the given title and old licensee code
+fn header(title: &[u8], licensee: u8) -> Vec<u8> {
+    let mut rom = vec![0; 0x0150];
+    rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+    rom[0x014B] = licensee;
+    rom
+}
+
+fn palettes_for(rom: &[u8]) -> [[u16; 4]; 3] {
+    palettes(|address| rom[usize::from(address)])
+}
+
+const DEFAULT: [[u16; 4]; 3] = [
+    [0x7FFF, 0x1BEF, 0x6180, 0x0000],
+    [0x7FFF, 0x421F, 0x1CF2, 0x0000],
+    [0x7FFF, 0x421F, 0x1CF2, 0x0000],
+];
+
+#[test]
+fn checksum_only_counts_nintendo_games() {
+    let rom = header(b"TETRIS", 0x01);
+    assert_eq!(title_checksum(|address| rom[usize::from(address)]), 0xDB);
+    let mut rom = header(b"TETRIS", 0x33);
+    rom[0x0144..0x0146].copy_from_slice(b"01");
+    assert_eq!(title_checksum(|address| rom[usize::from(address)]), 0xDB);
+    let rom = header(b"TETRIS", 0x08);
+    assert_eq!(title_checksum(|address| rom[usize::from(address)]), 0);
+}
+
+#[test]
+fn tetris() {
+    let yellow = [0x7FFF, 0x03FF, 0x001F, 0x0000];
+    assert_eq!(palettes_for(&header(b"TETRIS", 0x01)), [yellow; 3]);
+}
+
+#[test]
+fn pokemon_red() {
+    let red = [0x7FFF, 0x421F, 0x1CF2, 0x0000];
+    let green = [0x7FFF, 0x1BEF, 0x0200, 0x0000];
+    assert_eq!(
+        palettes_for(&header(b"POKEMON RED", 0x01)),
+        [red, green, red]
+    );
+}
+
+#[test]
+fn pokemon_blue_is_told_apart_by_its_fourth_letter() {
+    // POKEMON BLUE shares its checksum with VEGAS STAKES
+    let blue = [0x7FFF, 0x7E8C, 0x7C00, 0x0000];
+    let red = [0x7FFF, 0x421F, 0x1CF2, 0x0000];
+    assert_eq!(
+        palettes_for(&header(b"POKEMON BLUE", 0x01)),
+        [blue, red, blue]
+    );
+    // Same checksum, but a fourth letter no game in the table has
+    let mut rom = header(b"POKEMON BLUE", 0x01);
+    rom.swap(0x0137, 0x0138);
+    assert_eq!(palettes_for(&rom), DEFAULT);
+}
+
+#[test]
+fn super_mario_land_uses_colors_across_palettes() {
+    let sky = [0x7ED6, 0x4BFF, 0x2175, 0x0000];
+    let objects = [0x0000, 0x7FFF, 0x421F, 0x1CF2];
+    assert_eq!(
+        palettes_for(&header(b"SUPER MARIOLAND", 0x01)),
+        [sky, objects, objects]
+    );
+}
+
+#[test]
+fn unknown_games_get_the_default() {
+    assert_eq!(palettes_for(&header(b"", 0x01)), DEFAULT);
+    assert_eq!(palettes_for(&header(b"TEST", 0x01)), DEFAULT);
+    // Not licensed by Nintendo, so the title isn't looked up at all
+    assert_eq!(palettes_for(&header(b"TETRIS", 0x08)), DEFAULT);
+}
@@ -0,0 +1,76 @@
+use rgb_emu::compositor::{Compositor, Output, Overlay, BORDER_HEIGHT, BORDER_WIDTH};
+use rgb_emu::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use rgb_emu::video::Frame;
+
+fn pixel(output: &Output, x: usize, y: usize) -> [u8; 3] {
+    let offset = (y * output.width + x) * 3;
+    output.pixels[offset..offset + 3].try_into().unwrap()
+}
+
+#[test]
+fn screen_inside_border() {
+    let pixels = vec![3; SCREEN_WIDTH * SCREEN_HEIGHT];
+    let frame = Frame {
+        number: 1,
+        pixels: &pixels,
+        colors: None,
+    };
+    let mut compositor = Compositor::new();
+
+    let output = compositor.compose(&frame);
+    assert_eq!((output.width, output.height), (SCREEN_WIDTH, SCREEN_HEIGHT));
+    assert_eq!(pixel(output, 0, 0), [0x00, 0x00, 0x00]);
+
+    compositor.set_border(Some(vec![0x12; BORDER_WIDTH * BORDER_HEIGHT * 3]));
+    let output = compositor.compose(&frame);
+    assert_eq!((output.width, output.height), (BORDER_WIDTH, BORDER_HEIGHT));
+    assert_eq!(output.screen_origin, (48, 40));
+    assert_eq!(pixel(output, 47, 40), [0x12, 0x12, 0x12]);
+    assert_eq!(pixel(output, 48, 40), [0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn messages_expire() {
+    let pixels = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+    let mut compositor = Compositor::new();
+    compositor.show_message("Hi", 2);
+
+    // The message box is drawn in black in the bottom left corner
+    let output = compositor.compose(&Frame {
+        number: 1,
+        pixels: &pixels,
+        colors: None,
+    });
+    assert_eq!(pixel(output, 0, SCREEN_HEIGHT - 1), [0x00, 0x00, 0x00]);
+    assert!(compositor.has_messages());
+
+    let output = compositor.compose(&Frame {
+        number: 3,
+        pixels: &pixels,
+        colors: None,
+    });
+    assert_eq!(pixel(output, 0, SCREEN_HEIGHT - 1), [0xFF, 0xFF, 0xFF]);
+    assert!(!compositor.has_messages());
+}
+
+struct Crosshair;
+
+impl Overlay for Crosshair {
+    fn draw(&mut self, output: &mut Output) {
+        let (x, y) = output.screen_origin;
+        output.set_pixel(x + 80, y + 72, [0xFF, 0x00, 0x00]);
+    }
+}
+
+#[test]
+fn overlays_are_drawn_last() {
+    let pixels = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+    let mut compositor = Compositor::new();
+    compositor.add_overlay(Box::new(Crosshair));
+    let output = compositor.compose(&Frame {
+        number: 1,
+        pixels: &pixels,
+        colors: None,
+    });
+    assert_eq!(pixel(output, 80, 72), [0xFF, 0x00, 0x00]);
+}
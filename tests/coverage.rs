@@ -0,0 +1,78 @@
+use rgb_emu::cartridge;
+use rgb_emu::coverage::Coverage;
+use rgb_emu::cpu::Cpu;
+use rgb_emu::opcodes::format_instruction;
+use rgb_emu::symbols::Symbols;
+
+#[test]
+fn symbols() {
+    let symbols = Symbols::parse(
+        "; File generated by rgblink\n00:0150 Main\n01:4000 Far ; comment\n00:C000 wBuffer\n",
+    );
+    assert_eq!(symbols.label(0, 0x0150), Some("Main"));
+    assert_eq!(symbols.label(1, 0x4000), Some("Far"));
+    assert_eq!(symbols.label_from(2, 0x4000), None);
+    assert_eq!(symbols.label_from(5, 0xC000), Some("wBuffer"));
+    assert_eq!(symbols.address("Far"), Some((1, 0x4000)));
+}
+
+#[test]
+fn format_operands() {
+    let no_labels = |_| None;
+    assert_eq!(
+        format_instruction(&[0x21, 0x34, 0x12], 0, no_labels).as_deref(),
+        Some("LD HL,$1234")
+    );
+    assert_eq!(
+        format_instruction(&[0xE0, 0x40], 0, no_labels).as_deref(),
+        Some("LDH ($FF40),A")
+    );
+    assert_eq!(
+        format_instruction(&[0x18, 0xFE], 0x0150, no_labels).as_deref(),
+        Some("JR $0150")
+    );
+    assert_eq!(
+        format_instruction(&[0xF8, 0xFE], 0, no_labels).as_deref(),
+        Some("LD HL,SP-$02")
+    );
+    assert_eq!(
+        format_instruction(&[0xCB, 0x7C], 0, no_labels).as_deref(),
+        Some("BIT 7,H")
+    );
+    assert_eq!(format_instruction(&[0xD3], 0, no_labels), None);
+    assert_eq!(format_instruction(&[0xC3, 0x50], 0, no_labels), None);
+}
+
+#[test]
+fn disassemble_executed_code() {
+    let mut rom = vec![0; 0x8000];
+    // JP $0150; at $0150: LD A,$01; JR -2 (to itself)
+    rom[0x0100..0x0103].copy_from_slice(&[0xC3, 0x50, 0x01]);
+    rom[0x0150..0x0154].copy_from_slice(&[0x3E, 0x01, 0x18, 0xFE]);
+    let mut cpu = Cpu::new();
+    cpu.set_post_boot_state();
+    cpu.bus
+        .insert_cartridge(cartridge::from_rom(rom.clone()).unwrap());
+
+    let mut coverage = Coverage::new();
+    for _ in 0..5 {
+        coverage.record(&cpu);
+        let opcode = cpu.fetch();
+        let instruction = cpu.decode(opcode);
+        cpu.execute(instruction);
+    }
+    assert_eq!(coverage.len(), 3);
+    assert!(coverage.is_executed(0, 0x0152));
+
+    let symbols = Symbols::parse("00:0150 Main\n00:0152 .loop\n");
+    assert_eq!(
+        coverage.disassemble(&rom, &symbols),
+        "; ROM bank $00\n\
+         00:0100  C3 50 01  JP Main\n\
+         \n\
+         Main:\n\
+         00:0150  3E 01     LD A,$01\n\
+         .loop:\n\
+         00:0152  18 FE     JR .loop\n"
+    );
+}
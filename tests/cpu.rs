@@ -0,0 +1,227 @@
+use rgb_emu::cartridge;
+use rgb_emu::cpu::{Breakpoint, Cpu, RegisterPair};
+use rgb_emu::model::Model;
+use rgb_emu::watchpoints::{WatchKind, Watchpoint};
+
+/// A CPU at 0x0100 of a ROM with the given code there, and NOPs everywhere else
+fn cpu_with_code(code: &[u8]) -> Cpu {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + code.len()].copy_from_slice(code);
+    let mut cpu = Cpu::new();
+    cpu.set_post_boot_state();
+    cpu.bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+    cpu
+}
+
+fn step(cpu: &mut Cpu) {
+    let opcode = cpu.fetch();
+    let instruction = cpu.decode(opcode);
+    cpu.execute(instruction);
+}
+
+/// Runs `LD A,a; op A,b; DAA` and returns A and the Z and C flags
+fn bcd(op: u8, a: u8, b: u8) -> (u8, bool, bool) {
+    let mut cpu = cpu_with_code(&[0x3E, a, op, b, 0x27]);
+    cpu.flags.c = false;
+    for _ in 0..3 {
+        step(&mut cpu);
+    }
+    assert!(!cpu.flags.h);
+    (cpu.registers.a, cpu.flags.z, cpu.flags.c)
+}
+
+#[test]
+fn daa_after_addition() {
+    // ADD A,d8
+    assert_eq!(bcd(0xC6, 0x15, 0x27), (0x42, false, false));
+    assert_eq!(bcd(0xC6, 0x09, 0x01), (0x10, false, false));
+    assert_eq!(bcd(0xC6, 0x99, 0x01), (0x00, true, true));
+    assert_eq!(bcd(0xC6, 0x80, 0x80), (0x60, false, true));
+}
+
+#[test]
+fn daa_after_subtraction() {
+    // SUB d8
+    assert_eq!(bcd(0xD6, 0x42, 0x15), (0x27, false, false));
+    assert_eq!(bcd(0xD6, 0x10, 0x01), (0x09, false, false));
+    assert_eq!(bcd(0xD6, 0x00, 0x01), (0x99, false, true));
+    assert_eq!(bcd(0xD6, 0x25, 0x25), (0x00, true, false));
+}
+
+#[test]
+fn illegal_opcode_locks_cpu() {
+    // EI; illegal opcode 0xD3
+    let mut cpu = cpu_with_code(&[0xFB, 0xD3]);
+    cpu.bus.write_byte(0xFFFF, 0x01);
+    step(&mut cpu);
+    step(&mut cpu);
+    assert!(cpu.locked);
+    let pc = cpu.registers.pc;
+
+    // Pending interrupts are ignored, and the CPU goes nowhere while time keeps passing
+    cpu.bus.write_byte(0xFF0F, 0x01);
+    let div = cpu.bus.peek_byte(0xFF04);
+    for _ in 0..1000 {
+        step(&mut cpu);
+    }
+    assert_eq!(cpu.registers.pc, pc);
+    assert_ne!(cpu.bus.peek_byte(0xFF04), div);
+}
+
+#[test]
+fn trace_hook_sees_each_instruction() {
+    use rgb_emu::cpu::Instruction;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // LD A,$42; BIT 7,H; HALT
+    let mut cpu = cpu_with_code(&[0x3E, 0x42, 0xCB, 0x7C, 0x76]);
+    cpu.bus.write_byte(0xFFFF, 0x00);
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let recorded = Rc::clone(&events);
+    cpu.trace_hook = Some(Box::new(move |event| {
+        recorded.borrow_mut().push((
+            event.pc,
+            event.opcode,
+            event.registers.a,
+            matches!(event.instruction, Instruction::Halt),
+        ));
+    }));
+    for _ in 0..5 {
+        cpu.step();
+    }
+    // Nothing is traced while halted
+    assert_eq!(
+        *events.borrow(),
+        [
+            (0x0100, 0x3E, 0x01, false),
+            (0x0102, 0xCB7C, 0x42, false),
+            (0x0104, 0x76, 0x42, true)
+        ]
+    );
+}
+
+#[test]
+fn gameboy_doctor_log_line() {
+    let mut cpu = cpu_with_code(&[0x00, 0xC3, 0x50, 0x01]);
+    assert_eq!(
+        rgb_emu::debug::doctor_line(&cpu),
+        "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01"
+    );
+
+    cpu.bus.stub_ly(Some(0x90));
+    assert_eq!(cpu.bus.read_byte(0xFF44), 0x90);
+}
+
+#[test]
+fn breakpoints_are_hit_after_reaching_pc() {
+    // NOP; NOP; JR $0100
+    let mut cpu = cpu_with_code(&[0x00, 0x00, 0x18, 0xFC]);
+    let breakpoint = Breakpoint {
+        address: 0x0102,
+        bank: None,
+    };
+    cpu.breakpoints.insert(breakpoint);
+    // A breakpoint on a bank that isn't mapped there never hits
+    cpu.breakpoints.insert(Breakpoint {
+        address: 0x0101,
+        bank: Some(1),
+    });
+
+    cpu.step();
+    assert_eq!(cpu.breakpoint_hit, None);
+    cpu.step();
+    assert_eq!(cpu.breakpoint_hit, Some(breakpoint));
+    cpu.breakpoint_hit = None;
+    for _ in 0..3 {
+        cpu.step();
+    }
+    assert_eq!(cpu.breakpoint_hit, Some(breakpoint));
+}
+
+#[test]
+fn watchpoints_filter_by_access_and_value() {
+    // LD A,$12; LD ($C000),A; LD A,$34; LD ($C000),A; LD A,($C000)
+    let mut cpu = cpu_with_code(&[
+        0x3E, 0x12, 0xEA, 0x00, 0xC0, 0x3E, 0x34, 0xEA, 0x00, 0xC0, 0xFA, 0x00, 0xC0,
+    ]);
+    let watchpoints = cpu.bus.watchpoints_mut().unwrap();
+    watchpoints.insert(Watchpoint {
+        address: 0xC000,
+        kind: WatchKind::Write,
+        value: Some(0x34),
+    });
+    watchpoints.insert(Watchpoint {
+        address: 0xC000,
+        kind: WatchKind::Read,
+        value: None,
+    });
+
+    for _ in 0..3 {
+        step(&mut cpu);
+    }
+    assert!(!cpu.should_pause());
+    step(&mut cpu);
+    assert!(cpu.should_pause());
+    let hit = cpu.bus.watchpoints_mut().unwrap().hit.take().unwrap();
+    assert_eq!((hit.value, hit.write), (0x34, true));
+
+    step(&mut cpu);
+    let hit = cpu.bus.watchpoints_mut().unwrap().hit.take().unwrap();
+    assert_eq!((hit.watchpoint.kind, hit.value), (WatchKind::Read, 0x34));
+}
+
+/// AF, BC, DE and HL after setting up the post-boot state of a model with a cartridge
+fn post_boot_registers(model: Model, rom: &[u8]) -> [u16; 4] {
+    let mut cpu = Cpu::new();
+    cpu.bus
+        .insert_cartridge(cartridge::from_rom(rom.to_vec()).unwrap());
+    cpu.bus.set_model(model);
+    cpu.set_post_boot_state();
+    assert_eq!((cpu.registers.pc, cpu.registers.sp), (0x0100, 0xFFFE));
+    [
+        RegisterPair::AF,
+        RegisterPair::BC,
+        RegisterPair::DE,
+        RegisterPair::HL,
+    ]
+    .map(|pair| cpu.get_register_pair(&pair))
+}
+
+#[test]
+fn post_boot_registers_for_each_model() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x014D] = 0xE7;
+    assert_eq!(
+        post_boot_registers(Model::Dmg, &rom),
+        [0x01B0, 0x0013, 0x00D8, 0x014D]
+    );
+    assert_eq!(
+        post_boot_registers(Model::Dmg0, &rom),
+        [0x0100, 0xFF13, 0x00C1, 0x8403]
+    );
+    assert_eq!(
+        post_boot_registers(Model::Mgb, &rom),
+        [0xFFB0, 0x0013, 0x00D8, 0x014D]
+    );
+    assert_eq!(
+        post_boot_registers(Model::Sgb, &rom),
+        [0x0100, 0x0014, 0x0000, 0xC060]
+    );
+    // A DMG cartridge from Nintendo in DMG compatibility mode
+    rom[0x014B] = 0x01;
+    rom[0x0134..0x0138].copy_from_slice(b"TEST");
+    assert_eq!(
+        post_boot_registers(Model::Cgb, &rom),
+        [0x1180, 0x4000, 0x0008, 0x007C]
+    );
+    rom[0x0143] = 0x80;
+    assert_eq!(
+        post_boot_registers(Model::Cgb, &rom),
+        [0x1180, 0x0000, 0xFF56, 0x000D]
+    );
+
+    // The half-carry and carry flags are only set if the header checksum isn't 0
+    rom[0x014D] = 0x00;
+    assert_eq!(post_boot_registers(Model::Dmg, &rom)[0], 0x0180);
+}
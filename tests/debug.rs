@@ -0,0 +1,128 @@
+use rgb_emu::debug::{self, Hidden, Region};
+use rgb_emu::ppu::Ppu;
+
+#[test]
+fn tiles_in_both_banks() {
+    let mut ppu = Ppu::default();
+    // A vertical line in the first column of tile 1, and the last tile in bank 1
+    for row in 0..8 {
+        ppu.vram[0x10 + row * 2] = 0x80;
+        ppu.vram[0x2000 + 383 * 16 + row * 2 + 1] = 0xFF;
+    }
+    let image = debug::tiles(&ppu);
+    assert_eq!((image.width, image.height), (128, 192));
+    assert_eq!(image.pixels[8..10], [1, 0]);
+    assert_eq!(image.pixels[7 * 128 + 8], 1);
+
+    ppu.cgb = true;
+    let image = debug::tiles(&ppu);
+    assert_eq!((image.width, image.height), (256, 192));
+    assert_eq!(image.pixels[8], 1);
+    assert_eq!(image.pixels[191 * 256 + 255], 2);
+    assert_eq!(image.pixels[191 * 256 + 127], 0);
+}
+
+#[test]
+fn viewport_wraps_around_the_map() {
+    let mut ppu = Ppu::default();
+    assert_eq!(
+        debug::viewport(&ppu),
+        [Region {
+            x: 0,
+            y: 0,
+            width: 160,
+            height: 144
+        }]
+    );
+    ppu.scx = 200;
+    ppu.scy = 250;
+    let regions = debug::viewport(&ppu);
+    assert_eq!(regions.len(), 4);
+    assert_eq!(
+        regions[0],
+        Region {
+            x: 200,
+            y: 250,
+            width: 56,
+            height: 6
+        }
+    );
+    assert_eq!(
+        regions[3],
+        Region {
+            x: 0,
+            y: 0,
+            width: 104,
+            height: 138
+        }
+    );
+}
+
+#[test]
+fn window_viewport() {
+    let mut ppu = Ppu::default();
+    ppu.lcdc = 0x20;
+    ppu.wx = 87;
+    ppu.wy = 44;
+    assert_eq!(
+        debug::window_viewport(&ppu),
+        Some(Region {
+            x: 0,
+            y: 0,
+            width: 80,
+            height: 100
+        })
+    );
+    ppu.wx = 167;
+    assert_eq!(debug::window_viewport(&ppu), None);
+}
+
+#[test]
+fn window_map_follows_lcdc() {
+    let mut ppu = Ppu::default();
+    ppu.bgp = 0xE4;
+    ppu.lcdc = 0x50;
+    ppu.vram[0x10] = 0xFF;
+    ppu.vram[0x1C00] = 1;
+    assert_eq!(debug::window_map(&ppu).pixels[0], 1);
+    assert_eq!(debug::background_map(&ppu).pixels[0], 0);
+}
+
+#[test]
+fn oam_entries_explain_hidden_sprites() {
+    let mut ppu = Ppu::default();
+    ppu.lcdc = 0x02;
+    // Sprites 0-10 are all on line 0, sprite 11 is off screen and the rest are at (0, 0)
+    for index in 0..11 {
+        ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&[16, 8 + index as u8 * 8, 1, 0x20]);
+    }
+    ppu.oam[44..48].copy_from_slice(&[160, 8, 0, 0]);
+    let entries = debug::oam_entries(&ppu);
+    assert_eq!(entries.len(), 40);
+    assert_eq!(entries[9].hidden, None);
+    assert_eq!(entries[10].hidden, Some(Hidden::LineLimit));
+    assert_eq!(entries[11].hidden, Some(Hidden::OffScreen));
+    assert_eq!(entries[12].hidden, Some(Hidden::OffScreen));
+    assert_eq!(
+        entries[1].to_string(),
+        " 1: X:10 Y:10 tile:01 attributes:20 (OBP0, X flip)"
+    );
+    assert_eq!(
+        entries[10].to_string(),
+        "10: X:58 Y:10 tile:01 attributes:20 (OBP0, X flip) hidden: over 10 per line"
+    );
+
+    ppu.lcdc = 0;
+    assert_eq!(debug::oam_entries(&ppu)[0].hidden, Some(Hidden::Disabled));
+}
+
+#[test]
+fn sprites_are_flipped() {
+    let mut ppu = Ppu::default();
+    ppu.obp0 = 0xE4;
+    ppu.vram[0x10] = 0x80;
+    ppu.oam[2..4].copy_from_slice(&[1, 0x60]);
+    let image = debug::sprites(&ppu);
+    assert_eq!(image.pixels[7 * image.width + 7], 1);
+    assert_eq!(image.pixels[0], 0);
+}
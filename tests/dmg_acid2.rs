@@ -0,0 +1,19 @@
+//! Runs dmg-acid2, which draws a face where each feature is only right if a part of the PPU is:
+//!
+//! - the BG and window enable, object enable and object size bits in LCDC
+//! - BG and window tile map selection, and both tile data addressing modes
+//! - the window position, and its internal line counter when it's toggled mid-frame
+//! - object palettes, X and Y flipping, and the BG-over-object priority bit
+//! - the 10 objects per line limit, and priority between overlapping objects by X and OAM order
+//! - 8×16 objects ignoring bit 0 of the tile index
+//!
+//! The ROM and reference screenshot aren't included; put `dmg-acid2.gb` and
+//! `reference-dmg.png` from <https://github.com/mattcurrie/dmg-acid2> in `tests/dmg-acid2/`.
+mod screenshot;
+use screenshot::{compare, run_until_breakpoint};
+
+#[test]
+fn dmg_acid2() -> Result<(), String> {
+    let frame = run_until_breakpoint("tests/dmg-acid2/dmg-acid2.gb")?;
+    compare(&frame, "tests/dmg-acid2/reference-dmg.png")
+}
@@ -0,0 +1,83 @@
+use rgb_emu::ffi::*;
+use std::path::Path;
+use std::process::Command;
+use std::ptr;
+
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100] = 0x18; // JR -2
+    rom[0x0101] = 0xFE;
+    rom
+}
+
+#[test]
+fn run_and_save_state() {
+    let rom = rom();
+    unsafe {
+        let handle = rgb_create();
+        assert!(rgb_framebuffer(handle).is_null());
+        assert_eq!(rgb_save_state(handle, ptr::null_mut(), 0), 0);
+        assert!(!rgb_load_rom(handle, rom.as_ptr(), 0x100));
+        assert!(rgb_load_rom(handle, rom.as_ptr(), rom.len()));
+
+        rgb_set_buttons(handle, 0x80);
+        rgb_run_frame(handle);
+        assert!(!rgb_framebuffer(handle).is_null());
+        assert!(rgb_color_framebuffer(handle).is_null());
+
+        let size = rgb_save_state(handle, ptr::null_mut(), 0);
+        assert!(size > 0);
+        let mut state = vec![0; size];
+        assert_eq!(rgb_save_state(handle, state.as_mut_ptr(), size), size);
+        rgb_run_frame(handle);
+        assert!(rgb_load_state(handle, state.as_ptr(), size));
+        assert!(!rgb_load_state(handle, state.as_ptr(), 4));
+
+        rgb_destroy(handle);
+        rgb_destroy(ptr::null_mut());
+    }
+}
+
+#[test]
+fn header_declares_every_function() {
+    let header = include_str!("../include/rgb.h");
+    let source = include_str!("../src/ffi.rs");
+    let exported: Vec<_> = source
+        .lines()
+        .filter_map(|line| line.split("extern \"C\" fn ").nth(1))
+        .map(|rest| &rest[..rest.find('(').unwrap()])
+        .collect();
+    assert!(!exported.is_empty());
+    for function in exported {
+        assert!(
+            header.contains(&format!(" *{function}(")) || header.contains(&format!(" {function}(")),
+            "{function} is missing from include/rgb.h"
+        );
+    }
+}
+
+/// Compiles a C program that calls every function in include/rgb.h with the system C compiler
+/// (or `$CC`), links it against the shared library and runs it, so the header is checked
+/// against what the library actually exports
+#[test]
+#[cfg(unix)]
+fn header_compiles_against_library() {
+    // The shared library is built next to the test executables
+    let exe = std::env::current_exe().unwrap();
+    let library_dir = exe.parent().unwrap();
+    let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("rgb_header");
+    let compiler = std::env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    let status = Command::new(&compiler)
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-pedantic"])
+        .args(["-I", "include", "tests/ffi/header.c"])
+        .arg("-o")
+        .arg(&program)
+        .arg("-L")
+        .arg(library_dir)
+        .arg(format!("-Wl,-rpath,{}", library_dir.display()))
+        .arg("-lrgb_emu")
+        .status()
+        .unwrap_or_else(|error| panic!("Can't run C compiler {compiler}: {error}"));
+    assert!(status.success(), "tests/ffi/header.c doesn't compile");
+    assert_eq!(Command::new(&program).status().unwrap().code(), Some(0));
+}
@@ -0,0 +1,44 @@
+/* Calls every function in include/rgb.h, to check that the header compiles and matches the
+ * symbols the shared library exports. Run by tests/ffi.rs. */
+
+#include <stdlib.h>
+
+#include "rgb.h"
+
+int main(void) {
+    static uint8_t rom[0x8000];
+    /* JR -2 */
+    rom[0x0100] = 0x18;
+    rom[0x0101] = 0xFE;
+
+    RgbGameBoy *handle = rgb_create();
+    if (handle == NULL || rgb_framebuffer(handle) != NULL) {
+        return 1;
+    }
+    if (!rgb_load_rom(handle, rom, sizeof rom)) {
+        return 2;
+    }
+    rgb_set_buttons(handle, RGB_BUTTON_A | RGB_BUTTON_START);
+    rgb_run_frame(handle);
+    const uint8_t *frame = rgb_framebuffer(handle);
+    if (frame == NULL || frame[RGB_SCREEN_WIDTH * RGB_SCREEN_HEIGHT - 1] > 3) {
+        return 3;
+    }
+    if (rgb_color_framebuffer(handle) != NULL) {
+        return 4;
+    }
+
+    size_t size = rgb_save_state(handle, NULL, 0);
+    uint8_t *state = malloc(size);
+    if (state == NULL || rgb_save_state(handle, state, size) != size) {
+        return 5;
+    }
+    rgb_reset(handle);
+    if (!rgb_load_state(handle, state, size)) {
+        return 6;
+    }
+    free(state);
+
+    rgb_destroy(handle);
+    return 0;
+}
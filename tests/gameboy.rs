@@ -0,0 +1,134 @@
+use rgb_emu::bus::Bus;
+use rgb_emu::gameboy::{GameBoy, Options};
+use rgb_emu::joypad::Button;
+use rgb_emu::model::Model;
+
+/// A ROM that copies P1 to 0xC000 in a loop
+fn joypad_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0109].copy_from_slice(&[
+        0xF0, 0x00, // LDH A, [P1]
+        0xEA, 0x00, 0xC0, // LD [0xC000], A
+        0xC3, 0x00, 0x01, // JP 0x0100
+        0x00,
+    ]);
+    rom
+}
+
+#[test]
+fn runs_frames() {
+    let mut gameboy = GameBoy::new(joypad_rom(), Options::default()).unwrap();
+    assert_eq!(gameboy.cpu.bus.model(), Model::Dmg);
+    let frame = gameboy.frame().number;
+    gameboy.run_frame();
+    assert_eq!(gameboy.frame().number, frame + 1);
+    assert_eq!(gameboy.frame().pixels.len(), 160 * 144);
+    assert!(!gameboy.take_audio().is_empty());
+    assert!(gameboy.take_audio().is_empty());
+}
+
+#[test]
+fn buttons() {
+    let mut gameboy = GameBoy::new(joypad_rom(), Options::default()).unwrap();
+    gameboy.cpu.bus.write_byte(0xFF00, 0x10);
+    gameboy.press(Button::Start);
+    gameboy.press(Button::A);
+    gameboy.release(Button::A);
+    gameboy.run_frame();
+    assert_eq!(gameboy.cpu.bus.peek_byte(0xC000) & 0x0F, 0x07);
+}
+
+#[test]
+fn run_frame_returns_in_stop_mode() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0105].copy_from_slice(&[
+        0x10, 0x00, // STOP
+        0x3C, // INC A
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut gameboy = GameBoy::new(rom, Options::default()).unwrap();
+    let frame = gameboy.frame().number;
+    gameboy.run_frame();
+    assert!(gameboy.cpu.clocks_stopped());
+    assert_eq!(gameboy.frame().number, frame);
+
+    gameboy.press(Button::Start);
+    gameboy.run_frame();
+    assert!(!gameboy.cpu.stopped);
+    assert_eq!(gameboy.frame().number, frame + 1);
+    assert_eq!(gameboy.cpu.registers.a, 0x02);
+}
+
+#[test]
+fn missing_header() {
+    assert!(GameBoy::new(vec![0; 0x100], Options::default()).is_err());
+}
+
+#[test]
+fn screenshot_is_png() {
+    let mut gameboy = GameBoy::new(joypad_rom(), Options::default()).unwrap();
+    gameboy.run_frame();
+    let png = gameboy.screenshot();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(png[16..24], [0, 0, 0, 160, 0, 0, 0, 144]);
+    assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+}
+
+#[test]
+fn pause_and_resume() {
+    let mut gameboy = GameBoy::new(joypad_rom(), Options::default()).unwrap();
+    let frame = gameboy.frame().number;
+    gameboy.pause();
+    assert!(gameboy.is_paused());
+    gameboy.run_frame();
+    assert_eq!(gameboy.step(), 0);
+    assert_eq!(gameboy.frame().number, frame);
+    gameboy.resume();
+    gameboy.run_frame();
+    assert_eq!(gameboy.frame().number, frame + 1);
+}
+
+#[test]
+fn reset_keeps_battery_ram() {
+    let mut rom = joypad_rom();
+    rom[0x0147] = 0x03; // MBC1+RAM+BATTERY
+    rom[0x0149] = 0x02; // 8 KiB
+    let mut gameboy = GameBoy::new(rom, Options::default()).unwrap();
+    gameboy.run_frame();
+    gameboy.cpu.bus.write_byte(0x0000, 0x0A);
+    gameboy.cpu.bus.write_byte(0xA000, 0x42);
+    gameboy.cpu.bus.write_byte(0xC100, 0x42);
+    gameboy.cpu.ime = true;
+    gameboy.reset();
+    assert_eq!(gameboy.cpu.registers.pc, 0x0100);
+    assert!(!gameboy.cpu.ime);
+    assert_eq!(gameboy.cpu.bus.cycles(), 0);
+    assert_eq!(gameboy.cpu.bus.peek_byte(0xC100), 0);
+    // The mapper starts over with RAM disabled
+    assert_eq!(gameboy.cpu.bus.peek_byte(0xA000), 0xFF);
+    gameboy.cpu.bus.write_byte(0x0000, 0x0A);
+    assert_eq!(gameboy.cpu.bus.peek_byte(0xA000), 0x42);
+}
+
+#[test]
+fn hot_swap_cartridge() {
+    let mut gameboy = GameBoy::new(joypad_rom(), Options::default()).unwrap();
+    gameboy.run_frame();
+    gameboy.remove_cartridge();
+    assert_eq!(gameboy.cpu.bus.peek_byte(0x0100), 0xFF);
+    assert_eq!(gameboy.cpu.bus.peek_byte(0xA000), 0xFF);
+    gameboy.cpu.bus.write_byte(0x2000, 0x02);
+    // The CPU runs into RST 38 over and over, but keeps running
+    gameboy.run_frame();
+    gameboy.reset();
+    assert_eq!(gameboy.cpu.bus.peek_byte(0x0100), 0xFF);
+
+    assert!(gameboy.insert_cartridge(vec![0; 0x100]).is_err());
+    let mut rom = joypad_rom();
+    rom[0x0150] = 0x42;
+    gameboy.insert_cartridge(rom).unwrap();
+    assert_eq!(gameboy.cpu.bus.peek_byte(0x0150), 0x42);
+    gameboy.reset();
+    assert_eq!(gameboy.cpu.bus.peek_byte(0x0150), 0x42);
+}
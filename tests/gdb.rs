@@ -0,0 +1,55 @@
+use rgb_emu::cartridge;
+use rgb_emu::cpu::{Breakpoint, Cpu};
+use rgb_emu::gdb::{handle_packet, Action};
+
+fn cpu() -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.set_post_boot_state();
+    cpu.bus
+        .insert_cartridge(cartridge::from_rom(vec![0; 0x8000]).unwrap());
+    cpu
+}
+
+fn reply(cpu: &mut Cpu, packet: &str) -> String {
+    match handle_packet(cpu, packet) {
+        Action::Reply(reply) => reply,
+        action => panic!("Expected a reply to {packet}, got {action:?}"),
+    }
+}
+
+#[test]
+fn registers() {
+    let mut cpu = cpu();
+    cpu.registers.sp = 0xFFFE;
+    cpu.registers.pc = 0x0150;
+    assert!(reply(&mut cpu, "g").ends_with("feff5001"));
+
+    assert_eq!(reply(&mut cpu, "P5=0002"), "OK");
+    assert_eq!(cpu.registers.pc, 0x0200);
+    assert_eq!(reply(&mut cpu, "p5"), "0002");
+    assert_eq!(reply(&mut cpu, "p6"), "E01");
+}
+
+#[test]
+fn memory() {
+    let mut cpu = cpu();
+    assert_eq!(reply(&mut cpu, "Mc000,2:abcd"), "OK");
+    assert_eq!(reply(&mut cpu, "mc000,3"), "abcd00");
+}
+
+#[test]
+fn breakpoints() {
+    let mut cpu = cpu();
+    assert_eq!(reply(&mut cpu, "Z0,150,1"), "OK");
+    let breakpoint = Breakpoint {
+        address: 0x0150,
+        bank: None,
+    };
+    assert!(cpu.breakpoints.contains(&breakpoint));
+    assert_eq!(reply(&mut cpu, "z0,150,1"), "OK");
+    assert!(cpu.breakpoints.is_empty());
+    assert_eq!(reply(&mut cpu, "Z2,c000,2"), "OK");
+    assert_eq!(cpu.bus.watchpoints().unwrap().iter().count(), 2);
+    assert_eq!(reply(&mut cpu, "Z9,c000,1"), "");
+    assert_eq!(handle_packet(&mut cpu, "s"), Action::Step);
+}
@@ -0,0 +1,71 @@
+use rgb_emu::header::{CartridgeHeader, CgbSupport, ChecksumMismatch, Destination};
+
+#[test]
+fn parse_header() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0134..0x013F].copy_from_slice(b"POKEMON RED");
+    rom[0x0146] = 0x03;
+    rom[0x0147] = 0x13;
+    rom[0x0148] = 0x05;
+    rom[0x0149] = 0x03;
+    rom[0x014A] = 0x01;
+    rom[0x014B] = 0x01;
+    rom[0x014D] = 0x20;
+    rom[0x014E..0x0150].copy_from_slice(&[0x91, 0xE6]);
+
+    let header = CartridgeHeader::parse(&rom).unwrap();
+    assert_eq!(header.title, "POKEMON RED");
+    assert_eq!(header.cgb, CgbSupport::None);
+    assert!(header.sgb);
+    assert_eq!(header.cartridge_type, 0x13);
+    assert_eq!(header.rom_size, 1024 * 1024);
+    assert_eq!(header.ram_size, 32 * 1024);
+    assert_eq!(header.licensee, "01");
+    assert_eq!(header.destination, Destination::Overseas);
+    assert_eq!(header.header_checksum, 0x20);
+    assert_eq!(header.global_checksum, 0x91E6);
+}
+
+#[test]
+fn parse_cgb_header() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0134..0x0143].copy_from_slice(b"ZELDAAZ8EAPOKEM");
+    rom[0x0143] = 0x80;
+    rom[0x0144..0x0146].copy_from_slice(b"01");
+    rom[0x014B] = 0x33;
+
+    let header = CartridgeHeader::parse(&rom).unwrap();
+    assert_eq!(header.title, "ZELDAAZ8EAPOKEM");
+    assert_eq!(header.cgb, CgbSupport::Compatible);
+    assert_eq!(header.licensee, "01");
+    assert_eq!(header.destination, Destination::Japan);
+
+    assert_eq!(CartridgeHeader::parse(&rom[..0x100]), None);
+}
+
+#[test]
+fn checksum_mismatches() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0134..0x0138].copy_from_slice(b"TEST");
+    rom[0x014D] = 0xA7;
+    rom[0x014E..0x0150].copy_from_slice(&[0x01, 0xE7]);
+    let header = CartridgeHeader::parse(&rom).unwrap();
+    assert_eq!(header.checksum_mismatches(&rom), []);
+
+    rom[0x0134] = b'B';
+    rom[0x7FFF] = 0x01;
+    let header = CartridgeHeader::parse(&rom).unwrap();
+    assert_eq!(
+        header.checksum_mismatches(&rom),
+        [
+            ChecksumMismatch::Header {
+                expected: 0xA7,
+                computed: 0xB9
+            },
+            ChecksumMismatch::Global {
+                expected: 0x01E7,
+                computed: 0x01D6
+            }
+        ]
+    );
+}
@@ -0,0 +1,222 @@
+use rgb_emu::cartridge;
+use rgb_emu::cpu::Cpu;
+use rgb_emu::joypad::Button;
+
+/// A CPU at 0x0100 of a ROM with the given code there, and NOPs everywhere else
+fn cpu_with_code(code: &[u8]) -> Cpu {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + code.len()].copy_from_slice(code);
+    let mut cpu = Cpu::new();
+    cpu.set_post_boot_state();
+    cpu.bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+    // The boot ROM leaves the VBlank interrupt requested
+    cpu.bus.set_interrupt_flags(0);
+    cpu
+}
+
+fn nop_cpu() -> Cpu {
+    cpu_with_code(&[])
+}
+
+fn step(cpu: &mut Cpu) {
+    let opcode = cpu.fetch();
+    let instruction = cpu.decode(opcode);
+    cpu.execute(instruction);
+}
+
+#[test]
+fn dispatch_highest_priority_interrupt() {
+    let mut cpu = nop_cpu();
+    cpu.ime = true;
+    cpu.bus.write_byte(0xFFFF, 0x05);
+    cpu.bus.write_byte(0xFF0F, 0x06);
+
+    step(&mut cpu);
+    assert_eq!(cpu.registers.pc, 0x0050);
+    assert_eq!(cpu.registers.sp, 0xFFFC);
+    assert_eq!(cpu.bus.peek_byte(0xFFFC), 0x01);
+    assert_eq!(cpu.bus.peek_byte(0xFFFD), 0x01);
+    assert!(!cpu.ime);
+    assert_eq!(cpu.bus.get_interrupt_flags() & 0x1F, 0x02);
+}
+
+#[test]
+fn dispatch_takes_five_cycles() {
+    let mut cpu = nop_cpu();
+    cpu.ime = true;
+    cpu.bus.write_byte(0xFFFF, 0x01);
+    cpu.bus.write_byte(0xFF0F, 0x01);
+    let cycles = cpu.bus.cycles();
+    step(&mut cpu);
+    // One for the NOP
+    assert_eq!(cpu.bus.cycles() - cycles, 6);
+}
+
+#[test]
+fn push_to_ie_changes_dispatched_interrupt() {
+    let mut cpu = nop_cpu();
+    cpu.ime = true;
+    cpu.registers.sp = 0x0000;
+    cpu.registers.pc = 0x0200;
+    cpu.bus.write_byte(0xFFFF, 0x01);
+    cpu.bus.write_byte(0xFF0F, 0x03);
+
+    // Pushing 0x02 to IE leaves only STAT enabled
+    step(&mut cpu);
+    assert_eq!(cpu.registers.pc, 0x0048);
+    assert_eq!(cpu.bus.get_interrupt_flags() & 0x1F, 0x01);
+}
+
+#[test]
+fn push_to_ie_cancels_dispatch() {
+    let mut cpu = nop_cpu();
+    cpu.ime = true;
+    cpu.registers.sp = 0x0000;
+    cpu.registers.pc = 0x0200;
+    cpu.bus.write_byte(0xFFFF, 0x01);
+    cpu.bus.write_byte(0xFF0F, 0x01);
+
+    step(&mut cpu);
+    assert_eq!(cpu.registers.pc, 0x0000);
+    assert_eq!(cpu.registers.sp, 0xFFFE);
+    assert!(!cpu.ime);
+    assert_eq!(cpu.bus.get_interrupt_flags() & 0x1F, 0x01);
+}
+
+#[test]
+fn no_dispatch_without_ime() {
+    let mut cpu = nop_cpu();
+    cpu.bus.write_byte(0xFFFF, 0x01);
+    cpu.bus.write_byte(0xFF0F, 0x01);
+    cpu.halted = true;
+
+    step(&mut cpu);
+    assert!(!cpu.halted);
+    assert_eq!(cpu.registers.pc, 0x0100);
+    assert_eq!(cpu.bus.get_interrupt_flags() & 0x1F, 0x01);
+}
+
+#[test]
+fn halt_until_interrupt() {
+    let mut cpu = cpu_with_code(&[0x76]); // HALT
+    cpu.bus.write_byte(0xFFFF, 0x01);
+    step(&mut cpu);
+    assert!(cpu.halted);
+    step(&mut cpu);
+    assert!(cpu.halted);
+    assert_eq!(cpu.registers.pc, 0x0101);
+
+    cpu.bus.set_interrupt_flags(0x01);
+    step(&mut cpu);
+    assert!(!cpu.halted);
+}
+
+#[test]
+fn halt_bug() {
+    let mut cpu = cpu_with_code(&[0x76, 0x3C]); // HALT; INC A
+    cpu.bus.write_byte(0xFFFF, 0x04);
+    cpu.bus.write_byte(0xFF0F, 0x04);
+
+    step(&mut cpu);
+    assert!(!cpu.halted);
+    step(&mut cpu);
+    assert_eq!(cpu.registers.pc, 0x0101);
+    step(&mut cpu);
+    assert_eq!(cpu.registers.pc, 0x0102);
+    assert_eq!(cpu.registers.a, 0x03);
+}
+
+#[test]
+fn stop_until_joypad() {
+    let mut cpu = cpu_with_code(&[0x10, 0x00, 0x3C]); // STOP; INC A
+    step(&mut cpu);
+    assert!(cpu.stopped);
+    assert_eq!(cpu.registers.pc, 0x0102);
+    assert_eq!(cpu.bus.peek_byte(0xFF04), 0x00);
+
+    step(&mut cpu);
+    assert_eq!(cpu.registers.pc, 0x0102);
+
+    // Only an input line going low wakes the CPU, not a joypad interrupt request on its own
+    cpu.bus.set_interrupt_flags(0x10);
+    step(&mut cpu);
+    assert!(cpu.stopped);
+
+    cpu.bus.set_buttons(Button::A.mask());
+    step(&mut cpu);
+    assert!(!cpu.stopped);
+    assert_eq!(cpu.registers.a, 0x02);
+}
+
+#[test]
+fn stop_with_button_held_halts() {
+    let mut cpu = cpu_with_code(&[0x10, 0x00, 0x3C]); // STOP; INC A
+    cpu.bus.set_buttons(Button::Start.mask());
+    cpu.bus.set_interrupt_flags(0);
+    let div = cpu.bus.peek_byte(0xFF04);
+    step(&mut cpu);
+    assert!(cpu.halted);
+    assert!(!cpu.stopped);
+    assert_eq!(cpu.registers.pc, 0x0102);
+    assert_eq!(cpu.bus.peek_byte(0xFF04), div);
+
+    // With an interrupt pending, it does nothing, and the next byte is the next instruction
+    let mut cpu = cpu_with_code(&[0x10, 0x3C]); // STOP; INC A
+    cpu.bus.set_buttons(Button::Start.mask());
+    cpu.bus.write_byte(0xFFFF, 0x10);
+    step(&mut cpu);
+    assert!(!cpu.halted && !cpu.stopped);
+    assert_eq!(cpu.registers.pc, 0x0101);
+    step(&mut cpu);
+    assert_eq!(cpu.registers.a, 0x02);
+}
+
+#[test]
+fn button_press_requests_joypad_interrupt() {
+    let mut cpu = cpu_with_code(&[0x10, 0x00, 0x3C]); // STOP; INC A
+                                                      // Select the action buttons
+    cpu.bus.write_byte(0xFF00, 0x10);
+    step(&mut cpu);
+    assert!(cpu.stopped);
+
+    cpu.bus.set_buttons(Button::Up.mask());
+    assert_eq!(cpu.bus.get_interrupt_flags() & 0x10, 0);
+    cpu.bus
+        .set_buttons(Button::Up.mask() | Button::Start.mask());
+    assert_eq!(cpu.bus.get_interrupt_flags() & 0x10, 0x10);
+    step(&mut cpu);
+    assert!(!cpu.stopped);
+
+    // Selecting a row with a button held is a falling edge too
+    cpu.bus.set_interrupt_flags(0);
+    cpu.bus.write_byte(0xFF00, 0x20);
+    assert_eq!(cpu.bus.get_interrupt_flags() & 0x10, 0x10);
+}
+
+#[test]
+fn ei_is_delayed_one_instruction() {
+    let mut cpu = cpu_with_code(&[0xFB]); // EI
+    cpu.bus.write_byte(0xFFFF, 0x01);
+    cpu.bus.write_byte(0xFF0F, 0x01);
+
+    step(&mut cpu);
+    assert!(!cpu.ime);
+    assert_eq!(cpu.registers.pc, 0x0101);
+
+    step(&mut cpu);
+    assert_eq!(cpu.registers.pc, 0x0040);
+    assert_eq!(cpu.bus.peek_byte(0xFFFC), 0x02);
+}
+
+#[test]
+fn ei_di_never_enables_interrupts() {
+    let mut cpu = cpu_with_code(&[0xFB, 0xF3]); // EI; DI
+    cpu.bus.write_byte(0xFFFF, 0x01);
+    cpu.bus.write_byte(0xFF0F, 0x01);
+
+    step(&mut cpu);
+    step(&mut cpu);
+    step(&mut cpu);
+    assert!(!cpu.ime);
+    assert_eq!(cpu.registers.pc, 0x0103);
+}
@@ -0,0 +1,150 @@
+#![cfg(feature = "jit")]
+
+use rgb_emu::block_cache::BlockCache;
+use rgb_emu::cartridge;
+use rgb_emu::cpu::{instruction_length, Cpu, RegisterPair};
+use rgb_emu::jit::Jit;
+
+/// Where the random loop body starts
+const LOOP: u16 = 0x015C;
+
+/// Cycles to run before comparing, which is long enough for the loop to be compiled
+const CYCLES: u64 = 100_000;
+
+/// A xorshift generator, so failures can be reproduced from the seed
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u8 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 32) as u8
+    }
+}
+
+/// Whether an opcode can go in the loop body. Jumps, instructions that change IME or halt, and
+/// ones that move SP are left out, so the loop mostly keeps running. PUSH and POP are only
+/// generated in pairs.
+fn allowed(opcode: u8) -> bool {
+    !matches!(
+        opcode,
+        0x10 | 0x76 | 0xF3 | 0xFB // STOP, HALT, DI, EI
+            | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 // JR
+            | 0xC2 | 0xC3 | 0xCA | 0xD2 | 0xDA | 0xE9 // JP
+            | 0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC // CALL
+            | 0xC0 | 0xC8 | 0xC9 | 0xD0 | 0xD8 | 0xD9 // RET, RETI
+            | 0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF // RST
+            | 0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD
+            | 0x31 | 0x33 | 0x3B | 0xE8 | 0xF9 // SP
+            | 0xC1 | 0xD1 | 0xE1 | 0xF1 | 0xC5 | 0xD5 | 0xE5 | 0xF5 // POP, PUSH
+    )
+}
+
+/// A ROM that enables the timer interrupt and then loops over random instructions
+fn random_rom(random: &mut Random) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    for vector in [0x40, 0x48, 0x50, 0x58, 0x60] {
+        rom[vector] = 0xD9; // RETI
+    }
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // NOP; JP 0x0150
+    rom[0x0150..0x015C].copy_from_slice(&[
+        0x31, 0xF0, 0xDF, // LD SP, 0xDFF0
+        0x3E, 0x04, // LD A, 4
+        0xE0, 0xFF, // LDH [IE], A
+        0x3E, 0x04, // LD A, 4
+        0xE0, 0x07, // LDH [TAC], A
+        0xFB, // EI
+    ]);
+    let mut code = Vec::new();
+    for _ in 0..=random.next() % 12 {
+        if random.next().is_multiple_of(8) {
+            let (push, pop) = (random.next() & 0x30, random.next() & 0x30);
+            code.extend([0xC5 | push, 0xC1 | pop]);
+            continue;
+        }
+        // Give the CB-prefixed instructions as many chances as the rest
+        if random.next().is_multiple_of(4) {
+            code.extend([0xCB, random.next()]);
+            continue;
+        }
+        let opcode = loop {
+            let opcode = random.next();
+            if allowed(opcode) {
+                break opcode;
+            }
+        };
+        code.push(opcode);
+        match opcode {
+            // Keep fixed writes away from the stack and the hardware registers
+            0xE0 => code.push(0x80 + random.next() % 0x70),
+            0x08 | 0xEA => code.extend([random.next(), 0xC0 + random.next() % 0x10]),
+            _ => code.extend((1..instruction_length(opcode)).map(|_| random.next())),
+        }
+    }
+    code.extend([0xC3, LOOP as u8, (LOOP >> 8) as u8]); // JP LOOP
+    let start = usize::from(LOOP);
+    rom[start..start + code.len()].copy_from_slice(&code);
+    rom
+}
+
+fn cpu(rom: Vec<u8>) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+    cpu.set_post_boot_state();
+    cpu
+}
+
+/// Everything a block can change in the CPU, along with the time
+fn state(cpu: &Cpu) -> (u64, u16, [u16; 5], bool) {
+    let pairs = [
+        RegisterPair::AF,
+        RegisterPair::BC,
+        RegisterPair::DE,
+        RegisterPair::HL,
+        RegisterPair::SP,
+    ]
+    .map(|rp| cpu.get_register_pair(&rp));
+    (cpu.bus.cycles(), cpu.registers.pc, pairs, cpu.ime)
+}
+
+#[test]
+fn compiled_blocks_run_like_the_interpreter() {
+    let mut compiled_seeds = 0;
+    for seed in 1..=100 {
+        let rom = random_rom(&mut Random(seed));
+        let mut interpreted = cpu(rom.clone());
+        let mut compiled = cpu(rom);
+        compiled.block_cache = Some(BlockCache::with_jit(Jit::new().unwrap()));
+
+        // Compare after every block, catching up with single steps in the interpreter
+        while compiled.bus.cycles() < CYCLES {
+            compiled.run_block();
+            while interpreted.bus.cycles() < compiled.bus.cycles() {
+                interpreted.step();
+            }
+            assert_eq!(state(&compiled), state(&interpreted), "seed {seed}");
+        }
+        let jit = compiled.block_cache.as_ref().unwrap().jit.as_ref().unwrap();
+        if jit.compiled() > 0 {
+            compiled_seeds += 1;
+        }
+        for (start, length) in [
+            (0x8000, 0x2000),
+            (0xC000, 0x2000),
+            (0xFE00, 0xA0),
+            (0xFF80, 0x7F),
+        ] {
+            assert_eq!(
+                compiled.bus.peek_range(start, length),
+                interpreted.bus.peek_range(start, length),
+                "seed {seed}: memory at {start:04X}"
+            );
+        }
+    }
+    // A random write can hit the stack or the interrupt registers and send the loop off
+    assert!(
+        compiled_seeds > 75,
+        "only {compiled_seeds} loops were compiled"
+    );
+}
@@ -1,9 +1,20 @@
 use pretty_assertions::assert_eq;
+use rayon::prelude::*;
 use std::collections::HashMap;
 
+use rgb_emu::apu::Apu;
+use rgb_emu::audio::AudioSink;
 use rgb_emu::bus::Bus;
 use rgb_emu::cartridge::Cartridge;
 use rgb_emu::cpu::*;
+use rgb_emu::metadata::Metadata;
+use rgb_emu::model::Model;
+use rgb_emu::peripheral::PeripheralEvent;
+use rgb_emu::ppu::Ppu;
+use rgb_emu::serial::LinkDevice;
+use rgb_emu::state::{State, StateError, StateReader, StateWriter};
+use rgb_emu::video::VideoSink;
+use rgb_emu::watchpoints::Watchpoints;
 use serde::{Deserialize, Serialize};
 
 struct JsMooBus {
@@ -20,26 +31,31 @@ impl JsMooBus {
     }
 }
 
+impl State for JsMooBus {
+    fn save_state(&self, _: &mut StateWriter) {}
+    fn load_state(&mut self, _: &mut StateReader) -> Result<(), StateError> {
+        Ok(())
+    }
+}
+
 impl Bus for JsMooBus {
     fn tick(&mut self) {}
+    fn cycles(&self) -> u64 {
+        0
+    }
     fn peek_byte(&self, address: u16) -> u8 {
         *(self.ram.get(&address).unwrap_or(&0u8))
     }
     fn read_byte(&mut self, address: u16) -> u8 {
         self.peek_byte(address)
     }
-    fn read_word(&mut self, address: u16) -> u16 {
-        let low_byte = u16::from(self.read_byte(address));
-        u16::from(self.read_byte(address + 1)) << 8 | low_byte
-    }
     fn write_byte(&mut self, address: u16, value: u8) {
         self.ram.insert(address, value);
     }
-    fn write_word(&mut self, address: u16, value: u16) {
-        self.write_byte(address, (value & 0xFF) as u8);
-        self.write_byte(address.wrapping_add(1), (value >> 8) as u8);
-    }
     fn set_post_boot_state(&mut self) {}
+    fn stop(&mut self) -> bool {
+        false
+    }
     fn set_interrupt_enable(&mut self, value: u8) {
         self.interrupt_enable = value;
     }
@@ -53,7 +69,51 @@ impl Bus for JsMooBus {
 
     fn insert_cartridge(&mut self, _: Box<dyn Cartridge>) {}
     fn remove_cartridge(&mut self) {}
+    fn get_cartridge(&self) -> Option<&dyn Cartridge> {
+        None
+    }
     fn set_boot_rom(&mut self, _: Vec<u8>) {}
+    fn send_peripheral_event(&mut self, _: PeripheralEvent) -> bool {
+        false
+    }
+    fn boot_rom_mapped(&self) -> bool {
+        false
+    }
+    fn oam_dma_pending(&self) -> bool {
+        false
+    }
+    fn set_audio_sink(&mut self, _: Box<dyn AudioSink>) {}
+    fn add_video_sink(&mut self, _: Box<dyn VideoSink>) {}
+    fn set_cpu_overclock(&mut self, _: u8) {}
+    fn set_buttons(&mut self, _: u8) {}
+    fn set_link_device(&mut self, _: Box<dyn LinkDevice>) {}
+    fn reset(&mut self) {}
+    fn oam_bug(&mut self, _: u16) {}
+    fn set_oam_bug(&mut self, _: bool) {}
+    fn set_ppu_access_locks(&mut self, _: bool) {}
+    fn stub_ly(&mut self, _: Option<u8>) {}
+    fn model(&self) -> Model {
+        Model::Dmg
+    }
+    fn metadata(&self) -> Metadata {
+        Metadata::current(Model::Dmg, None)
+    }
+    fn set_model(&mut self, _: Model) {}
+    fn get_ppu(&self) -> Option<&Ppu> {
+        None
+    }
+    fn get_apu(&self) -> Option<&Apu> {
+        None
+    }
+    fn get_apu_mut(&mut self) -> Option<&mut Apu> {
+        None
+    }
+    fn watchpoints(&self) -> Option<&Watchpoints> {
+        None
+    }
+    fn watchpoints_mut(&mut self) -> Option<&mut Watchpoints> {
+        None
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -164,7 +224,7 @@ impl From<CpuState> for Cpu {
             },
             ime: cpu_state.ime == 1,
             ime_delayed: cpu_state.ei == 1,
-            bus: Box::new(JsMooBus::new()),
+            bus: Box::new(JsMooBus::new()) as Box<dyn Bus>,
             ..Cpu::default()
         };
 
@@ -200,59 +260,61 @@ fn set_state_from(cpu: &mut Cpu, cpu_state: CpuState) {
     }
 }
 
-#[test]
-pub(crate) fn jsmoo() -> Result<(), String> {
+/// Runs all the tests for one opcode on a fresh CPU
+fn run_opcode_tests(opcode: u16) -> Result<(), String> {
     #![allow(clippy::unwrap_used)]
     let mut cpu = Cpu::new(); // TODO fix bus
-    for i in [0x00, 0xCB] {
-        for j in 0x00..=0xFF {
-            let opcode = (i << 8) | j;
-            let skip_opcodes = [
-                0x0027, // TODO STOP
-                0x00CB, // Prefix opcode
-                // Illegal opcodes:
-                0x00D3, 0x00DB, 0x00DD, 0x00E3, 0x00E4, 0x00EB, 0x00EC, 0x00ED, 0x00F4, 0x00FC,
-                0x00FD,
-            ];
-
-            if skip_opcodes.contains(&opcode) {
-                continue;
-            }
 
-            println!("Testing opcode {opcode:02x}...");
+    println!("Testing opcode {opcode:02x}...");
 
-            let filename = if opcode > 0x00FF {
-                format!("cb {:02x}", opcode & 0xFF)
-            } else {
-                format!("{opcode:02x}")
-            };
-            let tests = serde_json::from_str::<Vec<CpuTest>>(
-                &String::from_utf8(
-                    std::fs::read(format!(
-                        "tests/jsmoo-sm83-tests/misc/tests/GeneratedTests/sm83/v1/{filename}.json",
-                    ))
-                    .unwrap(),
-                )
-                .unwrap(),
-            )
-            .unwrap();
+    let filename = if opcode > 0x00FF {
+        format!("cb {:02x}", opcode & 0xFF)
+    } else {
+        format!("{opcode:02x}")
+    };
+    let tests = serde_json::from_str::<Vec<CpuTest>>(
+        &String::from_utf8(
+            std::fs::read(format!(
+                "tests/jsmoo-sm83-tests/misc/tests/GeneratedTests/sm83/v1/{filename}.json",
+            ))
+            .unwrap(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
 
-            for test in tests {
-                set_state_from(&mut cpu, test.initial_state);
+    for test in tests {
+        set_state_from(&mut cpu, test.initial_state);
 
-                let opcode = cpu.fetch();
-                let opcode = cpu.decode(opcode);
-                let opcode_name = format!("{opcode:?}");
-                cpu.execute(opcode);
+        let opcode = cpu.fetch();
+        let opcode = cpu.decode(opcode);
+        let opcode_name = format!("{opcode:?}");
+        cpu.execute(opcode);
 
-                let final_state = CpuState::from(&cpu);
-                if final_state != test.final_state {
-                    assert_eq!(final_state, test.final_state);
-                    return Err(format!("{} ({opcode_name})", test.name));
-                }
-            }
+        let final_state = CpuState::from(&cpu);
+        if final_state != test.final_state {
+            assert_eq!(final_state, test.final_state);
+            return Err(format!("{} ({opcode_name})", test.name));
         }
     }
 
     Ok(())
 }
+
+#[test]
+pub(crate) fn jsmoo() -> Result<(), String> {
+    let skip_opcodes = [
+        0x00CB, // Prefix opcode
+        // Illegal opcodes:
+        0x00D3, 0x00DB, 0x00DD, 0x00E3, 0x00E4, 0x00EB, 0x00EC, 0x00ED, 0x00F4, 0x00FC, 0x00FD,
+    ];
+    let opcodes: Vec<u16> = (0x0000..=0x00FF)
+        .chain(0xCB00..=0xCBFF)
+        .filter(|opcode| !skip_opcodes.contains(opcode))
+        .collect();
+
+    // Each opcode file is tested on its own CPU in a worker thread
+    opcodes
+        .par_iter()
+        .try_for_each(|opcode| run_opcode_tests(*opcode))
+}
@@ -0,0 +1,39 @@
+use rgb_emu::link::TcpLink;
+use rgb_emu::serial::LinkDevice;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn exchange_over_tcp() {
+    let listener = thread::spawn(|| TcpLink::listen(47_123).unwrap());
+    let mut master = loop {
+        match TcpLink::connect(("127.0.0.1", 47_123)) {
+            Ok(link) => break link,
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    let mut slave = listener.join().unwrap();
+
+    let slave = thread::spawn(move || loop {
+        if let Some(byte) = slave.poll(0x99) {
+            return byte;
+        }
+        thread::sleep(Duration::from_millis(1));
+    });
+    assert_eq!(master.exchange(0x42), 0x99);
+    assert_eq!(slave.join().unwrap(), 0x42);
+}
+
+#[test]
+fn disconnected() {
+    let listener = thread::spawn(|| TcpLink::listen(47_124).unwrap());
+    let mut master = loop {
+        match TcpLink::connect(("127.0.0.1", 47_124)) {
+            Ok(link) => break link,
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    drop(listener.join().unwrap());
+    assert_eq!(master.exchange(0x42), 0xFF);
+    assert!(!master.is_connected());
+}
@@ -0,0 +1,52 @@
+//! Runs the DMG PPU tests from Mealybug Tearoom and compares the screen with the reference
+//! screenshots from a DMG-CPU B. They're not included; unzip the built tests from
+//! <https://github.com/mattcurrie/mealybug-tearoom-tests> into `tests/mealybug-tearoom-tests/`,
+//! so that the ROMs are in `ppu/` and the screenshots in `expected/DMG-blob/`.
+mod screenshot;
+use screenshot::{compare, run_until_breakpoint};
+
+fn run_mealybug_test(name: &str) -> Result<(), String> {
+    let frame = run_until_breakpoint(&format!("tests/mealybug-tearoom-tests/ppu/{name}.gb"))?;
+    compare(
+        &frame,
+        &format!("tests/mealybug-tearoom-tests/expected/DMG-blob/{name}.png"),
+    )
+}
+
+macro_rules! mealybug_tests {
+    ($($name:ident,)*) => {
+        $(
+            #[test]
+            fn $name() -> Result<(), String> {
+                run_mealybug_test(stringify!($name))
+            }
+        )*
+    };
+}
+
+mealybug_tests! {
+    m2_win_en_toggle,
+    m3_bgp_change,
+    m3_bgp_change_sprites,
+    m3_lcdc_bg_en_change,
+    m3_lcdc_bg_map_change,
+    m3_lcdc_obj_en_change,
+    m3_lcdc_obj_en_change_variant,
+    m3_lcdc_obj_size_change,
+    m3_lcdc_obj_size_change_scx,
+    m3_lcdc_tile_sel_change,
+    m3_lcdc_tile_sel_win_change,
+    m3_lcdc_win_en_change_multiple,
+    m3_lcdc_win_en_change_multiple_wx,
+    m3_lcdc_win_map_change,
+    m3_obp0_change,
+    m3_scx_high_5_bits,
+    m3_scx_low_3_bits,
+    m3_scy_change,
+    m3_window_timing,
+    m3_window_timing_wx_0,
+    m3_wx_4_change,
+    m3_wx_4_change_sprites,
+    m3_wx_5_change,
+    m3_wx_6_change,
+}
@@ -0,0 +1,36 @@
+use rgb_emu::metadata::{Metadata, Mismatch};
+use rgb_emu::model::Model;
+
+#[test]
+fn metadata_round_trip() {
+    let metadata = Metadata::current(Model::Dmg, Some(b"123456789"));
+    assert_eq!(metadata.boot_rom_crc, Some(0xCBF4_3926));
+    assert_eq!(Metadata::from_bytes(&metadata.to_bytes()), Some(metadata));
+
+    let metadata = Metadata {
+        ppu_access_locks: true,
+        ..Metadata::current(Model::Cgb, None)
+    };
+    assert_eq!(Metadata::from_bytes(&metadata.to_bytes()), Some(metadata));
+}
+
+#[test]
+fn metadata_mismatches() {
+    let saved = Metadata::current(Model::Dmg, Some(b"old boot ROM"));
+    let mut current = Metadata::current(Model::Dmg, None);
+    current.version = String::from("0.0.0");
+    assert_eq!(
+        saved.mismatches(&current),
+        [Mismatch::Version, Mismatch::BootRom]
+    );
+
+    current = Metadata {
+        model: Model::Cgb,
+        oam_bug: true,
+        ..saved.clone()
+    };
+    assert_eq!(
+        saved.mismatches(&current),
+        [Mismatch::Model, Mismatch::Accuracy]
+    );
+}
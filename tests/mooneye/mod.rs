@@ -0,0 +1,38 @@
+//! Harness for test ROMs that report results like the Mooneye Test Suite. Every ROM is its own
+//! `#[test]` with its own `Cpu`, so the test runner already runs them in parallel without
+//! rayon.
+#![allow(clippy::unwrap_used)]
+use rgb_emu::cartridge;
+use rgb_emu::cpu::Cpu;
+use rgb_emu::model::Model;
+
+/// Tests finish in a few seconds at most, so give up after 30 seconds of emulated time
+const TIMEOUT: u64 = 30 * 1_048_576;
+
+/// Runs a test ROM that reports its result like the Mooneye Test Suite does, which other suites
+/// have adopted too. A test signals that it's done by executing `LD B,B`, with the Fibonacci
+/// numbers in B, C, D, E, H and L if it passed, and 0x42 in all of them if it failed.
+pub(crate) fn run_mooneye_test(path: &str, model: Model) -> Result<(), String> {
+    let mut cpu = Cpu::new();
+    cpu.bus.set_model(model);
+    cpu.bus.set_ppu_access_locks(true);
+    cpu.set_post_boot_state();
+
+    let rom = std::fs::read(path).expect("Unable to open ROM");
+    cpu.bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+
+    while cpu.bus.cycles() < TIMEOUT {
+        if !cpu.halted && cpu.bus.peek_byte(cpu.registers.pc) == 0x40 {
+            let r = &cpu.registers;
+            return match [r.b, r.c, r.d, r.e, r.h, r.l] {
+                [3, 5, 8, 13, 21, 34] => Ok(()),
+                [0x42, 0x42, 0x42, 0x42, 0x42, 0x42] => Err(format!("{path} failed")),
+                registers => Err(format!(
+                    "{path} stopped with unexpected registers {registers:02X?}"
+                )),
+            };
+        }
+        cpu.step();
+    }
+    Err(format!("{path} timed out"))
+}
@@ -0,0 +1,89 @@
+//! Runs the acceptance tests from the Mooneye Test Suite. They're not included; build them or
+//! unzip a release from <https://github.com/Gekkio/mooneye-test-suite/releases> into
+//! `tests/mooneye-test-suite/`, so that `tests/mooneye-test-suite/acceptance/` exists.
+mod mooneye;
+use rgb_emu::model::Model;
+
+fn run_mooneye_acceptance_test(path: &str) -> Result<(), String> {
+    mooneye::run_mooneye_test(&format!("tests/mooneye-test-suite/{path}"), Model::Dmg)
+}
+
+macro_rules! mooneye_tests {
+    ($($name:ident: $path:literal,)*) => {
+        $(
+            #[test]
+            fn $name() -> Result<(), String> {
+                run_mooneye_acceptance_test($path)
+            }
+        )*
+    };
+}
+
+mooneye_tests! {
+    add_sp_e_timing: "acceptance/add_sp_e_timing.gb",
+    boot_div_dmg_abc_mgb: "acceptance/boot_div-dmgABCmgb.gb",
+    boot_hwio_dmg_abc_mgb: "acceptance/boot_hwio-dmgABCmgb.gb",
+    boot_regs_dmg_abc: "acceptance/boot_regs-dmgABC.gb",
+    call_cc_timing: "acceptance/call_cc_timing.gb",
+    call_cc_timing2: "acceptance/call_cc_timing2.gb",
+    call_timing: "acceptance/call_timing.gb",
+    call_timing2: "acceptance/call_timing2.gb",
+    di_timing_gs: "acceptance/di_timing-GS.gb",
+    div_timing: "acceptance/div_timing.gb",
+    ei_sequence: "acceptance/ei_sequence.gb",
+    ei_timing: "acceptance/ei_timing.gb",
+    halt_ime0_ei: "acceptance/halt_ime0_ei.gb",
+    halt_ime0_nointr_timing: "acceptance/halt_ime0_nointr_timing.gb",
+    halt_ime1_timing: "acceptance/halt_ime1_timing.gb",
+    halt_ime1_timing2_gs: "acceptance/halt_ime1_timing2-GS.gb",
+    if_ie_registers: "acceptance/if_ie_registers.gb",
+    intr_timing: "acceptance/intr_timing.gb",
+    jp_cc_timing: "acceptance/jp_cc_timing.gb",
+    jp_timing: "acceptance/jp_timing.gb",
+    ld_hl_sp_e_timing: "acceptance/ld_hl_sp_e_timing.gb",
+    oam_dma_restart: "acceptance/oam_dma_restart.gb",
+    oam_dma_start: "acceptance/oam_dma_start.gb",
+    oam_dma_timing: "acceptance/oam_dma_timing.gb",
+    pop_timing: "acceptance/pop_timing.gb",
+    push_timing: "acceptance/push_timing.gb",
+    rapid_di_ei: "acceptance/rapid_di_ei.gb",
+    ret_cc_timing: "acceptance/ret_cc_timing.gb",
+    ret_timing: "acceptance/ret_timing.gb",
+    reti_intr_timing: "acceptance/reti_intr_timing.gb",
+    reti_timing: "acceptance/reti_timing.gb",
+    rst_timing: "acceptance/rst_timing.gb",
+    bits_mem_oam: "acceptance/bits/mem_oam.gb",
+    bits_reg_f: "acceptance/bits/reg_f.gb",
+    bits_unused_hwio_gs: "acceptance/bits/unused_hwio-GS.gb",
+    instr_daa: "acceptance/instr/daa.gb",
+    interrupts_ie_push: "acceptance/interrupts/ie_push.gb",
+    oam_dma_basic: "acceptance/oam_dma/basic.gb",
+    oam_dma_reg_read: "acceptance/oam_dma/reg_read.gb",
+    oam_dma_sources_gs: "acceptance/oam_dma/sources-GS.gb",
+    ppu_hblank_ly_scx_timing_gs: "acceptance/ppu/hblank_ly_scx_timing-GS.gb",
+    ppu_intr_1_2_timing_gs: "acceptance/ppu/intr_1_2_timing-GS.gb",
+    ppu_intr_2_0_timing: "acceptance/ppu/intr_2_0_timing.gb",
+    ppu_intr_2_mode0_timing: "acceptance/ppu/intr_2_mode0_timing.gb",
+    ppu_intr_2_mode0_timing_sprites: "acceptance/ppu/intr_2_mode0_timing_sprites.gb",
+    ppu_intr_2_mode3_timing: "acceptance/ppu/intr_2_mode3_timing.gb",
+    ppu_intr_2_oam_ok_timing: "acceptance/ppu/intr_2_oam_ok_timing.gb",
+    ppu_lcdon_timing_gs: "acceptance/ppu/lcdon_timing-GS.gb",
+    ppu_lcdon_write_timing_gs: "acceptance/ppu/lcdon_write_timing-GS.gb",
+    ppu_stat_irq_blocking: "acceptance/ppu/stat_irq_blocking.gb",
+    ppu_stat_lyc_onoff: "acceptance/ppu/stat_lyc_onoff.gb",
+    ppu_vblank_stat_intr_gs: "acceptance/ppu/vblank_stat_intr-GS.gb",
+    serial_boot_sclk_align_dmg_abc_mgb: "acceptance/serial/boot_sclk_align-dmgABCmgb.gb",
+    timer_div_write: "acceptance/timer/div_write.gb",
+    timer_rapid_toggle: "acceptance/timer/rapid_toggle.gb",
+    timer_tim00: "acceptance/timer/tim00.gb",
+    timer_tim00_div_trigger: "acceptance/timer/tim00_div_trigger.gb",
+    timer_tim01: "acceptance/timer/tim01.gb",
+    timer_tim01_div_trigger: "acceptance/timer/tim01_div_trigger.gb",
+    timer_tim10: "acceptance/timer/tim10.gb",
+    timer_tim10_div_trigger: "acceptance/timer/tim10_div_trigger.gb",
+    timer_tim11: "acceptance/timer/tim11.gb",
+    timer_tim11_div_trigger: "acceptance/timer/tim11_div_trigger.gb",
+    timer_tima_reload: "acceptance/timer/tima_reload.gb",
+    timer_tima_write_reloading: "acceptance/timer/tima_write_reloading.gb",
+    timer_tma_write_reloading: "acceptance/timer/tma_write_reloading.gb",
+}
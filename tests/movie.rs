@@ -0,0 +1,57 @@
+use rgb_emu::cartridge;
+use rgb_emu::cpu::Cpu;
+use rgb_emu::joypad::Button;
+use rgb_emu::movie::Movie;
+
+/// A CPU at 0x0100 of a ROM that keeps adding the action buttons read from P1 to B
+fn cpu() -> Cpu {
+    let mut rom = vec![0; 0x8000];
+    // LD A,$10; LDH ($00),A; LDH A,($00); ADD A,B; LD B,A; JR $0100
+    rom[0x0100..0x010A]
+        .copy_from_slice(&[0x3E, 0x10, 0xE0, 0x00, 0xF0, 0x00, 0x80, 0x47, 0x18, 0xF6]);
+    let mut cpu = Cpu::new();
+    cpu.set_post_boot_state();
+    cpu.bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+    cpu
+}
+
+#[test]
+fn joypad_reads_selected_buttons() {
+    let mut cpu = cpu();
+    cpu.bus.set_buttons(Button::A.mask() | Button::Down.mask());
+    cpu.bus.write_byte(0xFF00, 0x10);
+    assert_eq!(cpu.bus.peek_byte(0xFF00), 0xDE);
+    cpu.bus.write_byte(0xFF00, 0x20);
+    assert_eq!(cpu.bus.peek_byte(0xFF00), 0xE7);
+    cpu.bus.write_byte(0xFF00, 0x30);
+    assert_eq!(cpu.bus.peek_byte(0xFF00), 0xFF);
+}
+
+fn run_frame(cpu: &mut Cpu) {
+    let frame = cpu.bus.get_ppu().unwrap().frame_count;
+    while cpu.bus.get_ppu().unwrap().frame_count == frame {
+        cpu.step();
+    }
+}
+
+#[test]
+fn movie_replays_inputs() {
+    let mut cpu = cpu();
+    let mut movie = Movie::record(&cpu);
+    for frame in 0..10_u8 {
+        assert!(movie.next_frame(&mut cpu, frame));
+        run_frame(&mut cpu);
+    }
+    let recorded = cpu.registers.b;
+
+    let mut movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+    assert_eq!(movie.len(), 10);
+    assert_eq!(movie.metadata(), &cpu.bus.metadata());
+    let mut cpu = self::cpu();
+    cpu.registers.b = 0x42;
+    movie.start(&mut cpu).unwrap();
+    while movie.next_frame(&mut cpu, 0) {
+        run_frame(&mut cpu);
+    }
+    assert_eq!(cpu.registers.b, recorded);
+}
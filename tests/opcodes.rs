@@ -0,0 +1,145 @@
+use rgb_emu::cartridge;
+use rgb_emu::cpu::{instruction_length, Cpu};
+use rgb_emu::opcodes;
+
+/// Decodes the opcode from WRAM and returns how many bytes the decoder consumed
+fn decoded_length(opcode: u16) -> u16 {
+    let mut cpu = Cpu::new();
+    cpu.bus
+        .insert_cartridge(cartridge::from_rom(vec![0; 0x8000]).unwrap());
+    if opcode > 0xFF {
+        cpu.bus.write_byte(0xC000, 0xCB);
+        cpu.bus.write_byte(0xC001, (opcode & 0xFF) as u8);
+    } else {
+        cpu.bus.write_byte(0xC000, opcode as u8);
+    }
+    cpu.registers.pc = 0xC000;
+    let byte = cpu.fetch();
+    let _ = cpu.decode(byte);
+    cpu.registers.pc - 0xC000
+}
+
+#[test]
+fn opcode_lengths_match_decoder() {
+    for opcode in (0x00..=0xFF).chain(0xCB00..=0xCBFF) {
+        let skip_opcodes = [
+            0x0010, // STOP skips its second byte when executed, not when decoded
+            0x00CB, // The prefix is decoded along with the prefixed opcode
+        ];
+        if skip_opcodes.contains(&opcode) {
+            continue;
+        }
+        if let Some(info) = opcodes::info(opcode) {
+            assert_eq!(
+                decoded_length(opcode),
+                u16::from(info.length),
+                "{opcode:04X} {}",
+                info.mnemonic
+            );
+            if opcode <= 0xFF {
+                assert_eq!(
+                    instruction_length(opcode as u8),
+                    u16::from(info.length),
+                    "{opcode:02X} {}",
+                    info.mnemonic
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn opcode_table_coverage() {
+    let legal = (0x00..=0xFF)
+        .filter(|opcode| opcodes::info(*opcode).is_some())
+        .count();
+    assert_eq!(legal, 256 - 11);
+    assert!((0xCB00..=0xCBFF).all(|opcode| opcodes::info(opcode).is_some()));
+    assert_eq!(opcodes::info(0xCB7E).unwrap().mnemonic, "BIT 7,(HL)");
+    assert_eq!(opcodes::info(0x20).unwrap().branch_cycles, Some(12));
+}
+
+/// Executes the opcode from WRAM with all flags set to `flags` and returns the M-cycles taken
+fn executed_cycles(opcode: u16, flags: bool) -> u32 {
+    let mut cpu = Cpu::new();
+    cpu.bus
+        .insert_cartridge(cartridge::from_rom(vec![0; 0x8000]).unwrap());
+    cpu.set_post_boot_state();
+    if opcode > 0xFF {
+        cpu.bus.write_byte(0xC000, 0xCB);
+        cpu.bus.write_byte(0xC001, (opcode & 0xFF) as u8);
+    } else {
+        cpu.bus.write_byte(0xC000, opcode as u8);
+    }
+    cpu.registers.pc = 0xC000;
+    cpu.flags.z = flags;
+    cpu.flags.c = flags;
+    cpu.step()
+}
+
+#[test]
+fn opcode_cycles_match_execution() {
+    for opcode in (0x00..=0xFF).chain(0xCB00..=0xCBFF) {
+        let skip_opcodes = [
+            0x0010, // STOP stops the clock
+            0x0076, // HALT
+            0x00CB, // Prefix
+        ];
+        let Some(info) = opcodes::info(opcode) else {
+            continue;
+        };
+        if skip_opcodes.contains(&opcode) {
+            continue;
+        }
+        for flags in [false, true] {
+            let condition = info.mnemonic.split([' ', ',']).nth(1);
+            let taken = match condition {
+                Some("NZ") => !flags,
+                Some("Z") => flags,
+                Some("NC") => !flags,
+                Some("C") if info.branch_cycles.is_some() => flags,
+                _ => false,
+            };
+            let expected = if taken {
+                info.branch_cycles.unwrap()
+            } else {
+                info.cycles
+            };
+            assert_eq!(
+                executed_cycles(opcode, flags) * 4,
+                u32::from(expected),
+                "{opcode:04X} {} with flags {flags}",
+                info.mnemonic
+            );
+        }
+    }
+}
+
+#[test]
+fn disassemble_with_decoder() {
+    use rgb_emu::disasm::{disassemble_bytes, disassemble_range};
+
+    let line = |bytes: &[u8]| disassemble_bytes(bytes, 0x0150).to_string();
+    assert_eq!(line(&[0x21, 0x34, 0x12]), "0150  21 34 12  LD HL,$1234");
+    assert_eq!(line(&[0xE0, 0x40]), "0150  E0 40     LDH ($FF40),A");
+    assert_eq!(line(&[0x20, 0xFE]), "0150  20 FE     JR NZ,$0150");
+    assert_eq!(line(&[0xF8, 0xFE]), "0150  F8 FE     LD HL,SP-$02");
+    assert_eq!(line(&[0xE8, 0x02]), "0150  E8 02     ADD SP,$02");
+    assert_eq!(line(&[0xCB, 0x7C]), "0150  CB 7C     BIT 7,H");
+    assert_eq!(line(&[0xC4, 0x00, 0x40]), "0150  C4 00 40  CALL NZ,$4000");
+    assert_eq!(line(&[0xD3]), "0150  D3        DB $D3");
+
+    // Disassembling doesn't advance the CPU
+    let mut cpu = Cpu::new();
+    cpu.bus
+        .insert_cartridge(cartridge::from_rom(vec![0; 0x8000]).unwrap());
+    for (offset, byte) in [0x3E, 0x01, 0x3C, 0x76].iter().enumerate() {
+        cpu.bus.write_byte(0xC000 + offset as u16, *byte);
+    }
+    let listing: Vec<String> = disassemble_range(cpu.bus.as_ref(), 0xC000, 3)
+        .iter()
+        .map(|disassembly| disassembly.instruction.to_string())
+        .collect();
+    assert_eq!(listing, ["LD A,$01", "INC A", "HALT"]);
+    assert_eq!(cpu.registers.pc, 0);
+}
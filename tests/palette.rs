@@ -0,0 +1,29 @@
+use rgb_emu::palette::Palette;
+use rgb_emu::video::Frame;
+
+#[test]
+fn parse_palettes() {
+    assert_eq!("green".parse(), Ok(Palette::GREEN));
+    assert_eq!("Grayscale".parse(), Ok(Palette::GRAYSCALE));
+    let custom: Palette = "#E0F8D0, 88c070,346856,#081820".parse().unwrap();
+    assert_eq!(custom.shades[0], [0xE0, 0xF8, 0xD0]);
+    assert_eq!(custom.shades[3], [0x08, 0x18, 0x20]);
+    assert_eq!(custom.to_string(), "#E0F8D0,#88C070,#346856,#081820");
+    assert_eq!(custom.to_string().parse(), Ok(custom));
+    assert!("E0F8D0,88C070,346856".parse::<Palette>().is_err());
+    assert!("E0F8D0,88C070,346856,+81820".parse::<Palette>().is_err());
+}
+
+#[test]
+fn frames_are_colored_with_palette() {
+    let pixels = [0, 3];
+    let frame = Frame {
+        number: 0,
+        pixels: &pixels,
+        colors: None,
+    };
+    assert_eq!(
+        frame.to_rgb24(&Palette::GREEN),
+        [0x9B, 0xBC, 0x0F, 0x0F, 0x38, 0x0F]
+    );
+}
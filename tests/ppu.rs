@@ -0,0 +1,444 @@
+use rgb_emu::bus::{Bus, DmgBus};
+use rgb_emu::cartridge;
+use rgb_emu::cpu::Cpu;
+use rgb_emu::ppu::Mode;
+use rgb_emu::video::{Frame, FrameHashWriter, VideoSink};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A CPU running a ROM that loops forever at 0x0100
+fn looping_cpu() -> Cpu {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100] = 0x18; // JR -2
+    rom[0x0101] = 0xFE;
+    let mut cpu = Cpu::new();
+    cpu.set_post_boot_state();
+    cpu.bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+    cpu
+}
+
+fn run_frame(cpu: &mut Cpu) {
+    let frame = cpu.bus.get_ppu().unwrap().frame_count;
+    while cpu.bus.get_ppu().unwrap().frame_count == frame {
+        let opcode = cpu.fetch();
+        let instruction = cpu.decode(opcode);
+        cpu.execute(instruction);
+    }
+}
+
+#[test]
+fn frame_changed_flag() {
+    let mut cpu = looping_cpu();
+    cpu.bus.write_byte(0xFF40, 0x91);
+    cpu.bus.write_byte(0xFF47, 0xE4);
+    run_frame(&mut cpu);
+    run_frame(&mut cpu);
+    assert!(!cpu.bus.get_ppu().unwrap().frame_changed);
+
+    // Fill the first tile with color 3
+    for address in 0x8000..0x8010 {
+        cpu.bus.write_byte(address, 0xFF);
+    }
+    run_frame(&mut cpu);
+    assert!(cpu.bus.get_ppu().unwrap().frame_changed);
+    run_frame(&mut cpu);
+    assert!(!cpu.bus.get_ppu().unwrap().frame_changed);
+}
+
+struct FrameCounter(Rc<RefCell<Vec<u64>>>);
+
+impl VideoSink for FrameCounter {
+    fn push_frame(&mut self, frame: &Frame) {
+        assert_eq!(frame.pixels.len(), 160 * 144);
+        self.0.borrow_mut().push(frame.number);
+    }
+}
+
+#[test]
+fn video_sinks_receive_frames() {
+    let mut cpu = looping_cpu();
+    let first = Rc::new(RefCell::new(Vec::new()));
+    let second = Rc::new(RefCell::new(Vec::new()));
+    cpu.bus
+        .add_video_sink(Box::new(FrameCounter(first.clone())));
+    cpu.bus
+        .add_video_sink(Box::new(FrameCounter(second.clone())));
+    run_frame(&mut cpu);
+    run_frame(&mut cpu);
+    assert_eq!(*first.borrow(), [1, 2]);
+    assert_eq!(*second.borrow(), [1, 2]);
+}
+
+#[test]
+fn frame_hashes() {
+    let mut hashes = Vec::new();
+    FrameHashWriter::new(&mut hashes).push_frame(&Frame {
+        number: 1,
+        pixels: &[0; 160 * 144],
+        colors: None,
+    });
+    assert_eq!(String::from_utf8(hashes).unwrap(), "1 B15161F6\n");
+}
+
+/// Ticks until the PPU is `row` M-cycles into OAM scan on the next line
+fn tick_into_oam_scan(bus: &mut DmgBus, row: u8) {
+    while bus.ppu.mode == Mode::OamScan {
+        bus.tick();
+    }
+    while bus.ppu.mode != Mode::OamScan {
+        bus.tick();
+    }
+    for _ in 0..row {
+        bus.tick();
+    }
+}
+
+#[test]
+fn turning_lcd_off_stops_ppu_and_blanks_screen() {
+    let mut bus = DmgBus::new();
+    bus.set_post_boot_state();
+    while bus.ppu.ly != 10 {
+        bus.tick();
+    }
+    bus.ppu.framebuffer.fill(3);
+    bus.write_byte(0xFF40, 0x11);
+    bus.set_interrupt_flags(0);
+    for _ in 0..1000 {
+        bus.tick();
+    }
+    assert_eq!(bus.read_byte(0xFF44), 0);
+    assert_eq!(bus.read_byte(0xFF41) & 0x03, 0);
+    assert_eq!(bus.get_interrupt_flags() & 0x03, 0);
+
+    // Blank frames keep coming, so frontends keep running
+    let frame = bus.ppu.frame_count;
+    for _ in 0..70224 / 4 {
+        bus.tick();
+    }
+    assert_eq!(bus.ppu.frame_count, frame + 1);
+    assert!(bus.ppu.framebuffer.iter().all(|&shade| shade == 0));
+}
+
+#[test]
+fn first_frame_after_turning_lcd_on() {
+    let mut bus = DmgBus::new();
+    bus.ppu.vram[0x1800..0x1C00].fill(0x01);
+    bus.ppu.vram[0x10..0x20].fill(0xFF);
+    bus.write_byte(0xFF47, 0xFC);
+    bus.write_byte(0xFF40, 0x91);
+    // The first line has no OAM scan
+    assert_eq!(bus.read_byte(0xFF41) & 0x03, 0);
+    while bus.ppu.mode == Mode::HBlank {
+        bus.tick();
+    }
+    assert_eq!(bus.ppu.mode, Mode::Drawing);
+
+    let frame = bus.ppu.frame_count;
+    while bus.ppu.frame_count == frame {
+        bus.tick();
+    }
+    assert!(bus.ppu.framebuffer.iter().all(|&shade| shade == 0));
+    while bus.ppu.frame_count == frame + 1 {
+        bus.tick();
+    }
+    assert!(bus.ppu.framebuffer.iter().all(|&shade| shade == 3));
+}
+
+/// Ticks until the PPU starts scanning OAM for a line
+fn tick_to_line(bus: &mut DmgBus, ly: u8) {
+    while bus.ppu.ly != ly || bus.ppu.mode != Mode::OamScan {
+        bus.tick();
+    }
+}
+
+/// A bus with a white background and a window whose first tile row is black
+fn window_bus() -> DmgBus {
+    let mut bus = DmgBus::new();
+    bus.set_post_boot_state();
+    bus.ppu.vram = [0; 0x4000];
+    bus.ppu.vram[0x10..0x20].fill(0xFF);
+    bus.ppu.vram[0x1C00..0x1C20].fill(0x01);
+    bus.write_byte(0xFF4A, 0);
+    bus.write_byte(0xFF4B, 7);
+    bus.write_byte(0xFF40, 0xF1);
+    // WY is checked at the start of each line, so start a new frame
+    tick_to_line(&mut bus, 1);
+    tick_to_line(&mut bus, 0);
+    bus
+}
+
+fn row(bus: &DmgBus, ly: usize) -> &[u8] {
+    &bus.ppu.framebuffer[ly * 160..(ly + 1) * 160]
+}
+
+#[test]
+fn window_line_counter_skips_hidden_lines() {
+    let mut bus = window_bus();
+    tick_to_line(&mut bus, 2);
+    bus.write_byte(0xFF40, 0xD1);
+    tick_to_line(&mut bus, 10);
+    bus.write_byte(0xFF40, 0xF1);
+    tick_to_line(&mut bus, 16);
+    assert!(row(&bus, 9).iter().all(|&shade| shade == 0));
+    // Lines 10-15 show window lines 2-7
+    for ly in 10..16 {
+        assert!(row(&bus, ly).iter().all(|&shade| shade == 3), "line {ly}");
+    }
+}
+
+#[test]
+fn window_stays_after_wy_changes() {
+    let mut bus = window_bus();
+    tick_to_line(&mut bus, 1);
+    bus.write_byte(0xFF4A, 100);
+    tick_to_line(&mut bus, 4);
+    assert!(row(&bus, 3).iter().all(|&shade| shade == 3));
+
+    // At WX=166, the window only covers the last pixel, but then all of the next line
+    bus.write_byte(0xFF4B, 166);
+    tick_to_line(&mut bus, 5);
+    assert_eq!(row(&bus, 4)[158..], [0, 3]);
+    bus.write_byte(0xFF4B, 167);
+    tick_to_line(&mut bus, 6);
+    assert!(row(&bus, 5).iter().all(|&shade| shade == 3));
+}
+
+#[test]
+fn sprite_priority_and_limit() {
+    let mut bus = DmgBus::new();
+    bus.set_post_boot_state();
+    bus.ppu.vram = [0; 0x4000];
+    bus.ppu.vram[0x10..0x20].fill(0xFF);
+    for byte in bus.ppu.vram[0x20..0x30].iter_mut().step_by(2) {
+        *byte = 0xFF;
+    }
+    bus.write_byte(0xFF48, 0xE4);
+    bus.write_byte(0xFF40, 0x93);
+    // The sprite with the lower X is on top, even though it's later in OAM
+    bus.ppu.oam[0..8].copy_from_slice(&[16, 12, 1, 0, 16, 8, 2, 0]);
+    // Only 10 sprites fit on a line, so the 11th isn't drawn
+    for i in 2..11 {
+        bus.ppu.oam[i * 4..i * 4 + 4].copy_from_slice(&[16, 24 + 8 * i as u8, 1, 0]);
+    }
+    tick_to_line(&mut bus, 1);
+    let line = row(&bus, 0);
+    assert_eq!(line[0..12], [1, 1, 1, 1, 1, 1, 1, 1, 3, 3, 3, 3]);
+    assert_eq!(line[88], 3);
+    assert_eq!(line[96], 0);
+}
+
+/// M-cycles the PPU spends in mode 3 on the next line
+fn drawing_cycles(bus: &mut DmgBus) -> u32 {
+    let ly = bus.ppu.ly.wrapping_add(1);
+    tick_to_line(bus, ly);
+    while bus.ppu.mode != Mode::Drawing {
+        bus.tick();
+    }
+    let mut cycles = 0;
+    while bus.ppu.mode == Mode::Drawing {
+        bus.tick();
+        cycles += 1;
+    }
+    cycles
+}
+
+#[test]
+fn mode_3_length() {
+    let mut bus = DmgBus::new();
+    bus.set_post_boot_state();
+    bus.write_byte(0xFF40, 0x93);
+    assert_eq!(drawing_cycles(&mut bus), 43);
+    // SCX % 8 pixels are thrown away
+    bus.write_byte(0xFF43, 0x13);
+    assert_eq!(drawing_cycles(&mut bus), 44);
+    bus.write_byte(0xFF43, 0);
+    // A sprite at the start of a tile stalls for 11 dots, and one more in the same tile for 6
+    bus.ppu.oam[0..8].copy_from_slice(&[0, 8, 0, 0, 0, 8, 0, 0]);
+    for sprite in bus.ppu.oam[0..8].chunks_exact_mut(4) {
+        sprite[0] = 16 + bus.ppu.ly + 1;
+    }
+    assert_eq!(drawing_cycles(&mut bus), 48);
+    // Sprites past the right edge aren't fetched
+    for sprite in bus.ppu.oam[0..8].chunks_exact_mut(4) {
+        sprite[0] = 16 + bus.ppu.ly + 1;
+        sprite[1] = 168;
+    }
+    assert_eq!(drawing_cycles(&mut bus), 43);
+}
+
+#[test]
+fn ly_reads_0_during_line_153() {
+    let mut bus = DmgBus::new();
+    bus.set_post_boot_state();
+    bus.write_byte(0xFF45, 0);
+    bus.write_byte(0xFF41, 0x40);
+    while bus.ppu.ly != 153 {
+        bus.tick();
+    }
+    bus.set_interrupt_flags(0);
+    assert_eq!(bus.read_byte(0xFF44), 153);
+    assert_eq!(bus.read_byte(0xFF44), 0);
+    // LYC=0 already matches, well before line 0
+    assert_eq!(bus.read_byte(0xFF41) & 0x04, 0x04);
+    assert_eq!(bus.get_interrupt_flags() & 0x02, 0x02);
+    assert_eq!(bus.ppu.ly, 153);
+}
+
+#[test]
+fn ppu_locks_vram_and_oam() {
+    let mut bus = DmgBus::new();
+    bus.set_post_boot_state();
+    bus.ppu.vram[0] = 0x12;
+    bus.ppu.oam[0] = 0x34;
+
+    tick_into_oam_scan(&mut bus, 0);
+    assert_eq!(bus.read_byte(0xFE00), 0x34, "the locks are off by default");
+
+    bus.set_ppu_access_locks(true);
+    tick_into_oam_scan(&mut bus, 0);
+    assert_eq!(bus.read_byte(0x8000), 0x12);
+    assert_eq!(bus.read_byte(0xFE00), 0xFF);
+    bus.write_byte(0xFE00, 0x56);
+    while bus.ppu.mode != Mode::Drawing {
+        bus.tick();
+    }
+    assert_eq!(bus.read_byte(0x8000), 0xFF);
+    bus.write_byte(0x8000, 0x78);
+    while bus.ppu.mode != Mode::HBlank {
+        bus.tick();
+    }
+    assert_eq!(bus.read_byte(0x8000), 0x12);
+    assert_eq!(bus.read_byte(0xFE00), 0x34);
+}
+
+#[test]
+fn oam_bug_corrupts_scanned_row() {
+    let mut bus = DmgBus::new();
+    bus.set_post_boot_state();
+    for (i, byte) in bus.ppu.oam.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let oam = bus.ppu.oam;
+
+    tick_into_oam_scan(&mut bus, 5);
+    bus.oam_bug(0xFE10);
+    assert_eq!(bus.ppu.oam, oam, "the bug is off by default");
+
+    bus.set_oam_bug(true);
+    tick_into_oam_scan(&mut bus, 5);
+    bus.oam_bug(0xC000);
+    assert_eq!(bus.ppu.oam, oam);
+    bus.oam_bug(0xFE10);
+    // a = 0x2928, b = 0x2120, c = 0x2524
+    assert_eq!(
+        bus.ppu.oam[40..48],
+        [0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27]
+    );
+    assert_eq!(bus.ppu.oam[32..40], oam[32..40]);
+    assert_eq!(bus.ppu.oam[48..], oam[48..]);
+}
+
+/// A bus with the LCD and the background turned on
+fn lcd_bus() -> DmgBus {
+    let mut bus = DmgBus::new();
+    bus.write_byte(0xFF47, 0xE4);
+    bus.write_byte(0xFF40, 0x91);
+    bus
+}
+
+#[test]
+fn ppu_mode_timing() {
+    let mut bus = lcd_bus();
+    while bus.ppu.ly != 1 {
+        bus.tick();
+    }
+    // M-cycles spent in each mode on a line without sprites or scrolling
+    let mut cycles = [0; 4];
+    while bus.ppu.ly == 1 {
+        cycles[bus.ppu.mode as usize] += 1;
+        bus.tick();
+    }
+    assert_eq!(cycles, [51, 0, 20, 43]);
+    assert_eq!(bus.peek_byte(0xFF44), 2);
+    assert_eq!(bus.peek_byte(0xFF41) & 0x03, Mode::OamScan as u8);
+}
+
+#[test]
+fn vblank_and_stat_interrupts() {
+    let mut bus = lcd_bus();
+    bus.write_byte(0xFF45, 2);
+    bus.write_byte(0xFF41, 0x40);
+    bus.write_byte(0xFF0F, 0);
+    while bus.ppu.ly < 2 {
+        assert_eq!(bus.peek_byte(0xFF0F) & 0x03, 0);
+        bus.tick();
+    }
+    while bus.ppu.ly < 3 {
+        bus.tick();
+    }
+    assert_eq!(bus.peek_byte(0xFF0F) & 0x03, 0x02);
+
+    bus.write_byte(0xFF0F, 0);
+    while bus.ppu.ly < 144 {
+        assert_eq!(bus.peek_byte(0xFF0F) & 0x01, 0);
+        bus.tick();
+    }
+    while bus.ppu.ly < 145 {
+        bus.tick();
+    }
+    assert_eq!(bus.peek_byte(0xFF0F) & 0x03, 0x01);
+}
+
+#[test]
+fn renders_background_and_sprites() {
+    let mut bus = DmgBus::new();
+    // Tile 1 is all color 3, in the top left corner of the map and in a sprite at X=16
+    bus.ppu.vram[0x10..0x20].fill(0xFF);
+    bus.ppu.vram[0x1800] = 1;
+    bus.ppu.oam[0..4].copy_from_slice(&[16, 24, 1, 0]);
+    bus.write_byte(0xFF47, 0xE4);
+    bus.write_byte(0xFF48, 0x54);
+    bus.write_byte(0xFF40, 0x93);
+    for _ in 0..2 {
+        let frame = bus.ppu.frame_count;
+        while bus.ppu.frame_count == frame {
+            bus.tick();
+        }
+    }
+    let line = &bus.ppu.framebuffer[0..160];
+    assert_eq!(line[0..8], [3; 8]);
+    assert_eq!(line[8..16], [0; 8]);
+    assert_eq!(line[16..24], [1; 8]);
+    assert_eq!(line[24], 0);
+}
+
+#[test]
+fn dmg_background_disabled_is_white() {
+    let mut bus = window_bus();
+    // An inverted BGP, so color 0 would be black
+    bus.write_byte(0xFF47, 0x1B);
+    bus.write_byte(0xFF40, 0xF0);
+    tick_to_line(&mut bus, 2);
+    // Neither the background nor the window is drawn
+    assert!(row(&bus, 0).iter().all(|&shade| shade == 0));
+    assert!(row(&bus, 1).iter().all(|&shade| shade == 0));
+
+    bus.write_byte(0xFF40, 0xD1);
+    tick_to_line(&mut bus, 3);
+    assert!(row(&bus, 2).iter().all(|&shade| shade == 3));
+}
+
+#[test]
+fn oam_dma_copies_to_oam() {
+    let mut bus = DmgBus::new();
+    for i in 0..0xA0 {
+        bus.write_byte(0xC000 + i, i as u8);
+    }
+    bus.write_byte(0xFF46, 0xC0);
+    for _ in 0..0xA2 {
+        bus.tick();
+    }
+    for (i, &byte) in bus.ppu.oam.iter().enumerate() {
+        assert_eq!(byte, i as u8);
+    }
+}
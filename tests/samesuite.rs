@@ -0,0 +1,94 @@
+//! Runs the APU tests from SameSuite, which check register read-back, DAC behavior and the
+//! timing of channel triggers on a CGB. They're not included; build the tests from
+//! <https://github.com/LIJI32/SameSuite> and put the built `apu/` directory in
+//! `tests/samesuite/`.
+//!
+//! Many of them fail until the delay before a triggered channel's first step, NRx2 writes while
+//! a channel plays (zombie mode) and wave RAM access while the wave channel plays are emulated.
+//! So that those failures show instead of being ignored, but don't fail every test run, the
+//! suite only runs with the `samesuite` feature:
+//! `cargo test --features samesuite --test samesuite`.
+#![cfg(feature = "samesuite")]
+
+mod mooneye;
+use rgb_emu::model::Model;
+
+fn run_samesuite_test(path: &str) -> Result<(), String> {
+    mooneye::run_mooneye_test(&format!("tests/samesuite/{path}"), Model::Cgb)
+}
+
+macro_rules! samesuite_tests {
+    ($($name:ident: $path:literal,)*) => {
+        $(
+            #[test]
+            fn $name() -> Result<(), String> {
+                run_samesuite_test($path)
+            }
+        )*
+    };
+}
+
+samesuite_tests! {
+    div_write_trigger: "apu/div_write_trigger.gb",
+    div_write_trigger_10: "apu/div_write_trigger_10.gb",
+    div_write_trigger_volume: "apu/div_write_trigger_volume.gb",
+    div_write_trigger_volume_10: "apu/div_write_trigger_volume_10.gb",
+    div_trigger_volume_10: "apu/div_trigger_volume_10.gb",
+    channel_1_align: "apu/channel_1/channel_1_align.gb",
+    channel_1_align_cpu: "apu/channel_1/channel_1_align_cpu.gb",
+    channel_1_delay: "apu/channel_1/channel_1_delay.gb",
+    channel_1_duty: "apu/channel_1/channel_1_duty.gb",
+    channel_1_duty_delay: "apu/channel_1/channel_1_duty_delay.gb",
+    channel_1_freq_change: "apu/channel_1/channel_1_freq_change.gb",
+    channel_1_nrx2_glitch: "apu/channel_1/channel_1_nrx2_glitch.gb",
+    channel_1_nrx2_speed_change: "apu/channel_1/channel_1_nrx2_speed_change.gb",
+    channel_1_restart: "apu/channel_1/channel_1_restart.gb",
+    channel_1_restart_nrx2_glitch: "apu/channel_1/channel_1_restart_nrx2_glitch.gb",
+    channel_1_stop_div: "apu/channel_1/channel_1_stop_div.gb",
+    channel_1_stop_restart: "apu/channel_1/channel_1_stop_restart.gb",
+    channel_1_sweep: "apu/channel_1/channel_1_sweep.gb",
+    channel_1_sweep_restart: "apu/channel_1/channel_1_sweep_restart.gb",
+    channel_1_sweep_restart_2: "apu/channel_1/channel_1_sweep_restart_2.gb",
+    channel_1_volume: "apu/channel_1/channel_1_volume.gb",
+    channel_1_volume_div: "apu/channel_1/channel_1_volume_div.gb",
+    channel_2_align: "apu/channel_2/channel_2_align.gb",
+    channel_2_align_cpu: "apu/channel_2/channel_2_align_cpu.gb",
+    channel_2_delay: "apu/channel_2/channel_2_delay.gb",
+    channel_2_duty: "apu/channel_2/channel_2_duty.gb",
+    channel_2_duty_delay: "apu/channel_2/channel_2_duty_delay.gb",
+    channel_2_freq_change: "apu/channel_2/channel_2_freq_change.gb",
+    channel_2_nrx2_glitch: "apu/channel_2/channel_2_nrx2_glitch.gb",
+    channel_2_nrx2_speed_change: "apu/channel_2/channel_2_nrx2_speed_change.gb",
+    channel_2_restart: "apu/channel_2/channel_2_restart.gb",
+    channel_2_restart_nrx2_glitch: "apu/channel_2/channel_2_restart_nrx2_glitch.gb",
+    channel_2_stop_div: "apu/channel_2/channel_2_stop_div.gb",
+    channel_2_stop_restart: "apu/channel_2/channel_2_stop_restart.gb",
+    channel_2_volume: "apu/channel_2/channel_2_volume.gb",
+    channel_2_volume_div: "apu/channel_2/channel_2_volume_div.gb",
+    channel_3_and_glitch: "apu/channel_3/channel_3_and_glitch.gb",
+    channel_3_delay: "apu/channel_3/channel_3_delay.gb",
+    channel_3_first_sample: "apu/channel_3/channel_3_first_sample.gb",
+    channel_3_freq_change_delay: "apu/channel_3/channel_3_freq_change_delay.gb",
+    channel_3_restart_delay: "apu/channel_3/channel_3_restart_delay.gb",
+    channel_3_restart_during_delay: "apu/channel_3/channel_3_restart_during_delay.gb",
+    channel_3_restart_stop_delay: "apu/channel_3/channel_3_restart_stop_delay.gb",
+    channel_3_shift_delay: "apu/channel_3/channel_3_shift_delay.gb",
+    channel_3_shift_skip_delay: "apu/channel_3/channel_3_shift_skip_delay.gb",
+    channel_3_stop_delay: "apu/channel_3/channel_3_stop_delay.gb",
+    channel_3_stop_div: "apu/channel_3/channel_3_stop_div.gb",
+    channel_3_wave_ram_dac_on_rw: "apu/channel_3/channel_3_wave_ram_dac_on_rw.gb",
+    channel_3_wave_ram_locked_write: "apu/channel_3/channel_3_wave_ram_locked_write.gb",
+    channel_3_wave_ram_sync: "apu/channel_3/channel_3_wave_ram_sync.gb",
+    channel_4_align: "apu/channel_4/channel_4_align.gb",
+    channel_4_delay: "apu/channel_4/channel_4_delay.gb",
+    channel_4_equivalent_frequencies: "apu/channel_4/channel_4_equivalent_frequencies.gb",
+    channel_4_freq_change: "apu/channel_4/channel_4_freq_change.gb",
+    channel_4_frequency_alignment: "apu/channel_4/channel_4_frequency_alignment.gb",
+    channel_4_lfsr: "apu/channel_4/channel_4_lfsr.gb",
+    channel_4_lfsr15: "apu/channel_4/channel_4_lfsr15.gb",
+    channel_4_lfsr_15_7: "apu/channel_4/channel_4_lfsr_15_7.gb",
+    channel_4_lfsr_7_15: "apu/channel_4/channel_4_lfsr_7_15.gb",
+    channel_4_lfsr_restart: "apu/channel_4/channel_4_lfsr_restart.gb",
+    channel_4_lfsr_restart_fast: "apu/channel_4/channel_4_lfsr_restart_fast.gb",
+    channel_4_volume_div: "apu/channel_4/channel_4_volume_div.gb",
+}
@@ -0,0 +1,53 @@
+use rgb_emu::scaler::{scale, Filter};
+
+const WHITE: [u8; 3] = [0xFF; 3];
+const BLACK: [u8; 3] = [0x00; 3];
+
+/// Splits an RGB24 image into rows of pixels
+fn rows(pixels: &[u8], width: usize) -> Vec<Vec<[u8; 3]>> {
+    pixels
+        .chunks(width * 3)
+        .map(|row| row.chunks(3).map(|p| [p[0], p[1], p[2]]).collect())
+        .collect()
+}
+
+#[test]
+fn nearest_neighbor() {
+    let image = [WHITE, BLACK].concat();
+    let scaled = rows(&scale(2, 1, &image, 3, Filter::None), 6);
+    assert_eq!(scaled.len(), 3);
+    for row in scaled {
+        assert_eq!(row, [WHITE, WHITE, WHITE, BLACK, BLACK, BLACK]);
+    }
+}
+
+#[test]
+fn scanlines_and_grid() {
+    let gray = [0xBF; 3];
+    let scaled = rows(&scale(1, 1, &WHITE, 2, Filter::Scanlines), 2);
+    assert_eq!(scaled, [vec![WHITE, WHITE], vec![gray, gray]]);
+    let scaled = rows(&scale(1, 1, &WHITE, 2, Filter::LcdGrid), 2);
+    assert_eq!(scaled, [vec![WHITE, gray], vec![gray, gray]]);
+    assert_eq!(scale(1, 1, &WHITE, 1, Filter::LcdGrid), WHITE);
+}
+
+#[test]
+fn smooth_diagonal() {
+    // A diagonal line from the top right to the bottom left
+    let image = [WHITE, BLACK, BLACK, WHITE].concat();
+    let scaled = rows(&scale(2, 2, &image, 2, Filter::Smooth), 4);
+    // The white pixels meet diagonally, so the corners between them are filled in
+    assert_eq!(scaled[0], [WHITE, WHITE, BLACK, BLACK]);
+    assert_eq!(scaled[1], [WHITE, BLACK, WHITE, BLACK]);
+    assert_eq!(scaled[2], [BLACK, WHITE, BLACK, WHITE]);
+    assert_eq!(scaled[3], [BLACK, BLACK, WHITE, WHITE]);
+    assert_eq!(scale(2, 2, &image, 6, Filter::Smooth).len(), 12 * 12 * 3);
+    assert_eq!(scale(2, 2, &image, 5, Filter::Smooth).len(), 10 * 10 * 3);
+}
+
+#[test]
+fn parse_filter() {
+    assert_eq!("LCD-Grid".parse(), Ok(Filter::LcdGrid));
+    assert_eq!(Filter::Scanlines.to_string().parse(), Ok(Filter::Scanlines));
+    assert!("blur".parse::<Filter>().is_err());
+}
@@ -0,0 +1,87 @@
+#![allow(clippy::unwrap_used)]
+use rgb_emu::cartridge;
+use rgb_emu::cpu::Cpu;
+use rgb_emu::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use rgb_emu::video::{Frame, VideoSink};
+use std::cell::RefCell;
+use std::fs::File;
+use std::rc::Rc;
+
+/// Give up if a test hasn't finished after 30 seconds of emulated time
+const TIMEOUT: u64 = 30 * 1_048_576;
+
+/// Keeps the last completed frame
+struct LastFrame(Rc<RefCell<Vec<u8>>>);
+
+impl VideoSink for LastFrame {
+    fn push_frame(&mut self, frame: &Frame) {
+        self.0.replace(frame.pixels.to_vec());
+    }
+}
+
+/// Runs a ROM until it executes `LD B,B`, which test ROMs use as a breakpoint when they're done
+/// drawing, and returns the shades of the last completed frame
+pub(crate) fn run_until_breakpoint(path: &str) -> Result<Vec<u8>, String> {
+    let mut cpu = Cpu::new();
+    cpu.set_post_boot_state();
+    let rom = std::fs::read(path).expect("Unable to open ROM");
+    cpu.bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+    let frame = Rc::new(RefCell::new(Vec::new()));
+    cpu.bus.add_video_sink(Box::new(LastFrame(frame.clone())));
+
+    while cpu.bus.cycles() < TIMEOUT {
+        if !cpu.halted && cpu.bus.peek_byte(cpu.registers.pc) == 0x40 {
+            return Ok(frame.take());
+        }
+        cpu.step();
+    }
+    Err(format!("{path} timed out"))
+}
+
+/// Compares a frame with a grayscale or RGB reference screenshot, where white is shade 0 and
+/// black is shade 3. If they differ, the frame is saved next to the reference as `.actual.png`
+/// and the region that differs is reported.
+pub(crate) fn compare(frame: &[u8], reference: &str) -> Result<(), String> {
+    let decoder = png::Decoder::new(File::open(reference).expect("Unable to open screenshot"));
+    let mut reader = decoder.read_info().unwrap();
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).unwrap();
+    let channels = info.color_type.samples();
+    assert_eq!(
+        (info.width as usize, info.height as usize),
+        (SCREEN_WIDTH, SCREEN_HEIGHT),
+        "{reference} isn't a screenshot"
+    );
+    let expected: Vec<u8> = buffer[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|pixel| 3 - pixel[0] / 0x40)
+        .collect();
+
+    let differing: Vec<(usize, usize)> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+        .filter(|&i| frame.get(i) != expected.get(i))
+        .map(|i| (i % SCREEN_WIDTH, i / SCREEN_WIDTH))
+        .collect();
+    if differing.is_empty() {
+        return Ok(());
+    }
+    let actual = reference.trim_end_matches(".png").to_string() + ".actual.png";
+    if frame.len() == SCREEN_WIDTH * SCREEN_HEIGHT {
+        let pixels: Vec<u8> = frame
+            .iter()
+            .flat_map(|shade| [0xFF - shade * 0x55; 3])
+            .collect();
+        let _ = std::fs::write(
+            &actual,
+            rgb_emu::png::encode(SCREEN_WIDTH, SCREEN_HEIGHT, &pixels),
+        );
+    }
+    let left = differing.iter().map(|(x, _)| x).min().unwrap();
+    let right = differing.iter().map(|(x, _)| x).max().unwrap();
+    let top = differing.iter().map(|(_, y)| y).min().unwrap();
+    let bottom = differing.iter().map(|(_, y)| y).max().unwrap();
+    Err(format!(
+        "{} pixels differ from {reference} between ({left}, {top}) and ({right}, {bottom}); \
+         see {actual}",
+        differing.len()
+    ))
+}
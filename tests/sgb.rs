@@ -0,0 +1,97 @@
+use rgb_emu::cartridge;
+use rgb_emu::cpu::Cpu;
+use rgb_emu::model::Model;
+
+/// An SGB running a ROM that loops forever at 0x0100
+fn sgb_cpu() -> Cpu {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100] = 0x18; // JR -2
+    rom[0x0101] = 0xFE;
+    let mut cpu = Cpu::new();
+    cpu.bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+    cpu.bus.set_model(Model::Sgb);
+    cpu.set_post_boot_state();
+    cpu
+}
+
+fn run_frame(cpu: &mut Cpu) {
+    let frame = cpu.bus.get_ppu().unwrap().frame_count;
+    while cpu.bus.get_ppu().unwrap().frame_count == frame {
+        cpu.step();
+    }
+}
+
+/// Sends a 16-byte packet through P1, bit by bit
+fn send_packet(cpu: &mut Cpu, packet: &[u8]) {
+    let mut bytes = [0; 16];
+    bytes[..packet.len()].copy_from_slice(packet);
+    cpu.bus.write_byte(0xFF00, 0x00);
+    cpu.bus.write_byte(0xFF00, 0x30);
+    for byte in bytes {
+        for bit in 0..8 {
+            let pulse = if byte >> bit & 1 != 0 { 0x10 } else { 0x20 };
+            cpu.bus.write_byte(0xFF00, pulse);
+            cpu.bus.write_byte(0xFF00, 0x30);
+        }
+    }
+    // Stop bit
+    cpu.bus.write_byte(0xFF00, 0x20);
+    cpu.bus.write_byte(0xFF00, 0x30);
+}
+
+#[test]
+fn multiplayer_joypad_ids() {
+    let mut cpu = sgb_cpu();
+    cpu.bus.write_byte(0xFF00, 0x30);
+    assert_eq!(cpu.bus.read_byte(0xFF00), 0xFF);
+    // MLT_REQ with 2 players
+    send_packet(&mut cpu, &[0x11 << 3 | 1, 0x01]);
+    assert_eq!(cpu.bus.read_byte(0xFF00), 0xFF);
+    cpu.bus.write_byte(0xFF00, 0x10);
+    cpu.bus.write_byte(0xFF00, 0x30);
+    assert_eq!(cpu.bus.read_byte(0xFF00), 0xFE);
+    cpu.bus.write_byte(0xFF00, 0x10);
+    cpu.bus.write_byte(0xFF00, 0x30);
+    assert_eq!(cpu.bus.read_byte(0xFF00), 0xFF);
+}
+
+#[test]
+fn palettes_and_attributes() {
+    let mut cpu = sgb_cpu();
+    cpu.bus.write_byte(0xFF40, 0x00);
+    cpu.bus.write_byte(0xFF47, 0xE4);
+    // Tile 0 has color 3, which covers the screen
+    for address in 0x8000..0x8010 {
+        cpu.bus.write_byte(address, 0xFF);
+    }
+    cpu.bus.write_byte(0xFF40, 0x91);
+
+    // PAL01: palette 0 color 3 is red, and palette 1 color 3 is blue
+    send_packet(
+        &mut cpu,
+        &[
+            0x01, 0xFF, 0x7F, 0, 0, 0, 0, 0x1F, 0x00, 0, 0, 0, 0, 0x00, 0x7C,
+        ],
+    );
+    // ATTR_BLK: one block setting the inside and edge of cells (1, 1)-(2, 2) to palette 1
+    send_packet(&mut cpu, &[0x04 << 3 | 1, 1, 0x03, 0x05, 1, 1, 2, 2]);
+    run_frame(&mut cpu);
+    run_frame(&mut cpu);
+
+    let frame = cpu.bus.get_ppu().unwrap().frame();
+    let colors = frame.colors.expect("SGB frames have colors");
+    assert_eq!(colors[0], 0x001F);
+    assert_eq!(colors[8 * 160 + 8], 0x7C00);
+    assert_eq!(colors[23 * 160 + 23], 0x7C00);
+    assert_eq!(colors[24 * 160 + 24], 0x001F);
+}
+
+#[test]
+fn mask() {
+    let mut cpu = sgb_cpu();
+    // MASK_EN with black
+    send_packet(&mut cpu, &[0x17 << 3 | 1, 0x02]);
+    run_frame(&mut cpu);
+    let frame = cpu.bus.get_ppu().unwrap().frame();
+    assert!(frame.colors.unwrap().iter().all(|color| *color == 0));
+}
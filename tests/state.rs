@@ -0,0 +1,157 @@
+use rgb_emu::cartridge;
+use rgb_emu::cpu::{Cpu, Flags, Registers};
+use rgb_emu::metadata::Mismatch;
+use rgb_emu::model::Model;
+use rgb_emu::state::{self, State, StateError, StateReader, StateWriter};
+
+fn registers() -> Registers {
+    Registers {
+        a: 0x01,
+        b: 0x23,
+        c: 0x45,
+        d: 0x67,
+        e: 0x89,
+        h: 0xAB,
+        l: 0xCD,
+        pc: 0x0150,
+        sp: 0xFFFE,
+    }
+}
+
+/// The serialized bytes are the same on every platform, so compare against a fixed encoding
+#[test]
+fn state_encoding_is_little_endian() {
+    let mut writer = StateWriter::new();
+    registers().save_state(&mut writer);
+    writer.write_u32(0x1234_5678);
+    writer.write_u64(0x0102_0304_0506_0708);
+    writer.write_bytes(&[0xAA, 0xBB]);
+    assert_eq!(
+        writer.into_bytes(),
+        [
+            0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0x50, 0x01, 0xFE, 0xFF, // registers
+            0x78, 0x56, 0x34, 0x12, // u32
+            0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, // u64
+            0x02, 0x00, 0x00, 0x00, 0xAA, 0xBB, // bytes
+        ]
+    );
+}
+
+#[test]
+fn state_round_trip() {
+    let mut writer = StateWriter::new();
+    registers().save_state(&mut writer);
+    Flags {
+        z: true,
+        c: false,
+        n: false,
+        h: true,
+    }
+    .save_state(&mut writer);
+    let bytes = writer.into_bytes();
+
+    let mut reader = StateReader::new(&bytes);
+    let mut loaded = Registers::default();
+    loaded.load_state(&mut reader).unwrap();
+    let mut flags = Flags::default();
+    flags.load_state(&mut reader).unwrap();
+    assert_eq!(loaded.pc, 0x0150);
+    assert_eq!(loaded.l, 0xCD);
+    assert!(flags.z && flags.h && !flags.c && !flags.n);
+    assert_eq!(reader.read_u8(), Err(StateError::UnexpectedEnd));
+}
+
+/// A CPU at 0x0100 of an MBC1 ROM with RAM, running `INC A; LD ($A000),A; JR $0100`
+fn machine(title: u8) -> Cpu {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0107].copy_from_slice(&[0x3C, 0xEA, 0x00, 0xA0, 0x18, 0xFA, 0x00]);
+    rom[0x0134] = title;
+    rom[0x0147] = 0x03;
+    rom[0x0149] = 0x02;
+    let mut cpu = Cpu::new();
+    cpu.set_post_boot_state();
+    cpu.bus.insert_cartridge(cartridge::from_rom(rom).unwrap());
+    // Enable cartridge RAM
+    cpu.bus.write_byte(0x0000, 0x0A);
+    cpu
+}
+
+#[test]
+fn machine_state_round_trip() {
+    let mut cpu = machine(0);
+    for _ in 0..30 {
+        cpu.step();
+    }
+    let saved = state::save(&cpu);
+    let a = cpu.registers.a;
+    let cycles = cpu.bus.cycles();
+    for _ in 0..30 {
+        cpu.step();
+    }
+    assert_ne!(cpu.registers.a, a);
+
+    state::load(&mut cpu, &saved).unwrap();
+    assert_eq!(cpu.registers.a, a);
+    assert_eq!(cpu.bus.cycles(), cycles);
+    assert_eq!(cpu.bus.peek_byte(0xA000), a);
+    assert_eq!(state::save(&cpu), saved);
+}
+
+#[test]
+fn machine_state_is_checked() {
+    let mut cpu = machine(0);
+    let saved = state::save(&cpu);
+    assert_eq!(
+        state::load(&mut machine(1), &saved),
+        Err(StateError::WrongRom)
+    );
+    assert_eq!(state::load(&mut cpu, b"RGB"), Err(StateError::NotAState));
+
+    // A truncated state leaves the machine as it was
+    cpu.registers.a = 0x42;
+    assert_eq!(
+        state::load(&mut cpu, &saved[..saved.len() - 1]),
+        Err(StateError::UnexpectedEnd)
+    );
+    assert_eq!(cpu.registers.a, 0x42);
+}
+
+#[test]
+fn machine_state_records_settings() {
+    let mut cpu = machine(0);
+    cpu.bus.set_model(Model::Cgb);
+    cpu.bus.set_ppu_access_locks(true);
+    let saved = state::save(&cpu);
+    assert_eq!(state::metadata(&saved), Ok(cpu.bus.metadata()));
+
+    let cpu = machine(0);
+    assert_eq!(
+        state::metadata(&saved)
+            .unwrap()
+            .mismatches(&cpu.bus.metadata()),
+        [Mismatch::Model, Mismatch::Accuracy]
+    );
+    assert_eq!(state::metadata(b"RGB"), Err(StateError::NotAState));
+}
+
+#[test]
+fn loaded_state_keeps_timing() {
+    let mut cpu = machine(0);
+    cpu.bus.write_byte(0xFF07, 0x05);
+    cpu.bus.write_byte(0xFF41, 0x40);
+    for _ in 0..1000 {
+        cpu.step();
+    }
+    let saved = state::save(&cpu);
+    let trace = |cpu: &mut Cpu| {
+        (0..5000)
+            .map(|_| {
+                cpu.step();
+                [0xFF04, 0xFF05, 0xFF0F, 0xFF41, 0xFF44].map(|address| cpu.bus.peek_byte(address))
+            })
+            .collect::<Vec<_>>()
+    };
+    let expected = trace(&mut cpu);
+    state::load(&mut cpu, &saved).unwrap();
+    assert_eq!(trace(&mut cpu), expected);
+}
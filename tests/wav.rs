@@ -0,0 +1,59 @@
+use std::cell::RefCell;
+use std::io::Cursor;
+use std::rc::Rc;
+
+use rgb_emu::audio::{AudioSink, WavWriter, SAMPLE_RATE};
+use rgb_emu::bus::{Bus, DmgBus};
+
+#[test]
+fn wav_header_sizes() {
+    let mut buffer = Cursor::new(Vec::new());
+    {
+        let mut wav = WavWriter::new(&mut buffer).unwrap();
+        for i in 0..100 {
+            wav.push_sample(i, -i);
+        }
+    }
+    let wav = buffer.into_inner();
+    assert_eq!(wav.len(), 44 + 100 * 4);
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 36 + 400);
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(
+        u32::from_le_bytes(wav[24..28].try_into().unwrap()),
+        SAMPLE_RATE
+    );
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 400);
+    assert_eq!(i16::from_le_bytes([wav[48], wav[49]]), 1);
+    assert_eq!(i16::from_le_bytes([wav[50], wav[51]]), -1);
+}
+
+struct Samples(Rc<RefCell<Vec<(i16, i16)>>>);
+
+impl AudioSink for Samples {
+    fn push_sample(&mut self, left: i16, right: i16) {
+        self.0.borrow_mut().push((left, right));
+    }
+}
+
+#[test]
+fn captures_mixed_apu_output() {
+    let samples = Rc::new(RefCell::new(Vec::new()));
+    let mut bus = DmgBus::new();
+    bus.set_audio_sink(Box::new(Samples(samples.clone())));
+    bus.write_byte(0xFF26, 0x80);
+    bus.write_byte(0xFF24, 0x77);
+    bus.write_byte(0xFF25, 0x02); // Square 2 on the right only
+    bus.write_byte(0xFF17, 0xF0);
+    bus.write_byte(0xFF19, 0x87);
+    for _ in 0..70224 / 4 {
+        bus.tick();
+    }
+
+    let samples = samples.borrow();
+    assert!(samples.len() >= SAMPLE_RATE as usize / 60);
+    assert!(samples.iter().all(|&(left, _)| left == 0));
+    assert!(samples.iter().any(|&(_, right)| right > 0x1000));
+    assert!(samples.iter().any(|&(_, right)| right < -0x1000));
+}
@@ -0,0 +1,61 @@
+use rgb_emu::video::{Frame, VideoSink};
+use rgb_emu::websocket::{accept_key, binary_message, WebSocketStreamer};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+#[test]
+fn handshake_accept_key() {
+    // The example from RFC 6455
+    assert_eq!(
+        accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+        "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+    );
+}
+
+#[test]
+fn message_lengths() {
+    assert_eq!(binary_message(&[1, 2]), [0x82, 2, 1, 2]);
+    let message = binary_message(&[0; 300]);
+    assert_eq!(message[..4], [0x82, 126, 0x01, 0x2C]);
+    assert_eq!(message.len(), 304);
+    let message = binary_message(&[0; 0x10000]);
+    assert_eq!(message[..10], [0x82, 127, 0, 0, 0, 0, 0, 1, 0, 0]);
+}
+
+#[test]
+fn streams_frames_to_clients() {
+    let mut streamer = WebSocketStreamer::bind(0).unwrap();
+    let mut client = TcpStream::connect(("127.0.0.1", streamer.port().unwrap())).unwrap();
+    client
+        .write_all(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+              Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        )
+        .unwrap();
+
+    let pixels = [3; 160 * 144];
+    streamer.push_frame(&Frame {
+        number: 7,
+        pixels: &pixels,
+        colors: None,
+    });
+
+    let mut reader = BufReader::new(client);
+    let mut response = String::new();
+    while !response.ends_with("\r\n\r\n") {
+        reader.read_line(&mut response).unwrap();
+    }
+    assert!(response.starts_with("HTTP/1.1 101"));
+    assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+    let mut header = [0; 10];
+    reader.read_exact(&mut header).unwrap();
+    assert_eq!(header[..2], [0x82, 127]);
+    let length = u64::from_be_bytes(header[2..].try_into().unwrap());
+    assert_eq!(length, 8 + 160 * 144 * 3);
+    let mut payload = vec![0; 8 + 3];
+    reader.read_exact(&mut payload).unwrap();
+    assert_eq!(payload[..8], 7_u64.to_le_bytes());
+    // The darkest shade of the default palette
+    assert_eq!(payload[8..], rgb_emu::palette::Palette::default().color(3));
+}